@@ -26,6 +26,9 @@ pub const EXCEPTION_STORE_ACCESS_FAULT: usize = 7;
 pub const EXCEPTION_ECALL_FROM_USER: usize = 8;
 pub const EXCEPTION_ECALL_FROM_SUPERVISOR: usize = 9;
 pub const EXCEPTION_ECALL_FROM_MACHINE: usize = 11;
+pub const EXCEPTION_INSTRUCTION_PAGE_FAULT: usize = 12;
+pub const EXCEPTION_LOAD_PAGE_FAULT: usize = 13;
+pub const EXCEPTION_STORE_PAGE_FAULT: usize = 15;
 
 // 中断类型
 pub const INTERRUPT_USER_SOFTWARE: usize = 0;
@@ -42,6 +45,13 @@ pub const INTERRUPT_MACHINE_EXTERNAL: usize = 11;
 #[inline]
 pub unsafe fn read_csr(csr: usize) -> usize {
     let value: usize;
+    // 在主机上运行单元测试时没有CSR，读出0
+    #[cfg(not(target_arch = "riscv64"))]
+    {
+        let _ = csr;
+        value = 0;
+    }
+    #[cfg(target_arch = "riscv64")]
     match csr {
         0x300 => core::arch::asm!("csrr {}, mstatus", out(reg) value),
         0x341 => core::arch::asm!("csrr {}, mepc", out(reg) value),
@@ -57,6 +67,9 @@ pub unsafe fn read_csr(csr: usize) -> usize {
 
 #[inline]
 pub unsafe fn write_csr(csr: usize, value: usize) {
+    #[cfg(not(target_arch = "riscv64"))]
+    let _ = (csr, value);
+    #[cfg(target_arch = "riscv64")]
     match csr {
         0x300 => core::arch::asm!("csrw mstatus, {}", in(reg) value),
         0x341 => core::arch::asm!("csrw mepc, {}", in(reg) value),
@@ -174,3 +187,33 @@ pub unsafe fn write_mie_global(value: bool) {
     };
     write_mstatus(new_mstatus);
 }
+
+// 地址转换：satp的模式字段 (Sv39)
+pub const SATP_MODE_SV39: usize = 8 << 60;
+
+/// 写入satp并刷新TLB (只影响U模式，M模式访问内存不经过页表)
+///
+/// # Safety
+/// value指向的页表必须有效，并且在U模式使用期间不被释放
+#[inline]
+pub unsafe fn write_satp(value: usize) {
+    #[cfg(not(target_arch = "riscv64"))]
+    let _ = value;
+    #[cfg(target_arch = "riscv64")]
+    core::arch::asm!("csrw satp, {}", "sfence.vma", in(reg) value);
+}
+
+/// 物理内存保护：没有PMP表项时U模式不能访问任何内存，
+/// 第0项按NAPOT覆盖整个地址空间并允许读写执行，U模式的访问范围由页表限制
+///
+/// # Safety
+/// 只在启动时调用，之后U模式能访问的内存完全由页表决定
+pub unsafe fn init_pmp() {
+    #[cfg(target_arch = "riscv64")]
+    core::arch::asm!(
+        "csrw pmpaddr0, {addr}",
+        "csrw pmpcfg0, {cfg}",
+        addr = in(reg) usize::MAX >> 10,
+        cfg = in(reg) 0x1F,
+    );
+}
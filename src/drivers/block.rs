@@ -0,0 +1,234 @@
+// 块设备抽象
+// 第15章：磁盘I/O - 文件系统通过BlockDevice访问磁盘、RAM盘或分区，不关心具体设备
+
+// 块大小 (字节)
+pub const BLOCK_SIZE: usize = 512;
+
+// 磁盘操作结果
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiskResult {
+    Success,
+    Error,
+    Timeout,
+    InvalidSector,
+}
+
+// 块设备：按块读写，缓冲区长度必须是块大小的整数倍
+pub trait BlockDevice {
+    // 从block开始读取 buffer.len() / block_size() 个块
+    fn read_blocks(&self, block: usize, buffer: &mut [u8]) -> DiskResult;
+    // 从block开始写入 buffer.len() / block_size() 个块
+    fn write_blocks(&self, block: usize, buffer: &[u8]) -> DiskResult;
+    fn block_count(&self) -> usize;
+    fn block_size(&self) -> usize;
+    // 把设备缓存中的数据写入持久存储
+    fn flush(&self) -> DiskResult;
+
+    // 在最底层设备上占用的范围：(设备地址, 起始块, 块数)
+    // 分区和缓存返回底层设备上的范围，用来判断两个块设备是否重叠
+    fn extent(&self) -> (*const (), usize, usize) {
+        (self as *const Self as *const (), 0, self.block_count())
+    }
+
+    // 设备正在处理请求；不可重入的驱动在请求期间返回true，中断处理据此推迟访问
+    fn is_busy(&self) -> bool {
+        false
+    }
+}
+
+// 两个块设备是否共用底层设备上的块
+pub fn overlaps(a: &dyn BlockDevice, b: &dyn BlockDevice) -> bool {
+    let (a_device, a_start, a_count) = a.extent();
+    let (b_device, b_start, b_count) = b.extent();
+    a_device == b_device && a_start < b_start + b_count && b_start < a_start + a_count
+}
+
+// 检查一次读写的范围，返回块数
+pub fn check_range(
+    device: &dyn BlockDevice,
+    block: usize,
+    len: usize,
+) -> Result<usize, DiskResult> {
+    let size = device.block_size();
+    if len == 0 || !len.is_multiple_of(size) {
+        return Err(DiskResult::Error);
+    }
+    let count = len / size;
+    match block.checked_add(count) {
+        Some(end) if end <= device.block_count() => Ok(count),
+        _ => Err(DiskResult::InvalidSector),
+    }
+}
+
+// 没有设备：所有读写都失败，用作未挂载时的占位
+pub struct NullDevice;
+
+impl BlockDevice for NullDevice {
+    fn read_blocks(&self, _block: usize, _buffer: &mut [u8]) -> DiskResult {
+        DiskResult::Error
+    }
+
+    fn write_blocks(&self, _block: usize, _buffer: &[u8]) -> DiskResult {
+        DiskResult::Error
+    }
+
+    fn block_count(&self) -> usize {
+        0
+    }
+
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn flush(&self) -> DiskResult {
+        DiskResult::Success
+    }
+}
+
+// RAM盘：把一段内存当作块设备
+pub struct RamDisk {
+    data: *mut u8,
+    blocks: usize,
+    read_only: bool,
+}
+
+impl RamDisk {
+    pub const fn empty() -> Self {
+        Self {
+            data: core::ptr::null_mut(),
+            blocks: 0,
+            read_only: false,
+        }
+    }
+
+    // 使用 [data, data + size) 这段内存，不足一块的尾部忽略
+    pub fn new(data: *mut u8, size: usize, read_only: bool) -> Self {
+        Self {
+            data,
+            blocks: size / BLOCK_SIZE,
+            read_only,
+        }
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    // 整个RAM盘的内容
+    pub fn as_bytes(&self) -> &[u8] {
+        if self.data.is_null() {
+            return &[];
+        }
+        unsafe { core::slice::from_raw_parts(self.data, self.blocks * BLOCK_SIZE) }
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn read_blocks(&self, block: usize, buffer: &mut [u8]) -> DiskResult {
+        if let Err(err) = check_range(self, block, buffer.len()) {
+            return err;
+        }
+        let offset = block * BLOCK_SIZE;
+        buffer.copy_from_slice(&self.as_bytes()[offset..offset + buffer.len()]);
+        DiskResult::Success
+    }
+
+    fn write_blocks(&self, block: usize, buffer: &[u8]) -> DiskResult {
+        if let Err(err) = check_range(self, block, buffer.len()) {
+            return err;
+        }
+        if self.read_only {
+            return DiskResult::Error;
+        }
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                buffer.as_ptr(),
+                self.data.add(block * BLOCK_SIZE),
+                buffer.len(),
+            );
+        }
+        DiskResult::Success
+    }
+
+    fn block_count(&self) -> usize {
+        self.blocks
+    }
+
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn flush(&self) -> DiskResult {
+        DiskResult::Success
+    }
+}
+
+// 分区：底层设备上从start开始的count个块
+pub struct Partition<'a> {
+    device: &'a dyn BlockDevice,
+    start: usize,
+    count: usize,
+}
+
+impl<'a> Partition<'a> {
+    // 范围超出底层设备时返回None
+    pub fn new(device: &'a dyn BlockDevice, start: usize, count: usize) -> Option<Self> {
+        match start.checked_add(count) {
+            Some(end) if count > 0 && end <= device.block_count() => Some(Self {
+                device,
+                start,
+                count,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+}
+
+impl BlockDevice for Partition<'_> {
+    fn read_blocks(&self, block: usize, buffer: &mut [u8]) -> DiskResult {
+        if let Err(err) = check_range(self, block, buffer.len()) {
+            return err;
+        }
+        self.device.read_blocks(self.start + block, buffer)
+    }
+
+    fn write_blocks(&self, block: usize, buffer: &[u8]) -> DiskResult {
+        if let Err(err) = check_range(self, block, buffer.len()) {
+            return err;
+        }
+        self.device.write_blocks(self.start + block, buffer)
+    }
+
+    fn block_count(&self) -> usize {
+        self.count
+    }
+
+    fn block_size(&self) -> usize {
+        self.device.block_size()
+    }
+
+    fn flush(&self) -> DiskResult {
+        self.device.flush()
+    }
+
+    fn extent(&self) -> (*const (), usize, usize) {
+        let (device, start, _) = self.device.extent();
+        (device, start + self.start, self.count)
+    }
+
+    fn is_busy(&self) -> bool {
+        self.device.is_busy()
+    }
+}
+
+// 按名字查找块设备：ram0，或者磁盘及其分区 (见 partition::lookup)
+pub fn lookup(name: &str) -> Option<&'static dyn BlockDevice> {
+    if name == crate::drivers::ramdisk::NAME {
+        return crate::drivers::ramdisk::is_ready().then(crate::drivers::ramdisk::block_device);
+    }
+    crate::drivers::partition::lookup(name)
+}
@@ -0,0 +1,66 @@
+// CLINT 定时器驱动
+// 第08章：异常 - mtime到达mtimecmp时产生机器模式定时器中断，用于时间片和CPU时间统计
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// CLINT 基地址 (QEMU virt 机器的默认值，启动时按设备树调整)
+const CLINT_BASE: usize = 0x02000000;
+
+// CLINT 寄存器偏移
+const CLINT_MTIMECMP: usize = 0x4000; // 每个hart一个64位比较寄存器
+const CLINT_MTIME: usize = 0xBFF8; // 全局计数器
+
+// 时钟节拍间隔：QEMU virt 的计数频率为10MHz，10ms一个节拍
+pub const TICK_INTERVAL: u64 = 100_000;
+
+pub struct Clint {
+    base: AtomicUsize,
+    hart: AtomicUsize,
+}
+
+impl Clint {
+    pub const fn new() -> Self {
+        Self {
+            base: AtomicUsize::new(CLINT_BASE),
+            hart: AtomicUsize::new(0),
+        }
+    }
+
+    // 设置寄存器基地址和接收定时器中断的hart (在init之前调用)
+    pub fn configure(&self, base: usize, hart_id: usize) {
+        self.base.store(base, Ordering::Relaxed);
+        self.hart.store(hart_id, Ordering::Relaxed);
+    }
+
+    pub fn mtime(&self) -> u64 {
+        unsafe { core::ptr::read_volatile((self.base() + CLINT_MTIME) as *const u64) }
+    }
+
+    // 在TICK_INTERVAL之后产生下一次定时器中断，同时清除当前挂起的中断
+    pub fn set_next_tick(&self) {
+        let next = self.mtime().wrapping_add(TICK_INTERVAL);
+        let hart = self.hart.load(Ordering::Relaxed);
+        unsafe {
+            core::ptr::write_volatile((self.base() + CLINT_MTIMECMP + hart * 8) as *mut u64, next);
+        }
+    }
+
+    fn base(&self) -> usize {
+        self.base.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for Clint {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 全局CLINT实例
+pub static CLINT: Clint = Clint::new();
+
+// 全局函数
+// 启动定时器，之后每个节拍由handle_interrupt重新设置
+pub fn init_timer() {
+    CLINT.set_next_tick();
+}
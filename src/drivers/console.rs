@@ -0,0 +1,199 @@
+// virtio-console 控制台驱动
+// 第15章：磁盘I/O - 只使用端口0：接收队列放着空缓冲区等设备写入，发送队列逐块提交
+
+use crate::drivers::driver::{self, Driver};
+use crate::drivers::uart;
+use crate::drivers::virtio::{
+    VirtQueue, VirtioDevice, VirtioMmio, DEVICE_ID_CONSOLE, QUEUE_SIZE, VIRTIO_F_VERSION_1,
+};
+use core::ptr::{addr_of, addr_of_mut};
+
+// 端口0的队列编号
+const RECEIVEQ: u32 = 0;
+const TRANSMITQ: u32 = 1;
+
+// 每个接收缓冲区和发送缓冲区的大小
+const RX_BUFFER_SIZE: usize = 64;
+const TX_BUFFER_SIZE: usize = 64;
+
+// 等待请求完成的轮询次数上限
+const REQUEST_TIMEOUT: usize = 10_000_000;
+
+// 设备通过DMA访问的内存
+struct ConsoleDma {
+    rx_queue: VirtQueue,
+    tx_queue: VirtQueue,
+    rx: [[u8; RX_BUFFER_SIZE]; QUEUE_SIZE],
+    tx: [u8; TX_BUFFER_SIZE],
+}
+
+static mut DMA: ConsoleDma = ConsoleDma {
+    rx_queue: VirtQueue::new(),
+    tx_queue: VirtQueue::new(),
+    rx: [[0; RX_BUFFER_SIZE]; QUEUE_SIZE],
+    tx: [0; TX_BUFFER_SIZE],
+};
+
+fn dma() -> &'static mut ConsoleDma {
+    unsafe { &mut *addr_of_mut!(DMA) }
+}
+
+pub struct ConsoleDriver {
+    base_address: usize,
+    initialized: bool,
+    // 接收队列中描述符编号对应的缓冲区
+    rx_slots: [usize; QUEUE_SIZE],
+}
+
+impl ConsoleDriver {
+    pub const fn new() -> Self {
+        Self {
+            base_address: 0,
+            initialized: false,
+            rx_slots: [0; QUEUE_SIZE],
+        }
+    }
+
+    // 把接收缓冲区放回接收队列
+    fn post_rx(&mut self, slot: usize) -> bool {
+        let dma = dma();
+        let chain = [(addr_of!(dma.rx[slot]) as u64, RX_BUFFER_SIZE as u32, true)];
+        match dma.rx_queue.submit(&chain) {
+            Some(head) => {
+                self.rx_slots[head as usize] = slot;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // 发送数据，返回实际发送的字节数
+    pub fn write(&self, data: &[u8]) -> usize {
+        if !self.initialized {
+            return 0;
+        }
+        let device = VirtioMmio::new(self.base_address);
+        let dma = dma();
+        let mut written = 0;
+        while written < data.len() {
+            let count = (data.len() - written).min(TX_BUFFER_SIZE);
+            dma.tx[..count].copy_from_slice(&data[written..written + count]);
+            let chain = [(addr_of!(dma.tx) as u64, count as u32, false)];
+            let head = match dma.tx_queue.submit(&chain) {
+                Some(head) => head,
+                None => break,
+            };
+            device.notify(TRANSMITQ);
+
+            let mut spins = 0;
+            loop {
+                match dma.tx_queue.pop_used() {
+                    Some((id, _)) if id == head => break,
+                    Some(_) => {}
+                    None if spins >= REQUEST_TIMEOUT => return written,
+                    None => {
+                        spins += 1;
+                        core::hint::spin_loop();
+                    }
+                }
+            }
+            device.ack_interrupt();
+            written += count;
+        }
+        written
+    }
+
+    // 读取设备已经写入的字符，不等待；返回读到的字节数
+    // 一个接收缓冲区没有被buffer装下的部分会被丢弃
+    pub fn read(&mut self, buffer: &mut [u8]) -> usize {
+        if !self.initialized {
+            return 0;
+        }
+        let device = VirtioMmio::new(self.base_address);
+        let mut count = 0;
+        while count < buffer.len() {
+            let (head, len) = match dma().rx_queue.pop_used() {
+                Some(used) => used,
+                None => break,
+            };
+            let slot = self.rx_slots[head as usize];
+            let len = (len as usize).min(RX_BUFFER_SIZE).min(buffer.len() - count);
+            buffer[count..count + len].copy_from_slice(&dma().rx[slot][..len]);
+            count += len;
+            self.post_rx(slot);
+        }
+        if count > 0 {
+            device.ack_interrupt();
+            device.notify(RECEIVEQ);
+        }
+        count
+    }
+}
+
+impl Default for ConsoleDriver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Driver for ConsoleDriver {
+    fn name(&self) -> &'static str {
+        "virtio-console"
+    }
+
+    fn device_id(&self) -> u32 {
+        DEVICE_ID_CONSOLE
+    }
+
+    fn probe(&mut self, virtio: &VirtioDevice) -> bool {
+        if self.initialized {
+            return false;
+        }
+        let device = virtio.mmio();
+        device.begin_init();
+        let dma = dma();
+        if device.negotiate(VIRTIO_F_VERSION_1).is_none()
+            || !device.setup_queue(RECEIVEQ, &mut dma.rx_queue)
+            || !device.setup_queue(TRANSMITQ, &mut dma.tx_queue)
+        {
+            uart::println("virtio-console initialization failed");
+            device.fail();
+            return false;
+        }
+        device.finish_init();
+
+        // 接收队列放满空缓冲区
+        for slot in 0..QUEUE_SIZE {
+            self.post_rx(slot);
+        }
+        device.notify(RECEIVEQ);
+
+        self.base_address = virtio.base;
+        self.initialized = true;
+        true
+    }
+}
+
+// 全局控制台驱动
+static mut CONSOLE: ConsoleDriver = ConsoleDriver::new();
+
+fn console() -> &'static mut ConsoleDriver {
+    unsafe { &mut *addr_of_mut!(CONSOLE) }
+}
+
+// 全局函数
+pub fn register() -> bool {
+    driver::register_driver(console())
+}
+
+pub fn is_ready() -> bool {
+    console().initialized
+}
+
+pub fn write(data: &[u8]) -> usize {
+    console().write(data)
+}
+
+pub fn read(buffer: &mut [u8]) -> usize {
+    console().read(buffer)
+}
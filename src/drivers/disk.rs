@@ -1,19 +1,62 @@
 // 第15章：磁盘I/O
-// 实现磁盘驱动
+// 实现磁盘驱动：QEMU virtio-blk 设备 (virtio-mmio)
 
-use crate::common::types::ToString;
+use crate::drivers::block::BlockDevice;
+use crate::drivers::driver::{self, Driver};
 use crate::drivers::uart;
+use crate::drivers::virtio::{
+    VirtQueue, VirtioDevice, VirtioMmio, DEVICE_ID_BLOCK, VIRTIO_F_VERSION_1,
+};
+use core::ptr::{addr_of, addr_of_mut};
+use core::sync::atomic::{AtomicBool, Ordering};
 
-// 磁盘块大小
-pub const BLOCK_SIZE: usize = 512;
+pub use crate::drivers::block::{DiskResult, BLOCK_SIZE};
 
-// 磁盘操作结果
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum DiskResult {
-    Success,
-    Error,
-    Timeout,
-    InvalidSector,
+// virtio-blk 请求类型和状态
+const VIRTIO_BLK_T_IN: u32 = 0;
+const VIRTIO_BLK_T_OUT: u32 = 1;
+const VIRTIO_BLK_T_FLUSH: u32 = 4;
+const VIRTIO_BLK_S_OK: u8 = 0;
+
+// virtio-blk 特性位：只读设备、支持刷新写缓存
+const VIRTIO_BLK_F_RO: u64 = 1 << 5;
+const VIRTIO_BLK_F_FLUSH: u64 = 1 << 9;
+
+// 配置空间：容量 (512字节扇区数，64位)
+const CONFIG_CAPACITY: usize = 0;
+
+// 等待请求完成的轮询次数上限
+const REQUEST_TIMEOUT: usize = 10_000_000;
+
+// 请求头
+#[repr(C)]
+struct BlockRequest {
+    kind: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+// 设备通过DMA访问的内存：队列、请求头、数据和状态字节
+struct BlockDma {
+    queue: VirtQueue,
+    request: BlockRequest,
+    buffer: [u8; BLOCK_SIZE],
+    status: u8,
+}
+
+static mut DMA: BlockDma = BlockDma {
+    queue: VirtQueue::new(),
+    request: BlockRequest {
+        kind: 0,
+        reserved: 0,
+        sector: 0,
+    },
+    buffer: [0; BLOCK_SIZE],
+    status: 0,
+};
+
+fn dma() -> &'static mut BlockDma {
+    unsafe { &mut *addr_of_mut!(DMA) }
 }
 
 // 磁盘驱动结构
@@ -21,96 +64,210 @@ pub struct DiskDriver {
     pub base_address: usize,
     pub sector_count: usize,
     pub initialized: bool,
+    pub read_only: bool,
+    pub flush_supported: bool,
+    // 请求超时后重置设备也失败，之后的请求直接返回错误
+    failed: AtomicBool,
+    // 正在使用DMA缓冲区处理请求，驱动不可重入
+    busy: AtomicBool,
 }
 
 impl DiskDriver {
     pub const fn new() -> Self {
         Self {
-            base_address: 0x10001000, // 假设磁盘控制器地址
+            base_address: 0,
             sector_count: 0,
             initialized: false,
+            read_only: false,
+            flush_supported: false,
+            failed: AtomicBool::new(false),
+            busy: AtomicBool::new(false),
+        }
+    }
+
+    // 重置设备，协商特性并设置请求队列，返回协商结果
+    fn setup(&self, device: &VirtioMmio) -> Option<u64> {
+        device.begin_init();
+        let features =
+            match device.negotiate(VIRTIO_BLK_F_RO | VIRTIO_BLK_F_FLUSH | VIRTIO_F_VERSION_1) {
+                Some(features) => features,
+                None => {
+                    uart::println("virtio-blk feature negotiation failed");
+                    device.fail();
+                    return None;
+                }
+            };
+        if !device.setup_queue(0, &mut dma().queue) {
+            uart::println("virtio-blk queue setup failed");
+            device.fail();
+            return None;
+        }
+        device.finish_init();
+        Some(features)
+    }
+
+    // 请求超时时描述符链还被设备占用：写状态0重置设备，丢弃队列重新设置
+    // 重置失败时把磁盘标记为失效
+    fn reset(&self) {
+        uart::println("Resetting virtio-blk device");
+        let device = VirtioMmio::new(self.base_address);
+        if self.setup(&device).is_none() {
+            uart::println("virtio-blk reset failed, disk disabled");
+            self.failed.store(true, Ordering::Relaxed);
         }
     }
 
-    // 初始化磁盘驱动
-    pub fn init(&mut self) -> DiskResult {
+    // 初始化绑定的virtio-blk设备 (只支持一个)
+    pub fn init(&mut self, virtio: &VirtioDevice) -> DiskResult {
+        if self.initialized {
+            return DiskResult::Error;
+        }
         uart::println("Initializing disk driver...");
+        self.base_address = virtio.base;
+
+        let device = virtio.mmio();
+        let features = match self.setup(&device) {
+            Some(features) => features,
+            None => return DiskResult::Error,
+        };
+        self.read_only = features & VIRTIO_BLK_F_RO != 0;
+        self.flush_supported = features & VIRTIO_BLK_F_FLUSH != 0;
 
-        // 模拟磁盘初始化
-        self.sector_count = 1024; // 假设有1024个扇区
+        let low = device.config_u32(CONFIG_CAPACITY) as u64;
+        let high = device.config_u32(CONFIG_CAPACITY + 4) as u64;
+        self.sector_count = ((high << 32) | low) as usize;
         self.initialized = true;
 
         uart::print("Disk initialized: ");
-        uart::print(&self.sector_count.to_string());
-        uart::println(" sectors");
+        uart::print_dec(self.sector_count);
+        uart::print(" sectors");
+        if self.read_only {
+            uart::print(" (read-only)");
+        }
+        uart::println("");
 
         DiskResult::Success
     }
 
     // 读取磁盘块
     pub fn read_block(&self, sector: usize, buffer: &mut [u8]) -> DiskResult {
-        if !self.initialized {
-            uart::println("Disk not initialized");
+        if buffer.len() < BLOCK_SIZE {
+            uart::println("Buffer too small");
             return DiskResult::Error;
         }
+        self.exclusive(|| {
+            let result = self.request(VIRTIO_BLK_T_IN, sector);
+            if result == DiskResult::Success {
+                buffer[..BLOCK_SIZE].copy_from_slice(&dma().buffer);
+            }
+            result
+        })
+    }
 
-        if sector >= self.sector_count {
-            uart::println("Invalid sector number");
-            return DiskResult::InvalidSector;
-        }
-
+    // 写入磁盘块
+    pub fn write_block(&self, sector: usize, buffer: &[u8]) -> DiskResult {
         if buffer.len() < BLOCK_SIZE {
             uart::println("Buffer too small");
             return DiskResult::Error;
         }
-
-        uart::print("Reading sector ");
-        uart::print(&sector.to_string());
-        uart::println("...");
-
-        // 模拟磁盘读取
-        unsafe {
-            // 从磁盘控制器读取数据
-            let disk_addr = self.base_address + (sector * BLOCK_SIZE);
-            let disk_data = core::slice::from_raw_parts(disk_addr as *const u8, BLOCK_SIZE);
-            buffer[..BLOCK_SIZE].copy_from_slice(disk_data);
+        if self.read_only {
+            return DiskResult::Error;
         }
+        self.exclusive(|| {
+            dma().buffer.copy_from_slice(&buffer[..BLOCK_SIZE]);
+            self.request(VIRTIO_BLK_T_OUT, sector)
+        })
+    }
 
-        uart::println("Sector read successfully");
-        DiskResult::Success
+    // 在DMA缓冲区的使用期间标记设备忙，期间中断处理不会再发请求
+    fn exclusive(&self, f: impl FnOnce() -> DiskResult) -> DiskResult {
+        self.busy.store(true, Ordering::SeqCst);
+        let result = f();
+        self.busy.store(false, Ordering::SeqCst);
+        result
     }
 
-    // 写入磁盘块
-    pub fn write_block(&self, sector: usize, buffer: &[u8]) -> DiskResult {
+    // 提交一个请求并轮询等待完成，数据在DMA缓冲区中
+    fn request(&self, kind: u32, sector: usize) -> DiskResult {
         if !self.initialized {
             uart::println("Disk not initialized");
             return DiskResult::Error;
         }
-
-        if sector >= self.sector_count {
+        if self.failed.load(Ordering::Relaxed) {
+            return DiskResult::Error;
+        }
+        if kind != VIRTIO_BLK_T_FLUSH && sector >= self.sector_count {
             uart::println("Invalid sector number");
             return DiskResult::InvalidSector;
         }
 
-        if buffer.len() < BLOCK_SIZE {
-            uart::println("Buffer too small");
-            return DiskResult::Error;
-        }
+        let dma = dma();
+        dma.request = BlockRequest {
+            kind,
+            reserved: 0,
+            sector: sector as u64,
+        };
+        dma.status = 0xFF;
+
+        // 请求头 -> 数据 -> 状态，读请求时设备写入数据缓冲区，刷新请求没有数据
+        let header = (
+            addr_of!(dma.request) as u64,
+            core::mem::size_of::<BlockRequest>() as u32,
+            false,
+        );
+        let data = (
+            addr_of!(dma.buffer) as u64,
+            BLOCK_SIZE as u32,
+            kind == VIRTIO_BLK_T_IN,
+        );
+        let status = (addr_of!(dma.status) as u64, 1, true);
+        let with_data = [header, data, status];
+        let without_data = [header, status];
+        let chain: &[(u64, u32, bool)] = if kind == VIRTIO_BLK_T_FLUSH {
+            &without_data
+        } else {
+            &with_data
+        };
+        let head = match dma.queue.submit(chain) {
+            Some(head) => head,
+            None => return DiskResult::Error,
+        };
 
-        uart::print("Writing sector ");
-        uart::print(&sector.to_string());
-        uart::println("...");
+        let device = VirtioMmio::new(self.base_address);
+        device.notify(0);
 
-        // 模拟磁盘写入
-        unsafe {
-            // 向磁盘控制器写入数据
-            let disk_addr = self.base_address + (sector * BLOCK_SIZE);
-            let disk_data = core::slice::from_raw_parts_mut(disk_addr as *mut u8, BLOCK_SIZE);
-            disk_data.copy_from_slice(&buffer[..BLOCK_SIZE]);
+        let mut spins = 0;
+        loop {
+            match dma.queue.pop_used() {
+                Some((id, _)) if id == head => break,
+                Some(_) => {}
+                None if spins >= REQUEST_TIMEOUT => {
+                    uart::println("Disk request timed out");
+                    self.reset();
+                    return DiskResult::Timeout;
+                }
+                None => {
+                    spins += 1;
+                    core::hint::spin_loop();
+                }
+            }
         }
+        device.ack_interrupt();
 
-        uart::println("Sector written successfully");
-        DiskResult::Success
+        let status = unsafe { core::ptr::read_volatile(addr_of!(dma.status)) };
+        if status == VIRTIO_BLK_S_OK {
+            DiskResult::Success
+        } else {
+            DiskResult::Error
+        }
+    }
+
+    // 刷新设备的写缓存，设备不支持时写入已经是持久的
+    pub fn flush(&self) -> DiskResult {
+        if !self.flush_supported {
+            return DiskResult::Success;
+        }
+        self.exclusive(|| self.request(VIRTIO_BLK_T_FLUSH, 0))
     }
 
     // 获取磁盘信息
@@ -119,12 +276,80 @@ impl DiskDriver {
     }
 }
 
+impl BlockDevice for DiskDriver {
+    fn read_blocks(&self, block: usize, buffer: &mut [u8]) -> DiskResult {
+        if let Err(err) = crate::drivers::block::check_range(self, block, buffer.len()) {
+            return err;
+        }
+        for (i, chunk) in buffer.chunks_exact_mut(BLOCK_SIZE).enumerate() {
+            let result = self.read_block(block + i, chunk);
+            if result != DiskResult::Success {
+                return result;
+            }
+        }
+        DiskResult::Success
+    }
+
+    fn write_blocks(&self, block: usize, buffer: &[u8]) -> DiskResult {
+        if let Err(err) = crate::drivers::block::check_range(self, block, buffer.len()) {
+            return err;
+        }
+        for (i, chunk) in buffer.chunks_exact(BLOCK_SIZE).enumerate() {
+            let result = self.write_block(block + i, chunk);
+            if result != DiskResult::Success {
+                return result;
+            }
+        }
+        DiskResult::Success
+    }
+
+    fn block_count(&self) -> usize {
+        self.sector_count
+    }
+
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn flush(&self) -> DiskResult {
+        DiskDriver::flush(self)
+    }
+
+    fn is_busy(&self) -> bool {
+        self.busy.load(Ordering::SeqCst)
+    }
+}
+
+impl Driver for DiskDriver {
+    fn name(&self) -> &'static str {
+        "virtio-blk"
+    }
+
+    fn device_id(&self) -> u32 {
+        DEVICE_ID_BLOCK
+    }
+
+    fn probe(&mut self, device: &VirtioDevice) -> bool {
+        self.init(device) == DiskResult::Success
+    }
+}
+
 // 全局磁盘驱动
 pub static mut DISK: DiskDriver = DiskDriver::new();
 
 // 全局函数
-pub fn init_disk() -> DiskResult {
-    unsafe { DISK.init() }
+pub fn register() -> bool {
+    driver::register_driver(unsafe { &mut *addr_of_mut!(DISK) })
+}
+
+pub fn is_ready() -> bool {
+    let disk = unsafe { &*addr_of!(DISK) };
+    disk.initialized && !disk.failed.load(Ordering::Relaxed)
+}
+
+// 作为块设备使用的磁盘
+pub fn block_device() -> &'static dyn BlockDevice {
+    unsafe { &*addr_of!(DISK) }
 }
 
 pub fn read_disk_block(sector: usize, buffer: &mut [u8]) -> DiskResult {
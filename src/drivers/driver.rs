@@ -0,0 +1,131 @@
+// 驱动框架
+// 第15章：磁盘I/O - 驱动注册表，virtio-mmio总线上的设备按类型绑定到驱动
+
+use crate::drivers::virtio::{self, VirtioDevice, VIRTIO_MMIO_COUNT};
+use crate::kernel::stdio;
+
+// 注册表最多容纳的驱动数量
+pub const MAX_DRIVERS: usize = 8;
+
+// 设备驱动
+pub trait Driver {
+    // 驱动名称
+    fn name(&self) -> &'static str;
+    // 驱动支持的virtio设备类型
+    fn device_id(&self) -> u32;
+    // 初始化并接管设备，成功返回true
+    fn probe(&mut self, device: &VirtioDevice) -> bool;
+}
+
+// 一个设备及绑定到它的驱动 (注册表下标)
+#[derive(Clone, Copy)]
+pub struct Binding {
+    pub device: VirtioDevice,
+    pub driver: Option<usize>,
+}
+
+pub struct DriverRegistry {
+    drivers: [Option<&'static mut dyn Driver>; MAX_DRIVERS],
+    bindings: [Option<Binding>; VIRTIO_MMIO_COUNT],
+}
+
+impl DriverRegistry {
+    pub const fn new() -> Self {
+        const EMPTY: Option<&'static mut dyn Driver> = None;
+        Self {
+            drivers: [EMPTY; MAX_DRIVERS],
+            bindings: [None; VIRTIO_MMIO_COUNT],
+        }
+    }
+
+    pub fn register(&mut self, driver: &'static mut dyn Driver) -> bool {
+        match self.drivers.iter().position(|slot| slot.is_none()) {
+            Some(index) => {
+                self.drivers[index] = Some(driver);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // 枚举总线，每个设备交给第一个类型匹配且probe成功的驱动
+    pub fn probe_devices(&mut self) {
+        let drivers = &mut self.drivers;
+        let bindings = &mut self.bindings;
+        *bindings = [None; VIRTIO_MMIO_COUNT];
+
+        virtio::probe_bus(|device| {
+            let driver = drivers.iter_mut().position(|driver| match driver {
+                Some(driver) => driver.device_id() == device.device_id && driver.probe(&device),
+                None => false,
+            });
+            bindings[device.slot] = Some(Binding { device, driver });
+        });
+    }
+
+    pub fn driver_name(&self, index: usize) -> Option<&'static str> {
+        match self.drivers.get(index) {
+            Some(Some(driver)) => Some(driver.name()),
+            _ => None,
+        }
+    }
+
+    pub fn for_each_binding(&self, mut f: impl FnMut(&Binding)) {
+        for binding in self.bindings.iter().flatten() {
+            f(binding);
+        }
+    }
+}
+
+impl Default for DriverRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 全局驱动注册表
+static mut REGISTRY: DriverRegistry = DriverRegistry::new();
+
+fn registry() -> &'static mut DriverRegistry {
+    unsafe { &mut *core::ptr::addr_of_mut!(REGISTRY) }
+}
+
+// 全局函数
+pub fn register_driver(driver: &'static mut dyn Driver) -> bool {
+    registry().register(driver)
+}
+
+pub fn probe_devices() {
+    registry().probe_devices();
+}
+
+// 遍历总线上的设备：(设备, 绑定的驱动名称)
+pub fn for_each_device(mut f: impl FnMut(&VirtioDevice, Option<&'static str>)) {
+    let registry = registry();
+    registry.for_each_binding(|binding| {
+        f(
+            &binding.device,
+            binding.driver.and_then(|index| registry.driver_name(index)),
+        )
+    });
+}
+
+// 打印设备列表
+pub fn list_devices() {
+    stdio::println("SLOT ADDRESS    VER TYPE     DRIVER");
+    for_each_device(|device, driver| {
+        stdio::print_dec_padded(device.slot, 4);
+        stdio::print(" 0x");
+        stdio::print_hex(device.base);
+        stdio::print(" ");
+        stdio::print_dec_padded(device.version as usize, 3);
+        stdio::print(" ");
+        let kind = virtio::device_type_name(device.device_id);
+        stdio::print(kind);
+        for _ in kind.len()..8 {
+            stdio::put_char(b' ');
+        }
+        stdio::print(" ");
+        stdio::println(driver.unwrap_or("-"));
+    });
+}
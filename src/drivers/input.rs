@@ -0,0 +1,195 @@
+// virtio-input 输入设备驱动 (键盘、鼠标、触摸板)
+// 第15章：磁盘I/O - 事件队列放着空的事件缓冲区，设备每次写入一个8字节的输入事件
+
+use crate::drivers::driver::{self, Driver};
+use crate::drivers::uart;
+use crate::drivers::virtio::{
+    VirtQueue, VirtioDevice, VirtioMmio, DEVICE_ID_INPUT, QUEUE_SIZE, VIRTIO_F_VERSION_1,
+};
+use core::ptr::{addr_of, addr_of_mut};
+
+// 事件队列编号 (状态队列用于设置LED，这里不使用)
+const EVENTQ: u32 = 0;
+
+// 配置空间布局：select、subsel、size，数据从偏移8开始
+const CONFIG_SELECT: usize = 0;
+const CONFIG_SUBSEL: usize = 1;
+const CONFIG_SIZE: usize = 2;
+const CONFIG_DATA: usize = 8;
+const VIRTIO_INPUT_CFG_ID_NAME: u8 = 0x01;
+
+// 设备名的最大长度
+const MAX_NAME_LEN: usize = 32;
+
+// 事件类型 (与Linux的input事件一致)
+pub const EV_SYN: u16 = 0x00;
+pub const EV_KEY: u16 = 0x01;
+pub const EV_REL: u16 = 0x02;
+pub const EV_ABS: u16 = 0x03;
+
+// 设备写入的输入事件
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct InputEvent {
+    pub event_type: u16,
+    pub code: u16,
+    pub value: u32,
+}
+
+impl InputEvent {
+    const fn empty() -> Self {
+        Self {
+            event_type: 0,
+            code: 0,
+            value: 0,
+        }
+    }
+}
+
+// 设备通过DMA访问的内存
+struct InputDma {
+    queue: VirtQueue,
+    events: [InputEvent; QUEUE_SIZE],
+}
+
+static mut DMA: InputDma = InputDma {
+    queue: VirtQueue::new(),
+    events: [InputEvent::empty(); QUEUE_SIZE],
+};
+
+fn dma() -> &'static mut InputDma {
+    unsafe { &mut *addr_of_mut!(DMA) }
+}
+
+pub struct InputDriver {
+    base_address: usize,
+    initialized: bool,
+    name: [u8; MAX_NAME_LEN],
+    name_len: usize,
+    // 事件队列中描述符编号对应的事件缓冲区
+    slots: [usize; QUEUE_SIZE],
+}
+
+impl InputDriver {
+    pub const fn new() -> Self {
+        Self {
+            base_address: 0,
+            initialized: false,
+            name: [0; MAX_NAME_LEN],
+            name_len: 0,
+            slots: [0; QUEUE_SIZE],
+        }
+    }
+
+    // 把事件缓冲区放回事件队列
+    fn post_event(&mut self, slot: usize) -> bool {
+        let dma = dma();
+        let size = core::mem::size_of::<InputEvent>() as u32;
+        let chain = [(addr_of!(dma.events[slot]) as u64, size, true)];
+        match dma.queue.submit(&chain) {
+            Some(head) => {
+                self.slots[head as usize] = slot;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // 从配置空间读取设备名
+    fn read_name(&mut self, device: &VirtioMmio) {
+        device.write_config_u8(CONFIG_SELECT, VIRTIO_INPUT_CFG_ID_NAME);
+        device.write_config_u8(CONFIG_SUBSEL, 0);
+        let size = (device.config_u8(CONFIG_SIZE) as usize).min(MAX_NAME_LEN);
+        for (i, byte) in self.name[..size].iter_mut().enumerate() {
+            *byte = device.config_u8(CONFIG_DATA + i);
+        }
+        self.name_len = size;
+    }
+
+    // 取出一个输入事件，不等待
+    pub fn poll_event(&mut self) -> Option<InputEvent> {
+        if !self.initialized {
+            return None;
+        }
+        let device = VirtioMmio::new(self.base_address);
+        let (head, _) = dma().queue.pop_used()?;
+        let slot = self.slots[head as usize];
+        let event = dma().events[slot];
+        self.post_event(slot);
+        device.ack_interrupt();
+        device.notify(EVENTQ);
+        Some(event)
+    }
+
+    pub fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len]).unwrap_or("")
+    }
+}
+
+impl Default for InputDriver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Driver for InputDriver {
+    fn name(&self) -> &'static str {
+        "virtio-input"
+    }
+
+    fn device_id(&self) -> u32 {
+        DEVICE_ID_INPUT
+    }
+
+    fn probe(&mut self, virtio: &VirtioDevice) -> bool {
+        if self.initialized {
+            return false;
+        }
+        let device = virtio.mmio();
+        device.begin_init();
+        if device.negotiate(VIRTIO_F_VERSION_1).is_none()
+            || !device.setup_queue(EVENTQ, &mut dma().queue)
+        {
+            uart::println("virtio-input initialization failed");
+            device.fail();
+            return false;
+        }
+        self.read_name(&device);
+        device.finish_init();
+
+        // 事件队列放满空缓冲区
+        for slot in 0..QUEUE_SIZE {
+            self.post_event(slot);
+        }
+        device.notify(EVENTQ);
+
+        self.base_address = virtio.base;
+        self.initialized = true;
+        true
+    }
+}
+
+// 全局输入设备驱动
+static mut INPUT: InputDriver = InputDriver::new();
+
+fn input() -> &'static mut InputDriver {
+    unsafe { &mut *addr_of_mut!(INPUT) }
+}
+
+// 全局函数
+pub fn register() -> bool {
+    driver::register_driver(input())
+}
+
+pub fn is_ready() -> bool {
+    input().initialized
+}
+
+// 设备名 (例如 "QEMU Virtio Keyboard")
+pub fn device_name() -> &'static str {
+    input().name()
+}
+
+pub fn poll_event() -> Option<InputEvent> {
+    input().poll_event()
+}
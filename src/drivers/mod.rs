@@ -1,5 +1,19 @@
 // 设备驱动
 // 第15章：磁盘I/O
 
+pub mod block;
+pub mod clint;
+pub mod console;
 pub mod disk;
+pub mod driver;
+pub mod input;
+pub mod net;
+pub mod partition;
+pub mod plic;
+pub mod power;
+pub mod ramdisk;
+pub mod rng;
+pub mod rtc;
+pub mod tty;
 pub mod uart;
+pub mod virtio;
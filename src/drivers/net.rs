@@ -0,0 +1,232 @@
+// virtio-net 网卡驱动
+// 第15章：磁盘I/O - 每个以太网帧前面有一个virtio_net_hdr，不协商校验和卸载，头部全部为0
+
+use crate::drivers::driver::{self, Driver};
+use crate::drivers::uart;
+use crate::drivers::virtio::{
+    VirtQueue, VirtioDevice, VirtioMmio, DEVICE_ID_NET, QUEUE_SIZE, VIRTIO_F_VERSION_1,
+};
+use core::ptr::{addr_of, addr_of_mut};
+
+// 队列编号
+const RECEIVEQ: u32 = 0;
+const TRANSMITQ: u32 = 1;
+
+// 设备在配置空间提供MAC地址
+const VIRTIO_NET_F_MAC: u64 = 1 << 5;
+
+// virtio_net_hdr的长度：协商VERSION_1时带num_buffers字段
+const NET_HDR_SIZE: usize = 12;
+const NET_HDR_SIZE_LEGACY: usize = 10;
+
+// 以太网帧的最大长度 (不含FCS)
+pub const MAX_FRAME_SIZE: usize = 1514;
+
+// 缓冲区大小：头部加一个完整的帧
+const BUFFER_SIZE: usize = NET_HDR_SIZE + MAX_FRAME_SIZE;
+
+// 等待请求完成的轮询次数上限
+const REQUEST_TIMEOUT: usize = 10_000_000;
+
+// 设备通过DMA访问的内存
+struct NetDma {
+    rx_queue: VirtQueue,
+    tx_queue: VirtQueue,
+    rx: [[u8; BUFFER_SIZE]; QUEUE_SIZE],
+    tx: [u8; BUFFER_SIZE],
+}
+
+static mut DMA: NetDma = NetDma {
+    rx_queue: VirtQueue::new(),
+    tx_queue: VirtQueue::new(),
+    rx: [[0; BUFFER_SIZE]; QUEUE_SIZE],
+    tx: [0; BUFFER_SIZE],
+};
+
+fn dma() -> &'static mut NetDma {
+    unsafe { &mut *addr_of_mut!(DMA) }
+}
+
+pub struct NetDriver {
+    base_address: usize,
+    initialized: bool,
+    mac: [u8; 6],
+    hdr_size: usize,
+    // 接收队列中描述符编号对应的缓冲区
+    rx_slots: [usize; QUEUE_SIZE],
+}
+
+impl NetDriver {
+    pub const fn new() -> Self {
+        Self {
+            base_address: 0,
+            initialized: false,
+            mac: [0; 6],
+            hdr_size: NET_HDR_SIZE,
+            rx_slots: [0; QUEUE_SIZE],
+        }
+    }
+
+    // 把接收缓冲区放回接收队列
+    fn post_rx(&mut self, slot: usize) -> bool {
+        let dma = dma();
+        let chain = [(addr_of!(dma.rx[slot]) as u64, BUFFER_SIZE as u32, true)];
+        match dma.rx_queue.submit(&chain) {
+            Some(head) => {
+                self.rx_slots[head as usize] = slot;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // 发送一个以太网帧，等待设备取走
+    pub fn send(&self, frame: &[u8]) -> bool {
+        if !self.initialized || frame.len() > MAX_FRAME_SIZE {
+            return false;
+        }
+        let device = VirtioMmio::new(self.base_address);
+        let dma = dma();
+        let len = self.hdr_size + frame.len();
+        dma.tx[..self.hdr_size].fill(0);
+        dma.tx[self.hdr_size..len].copy_from_slice(frame);
+        let chain = [(addr_of!(dma.tx) as u64, len as u32, false)];
+        let head = match dma.tx_queue.submit(&chain) {
+            Some(head) => head,
+            None => return false,
+        };
+        device.notify(TRANSMITQ);
+
+        let mut spins = 0;
+        loop {
+            match dma.tx_queue.pop_used() {
+                Some((id, _)) if id == head => break,
+                Some(_) => {}
+                None if spins >= REQUEST_TIMEOUT => return false,
+                None => {
+                    spins += 1;
+                    core::hint::spin_loop();
+                }
+            }
+        }
+        device.ack_interrupt();
+        true
+    }
+
+    // 取出一个收到的帧复制到buffer，不等待；返回帧长度，buffer装不下的部分被丢弃
+    pub fn receive(&mut self, buffer: &mut [u8]) -> Option<usize> {
+        if !self.initialized {
+            return None;
+        }
+        let device = VirtioMmio::new(self.base_address);
+        let (head, len) = dma().rx_queue.pop_used()?;
+        let slot = self.rx_slots[head as usize];
+        let len = (len as usize)
+            .min(BUFFER_SIZE)
+            .saturating_sub(self.hdr_size);
+        let count = len.min(buffer.len());
+        let start = self.hdr_size;
+        buffer[..count].copy_from_slice(&dma().rx[slot][start..start + count]);
+        self.post_rx(slot);
+        device.ack_interrupt();
+        device.notify(RECEIVEQ);
+        Some(count)
+    }
+}
+
+impl Default for NetDriver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Driver for NetDriver {
+    fn name(&self) -> &'static str {
+        "virtio-net"
+    }
+
+    fn device_id(&self) -> u32 {
+        DEVICE_ID_NET
+    }
+
+    fn probe(&mut self, virtio: &VirtioDevice) -> bool {
+        if self.initialized {
+            return false;
+        }
+        let device = virtio.mmio();
+        device.begin_init();
+        let dma = dma();
+        let features = match device.negotiate(VIRTIO_F_VERSION_1 | VIRTIO_NET_F_MAC) {
+            Some(features) => features,
+            None => {
+                uart::println("virtio-net initialization failed");
+                device.fail();
+                return false;
+            }
+        };
+        if !device.setup_queue(RECEIVEQ, &mut dma.rx_queue)
+            || !device.setup_queue(TRANSMITQ, &mut dma.tx_queue)
+        {
+            uart::println("virtio-net initialization failed");
+            device.fail();
+            return false;
+        }
+        device.finish_init();
+
+        self.hdr_size = if features & VIRTIO_F_VERSION_1 != 0 {
+            NET_HDR_SIZE
+        } else {
+            NET_HDR_SIZE_LEGACY
+        };
+        // 设备不提供MAC地址时保持全0
+        self.mac = [0; 6];
+        if features & VIRTIO_NET_F_MAC != 0 {
+            for (i, byte) in self.mac.iter_mut().enumerate() {
+                *byte = device.config_u8(i);
+            }
+        }
+
+        // 接收队列放满空缓冲区
+        for slot in 0..QUEUE_SIZE {
+            self.post_rx(slot);
+        }
+        device.notify(RECEIVEQ);
+
+        self.base_address = virtio.base;
+        self.initialized = true;
+        true
+    }
+}
+
+// 全局网卡驱动
+static mut NET: NetDriver = NetDriver::new();
+
+fn net() -> &'static mut NetDriver {
+    unsafe { &mut *addr_of_mut!(NET) }
+}
+
+// 全局函数
+pub fn register() -> bool {
+    driver::register_driver(net())
+}
+
+pub fn is_ready() -> bool {
+    net().initialized
+}
+
+// 网卡的MAC地址，网卡未就绪时返回None
+pub fn mac_address() -> Option<[u8; 6]> {
+    if is_ready() {
+        Some(net().mac)
+    } else {
+        None
+    }
+}
+
+pub fn send(frame: &[u8]) -> bool {
+    net().send(frame)
+}
+
+pub fn receive(buffer: &mut [u8]) -> Option<usize> {
+    net().receive(buffer)
+}
@@ -0,0 +1,806 @@
+// 分区表
+// 第15章：磁盘I/O - 解析MBR和GPT分区表，每个分区作为一个独立的块设备
+
+use crate::drivers::block::{BlockDevice, DiskResult, Partition, BLOCK_SIZE};
+use crate::drivers::uart;
+use crate::kernel::stdio;
+
+// 最多记录的分区数
+pub const MAX_PARTITIONS: usize = 16;
+
+// MBR：分区表位于引导扇区446字节处，4个16字节的表项
+const MBR_TABLE_OFFSET: usize = 446;
+const MBR_ENTRY_SIZE: usize = 16;
+const MBR_SIGNATURE: u16 = 0xAA55;
+
+// MBR分区类型
+const MBR_TYPE_EMPTY: u8 = 0x00;
+const MBR_TYPE_EXTENDED_CHS: u8 = 0x05;
+const MBR_TYPE_EXTENDED_LBA: u8 = 0x0F;
+const MBR_TYPE_EXTENDED_LINUX: u8 = 0x85;
+const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xEE;
+
+// 逻辑分区从5开始编号 (与Linux相同)
+const FIRST_LOGICAL: usize = 5;
+
+// EBR链最多读这么多个扇区，防止损坏的链表 (指向自己或成环) 无限循环
+const MAX_EBRS: usize = 64;
+
+// GPT头位于LBA 1，备份头位于磁盘最后一个块
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+const GPT_HEADER_MIN_SIZE: usize = 92;
+const GPT_ENTRY_MIN_SIZE: usize = 128;
+const GPT_MAX_ENTRIES: usize = 1024;
+
+// GPT分区名：36个UTF-16字符，这里只保留ASCII
+pub const GPT_NAME_LEN: usize = 36;
+
+// 分区表类型
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scheme {
+    None,
+    Mbr,
+    Gpt,
+}
+
+impl Scheme {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scheme::None => "none",
+            Scheme::Mbr => "MBR",
+            Scheme::Gpt => "GPT",
+        }
+    }
+}
+
+// GPT的GUID，按磁盘上的字节顺序保存 (前三段为小端)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Guid([u8; 16]);
+
+impl Guid {
+    pub const ZERO: Guid = Guid([0; 16]);
+
+    // 文本形式中每个字节对应的磁盘字节位置
+    const TEXT_ORDER: [usize; 16] = [3, 2, 1, 0, 5, 4, 7, 6, 8, 9, 10, 11, 12, 13, 14, 15];
+
+    pub fn from_bytes(bytes: &[u8]) -> Guid {
+        let mut guid = [0u8; 16];
+        guid.copy_from_slice(&bytes[..16]);
+        Guid(guid)
+    }
+
+    // 解析 xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx (不区分大小写)
+    pub fn parse(text: &str) -> Option<Guid> {
+        let text = text.as_bytes();
+        if text.len() != 36 {
+            return None;
+        }
+        let mut guid = [0u8; 16];
+        let mut digits = text
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !matches!(i, 8 | 13 | 18 | 23));
+        if [8, 13, 18, 23].iter().any(|&i| text[i] != b'-') {
+            return None;
+        }
+        for &position in Self::TEXT_ORDER.iter() {
+            let high = hex_value(*digits.next()?.1)?;
+            let low = hex_value(*digits.next()?.1)?;
+            guid[position] = (high << 4) | low;
+        }
+        Some(Guid(guid))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        *self == Guid::ZERO
+    }
+
+    pub fn print(&self) {
+        const HEX: &[u8; 16] = b"0123456789abcdef";
+        for (i, &position) in Self::TEXT_ORDER.iter().enumerate() {
+            if matches!(i, 4 | 6 | 8 | 10) {
+                stdio::put_char(b'-');
+            }
+            let byte = self.0[position];
+            stdio::put_char(HEX[(byte >> 4) as usize]);
+            stdio::put_char(HEX[(byte & 0xF) as usize]);
+        }
+    }
+}
+
+fn hex_value(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+// 分区类型：MBR的类型字节或GPT的类型GUID和分区GUID
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PartitionKind {
+    Mbr(u8),
+    Gpt { type_guid: Guid, guid: Guid },
+}
+
+// 分区表中的一项
+#[derive(Debug, Clone, Copy)]
+pub struct PartitionEntry {
+    pub number: usize, // 分区号，从1开始
+    pub start: usize,  // 起始LBA
+    pub count: usize,  // 块数
+    pub kind: PartitionKind,
+    name: [u8; GPT_NAME_LEN],
+    name_len: usize,
+}
+
+impl PartitionEntry {
+    const fn empty() -> Self {
+        Self {
+            number: 0,
+            start: 0,
+            count: 0,
+            kind: PartitionKind::Mbr(MBR_TYPE_EMPTY),
+            name: [0; GPT_NAME_LEN],
+            name_len: 0,
+        }
+    }
+
+    // GPT分区名 (MBR分区没有名字)
+    pub fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len]).unwrap_or("")
+    }
+
+    pub fn guid(&self) -> Option<Guid> {
+        match self.kind {
+            PartitionKind::Gpt { guid, .. } => Some(guid),
+            PartitionKind::Mbr(_) => None,
+        }
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    read_u32(data, offset) as u64 | ((read_u32(data, offset + 4) as u64) << 32)
+}
+
+// CRC32 (IEEE 802.3)，GPT头和分区表项数组的校验
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    !crc32_update(!0, data)
+}
+
+// 引导扇区是否是直接放在磁盘开头的FAT文件系统 (没有分区表)
+fn is_fat_boot_sector(sector: &[u8; BLOCK_SIZE]) -> bool {
+    let jump = sector[0] == 0xEB || sector[0] == 0xE9;
+    let bytes_per_sector = u16::from_le_bytes([sector[11], sector[12]]) as usize;
+    let fs_type = &sector[54..57] == b"FAT" || &sector[82..85] == b"FAT";
+    jump && bytes_per_sector == BLOCK_SIZE && fs_type
+}
+
+// 解析后的分区表
+pub struct PartitionTable {
+    scheme: Scheme,
+    entries: [PartitionEntry; MAX_PARTITIONS],
+    count: usize,
+}
+
+impl PartitionTable {
+    pub const fn new() -> Self {
+        const EMPTY: PartitionEntry = PartitionEntry::empty();
+        Self {
+            scheme: Scheme::None,
+            entries: [EMPTY; MAX_PARTITIONS],
+            count: 0,
+        }
+    }
+
+    pub fn scheme(&self) -> Scheme {
+        self.scheme
+    }
+
+    pub fn entries(&self) -> &[PartitionEntry] {
+        &self.entries[..self.count]
+    }
+
+    // 读取设备上的分区表，没有可识别的分区表时返回Scheme::None
+    pub fn scan(&mut self, device: &dyn BlockDevice) -> Result<Scheme, DiskResult> {
+        self.scheme = Scheme::None;
+        self.count = 0;
+        if device.block_size() != BLOCK_SIZE || device.block_count() < 2 {
+            return Ok(Scheme::None);
+        }
+
+        let mut sector = [0u8; BLOCK_SIZE];
+        match device.read_blocks(0, &mut sector) {
+            DiskResult::Success => {}
+            err => return Err(err),
+        }
+        if u16::from_le_bytes([sector[510], sector[511]]) != MBR_SIGNATURE
+            || is_fat_boot_sector(&sector)
+        {
+            return Ok(Scheme::None);
+        }
+
+        // 引导标志只能是0或0x80，否则这不是分区表
+        let table = &sector[MBR_TABLE_OFFSET..MBR_TABLE_OFFSET + 4 * MBR_ENTRY_SIZE];
+        if table
+            .chunks_exact(MBR_ENTRY_SIZE)
+            .any(|entry| entry[0] != 0 && entry[0] != 0x80)
+        {
+            return Ok(Scheme::None);
+        }
+
+        // 保护性MBR：真正的分区表是GPT
+        if table
+            .chunks_exact(MBR_ENTRY_SIZE)
+            .any(|entry| entry[4] == MBR_TYPE_GPT_PROTECTIVE)
+        {
+            self.scan_gpt(device)?;
+            self.scheme = Scheme::Gpt;
+        } else {
+            self.scan_mbr(device, &sector)?;
+            self.scheme = Scheme::Mbr;
+        }
+        Ok(self.scheme)
+    }
+
+    fn push(&mut self, entry: PartitionEntry, device: &dyn BlockDevice) {
+        // 超出磁盘范围的分区忽略
+        let end = match entry.start.checked_add(entry.count) {
+            Some(end) => end,
+            None => return,
+        };
+        if entry.count == 0 || end > device.block_count() || self.count == MAX_PARTITIONS {
+            return;
+        }
+        self.entries[self.count] = entry;
+        self.count += 1;
+    }
+
+    // MBR：4个主分区，扩展分区中的逻辑分区通过EBR链表串起来
+    fn scan_mbr(
+        &mut self,
+        device: &dyn BlockDevice,
+        mbr: &[u8; BLOCK_SIZE],
+    ) -> Result<(), DiskResult> {
+        let mut extended = None;
+        for slot in 0..4 {
+            let entry = &mbr[MBR_TABLE_OFFSET + slot * MBR_ENTRY_SIZE..];
+            let kind = entry[4];
+            let start = read_u32(entry, 8) as usize;
+            let count = read_u32(entry, 12) as usize;
+            match kind {
+                MBR_TYPE_EMPTY => {}
+                MBR_TYPE_EXTENDED_CHS | MBR_TYPE_EXTENDED_LBA | MBR_TYPE_EXTENDED_LINUX => {
+                    extended = extended.or(Some(start));
+                }
+                _ => self.push(
+                    PartitionEntry {
+                        number: slot + 1,
+                        start,
+                        count,
+                        kind: PartitionKind::Mbr(kind),
+                        ..PartitionEntry::empty()
+                    },
+                    device,
+                ),
+            }
+        }
+
+        let extended_start = match extended {
+            Some(start) => start,
+            None => return Ok(()),
+        };
+
+        // EBR：第一项是逻辑分区 (相对当前EBR)，第二项指向下一个EBR (相对扩展分区开头)
+        let mut ebr = extended_start;
+        let mut number = FIRST_LOGICAL;
+        let mut sector = [0u8; BLOCK_SIZE];
+        for _ in 0..MAX_EBRS {
+            if number >= FIRST_LOGICAL + MAX_PARTITIONS || ebr >= device.block_count() {
+                break;
+            }
+            match device.read_blocks(ebr, &mut sector) {
+                DiskResult::Success => {}
+                err => return Err(err),
+            }
+            if u16::from_le_bytes([sector[510], sector[511]]) != MBR_SIGNATURE {
+                break;
+            }
+            let logical = &sector[MBR_TABLE_OFFSET..];
+            if logical[4] != MBR_TYPE_EMPTY {
+                self.push(
+                    PartitionEntry {
+                        number,
+                        start: ebr + read_u32(logical, 8) as usize,
+                        count: read_u32(logical, 12) as usize,
+                        kind: PartitionKind::Mbr(logical[4]),
+                        ..PartitionEntry::empty()
+                    },
+                    device,
+                );
+                number += 1;
+            }
+            let next = &sector[MBR_TABLE_OFFSET + MBR_ENTRY_SIZE..];
+            let offset = read_u32(next, 8) as usize;
+            if next[4] == MBR_TYPE_EMPTY || offset == 0 {
+                break;
+            }
+            ebr = extended_start + offset;
+        }
+        Ok(())
+    }
+
+    // GPT：先用LBA 1的主头，校验失败时使用磁盘末尾的备份头
+    fn scan_gpt(&mut self, device: &dyn BlockDevice) -> Result<(), DiskResult> {
+        let mut header = [0u8; BLOCK_SIZE];
+        for lba in [1, device.block_count() - 1] {
+            match device.read_blocks(lba, &mut header) {
+                DiskResult::Success => {}
+                err => return Err(err),
+            }
+            if !gpt_header_valid(&header, lba) {
+                uart::println("Warning: invalid GPT header");
+                continue;
+            }
+            if self.load_gpt_entries(device, &header)? {
+                return Ok(());
+            }
+            uart::println("Warning: GPT partition entries checksum mismatch");
+            self.count = 0;
+        }
+        Ok(())
+    }
+
+    // 读取分区表项数组，校验和不匹配时返回false
+    fn load_gpt_entries(
+        &mut self,
+        device: &dyn BlockDevice,
+        header: &[u8],
+    ) -> Result<bool, DiskResult> {
+        let entries_lba = read_u64(header, 72) as usize;
+        let entry_count = read_u32(header, 80) as usize;
+        let entry_size = read_u32(header, 84) as usize;
+        let expected_crc = read_u32(header, 88);
+
+        let mut crc = !0;
+        let mut sector = [0u8; BLOCK_SIZE];
+        let entries_per_block = BLOCK_SIZE / entry_size;
+        for index in 0..entry_count {
+            let offset = (index % entries_per_block) * entry_size;
+            if offset == 0 {
+                let lba = entries_lba + index / entries_per_block;
+                match device.read_blocks(lba, &mut sector) {
+                    DiskResult::Success => {}
+                    err => return Err(err),
+                }
+            }
+            let raw = &sector[offset..offset + entry_size];
+            crc = crc32_update(crc, raw);
+
+            let type_guid = Guid::from_bytes(&raw[0..16]);
+            if type_guid.is_zero() {
+                continue;
+            }
+            let first = read_u64(raw, 32) as usize;
+            let last = read_u64(raw, 40) as usize;
+            if last < first {
+                continue;
+            }
+            let mut entry = PartitionEntry {
+                number: index + 1,
+                start: first,
+                count: last - first + 1,
+                kind: PartitionKind::Gpt {
+                    type_guid,
+                    guid: Guid::from_bytes(&raw[16..32]),
+                },
+                ..PartitionEntry::empty()
+            };
+            // 分区名是UTF-16LE，非ASCII字符用'?'代替
+            for unit in raw[56..56 + GPT_NAME_LEN * 2].chunks_exact(2) {
+                let c = u16::from_le_bytes([unit[0], unit[1]]);
+                if c == 0 {
+                    break;
+                }
+                entry.name[entry.name_len] = if c < 0x80 { c as u8 } else { b'?' };
+                entry.name_len += 1;
+            }
+            self.push(entry, device);
+        }
+        Ok(!crc == expected_crc)
+    }
+
+    // 按分区号查找
+    pub fn find(&self, number: usize) -> Option<usize> {
+        self.entries()
+            .iter()
+            .position(|entry| entry.number == number)
+    }
+
+    // 按GPT分区GUID查找
+    pub fn find_guid(&self, guid: &Guid) -> Option<usize> {
+        self.entries()
+            .iter()
+            .position(|entry| entry.guid().as_ref() == Some(guid))
+    }
+}
+
+impl Default for PartitionTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 检查GPT头：签名、大小、位置、表项参数和头部CRC
+fn gpt_header_valid(header: &[u8; BLOCK_SIZE], lba: usize) -> bool {
+    if &header[0..8] != GPT_SIGNATURE {
+        return false;
+    }
+    let header_size = read_u32(header, 12) as usize;
+    if !(GPT_HEADER_MIN_SIZE..=BLOCK_SIZE).contains(&header_size)
+        || read_u64(header, 24) as usize != lba
+    {
+        return false;
+    }
+    let entry_count = read_u32(header, 80) as usize;
+    let entry_size = read_u32(header, 84) as usize;
+    if entry_count > GPT_MAX_ENTRIES
+        || entry_size < GPT_ENTRY_MIN_SIZE
+        || !BLOCK_SIZE.is_multiple_of(entry_size)
+    {
+        return false;
+    }
+
+    // 计算CRC时校验和字段本身按0处理
+    let mut copy = [0u8; BLOCK_SIZE];
+    copy[..header_size].copy_from_slice(&header[..header_size]);
+    copy[16..20].fill(0);
+    crc32(&copy[..header_size]) == read_u32(header, 16)
+}
+
+// 磁盘的设备名，分区名是在后面加上 p<分区号>
+pub const DISK_NAME: &str = "virtio0";
+
+// 全局分区表 (根磁盘) 和对应的块设备
+static mut DISK: Option<&'static dyn BlockDevice> = None;
+
+static mut TABLE: PartitionTable = PartitionTable::new();
+
+static mut DEVICES: [Option<Partition<'static>>; MAX_PARTITIONS] = {
+    const NONE: Option<Partition<'static>> = None;
+    [NONE; MAX_PARTITIONS]
+};
+
+fn table() -> &'static mut PartitionTable {
+    unsafe { &mut *core::ptr::addr_of_mut!(TABLE) }
+}
+
+fn devices() -> &'static mut [Option<Partition<'static>>; MAX_PARTITIONS] {
+    unsafe { &mut *core::ptr::addr_of_mut!(DEVICES) }
+}
+
+// 全局函数
+// 扫描磁盘上的分区表，返回找到的分区数
+pub fn scan(device: &'static dyn BlockDevice) -> usize {
+    let table = table();
+    let devices = devices();
+    devices.iter_mut().for_each(|slot| *slot = None);
+    unsafe { *core::ptr::addr_of_mut!(DISK) = Some(device) };
+
+    match table.scan(device) {
+        Ok(Scheme::None) => return 0,
+        Ok(_) => {}
+        Err(_) => {
+            uart::println("Failed to read partition table");
+            return 0;
+        }
+    }
+    for (slot, entry) in devices.iter_mut().zip(table.entries()) {
+        *slot = Partition::new(device, entry.start, entry.count);
+    }
+
+    uart::print(table.scheme().as_str());
+    uart::print(" partition table: ");
+    uart::print_dec(table.entries().len());
+    uart::println(" partition(s)");
+    table.entries().len()
+}
+
+pub fn scheme() -> Scheme {
+    table().scheme()
+}
+
+fn device_at(index: usize) -> Option<&'static dyn BlockDevice> {
+    devices()[index]
+        .as_ref()
+        .map(|partition| partition as &'static dyn BlockDevice)
+}
+
+// 分区号对应的块设备 (virtio0p1 中的1)
+pub fn device(number: usize) -> Option<&'static dyn BlockDevice> {
+    device_at(table().find(number)?)
+}
+
+// GPT分区GUID对应的块设备
+pub fn device_by_guid(guid: &Guid) -> Option<&'static dyn BlockDevice> {
+    device_at(table().find_guid(guid)?)
+}
+
+// 按名字查找块设备：virtio0 (整个磁盘)、virtio0p<N> 或 PARTUUID=<GUID>
+pub fn lookup(name: &str) -> Option<&'static dyn BlockDevice> {
+    if let Some(guid) = name.strip_prefix("PARTUUID=") {
+        return device_by_guid(&Guid::parse(guid)?);
+    }
+    let rest = name.strip_prefix(DISK_NAME)?;
+    if rest.is_empty() {
+        return unsafe { *core::ptr::addr_of!(DISK) };
+    }
+    device(rest.strip_prefix('p')?.parse().ok()?)
+}
+
+// 分区的设备名 virtio0p<N>，写在buffer中
+pub fn partition_name(number: usize, buffer: &mut [u8; 16]) -> &str {
+    let mut len = DISK_NAME.len();
+    buffer[..len].copy_from_slice(DISK_NAME.as_bytes());
+    buffer[len] = b'p';
+    len += 1;
+    let mut digits = [0u8; 8];
+    let mut count = 0;
+    let mut value = number;
+    loop {
+        digits[count] = b'0' + (value % 10) as u8;
+        count += 1;
+        value /= 10;
+        if value == 0 || count == digits.len() {
+            break;
+        }
+    }
+    for &digit in digits[..count].iter().rev() {
+        buffer[len] = digit;
+        len += 1;
+    }
+    core::str::from_utf8(&buffer[..len]).unwrap_or(DISK_NAME)
+}
+
+// 第一个分区的分区号
+pub fn first_partition() -> Option<usize> {
+    table().entries().first().map(|entry| entry.number)
+}
+
+pub fn for_each_partition(f: impl FnMut(&PartitionEntry)) {
+    table().entries().iter().for_each(f);
+}
+
+// 打印分区表
+pub fn list_partitions() {
+    if scheme() == Scheme::None {
+        stdio::println("No partition table");
+        return;
+    }
+    stdio::print("Partition table: ");
+    stdio::println(scheme().as_str());
+    stdio::println("  #      START    SECTORS TYPE/PARTUUID");
+    for_each_partition(|entry| {
+        stdio::print_dec_padded(entry.number, 3);
+        stdio::print(" ");
+        stdio::print_dec_padded(entry.start, 10);
+        stdio::print(" ");
+        stdio::print_dec_padded(entry.count, 10);
+        stdio::print(" ");
+        match entry.kind {
+            PartitionKind::Mbr(kind) => {
+                stdio::print("0x");
+                stdio::print_hex(kind as usize);
+            }
+            PartitionKind::Gpt { guid, .. } => {
+                guid.print();
+                if !entry.name().is_empty() {
+                    stdio::print(" ");
+                    stdio::print(entry.name());
+                }
+            }
+        }
+        stdio::println("");
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drivers::block::RamDisk;
+    use std::vec;
+
+    const DISK_BLOCKS: usize = 128;
+
+    fn disk() -> &'static mut [u8] {
+        vec![0u8; DISK_BLOCKS * BLOCK_SIZE].leak()
+    }
+
+    fn block(data: &mut [u8], lba: usize) -> &mut [u8] {
+        &mut data[lba * BLOCK_SIZE..(lba + 1) * BLOCK_SIZE]
+    }
+
+    // 在lba处的MBR或EBR中写入第slot项并加上签名
+    fn set_mbr_entry(data: &mut [u8], lba: usize, slot: usize, kind: u8, start: u32, count: u32) {
+        let sector = block(data, lba);
+        let entry = &mut sector[MBR_TABLE_OFFSET + slot * MBR_ENTRY_SIZE..];
+        entry[4] = kind;
+        entry[8..12].copy_from_slice(&start.to_le_bytes());
+        entry[12..16].copy_from_slice(&count.to_le_bytes());
+        sector[510..512].copy_from_slice(&MBR_SIGNATURE.to_le_bytes());
+    }
+
+    fn scan(data: &mut [u8]) -> (Result<Scheme, DiskResult>, PartitionTable) {
+        let device = RamDisk::new(data.as_mut_ptr(), data.len(), true);
+        let mut table = PartitionTable::new();
+        let result = table.scan(&device);
+        (result, table)
+    }
+
+    #[test]
+    fn no_table() {
+        let data = disk();
+        assert_eq!(scan(data).0, Ok(Scheme::None));
+
+        // 带签名但看起来像FAT引导扇区
+        set_mbr_entry(data, 0, 0, 0x0C, 1, 8);
+        let sector = block(data, 0);
+        sector[0] = 0xEB;
+        sector[11..13].copy_from_slice(&(BLOCK_SIZE as u16).to_le_bytes());
+        sector[82..85].copy_from_slice(b"FAT");
+        assert_eq!(scan(data).0, Ok(Scheme::None));
+    }
+
+    #[test]
+    fn mbr_primary_and_logical() {
+        let data = disk();
+        set_mbr_entry(data, 0, 0, 0x0C, 1, 15);
+        set_mbr_entry(data, 0, 1, MBR_TYPE_EXTENDED_LBA, 16, 64);
+        // 超出磁盘范围的分区被忽略
+        set_mbr_entry(data, 0, 2, 0x83, 100, 100);
+        // 第一个EBR：逻辑分区5，下一个EBR在扩展分区内偏移32处
+        set_mbr_entry(data, 16, 0, 0x83, 1, 15);
+        set_mbr_entry(data, 16, 1, MBR_TYPE_EXTENDED_LBA, 32, 32);
+        set_mbr_entry(data, 48, 0, 0x83, 2, 10);
+
+        let (result, table) = scan(data);
+        assert_eq!(result, Ok(Scheme::Mbr));
+        let entries: std::vec::Vec<_> = table
+            .entries()
+            .iter()
+            .map(|entry| (entry.number, entry.start, entry.count))
+            .collect();
+        assert_eq!(entries, [(1, 1, 15), (5, 17, 15), (6, 50, 10)]);
+        assert_eq!(table.entries()[0].kind, PartitionKind::Mbr(0x0C));
+        assert_eq!(table.find(6), Some(2));
+        assert_eq!(table.find(2), None);
+    }
+
+    #[test]
+    fn ebr_loop_terminates() {
+        let data = disk();
+        set_mbr_entry(data, 0, 0, MBR_TYPE_EXTENDED_LBA, 16, 64);
+        // 没有逻辑分区的EBR链，第二个EBR指向自己
+        set_mbr_entry(data, 16, 1, MBR_TYPE_EXTENDED_LBA, 16, 8);
+        set_mbr_entry(data, 32, 1, MBR_TYPE_EXTENDED_LBA, 16, 8);
+
+        let (result, table) = scan(data);
+        assert_eq!(result, Ok(Scheme::Mbr));
+        assert!(table.entries().is_empty());
+    }
+
+    const LINUX_FS: &str = "0fc63daf-8483-4772-8e79-3d69d8477de4";
+    const PART_GUID: &str = "c12a7328-f81f-11d2-ba4b-00a0c93ec93b";
+
+    // 保护性MBR、LBA 1的GPT头和LBA 2的分区表项 (4项，每项128字节)
+    fn write_gpt(data: &mut [u8]) {
+        set_mbr_entry(
+            data,
+            0,
+            0,
+            MBR_TYPE_GPT_PROTECTIVE,
+            1,
+            DISK_BLOCKS as u32 - 1,
+        );
+
+        let entries = block(data, 2);
+        entries[0..16].copy_from_slice(&Guid::parse(LINUX_FS).unwrap().0);
+        entries[16..32].copy_from_slice(&Guid::parse(PART_GUID).unwrap().0);
+        entries[32..40].copy_from_slice(&34u64.to_le_bytes());
+        entries[40..48].copy_from_slice(&99u64.to_le_bytes());
+        for (i, c) in "root\u{e9}".encode_utf16().enumerate() {
+            entries[56 + i * 2..58 + i * 2].copy_from_slice(&c.to_le_bytes());
+        }
+        let entries_crc = crc32(&entries[..4 * GPT_ENTRY_MIN_SIZE]);
+
+        let header = block(data, 1);
+        header[0..8].copy_from_slice(GPT_SIGNATURE);
+        header[12..16].copy_from_slice(&(GPT_HEADER_MIN_SIZE as u32).to_le_bytes());
+        header[24..32].copy_from_slice(&1u64.to_le_bytes());
+        header[72..80].copy_from_slice(&2u64.to_le_bytes());
+        header[80..84].copy_from_slice(&4u32.to_le_bytes());
+        header[84..88].copy_from_slice(&(GPT_ENTRY_MIN_SIZE as u32).to_le_bytes());
+        header[88..92].copy_from_slice(&entries_crc.to_le_bytes());
+        let header_crc = crc32(&header[..GPT_HEADER_MIN_SIZE]);
+        header[16..20].copy_from_slice(&header_crc.to_le_bytes());
+    }
+
+    #[test]
+    fn gpt_entries() {
+        let data = disk();
+        write_gpt(data);
+        let (result, table) = scan(data);
+        assert_eq!(result, Ok(Scheme::Gpt));
+        assert_eq!(table.entries().len(), 1);
+        let entry = &table.entries()[0];
+        assert_eq!((entry.number, entry.start, entry.count), (1, 34, 66));
+        assert_eq!(entry.name(), "root?");
+        assert_eq!(
+            entry.kind,
+            PartitionKind::Gpt {
+                type_guid: Guid::parse(LINUX_FS).unwrap(),
+                guid: Guid::parse(PART_GUID).unwrap(),
+            }
+        );
+        let guid = Guid::parse(&PART_GUID.to_uppercase()).unwrap();
+        assert_eq!(table.find_guid(&guid), Some(0));
+    }
+
+    #[test]
+    fn gpt_header_checks() {
+        let data = disk();
+        write_gpt(data);
+        let mut header = [0u8; BLOCK_SIZE];
+        header.copy_from_slice(block(data, 1));
+        assert!(gpt_header_valid(&header, 1));
+        // 位置不对 (备份头应该在磁盘末尾)
+        assert!(!gpt_header_valid(&header, DISK_BLOCKS - 1));
+        // 头部CRC不匹配
+        header[72] ^= 1;
+        assert!(!gpt_header_valid(&header, 1));
+    }
+
+    #[test]
+    fn guid_text_order() {
+        let guid = Guid::parse(PART_GUID).unwrap();
+        assert_eq!(
+            guid.0[..8],
+            [0x28, 0x73, 0x2a, 0xc1, 0x1f, 0xf8, 0xd2, 0x11]
+        );
+        assert_eq!(
+            guid.0[8..],
+            [0xba, 0x4b, 0x00, 0xa0, 0xc9, 0x3e, 0xc9, 0x3b]
+        );
+        assert!(Guid::parse("c12a7328f81f11d2ba4b00a0c93ec93b").is_none());
+        assert!(Guid::parse("g12a7328-f81f-11d2-ba4b-00a0c93ec93b").is_none());
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+}
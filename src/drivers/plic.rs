@@ -0,0 +1,116 @@
+// PLIC 中断控制器驱动
+// 第08章：异常 - 外部中断分发
+
+use crate::drivers::uart;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// PLIC 基地址 (QEMU virt 机器的默认值，启动时按设备树调整)
+const PLIC_BASE: usize = 0x0c000000;
+
+// PLIC 寄存器偏移
+const PLIC_PRIORITY: usize = 0x000000; // 每个中断源的优先级
+const PLIC_ENABLE: usize = 0x002000; // 每个上下文的中断使能位
+const PLIC_ENABLE_STRIDE: usize = 0x80;
+const PLIC_THRESHOLD: usize = 0x200000; // 每个上下文的优先级阈值
+const PLIC_CLAIM: usize = 0x200004; // 每个上下文的认领/完成寄存器
+const PLIC_CONTEXT_STRIDE: usize = 0x1000;
+
+// hart 0 的机器模式上下文，QEMU virt 上每个hart依次有M模式和S模式两个上下文
+const HART0_M_CONTEXT: usize = 0;
+const CONTEXTS_PER_HART: usize = 2;
+
+pub struct Plic {
+    base: AtomicUsize,
+    context: AtomicUsize,
+}
+
+impl Plic {
+    pub const fn new() -> Self {
+        Self {
+            base: AtomicUsize::new(PLIC_BASE),
+            context: AtomicUsize::new(HART0_M_CONTEXT),
+        }
+    }
+
+    // 设置寄存器基地址和处理中断的hart (在init之前调用)
+    pub fn configure(&self, base: usize, hart_id: usize) {
+        self.base.store(base, Ordering::Relaxed);
+        self.context
+            .store(hart_id * CONTEXTS_PER_HART, Ordering::Relaxed);
+    }
+
+    // 初始化PLIC：接受所有优先级大于0的中断
+    pub fn init(&self) {
+        unsafe {
+            self.write_reg(PLIC_THRESHOLD + self.context() * PLIC_CONTEXT_STRIDE, 0);
+        }
+    }
+
+    // 使能中断源
+    pub fn enable(&self, irq: u32, priority: u32) {
+        let enable_reg =
+            PLIC_ENABLE + self.context() * PLIC_ENABLE_STRIDE + (irq as usize / 32) * 4;
+        unsafe {
+            self.write_reg(PLIC_PRIORITY + irq as usize * 4, priority);
+            let bits = self.read_reg(enable_reg);
+            self.write_reg(enable_reg, bits | (1 << (irq % 32)));
+        }
+    }
+
+    // 认领一个待处理的中断
+    pub fn claim(&self) -> Option<u32> {
+        let irq = unsafe { self.read_reg(PLIC_CLAIM + self.context() * PLIC_CONTEXT_STRIDE) };
+        if irq == 0 {
+            None
+        } else {
+            Some(irq)
+        }
+    }
+
+    // 通知PLIC中断处理完成
+    pub fn complete(&self, irq: u32) {
+        unsafe {
+            self.write_reg(PLIC_CLAIM + self.context() * PLIC_CONTEXT_STRIDE, irq);
+        }
+    }
+
+    fn context(&self) -> usize {
+        self.context.load(Ordering::Relaxed)
+    }
+
+    unsafe fn write_reg(&self, offset: usize, value: u32) {
+        core::ptr::write_volatile(
+            (self.base.load(Ordering::Relaxed) + offset) as *mut u32,
+            value,
+        );
+    }
+
+    unsafe fn read_reg(&self, offset: usize) -> u32 {
+        core::ptr::read_volatile((self.base.load(Ordering::Relaxed) + offset) as *const u32)
+    }
+}
+
+impl Default for Plic {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 全局PLIC实例
+pub static PLIC: Plic = Plic::new();
+
+// 全局函数
+pub fn init_plic() {
+    PLIC.init();
+    PLIC.enable(uart::UART_IRQ, 1);
+}
+
+// 处理外部中断：认领、分发、完成
+pub fn handle_external_interrupt() {
+    while let Some(irq) = PLIC.claim() {
+        if irq == uart::UART_IRQ {
+            uart::UART.handle_interrupt();
+        }
+        PLIC.complete(irq);
+    }
+}
@@ -0,0 +1,63 @@
+// 关机和重启
+// QEMU virt 的 sifive,test 设备：写入特定的值让QEMU退出或复位
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// 设备基地址 (QEMU virt 机器的默认值，启动时按设备树调整)
+const SYSCON_BASE: usize = 0x100000;
+
+// 写入的命令
+const SYSCON_POWEROFF: u32 = 0x5555;
+const SYSCON_REBOOT: u32 = 0x7777;
+
+pub struct Syscon {
+    base: AtomicUsize,
+}
+
+impl Syscon {
+    pub const fn new() -> Self {
+        Self {
+            base: AtomicUsize::new(SYSCON_BASE),
+        }
+    }
+
+    pub fn set_base(&self, base: usize) {
+        self.base.store(base, Ordering::Relaxed);
+    }
+
+    pub fn poweroff(&self) -> ! {
+        self.command(SYSCON_POWEROFF)
+    }
+
+    pub fn reboot(&self) -> ! {
+        self.command(SYSCON_REBOOT)
+    }
+
+    fn command(&self, value: u32) -> ! {
+        unsafe {
+            core::ptr::write_volatile(self.base.load(Ordering::Relaxed) as *mut u32, value);
+        }
+        // 设备不存在时停在这里
+        loop {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+impl Default for Syscon {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 全局实例
+pub static SYSCON: Syscon = Syscon::new();
+
+// 全局函数
+pub fn poweroff() -> ! {
+    SYSCON.poweroff()
+}
+
+pub fn reboot() -> ! {
+    SYSCON.reboot()
+}
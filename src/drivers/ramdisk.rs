@@ -0,0 +1,28 @@
+// RAM盘设备
+// 第15章：磁盘I/O - QEMU -initrd 加载的磁盘镜像 (不是cpio归档时) 作为块设备 ram0
+
+use crate::drivers::block::{BlockDevice, RamDisk};
+
+// 设备名
+pub const NAME: &str = "ram0";
+
+static mut RAM0: RamDisk = RamDisk::empty();
+
+fn ram0() -> &'static mut RamDisk {
+    unsafe { &mut *core::ptr::addr_of_mut!(RAM0) }
+}
+
+// 全局函数
+// 把 [start, start + size) 这段内存作为ram0，返回块数
+pub fn init(start: usize, size: usize) -> usize {
+    *ram0() = RamDisk::new(start as *mut u8, size, false);
+    ram0().block_count()
+}
+
+pub fn is_ready() -> bool {
+    ram0().block_count() > 0
+}
+
+pub fn block_device() -> &'static dyn BlockDevice {
+    ram0()
+}
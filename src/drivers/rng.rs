@@ -0,0 +1,140 @@
+// virtio-rng 熵源驱动
+// 第15章：磁盘I/O - 设备把随机字节写入驱动提供的缓冲区
+
+use crate::drivers::driver::{self, Driver};
+use crate::drivers::uart;
+use crate::drivers::virtio::{
+    VirtQueue, VirtioDevice, VirtioMmio, DEVICE_ID_RNG, VIRTIO_F_VERSION_1,
+};
+use core::ptr::{addr_of, addr_of_mut};
+
+// 每次请求的字节数
+const RNG_BUFFER_SIZE: usize = 64;
+
+// 等待请求完成的轮询次数上限
+const REQUEST_TIMEOUT: usize = 10_000_000;
+
+// 设备通过DMA访问的内存
+struct RngDma {
+    queue: VirtQueue,
+    buffer: [u8; RNG_BUFFER_SIZE],
+}
+
+static mut DMA: RngDma = RngDma {
+    queue: VirtQueue::new(),
+    buffer: [0; RNG_BUFFER_SIZE],
+};
+
+fn dma() -> &'static mut RngDma {
+    unsafe { &mut *addr_of_mut!(DMA) }
+}
+
+pub struct RngDriver {
+    base_address: usize,
+    initialized: bool,
+}
+
+impl RngDriver {
+    pub const fn new() -> Self {
+        Self {
+            base_address: 0,
+            initialized: false,
+        }
+    }
+
+    // 用随机字节填满buffer，返回实际填充的字节数
+    pub fn fill(&self, buffer: &mut [u8]) -> usize {
+        if !self.initialized {
+            return 0;
+        }
+        let device = VirtioMmio::new(self.base_address);
+        let dma = dma();
+        let mut filled = 0;
+        while filled < buffer.len() {
+            let chain = [(addr_of!(dma.buffer) as u64, RNG_BUFFER_SIZE as u32, true)];
+            let head = match dma.queue.submit(&chain) {
+                Some(head) => head,
+                None => break,
+            };
+            device.notify(0);
+
+            let mut spins = 0;
+            let len = loop {
+                match dma.queue.pop_used() {
+                    Some((id, len)) if id == head => break len as usize,
+                    Some(_) => {}
+                    None if spins >= REQUEST_TIMEOUT => return filled,
+                    None => {
+                        spins += 1;
+                        core::hint::spin_loop();
+                    }
+                }
+            };
+            device.ack_interrupt();
+
+            let count = len.min(RNG_BUFFER_SIZE).min(buffer.len() - filled);
+            if count == 0 {
+                break;
+            }
+            buffer[filled..filled + count].copy_from_slice(&dma.buffer[..count]);
+            filled += count;
+        }
+        filled
+    }
+}
+
+impl Default for RngDriver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Driver for RngDriver {
+    fn name(&self) -> &'static str {
+        "virtio-rng"
+    }
+
+    fn device_id(&self) -> u32 {
+        DEVICE_ID_RNG
+    }
+
+    fn probe(&mut self, virtio: &VirtioDevice) -> bool {
+        if self.initialized {
+            return false;
+        }
+        let device = virtio.mmio();
+        device.begin_init();
+        if device.negotiate(VIRTIO_F_VERSION_1).is_none()
+            || !device.setup_queue(0, &mut dma().queue)
+        {
+            uart::println("virtio-rng initialization failed");
+            device.fail();
+            return false;
+        }
+        device.finish_init();
+
+        self.base_address = virtio.base;
+        self.initialized = true;
+        true
+    }
+}
+
+// 全局熵源驱动
+static mut RNG: RngDriver = RngDriver::new();
+
+fn rng() -> &'static mut RngDriver {
+    unsafe { &mut *addr_of_mut!(RNG) }
+}
+
+// 全局函数
+pub fn register() -> bool {
+    driver::register_driver(rng())
+}
+
+pub fn is_ready() -> bool {
+    rng().initialized
+}
+
+pub fn fill_random(buffer: &mut [u8]) -> usize {
+    rng().fill(buffer)
+}
@@ -0,0 +1,49 @@
+// Goldfish RTC 实时时钟驱动
+// 第16章：文件系统 - 为文件时间戳提供当前时间
+
+// RTC 基地址 (QEMU virt 机器)
+const RTC_BASE: usize = 0x101000;
+
+// RTC 寄存器偏移：自1970年以来的纳秒数，先读低32位会锁存高32位
+const RTC_TIME_LOW: usize = 0x00;
+const RTC_TIME_HIGH: usize = 0x04;
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+pub struct Rtc {
+    base: usize,
+}
+
+impl Rtc {
+    pub const fn new() -> Self {
+        Self { base: RTC_BASE }
+    }
+
+    // 当前时间 (Unix时间，秒)
+    pub fn now(&self) -> u64 {
+        let nanos = unsafe {
+            let low = self.read_reg(RTC_TIME_LOW) as u64;
+            let high = self.read_reg(RTC_TIME_HIGH) as u64;
+            (high << 32) | low
+        };
+        nanos / NANOS_PER_SECOND
+    }
+
+    unsafe fn read_reg(&self, offset: usize) -> u32 {
+        core::ptr::read_volatile((self.base + offset) as *const u32)
+    }
+}
+
+impl Default for Rtc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 全局RTC实例
+pub static RTC: Rtc = Rtc::new();
+
+// 全局函数
+pub fn now() -> u64 {
+    RTC.now()
+}
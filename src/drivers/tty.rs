@@ -0,0 +1,483 @@
+// 终端行规程 (TTY)
+// 位于UART驱动和程序之间：回显、行编辑、Ctrl-C/Ctrl-D处理
+
+use crate::arch::riscv::*;
+use crate::drivers::uart;
+use crate::kernel::process;
+
+// ioctl 请求号 (与Linux一致)
+pub const TCGETS: usize = 0x5401;
+pub const TCSETS: usize = 0x5402;
+
+// c_lflag 位 (与Linux一致)
+pub const ISIG: u32 = 0x0001; // 处理Ctrl-C等信号字符
+pub const ICANON: u32 = 0x0002; // 规范模式 (行缓冲)
+pub const ECHO: u32 = 0x0008; // 回显输入
+
+// c_oflag 位
+pub const OPOST: u32 = 0x0001; // 输出处理
+pub const ONLCR: u32 = 0x0004; // 输出时把 \n 转换为 \r\n
+
+// c_iflag 位
+pub const ICRNL: u32 = 0x0100; // 输入时把 \r 转换为 \n
+
+// c_cc 下标
+pub const VINTR: usize = 0;
+pub const VERASE: usize = 2;
+pub const VKILL: usize = 3;
+pub const VEOF: usize = 4;
+pub const NCCS: usize = 19;
+
+// 默认控制字符
+const CTRL_C: u8 = 0x03;
+const CTRL_D: u8 = 0x04;
+const CTRL_U: u8 = 0x15;
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7F;
+
+// 终端属性 (布局与Linux的 struct termios 一致，供 TCGETS/TCSETS 使用)
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Termios {
+    pub c_iflag: u32,
+    pub c_oflag: u32,
+    pub c_cflag: u32,
+    pub c_lflag: u32,
+    pub c_line: u8,
+    pub c_cc: [u8; NCCS],
+}
+
+impl Termios {
+    pub const fn new() -> Self {
+        let mut c_cc = [0; NCCS];
+        c_cc[VINTR] = CTRL_C;
+        c_cc[VERASE] = DELETE;
+        c_cc[VKILL] = CTRL_U;
+        c_cc[VEOF] = CTRL_D;
+        Self {
+            c_iflag: ICRNL,
+            c_oflag: OPOST | ONLCR,
+            c_cflag: 0,
+            c_lflag: ISIG | ICANON | ECHO,
+            c_line: 0,
+            c_cc,
+        }
+    }
+
+    pub fn is_canonical(&self) -> bool {
+        (self.c_lflag & ICANON) != 0
+    }
+
+    pub fn is_echo(&self) -> bool {
+        (self.c_lflag & ECHO) != 0
+    }
+}
+
+impl Default for Termios {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 缓冲区大小
+const INPUT_QUEUE_SIZE: usize = 1024;
+const LINE_BUFFER_SIZE: usize = 256;
+const MAX_PENDING_LINES: usize = 32;
+
+// 终端设备
+pub struct Tty {
+    pub termios: Termios,
+    // 已经可以被读取的输入
+    queue: [u8; INPUT_QUEUE_SIZE],
+    queue_head: usize,
+    queue_len: usize,
+    // 规范模式下已完成的行长度 (长度为0表示EOF)
+    lines: [usize; MAX_PENDING_LINES],
+    lines_head: usize,
+    lines_len: usize,
+    // 正在编辑的行
+    line: [u8; LINE_BUFFER_SIZE],
+    line_len: usize,
+    // 前台进程，接收Ctrl-C
+    foreground_pid: Option<usize>,
+    // 等待发给前台进程的SIGINT (receive可能在中断上下文中运行，不能直接终止进程)
+    pending_sigint: Option<usize>,
+    // 没有前台进程时收到的Ctrl-C，由shell中断脚本
+    interrupted: bool,
+}
+
+impl Tty {
+    pub const fn new() -> Self {
+        Self {
+            termios: Termios::new(),
+            queue: [0; INPUT_QUEUE_SIZE],
+            queue_head: 0,
+            queue_len: 0,
+            lines: [0; MAX_PENDING_LINES],
+            lines_head: 0,
+            lines_len: 0,
+            line: [0; LINE_BUFFER_SIZE],
+            line_len: 0,
+            foreground_pid: None,
+            pending_sigint: None,
+            interrupted: false,
+        }
+    }
+
+    // 处理一个输入字符 (来自UART中断或轮询)
+    pub fn receive(&mut self, c: u8) {
+        let mut c = c;
+        if c == b'\r' && (self.termios.c_iflag & ICRNL) != 0 {
+            c = b'\n';
+        }
+
+        let cc = self.termios.c_cc;
+        if (self.termios.c_lflag & ISIG) != 0 && c == cc[VINTR] {
+            self.interrupt();
+            return;
+        }
+
+        if !self.termios.is_canonical() {
+            self.push_queue(c);
+            if self.termios.is_echo() {
+                self.echo(c);
+            }
+            return;
+        }
+
+        if c == cc[VERASE] || c == BACKSPACE {
+            if self.line_len > 0 {
+                self.line_len -= 1;
+                if self.termios.is_echo() {
+                    self.output_str("\x08 \x08");
+                }
+            }
+        } else if c == cc[VKILL] {
+            while self.line_len > 0 {
+                self.line_len -= 1;
+                if self.termios.is_echo() {
+                    self.output_str("\x08 \x08");
+                }
+            }
+        } else if c == cc[VEOF] {
+            // 行首的Ctrl-D表示EOF，否则把当前行交给读者 (不带换行)
+            self.commit_line();
+        } else if c == b'\n' {
+            if self.termios.is_echo() {
+                self.echo(b'\n');
+            }
+            self.append_line(b'\n');
+            self.commit_line();
+        } else if self.line_len < LINE_BUFFER_SIZE - 1 {
+            if self.termios.is_echo() {
+                self.echo(c);
+            }
+            self.append_line(c);
+        }
+    }
+
+    // Ctrl-C：丢弃当前行，记下要向前台进程发送的SIGINT，由poll_input送达
+    fn interrupt(&mut self) {
+        self.line_len = 0;
+        if self.termios.is_echo() {
+            self.output_str("^C\n");
+        }
+        match self.foreground_pid {
+            Some(pid) => self.pending_sigint = Some(pid),
+            None => self.interrupted = true,
+        }
+    }
+
+    fn append_line(&mut self, c: u8) {
+        if self.line_len < LINE_BUFFER_SIZE {
+            self.line[self.line_len] = c;
+            self.line_len += 1;
+        }
+    }
+
+    // 把编辑中的行移入输入队列
+    fn commit_line(&mut self) {
+        if self.lines_len == MAX_PENDING_LINES {
+            // 读者太慢，丢弃这一行
+            self.line_len = 0;
+            return;
+        }
+
+        let mut len = 0;
+        for i in 0..self.line_len {
+            if !self.push_queue(self.line[i]) {
+                break;
+            }
+            len += 1;
+        }
+        self.line_len = 0;
+
+        let index = (self.lines_head + self.lines_len) % MAX_PENDING_LINES;
+        self.lines[index] = len;
+        self.lines_len += 1;
+    }
+
+    fn push_queue(&mut self, c: u8) -> bool {
+        if self.queue_len == INPUT_QUEUE_SIZE {
+            return false;
+        }
+        let index = (self.queue_head + self.queue_len) % INPUT_QUEUE_SIZE;
+        self.queue[index] = c;
+        self.queue_len += 1;
+        true
+    }
+
+    fn pop_queue(&mut self) -> Option<u8> {
+        if self.queue_len == 0 {
+            return None;
+        }
+        let c = self.queue[self.queue_head];
+        self.queue_head = (self.queue_head + 1) % INPUT_QUEUE_SIZE;
+        self.queue_len -= 1;
+        Some(c)
+    }
+
+    // 是否有数据可读
+    pub fn input_ready(&self) -> bool {
+        if self.termios.is_canonical() {
+            self.lines_len > 0
+        } else {
+            self.queue_len > 0
+        }
+    }
+
+    // 非阻塞读取：规范模式下一次最多返回一行，返回0表示EOF
+    pub fn read_available(&mut self, buffer: &mut [u8]) -> usize {
+        if !self.termios.is_canonical() {
+            let mut count = 0;
+            while count < buffer.len() {
+                match self.pop_queue() {
+                    Some(c) => {
+                        buffer[count] = c;
+                        count += 1;
+                    }
+                    None => break,
+                }
+            }
+            return count;
+        }
+
+        if self.lines_len == 0 {
+            return 0;
+        }
+
+        let remaining = self.lines[self.lines_head];
+        let mut count = 0;
+        while count < buffer.len() && count < remaining {
+            match self.pop_queue() {
+                Some(c) => {
+                    buffer[count] = c;
+                    count += 1;
+                }
+                None => break,
+            }
+        }
+
+        if count == remaining {
+            self.lines_head = (self.lines_head + 1) % MAX_PENDING_LINES;
+            self.lines_len -= 1;
+        } else {
+            self.lines[self.lines_head] = remaining - count;
+        }
+        count
+    }
+
+    // 回显一个输入字符，控制字符显示为 ^X
+    fn echo(&self, c: u8) {
+        if c < 0x20 && c != b'\n' && c != b'\t' {
+            self.output(b'^');
+            self.output(c + b'@');
+        } else {
+            self.output(c);
+        }
+    }
+
+    // 输出一个字符 (带输出处理)
+    pub fn output(&self, c: u8) {
+        let oflag = self.termios.c_oflag;
+        if c == b'\n' && (oflag & OPOST) != 0 && (oflag & ONLCR) != 0 {
+            uart::put_char(b'\r');
+        }
+        uart::put_char(c);
+    }
+
+    fn output_str(&self, s: &str) {
+        for &c in s.as_bytes() {
+            self.output(c);
+        }
+    }
+
+    // 切换模式：离开规范模式时，未完成的行直接交给读者；
+    // 回到规范模式时，队列中原始模式的输入没有行记录，作为一行交给读者
+    pub fn set_termios(&mut self, termios: Termios) {
+        let was_canonical = self.termios.is_canonical();
+        self.termios = termios;
+        if was_canonical && !termios.is_canonical() {
+            for i in 0..self.line_len {
+                self.push_queue(self.line[i]);
+            }
+            self.line_len = 0;
+            self.lines_len = 0;
+        } else if !was_canonical && termios.is_canonical() {
+            self.lines_head = 0;
+            self.lines_len = 0;
+            if self.queue_len > 0 {
+                self.lines[0] = self.queue_len;
+                self.lines_len = 1;
+            }
+        }
+    }
+
+    pub fn set_foreground(&mut self, pid: Option<usize>) {
+        self.foreground_pid = pid;
+    }
+
+    pub fn foreground(&self) -> Option<usize> {
+        self.foreground_pid
+    }
+
+    pub fn take_interrupt(&mut self) -> bool {
+        core::mem::take(&mut self.interrupted)
+    }
+
+    pub fn take_pending_sigint(&mut self) -> Option<usize> {
+        self.pending_sigint.take()
+    }
+}
+
+impl Default for Tty {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 全局终端
+static mut TTY: Tty = Tty::new();
+
+fn tty() -> &'static mut Tty {
+    unsafe { &mut *core::ptr::addr_of_mut!(TTY) }
+}
+
+// 在关闭中断的情况下访问终端，避免与UART接收中断竞争
+fn with_tty<R>(f: impl FnOnce(&mut Tty) -> R) -> R {
+    let irq_enabled = unsafe { read_mie_global() };
+    unsafe { write_mie_global(false) };
+    let result = f(tty());
+    unsafe { write_mie_global(irq_enabled) };
+    result
+}
+
+// 全局函数
+pub fn init_tty() {
+    // 输入由UART接收中断送入终端
+    uart::UART.set_rx_interrupt(true);
+    uart::println("TTY line discipline initialized");
+}
+
+// UART接收到一个字符
+pub fn receive_char(c: u8) {
+    with_tty(|tty| tty.receive(c));
+}
+
+// 轮询UART，处理所有已到达的输入，并送达Ctrl-C产生的SIGINT (等待进程时调用)
+pub fn poll_input() {
+    while let Some(c) = uart::UART.get_char() {
+        receive_char(c);
+    }
+    if let Some(pid) = with_tty(|tty| tty.take_pending_sigint()) {
+        process::send_signal(pid, process::SIGINT);
+    }
+}
+
+// 阻塞读取：规范模式下等待一整行，原始模式下至少等待一个字节
+pub fn read(buffer: &mut [u8]) -> usize {
+    if buffer.is_empty() {
+        return 0;
+    }
+    loop {
+        poll_input();
+        if with_tty(|tty| tty.input_ready()) {
+            return with_tty(|tty| tty.read_available(buffer));
+        }
+        // 内核等待输入时让后台进程运行
+        if process::current_pid().is_none() {
+            process::schedule();
+        }
+        core::hint::spin_loop();
+    }
+}
+
+// 是否有可以读取的输入 (规范模式下是完整的一行)
+pub fn input_ready() -> bool {
+    poll_input();
+    with_tty(|tty| tty.input_ready())
+}
+
+// 写入终端 (带输出处理)
+pub fn write(buffer: &[u8]) -> usize {
+    let tty = tty();
+    for &c in buffer {
+        tty.output(c);
+    }
+    buffer.len()
+}
+
+pub fn get_termios() -> Termios {
+    with_tty(|tty| tty.termios)
+}
+
+pub fn set_termios(termios: Termios) {
+    with_tty(|tty| tty.set_termios(termios));
+}
+
+// 切换规范模式/原始模式
+pub fn set_canonical(canonical: bool, echo: bool) {
+    let mut termios = get_termios();
+    termios.c_lflag &= !(ICANON | ECHO);
+    if canonical {
+        termios.c_lflag |= ICANON;
+    }
+    if echo {
+        termios.c_lflag |= ECHO;
+    }
+    set_termios(termios);
+}
+
+pub fn set_foreground(pid: Option<usize>) {
+    with_tty(|tty| tty.set_foreground(pid));
+}
+
+pub fn foreground() -> Option<usize> {
+    with_tty(|tty| tty.foreground())
+}
+
+// 取出并清除未交给前台进程的Ctrl-C
+pub fn take_interrupt() -> bool {
+    with_tty(|tty| tty.take_interrupt())
+}
+
+// ioctl：TCGETS/TCSETS，参数为指向 Termios 的指针
+pub fn ioctl(request: usize, arg: usize) -> Option<usize> {
+    if arg == 0 {
+        return None;
+    }
+    match request {
+        TCGETS => {
+            unsafe {
+                *(arg as *mut Termios) = get_termios();
+            }
+            Some(0)
+        }
+        TCSETS => {
+            let termios = unsafe { *(arg as *const Termios) };
+            set_termios(termios);
+            Some(0)
+        }
+        _ => None,
+    }
+}
@@ -1,12 +1,25 @@
 // UART 驱动
 // 第05章：Hello World - 实现串口输出
 
-// UART 寄存器地址 (QEMU virt 机器)
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::arch::riscv::*;
+
+// UART 寄存器地址 (QEMU virt 机器的默认值，启动时按设备树调整)
 const UART_BASE: usize = 0x10000000;
 
+// UART 输入时钟和默认波特率 (QEMU virt 的 ns16550a 时钟为 3.6864MHz)
+pub const UART_CLOCK_HZ: u32 = 3_686_400;
+pub const UART_BAUD_RATE: u32 = 115_200;
+
+// UART 在PLIC上的中断号 (QEMU virt 机器)
+pub const UART_IRQ: u32 = 10;
+
 // UART 寄存器偏移
 const UART_RBR: usize = 0x00; // 接收缓冲区寄存器
 const UART_THR: usize = 0x00; // 发送保持寄存器
+const UART_DLL: usize = 0x00; // 除数锁存器低字节 (DLAB=1)
+const UART_DLM: usize = 0x01; // 除数锁存器高字节 (DLAB=1)
 const UART_IER: usize = 0x01; // 中断使能寄存器
 const UART_IIR: usize = 0x02; // 中断标识寄存器
 const UART_FCR: usize = 0x02; // FIFO控制寄存器
@@ -16,42 +29,170 @@ const UART_LSR: usize = 0x05; // 线路状态寄存器
 const UART_MSR: usize = 0x06; // 调制解调器状态寄存器
 const UART_SCR: usize = 0x07; // 暂存寄存器
 
+// 线路控制寄存器位
+const LCR_DLAB: u8 = 0x80; // 除数锁存器访问
+const LCR_8N1: u8 = 0x03; // 8位数据，1个停止位，无奇偶校验
+
+// FIFO控制寄存器位
+const FCR_ENABLE: u8 = 0x01; // 启用FIFO
+const FCR_CLEAR_RX: u8 = 0x02; // 清空接收FIFO
+const FCR_CLEAR_TX: u8 = 0x04; // 清空发送FIFO
+const FCR_TRIGGER_14: u8 = 0xC0; // 接收FIFO触发阈值14字节
+
+// 调制解调器控制寄存器位
+const MCR_DTR: u8 = 0x01;
+const MCR_RTS: u8 = 0x02;
+const MCR_OUT2: u8 = 0x08; // 将UART中断连接到中断控制器
+
+// 中断使能寄存器位
+const IER_RX_AVAILABLE: u8 = 0x01; // 接收数据可用中断
+const IER_THR_EMPTY: u8 = 0x02; // 发送保持寄存器空中断
+
+// 中断标识寄存器
+const IIR_NO_PENDING: u8 = 0x01; // 无待处理中断
+const IIR_ID_MASK: u8 = 0x0E;
+const IIR_MODEM_STATUS: u8 = 0x00;
+const IIR_THR_EMPTY: u8 = 0x02;
+const IIR_RX_AVAILABLE: u8 = 0x04;
+const IIR_LINE_STATUS: u8 = 0x06;
+const IIR_RX_TIMEOUT: u8 = 0x0C;
+
 // 线路状态寄存器位
 const LSR_THRE: u8 = 0x20; // 发送保持寄存器空
 const LSR_DR: u8 = 0x01; // 数据就绪
 
+// 16550 发送FIFO深度
+const FIFO_SIZE: usize = 16;
+
+// 发送环形缓冲区大小
+const TX_BUFFER_SIZE: usize = 1024;
+
+// 发送环形缓冲区，由THRE中断排空
+struct TxBuffer {
+    data: [u8; TX_BUFFER_SIZE],
+    head: usize, // 下一个写入位置
+    tail: usize, // 下一个发送位置
+}
+
+impl TxBuffer {
+    const fn new() -> Self {
+        Self {
+            data: [0; TX_BUFFER_SIZE],
+            head: 0,
+            tail: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.head == self.tail
+    }
+
+    fn is_full(&self) -> bool {
+        (self.head + 1) % TX_BUFFER_SIZE == self.tail
+    }
+
+    fn push(&mut self, c: u8) -> bool {
+        if self.is_full() {
+            return false;
+        }
+        self.data[self.head] = c;
+        self.head = (self.head + 1) % TX_BUFFER_SIZE;
+        true
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        if self.is_empty() {
+            return None;
+        }
+        let c = self.data[self.tail];
+        self.tail = (self.tail + 1) % TX_BUFFER_SIZE;
+        Some(c)
+    }
+}
+
+static mut TX_BUFFER: TxBuffer = TxBuffer::new();
+
+fn tx_buffer() -> &'static mut TxBuffer {
+    unsafe { &mut *core::ptr::addr_of_mut!(TX_BUFFER) }
+}
+
 pub struct Uart {
-    base: usize,
+    base: AtomicUsize,
+    // 是否使用中断驱动发送
+    tx_interrupt: AtomicBool,
+    // 发送FIFO中剩余的空位 (仅轮询模式使用)
+    tx_fifo_room: AtomicUsize,
 }
 
 impl Uart {
     pub const fn new() -> Self {
-        Self { base: UART_BASE }
+        Self {
+            base: AtomicUsize::new(UART_BASE),
+            tx_interrupt: AtomicBool::new(false),
+            tx_fifo_room: AtomicUsize::new(0),
+        }
     }
 
-    // 初始化UART
+    // 设置寄存器基地址 (在init之前调用)
+    pub fn set_base(&self, base: usize) {
+        self.base.store(base, Ordering::Relaxed);
+    }
+
+    // 初始化UART (默认时钟和波特率)
     pub fn init(&self) {
+        self.configure(UART_CLOCK_HZ, UART_BAUD_RATE);
+    }
+
+    // 按给定的输入时钟和波特率配置UART，除数超出范围时不修改UART并返回false
+    pub fn configure(&self, clock_hz: u32, baud_rate: u32) -> bool {
+        let divisor = match Self::divisor(clock_hz, baud_rate) {
+            Some(divisor) => divisor,
+            None => return false,
+        };
+
         unsafe {
-            // 设置波特率 (115200)
-            // 这里简化处理，实际需要设置除数锁存器
-            self.write_reg(UART_LCR, 0x80); // 启用除数锁存器访问
-            self.write_reg(UART_THR, 0x00); // 除数低字节
-            self.write_reg(UART_IER, 0x00); // 除数高字节
-            self.write_reg(UART_LCR, 0x03); // 8位数据，1个停止位，无奇偶校验
-            self.write_reg(UART_FCR, 0x00); // 禁用FIFO
-            self.write_reg(UART_MCR, 0x00); // 禁用调制解调器控制
+            self.write_reg(UART_IER, 0x00); // 配置期间关闭中断
+            self.write_reg(UART_LCR, LCR_DLAB); // 启用除数锁存器访问
+            self.write_reg(UART_DLL, (divisor & 0xFF) as u8); // 除数低字节
+            self.write_reg(UART_DLM, (divisor >> 8) as u8); // 除数高字节
+            self.write_reg(UART_LCR, LCR_8N1); // 8位数据，1个停止位，无奇偶校验
+            self.write_reg(
+                UART_FCR,
+                FCR_ENABLE | FCR_CLEAR_RX | FCR_CLEAR_TX | FCR_TRIGGER_14,
+            ); // 启用并清空16字节FIFO
+            self.write_reg(UART_MCR, MCR_DTR | MCR_RTS | MCR_OUT2);
+        }
+
+        self.tx_fifo_room.store(0, Ordering::Relaxed);
+        true
+    }
+
+    // 计算除数: clock / (16 * baud)，四舍五入
+    // 波特率为0、太高 (除数为0) 或太低 (除数超过16位) 时返回None
+    pub const fn divisor(clock_hz: u32, baud_rate: u32) -> Option<u16> {
+        if baud_rate == 0 {
+            return None;
+        }
+        // 用64位计算，避免时钟或波特率很大时溢出
+        let clock_hz = clock_hz as u64;
+        let baud_rate = baud_rate as u64;
+        let div = (clock_hz + baud_rate * 8) / (baud_rate * 16);
+        if div == 0 || div > 0xFFFF {
+            None
+        } else {
+            Some(div as u16)
         }
     }
 
     // 写入寄存器
     unsafe fn write_reg(&self, offset: usize, value: u8) {
-        let addr = self.base + offset;
+        let addr = self.base.load(Ordering::Relaxed) + offset;
         core::ptr::write_volatile(addr as *mut u8, value);
     }
 
     // 读取寄存器
     unsafe fn read_reg(&self, offset: usize) -> u8 {
-        let addr = self.base + offset;
+        let addr = self.base.load(Ordering::Relaxed) + offset;
         core::ptr::read_volatile(addr as *const u8)
     }
 
@@ -62,12 +203,134 @@ impl Uart {
 
     // 发送一个字符
     pub fn put_char(&self, c: u8) {
-        // 等待发送缓冲区为空
-        while !self.is_transmit_empty() {}
+        if self.tx_interrupt.load(Ordering::Relaxed) {
+            self.put_char_buffered(c);
+        } else {
+            self.put_char_sync(c);
+        }
+    }
+
+    // 轮询发送：THRE置位说明FIFO已空，之后可以连续写入16字节
+    fn put_char_sync(&self, c: u8) {
+        if self.tx_fifo_room.load(Ordering::Relaxed) == 0 {
+            while !self.is_transmit_empty() {}
+            self.tx_fifo_room.store(FIFO_SIZE, Ordering::Relaxed);
+        }
 
         unsafe {
             self.write_reg(UART_THR, c);
         }
+        self.tx_fifo_room.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    // 中断发送：字符先进入环形缓冲区，由THRE中断写入FIFO
+    fn put_char_buffered(&self, c: u8) {
+        let irq_enabled = unsafe { read_mie_global() };
+        unsafe { write_mie_global(false) };
+
+        let buffer = tx_buffer();
+        while !buffer.push(c) {
+            // 缓冲区已满，同步排空一部分
+            while !self.is_transmit_empty() {}
+            self.fill_fifo();
+        }
+
+        if self.is_transmit_empty() {
+            self.fill_fifo();
+        }
+        self.update_tx_interrupt();
+
+        unsafe { write_mie_global(irq_enabled) };
+    }
+
+    // 从环形缓冲区向空的发送FIFO写入最多16字节
+    fn fill_fifo(&self) {
+        let buffer = tx_buffer();
+        for _ in 0..FIFO_SIZE {
+            match buffer.pop() {
+                Some(c) => unsafe { self.write_reg(UART_THR, c) },
+                None => break,
+            }
+        }
+    }
+
+    // 缓冲区非空时打开THRE中断，否则关闭
+    fn update_tx_interrupt(&self) {
+        unsafe {
+            let ier = self.read_reg(UART_IER);
+            if tx_buffer().is_empty() {
+                self.write_reg(UART_IER, ier & !IER_THR_EMPTY);
+            } else {
+                self.write_reg(UART_IER, ier | IER_THR_EMPTY);
+            }
+        }
+    }
+
+    // 切换到中断驱动发送 (需要先初始化PLIC)
+    pub fn enable_tx_interrupt(&self) {
+        self.tx_interrupt.store(true, Ordering::Relaxed);
+    }
+
+    // 切换回轮询发送，并排空环形缓冲区
+    pub fn disable_tx_interrupt(&self) {
+        self.tx_interrupt.store(false, Ordering::Relaxed);
+        unsafe {
+            let ier = self.read_reg(UART_IER);
+            self.write_reg(UART_IER, ier & !IER_THR_EMPTY);
+        }
+        self.flush();
+    }
+
+    // 同步排空环形缓冲区中待发送的字符
+    pub fn flush(&self) {
+        let buffer = tx_buffer();
+        while !buffer.is_empty() {
+            while !self.is_transmit_empty() {}
+            self.fill_fifo();
+        }
+        self.tx_fifo_room.store(0, Ordering::Relaxed);
+    }
+
+    // 打开或关闭接收中断
+    pub fn set_rx_interrupt(&self, enabled: bool) {
+        unsafe {
+            let ier = self.read_reg(UART_IER);
+            if enabled {
+                self.write_reg(UART_IER, ier | IER_RX_AVAILABLE);
+            } else {
+                self.write_reg(UART_IER, ier & !IER_RX_AVAILABLE);
+            }
+        }
+    }
+
+    // UART中断处理 (由PLIC分发)
+    pub fn handle_interrupt(&self) {
+        loop {
+            let iir = unsafe { self.read_reg(UART_IIR) };
+            if iir & IIR_NO_PENDING != 0 {
+                break;
+            }
+
+            match iir & IIR_ID_MASK {
+                IIR_THR_EMPTY => {
+                    self.fill_fifo();
+                    self.update_tx_interrupt();
+                }
+                IIR_RX_AVAILABLE | IIR_RX_TIMEOUT => {
+                    // 接收到的数据交给终端行规程
+                    while let Some(c) = self.get_char() {
+                        crate::drivers::tty::receive_char(c);
+                    }
+                }
+                IIR_LINE_STATUS => unsafe {
+                    self.read_reg(UART_LSR);
+                },
+                IIR_MODEM_STATUS => unsafe {
+                    self.read_reg(UART_MSR);
+                },
+                _ => break,
+            }
+        }
     }
 
     // 发送字符串
@@ -105,6 +368,61 @@ pub fn println(s: &str) {
     UART.put_char(b'\n');
 }
 
+// 打印十进制数
+pub fn print_dec(value: usize) {
+    print_dec_padded(value, 0);
+}
+
+// 打印十进制数，右对齐到指定宽度
+pub fn print_dec_padded(value: usize, width: usize) {
+    print_number(false, value, width);
+}
+
+// 打印有符号十进制数 (退出码等可能为负)
+pub fn print_int(value: isize) {
+    print_int_padded(value, 0);
+}
+
+pub fn print_int_padded(value: isize, width: usize) {
+    print_number(value < 0, value.unsigned_abs(), width);
+}
+
+fn print_number(negative: bool, value: usize, width: usize) {
+    let mut digits = [0u8; 21];
+    let mut len = 0;
+    let mut value = value;
+    loop {
+        digits[len] = b'0' + (value % 10) as u8;
+        len += 1;
+        value /= 10;
+        if value == 0 {
+            break;
+        }
+    }
+    if negative {
+        digits[len] = b'-';
+        len += 1;
+    }
+    for _ in len..width {
+        UART.put_char(b' ');
+    }
+    for i in (0..len).rev() {
+        UART.put_char(digits[i]);
+    }
+}
+
+// 打印十六进制数 (不带0x前缀)
+pub fn print_hex(value: usize) {
+    let mut started = false;
+    for shift in (0..16).rev() {
+        let nibble = (value >> (shift * 4)) & 0xF;
+        if nibble != 0 || started || shift == 0 {
+            started = true;
+            UART.put_char(b"0123456789abcdef"[nibble]);
+        }
+    }
+}
+
 // 兼容性函数
 pub fn put_char(c: u8) {
     UART.put_char(c);
@@ -0,0 +1,454 @@
+// virtio-mmio 传输层
+// 第15章：磁盘I/O - 设备寄存器、特性协商和split virtqueue
+
+use core::ptr::{addr_of, addr_of_mut, read_volatile, write_volatile};
+use core::sync::atomic::{fence, Ordering};
+
+// virtio-mmio 设备窗口 (QEMU virt 机器：8个槽位，每个0x1000字节)
+// 设备树中列出了槽位时以设备树为准
+pub const VIRTIO_MMIO_BASE: usize = 0x10001000;
+pub const VIRTIO_MMIO_SIZE: usize = 0x1000;
+pub const VIRTIO_MMIO_COUNT: usize = 8;
+
+const VIRTIO_MAGIC: u32 = 0x74726976; // "virt"
+
+// 寄存器偏移
+const REG_MAGIC: usize = 0x000;
+const REG_VERSION: usize = 0x004;
+const REG_DEVICE_ID: usize = 0x008;
+const REG_VENDOR_ID: usize = 0x00c;
+const REG_DEVICE_FEATURES: usize = 0x010;
+const REG_DEVICE_FEATURES_SEL: usize = 0x014;
+const REG_DRIVER_FEATURES: usize = 0x020;
+const REG_DRIVER_FEATURES_SEL: usize = 0x024;
+const REG_GUEST_PAGE_SIZE: usize = 0x028; // 仅legacy
+const REG_QUEUE_SEL: usize = 0x030;
+const REG_QUEUE_NUM_MAX: usize = 0x034;
+const REG_QUEUE_NUM: usize = 0x038;
+const REG_QUEUE_ALIGN: usize = 0x03c; // 仅legacy
+const REG_QUEUE_PFN: usize = 0x040; // 仅legacy
+const REG_QUEUE_READY: usize = 0x044;
+const REG_QUEUE_NOTIFY: usize = 0x050;
+const REG_INTERRUPT_STATUS: usize = 0x060;
+const REG_INTERRUPT_ACK: usize = 0x064;
+const REG_STATUS: usize = 0x070;
+const REG_QUEUE_DESC_LOW: usize = 0x080;
+const REG_QUEUE_DESC_HIGH: usize = 0x084;
+const REG_QUEUE_DRIVER_LOW: usize = 0x090;
+const REG_QUEUE_DRIVER_HIGH: usize = 0x094;
+const REG_QUEUE_DEVICE_LOW: usize = 0x0a0;
+const REG_QUEUE_DEVICE_HIGH: usize = 0x0a4;
+const REG_CONFIG: usize = 0x100;
+
+// 设备类型
+pub const DEVICE_ID_NET: u32 = 1;
+pub const DEVICE_ID_BLOCK: u32 = 2;
+pub const DEVICE_ID_CONSOLE: u32 = 3;
+pub const DEVICE_ID_RNG: u32 = 4;
+pub const DEVICE_ID_GPU: u32 = 16;
+pub const DEVICE_ID_INPUT: u32 = 18;
+
+// 设备类型名称
+pub fn device_type_name(device_id: u32) -> &'static str {
+    match device_id {
+        DEVICE_ID_NET => "net",
+        DEVICE_ID_BLOCK => "block",
+        DEVICE_ID_CONSOLE => "console",
+        DEVICE_ID_RNG => "rng",
+        DEVICE_ID_GPU => "gpu",
+        DEVICE_ID_INPUT => "input",
+        _ => "unknown",
+    }
+}
+
+// 设备状态位
+pub const STATUS_ACKNOWLEDGE: u32 = 1;
+pub const STATUS_DRIVER: u32 = 2;
+pub const STATUS_DRIVER_OK: u32 = 4;
+pub const STATUS_FEATURES_OK: u32 = 8;
+pub const STATUS_FAILED: u32 = 128;
+
+// 通用特性位：非legacy设备必须协商
+pub const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+// legacy 接口的页大小，也是used ring的对齐
+const PAGE_SIZE: usize = 4096;
+
+// 队列大小
+pub const QUEUE_SIZE: usize = 8;
+
+// 描述符标志
+const DESC_F_NEXT: u16 = 1;
+const DESC_F_WRITE: u16 = 2;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+struct AvailRing {
+    flags: u16,
+    idx: u16,
+    ring: [u16; QUEUE_SIZE],
+    used_event: u16,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct UsedElem {
+    id: u32,
+    len: u32,
+}
+
+#[repr(C)]
+struct UsedRing {
+    flags: u16,
+    idx: u16,
+    ring: [UsedElem; QUEUE_SIZE],
+    avail_event: u16,
+}
+
+const DESC_TABLE_SIZE: usize = core::mem::size_of::<[Descriptor; QUEUE_SIZE]>();
+const AVAIL_RING_SIZE: usize = core::mem::size_of::<AvailRing>();
+
+// split virtqueue：描述符表和available ring在第一页，used ring从下一页开始 (legacy布局)
+#[repr(C, align(4096))]
+pub struct VirtQueue {
+    desc: [Descriptor; QUEUE_SIZE],
+    avail: AvailRing,
+    _padding: [u8; PAGE_SIZE - DESC_TABLE_SIZE - AVAIL_RING_SIZE],
+    used: UsedRing,
+    // 驱动端状态
+    free: [bool; QUEUE_SIZE],
+    last_used: u16,
+}
+
+impl VirtQueue {
+    pub const fn new() -> Self {
+        const EMPTY: Descriptor = Descriptor {
+            addr: 0,
+            len: 0,
+            flags: 0,
+            next: 0,
+        };
+        Self {
+            desc: [EMPTY; QUEUE_SIZE],
+            avail: AvailRing {
+                flags: 0,
+                idx: 0,
+                ring: [0; QUEUE_SIZE],
+                used_event: 0,
+            },
+            _padding: [0; PAGE_SIZE - DESC_TABLE_SIZE - AVAIL_RING_SIZE],
+            used: UsedRing {
+                flags: 0,
+                idx: 0,
+                ring: [UsedElem { id: 0, len: 0 }; QUEUE_SIZE],
+                avail_event: 0,
+            },
+            free: [true; QUEUE_SIZE],
+            last_used: 0,
+        }
+    }
+
+    // 把缓冲区 (地址, 长度, 设备是否写入) 串成描述符链放入available ring，返回链头
+    pub fn submit(&mut self, buffers: &[(u64, u32, bool)]) -> Option<u16> {
+        let mut ids = [0u16; QUEUE_SIZE];
+        let mut count = 0;
+        for (i, _) in self.free.iter().enumerate().filter(|(_, &free)| free) {
+            if count == buffers.len() {
+                break;
+            }
+            ids[count] = i as u16;
+            count += 1;
+        }
+        if buffers.is_empty() || count < buffers.len() {
+            return None;
+        }
+
+        for (k, &(addr, len, writable)) in buffers.iter().enumerate() {
+            let last = k + 1 == buffers.len();
+            let mut flags = 0;
+            if writable {
+                flags |= DESC_F_WRITE;
+            }
+            if !last {
+                flags |= DESC_F_NEXT;
+            }
+            self.desc[ids[k] as usize] = Descriptor {
+                addr,
+                len,
+                flags,
+                next: if last { 0 } else { ids[k + 1] },
+            };
+            self.free[ids[k] as usize] = false;
+        }
+
+        // 先写ring再更新idx，设备看到idx时描述符必须已经就绪
+        unsafe {
+            let idx = read_volatile(addr_of!(self.avail.idx));
+            write_volatile(
+                addr_of_mut!(self.avail.ring[idx as usize % QUEUE_SIZE]),
+                ids[0],
+            );
+            fence(Ordering::SeqCst);
+            write_volatile(addr_of_mut!(self.avail.idx), idx.wrapping_add(1));
+        }
+        fence(Ordering::SeqCst);
+        Some(ids[0])
+    }
+
+    // 取出一个设备已完成的请求：(链头, 写入的字节数)，并回收它的描述符
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        fence(Ordering::SeqCst);
+        let idx = unsafe { read_volatile(addr_of!(self.used.idx)) };
+        if idx == self.last_used {
+            return None;
+        }
+        let elem = unsafe {
+            read_volatile(addr_of!(
+                self.used.ring[self.last_used as usize % QUEUE_SIZE]
+            ))
+        };
+        self.last_used = self.last_used.wrapping_add(1);
+
+        let head = elem.id as u16;
+        let mut i = head as usize;
+        loop {
+            self.free[i] = true;
+            let desc = self.desc[i];
+            if desc.flags & DESC_F_NEXT == 0 {
+                break;
+            }
+            i = desc.next as usize;
+        }
+        Some((head, elem.len))
+    }
+}
+
+impl Default for VirtQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 总线上探测到的设备
+#[derive(Debug, Clone, Copy)]
+pub struct VirtioDevice {
+    pub slot: usize,
+    pub base: usize,
+    pub version: u32,
+    pub device_id: u32,
+    pub vendor_id: u32,
+}
+
+impl VirtioDevice {
+    pub fn mmio(&self) -> VirtioMmio {
+        VirtioMmio::new(self.base)
+    }
+}
+
+// 总线上的槽位地址
+struct MmioSlots {
+    bases: [usize; VIRTIO_MMIO_COUNT],
+    count: usize,
+}
+
+static mut SLOTS: MmioSlots = MmioSlots {
+    bases: [0; VIRTIO_MMIO_COUNT],
+    count: 0,
+};
+
+fn slots() -> &'static mut MmioSlots {
+    unsafe { &mut *addr_of_mut!(SLOTS) }
+}
+
+// 使用设备树给出的槽位地址，为空时使用QEMU virt的默认布局
+pub fn set_mmio_slots(bases: &[usize]) {
+    let slots = slots();
+    slots.count = bases.len().min(VIRTIO_MMIO_COUNT);
+    slots.bases[..slots.count].copy_from_slice(&bases[..slots.count]);
+}
+
+fn slot_base(slot: usize) -> Option<usize> {
+    let slots = slots();
+    if slots.count == 0 {
+        (slot < VIRTIO_MMIO_COUNT).then(|| VIRTIO_MMIO_BASE + slot * VIRTIO_MMIO_SIZE)
+    } else {
+        slots.bases[..slots.count].get(slot).copied()
+    }
+}
+
+// 枚举virtio-mmio总线：依次读取每个槽位的magic、版本和设备类型
+pub fn probe_bus(mut f: impl FnMut(VirtioDevice)) {
+    for slot in 0..VIRTIO_MMIO_COUNT {
+        let base = match slot_base(slot) {
+            Some(base) => base,
+            None => break,
+        };
+        let mmio = VirtioMmio::new(base);
+        if !mmio.is_present() {
+            continue;
+        }
+        f(VirtioDevice {
+            slot,
+            base,
+            version: mmio.version(),
+            device_id: mmio.device_id(),
+            vendor_id: unsafe { mmio.read_reg(REG_VENDOR_ID) },
+        });
+    }
+}
+
+// 一个virtio-mmio设备的寄存器窗口
+pub struct VirtioMmio {
+    base: usize,
+}
+
+impl VirtioMmio {
+    pub const fn new(base: usize) -> Self {
+        Self { base }
+    }
+
+    // 槽位上是否有设备 (空槽位的设备类型为0)
+    pub fn is_present(&self) -> bool {
+        unsafe { self.read_reg(REG_MAGIC) == VIRTIO_MAGIC && self.read_reg(REG_DEVICE_ID) != 0 }
+    }
+
+    pub fn version(&self) -> u32 {
+        unsafe { self.read_reg(REG_VERSION) }
+    }
+
+    pub fn device_id(&self) -> u32 {
+        unsafe { self.read_reg(REG_DEVICE_ID) }
+    }
+
+    // 复位设备，然后声明已识别设备并有驱动
+    pub fn begin_init(&self) {
+        unsafe {
+            self.write_reg(REG_STATUS, 0);
+            self.add_status(STATUS_ACKNOWLEDGE);
+            self.add_status(STATUS_DRIVER);
+            if self.version() == 1 {
+                self.write_reg(REG_GUEST_PAGE_SIZE, PAGE_SIZE as u32);
+            }
+        }
+    }
+
+    // 协商特性：接受设备支持的supported子集，失败时返回None
+    pub fn negotiate(&self, supported: u64) -> Option<u64> {
+        let legacy = self.version() == 1;
+        unsafe {
+            self.write_reg(REG_DEVICE_FEATURES_SEL, 0);
+            let low = self.read_reg(REG_DEVICE_FEATURES) as u64;
+            self.write_reg(REG_DEVICE_FEATURES_SEL, 1);
+            let high = self.read_reg(REG_DEVICE_FEATURES) as u64;
+            let features = ((high << 32) | low) & supported;
+            if !legacy && features & VIRTIO_F_VERSION_1 == 0 {
+                return None;
+            }
+
+            self.write_reg(REG_DRIVER_FEATURES_SEL, 0);
+            self.write_reg(REG_DRIVER_FEATURES, features as u32);
+            self.write_reg(REG_DRIVER_FEATURES_SEL, 1);
+            self.write_reg(REG_DRIVER_FEATURES, (features >> 32) as u32);
+
+            // legacy设备没有FEATURES_OK步骤
+            if !legacy {
+                self.add_status(STATUS_FEATURES_OK);
+                if self.read_reg(REG_STATUS) & STATUS_FEATURES_OK == 0 {
+                    return None;
+                }
+            }
+            Some(features)
+        }
+    }
+
+    // 把queue设置为第index个队列
+    pub fn setup_queue(&self, index: u32, queue: &mut VirtQueue) -> bool {
+        *queue = VirtQueue::new();
+        unsafe {
+            self.write_reg(REG_QUEUE_SEL, index);
+            if self.version() != 1 && self.read_reg(REG_QUEUE_READY) != 0 {
+                return false;
+            }
+            let max = self.read_reg(REG_QUEUE_NUM_MAX) as usize;
+            if max < QUEUE_SIZE {
+                return false;
+            }
+            self.write_reg(REG_QUEUE_NUM, QUEUE_SIZE as u32);
+
+            let desc = addr_of!(queue.desc) as u64;
+            let avail = addr_of!(queue.avail) as u64;
+            let used = addr_of!(queue.used) as u64;
+            if self.version() == 1 {
+                self.write_reg(REG_QUEUE_ALIGN, PAGE_SIZE as u32);
+                self.write_reg(REG_QUEUE_PFN, (desc / PAGE_SIZE as u64) as u32);
+            } else {
+                self.write_reg(REG_QUEUE_DESC_LOW, desc as u32);
+                self.write_reg(REG_QUEUE_DESC_HIGH, (desc >> 32) as u32);
+                self.write_reg(REG_QUEUE_DRIVER_LOW, avail as u32);
+                self.write_reg(REG_QUEUE_DRIVER_HIGH, (avail >> 32) as u32);
+                self.write_reg(REG_QUEUE_DEVICE_LOW, used as u32);
+                self.write_reg(REG_QUEUE_DEVICE_HIGH, (used >> 32) as u32);
+                self.write_reg(REG_QUEUE_READY, 1);
+            }
+        }
+        true
+    }
+
+    // 初始化完成，设备开始工作
+    pub fn finish_init(&self) {
+        unsafe { self.add_status(STATUS_DRIVER_OK) }
+    }
+
+    // 初始化失败
+    pub fn fail(&self) {
+        unsafe { self.add_status(STATUS_FAILED) }
+    }
+
+    // 通知设备队列中有新请求
+    pub fn notify(&self, queue: u32) {
+        unsafe { self.write_reg(REG_QUEUE_NOTIFY, queue) }
+    }
+
+    // 确认中断
+    pub fn ack_interrupt(&self) {
+        unsafe {
+            let status = self.read_reg(REG_INTERRUPT_STATUS);
+            self.write_reg(REG_INTERRUPT_ACK, status);
+        }
+    }
+
+    // 读取设备配置空间
+    pub fn config_u32(&self, offset: usize) -> u32 {
+        unsafe { self.read_reg(REG_CONFIG + offset) }
+    }
+
+    // 按字节读写配置空间 (MAC地址、设备名等字段没有4字节对齐)
+    pub fn config_u8(&self, offset: usize) -> u8 {
+        unsafe { read_volatile((self.base + REG_CONFIG + offset) as *const u8) }
+    }
+
+    pub fn write_config_u8(&self, offset: usize, value: u8) {
+        unsafe { write_volatile((self.base + REG_CONFIG + offset) as *mut u8, value) }
+    }
+
+    unsafe fn add_status(&self, bits: u32) {
+        let status = self.read_reg(REG_STATUS);
+        self.write_reg(REG_STATUS, status | bits);
+    }
+
+    unsafe fn read_reg(&self, offset: usize) -> u32 {
+        read_volatile((self.base + offset) as *const u32)
+    }
+
+    unsafe fn write_reg(&self, offset: usize, value: u32) {
+        write_volatile((self.base + offset) as *mut u32, value);
+    }
+}
@@ -0,0 +1,486 @@
+// 块缓存
+// 第16章：文件系统 - 文件系统和块设备之间的LRU缓存，写入先留在缓存中，sync或定期写回
+
+use crate::drivers::block::{check_range, BlockDevice, DiskResult, BLOCK_SIZE};
+use crate::drivers::rtc;
+use crate::kernel::stdio;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// 缓存块数量
+pub const CACHE_BUFFERS: usize = 64;
+
+// 可以挂上缓存的设备数量
+pub const MAX_CACHED_DEVICES: usize = 4;
+
+// 脏块在缓存中停留超过这个时间 (秒) 后定期写回
+pub const WRITEBACK_INTERVAL: u64 = 5;
+
+// 一次读写超过这么多块时绕过缓存，避免整个FAT表把缓存冲掉
+const BYPASS_BLOCKS: usize = CACHE_BUFFERS / 4;
+
+// 缓存块
+struct Buffer {
+    device: Option<&'static dyn BlockDevice>,
+    block: usize,
+    data: [u8; BLOCK_SIZE],
+    dirty: bool,
+    dirty_since: u64, // 第一次变脏的时间
+    pins: usize,      // 正在使用的次数，大于0时不会被换出
+    last_used: u64,   // LRU时间戳
+}
+
+impl Buffer {
+    const fn new() -> Self {
+        Self {
+            device: None,
+            block: 0,
+            data: [0; BLOCK_SIZE],
+            dirty: false,
+            dirty_since: 0,
+            pins: 0,
+            last_used: 0,
+        }
+    }
+
+    fn holds(&self, device: &dyn BlockDevice, block: usize) -> bool {
+        match self.device {
+            Some(owner) => same_device(owner, device) && self.block == block,
+            None => false,
+        }
+    }
+
+    // 把脏数据写回设备
+    fn write_back(&mut self) -> DiskResult {
+        if !self.dirty {
+            return DiskResult::Success;
+        }
+        let device = match self.device {
+            Some(device) => device,
+            None => return DiskResult::Error,
+        };
+        let result = device.write_blocks(self.block, &self.data);
+        if result == DiskResult::Success {
+            self.dirty = false;
+        }
+        result
+    }
+}
+
+// 两个引用是否指向同一个设备对象
+fn same_device(a: &dyn BlockDevice, b: &dyn BlockDevice) -> bool {
+    core::ptr::addr_eq(a as *const dyn BlockDevice, b as *const dyn BlockDevice)
+}
+
+// 缓存统计
+#[derive(Debug, Clone, Copy)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub writebacks: usize,
+    pub evictions: usize,
+}
+
+pub struct BufferCache {
+    buffers: [Buffer; CACHE_BUFFERS],
+    clock: u64,
+    stats: CacheStats,
+    // 上次定期写回的时间 (秒)
+    last_writeback: u64,
+}
+
+impl BufferCache {
+    pub const fn new() -> Self {
+        const EMPTY: Buffer = Buffer::new();
+        Self {
+            buffers: [EMPTY; CACHE_BUFFERS],
+            clock: 0,
+            stats: CacheStats {
+                hits: 0,
+                misses: 0,
+                writebacks: 0,
+                evictions: 0,
+            },
+            last_writeback: 0,
+        }
+    }
+
+    fn lookup(&self, device: &dyn BlockDevice, block: usize) -> Option<usize> {
+        self.buffers
+            .iter()
+            .position(|buffer| buffer.holds(device, block))
+    }
+
+    // 取得一个缓存块并固定，read为true时缓存未命中要从设备读入
+    // (整块覆盖时不需要读)
+    pub fn get(
+        &mut self,
+        device: &'static dyn BlockDevice,
+        block: usize,
+        read: bool,
+    ) -> Result<usize, DiskResult> {
+        self.clock += 1;
+        if let Some(index) = self.lookup(device, block) {
+            self.stats.hits += 1;
+            let buffer = &mut self.buffers[index];
+            buffer.pins += 1;
+            buffer.last_used = self.clock;
+            return Ok(index);
+        }
+
+        self.stats.misses += 1;
+        let index = self.evict()?;
+        let buffer = &mut self.buffers[index];
+        buffer.device = None;
+        if read {
+            let result = device.read_blocks(block, &mut buffer.data);
+            if result != DiskResult::Success {
+                return Err(result);
+            }
+        }
+        buffer.device = Some(device);
+        buffer.block = block;
+        buffer.dirty = false;
+        buffer.pins = 1;
+        buffer.last_used = self.clock;
+        Ok(index)
+    }
+
+    // 选出最久未使用且没有固定的块，脏块先写回
+    fn evict(&mut self) -> Result<usize, DiskResult> {
+        let index = self
+            .buffers
+            .iter()
+            .enumerate()
+            .filter(|(_, buffer)| buffer.pins == 0)
+            .min_by_key(|(_, buffer)| (buffer.device.is_some(), buffer.last_used))
+            .map(|(index, _)| index)
+            .ok_or(DiskResult::Error)?;
+
+        let buffer = &mut self.buffers[index];
+        if buffer.device.is_some() {
+            self.stats.evictions += 1;
+            if buffer.dirty {
+                let result = buffer.write_back();
+                if result != DiskResult::Success {
+                    return Err(result);
+                }
+                self.stats.writebacks += 1;
+            }
+        }
+        Ok(index)
+    }
+
+    pub fn data(&mut self, index: usize) -> &mut [u8; BLOCK_SIZE] {
+        &mut self.buffers[index].data
+    }
+
+    pub fn mark_dirty(&mut self, index: usize) {
+        let buffer = &mut self.buffers[index];
+        if !buffer.dirty {
+            buffer.dirty = true;
+            buffer.dirty_since = rtc::now();
+        }
+    }
+
+    // 解除固定
+    pub fn release(&mut self, index: usize) {
+        let buffer = &mut self.buffers[index];
+        buffer.pins = buffer.pins.saturating_sub(1);
+    }
+
+    // 写回脏块：device为None时写回所有设备，older_than给出时只写回变脏早于它的块
+    fn write_back(
+        &mut self,
+        device: Option<&dyn BlockDevice>,
+        older_than: Option<u64>,
+    ) -> DiskResult {
+        let mut result = DiskResult::Success;
+        for buffer in self.buffers.iter_mut() {
+            let owner = match buffer.device {
+                Some(owner) if buffer.dirty => owner,
+                _ => continue,
+            };
+            if device.is_some_and(|device| !same_device(owner, device)) {
+                continue;
+            }
+            if older_than.is_some_and(|time| buffer.dirty_since > time) {
+                continue;
+            }
+            // 定期写回在定时器中断中进行，跳过正在使用的块和正在处理请求的设备
+            if older_than.is_some() && (buffer.pins > 0 || owner.is_busy()) {
+                continue;
+            }
+            match buffer.write_back() {
+                DiskResult::Success => self.stats.writebacks += 1,
+                err => result = err,
+            }
+        }
+        result
+    }
+
+    // 丢弃落在设备范围内的缓存块 (卸载设备后调用，先sync)
+    // 分区只丢弃自己的块，同一磁盘上其他分区的缓存不受影响
+    pub fn invalidate(&mut self, device: &dyn BlockDevice) {
+        let (base, start, count) = device.extent();
+        for buffer in self.buffers.iter_mut() {
+            let Some(owner) = buffer.device else { continue };
+            let (owner_base, owner_start, _) = owner.extent();
+            let block = owner_start + buffer.block;
+            if owner_base == base && block >= start && block - start < count {
+                buffer.device = None;
+                buffer.dirty = false;
+            }
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    // (已使用, 脏, 固定) 的缓存块数
+    pub fn usage(&self) -> (usize, usize, usize) {
+        let used = self.buffers.iter().filter(|b| b.device.is_some()).count();
+        let dirty = self.buffers.iter().filter(|b| b.dirty).count();
+        let pinned = self.buffers.iter().filter(|b| b.pins > 0).count();
+        (used, dirty, pinned)
+    }
+}
+
+impl Default for BufferCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 全局块缓存
+static mut CACHE: BufferCache = BufferCache::new();
+
+fn cache() -> &'static mut BufferCache {
+    unsafe { &mut *core::ptr::addr_of_mut!(CACHE) }
+}
+
+// 正在进行的缓存操作数，定时器中断只在没有缓存操作时写回
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+// 执行一次缓存操作，期间定时器中断不会修改缓存
+fn exclusive<T>(f: impl FnOnce(&mut BufferCache) -> T) -> T {
+    ACTIVE.fetch_add(1, Ordering::SeqCst);
+    let result = f(cache());
+    ACTIVE.fetch_sub(1, Ordering::SeqCst);
+    result
+}
+
+// 经过缓存访问的块设备
+pub struct CachedDevice {
+    inner: &'static dyn BlockDevice,
+}
+
+impl CachedDevice {
+    pub fn inner(&self) -> &'static dyn BlockDevice {
+        self.inner
+    }
+}
+
+impl BlockDevice for CachedDevice {
+    fn read_blocks(&self, block: usize, buffer: &mut [u8]) -> DiskResult {
+        exclusive(|cache| {
+            let count = match check_range(self, block, buffer.len()) {
+                Ok(count) => count,
+                Err(err) => return err,
+            };
+
+            // 大块读取直接读设备，再用缓存中较新的块覆盖
+            if count > BYPASS_BLOCKS {
+                let result = self.inner.read_blocks(block, buffer);
+                if result != DiskResult::Success {
+                    return result;
+                }
+                for (i, chunk) in buffer.chunks_exact_mut(BLOCK_SIZE).enumerate() {
+                    if let Some(index) = cache.lookup(self.inner, block + i) {
+                        chunk.copy_from_slice(cache.data(index));
+                    }
+                }
+                return DiskResult::Success;
+            }
+
+            for (i, chunk) in buffer.chunks_exact_mut(BLOCK_SIZE).enumerate() {
+                let index = match cache.get(self.inner, block + i, true) {
+                    Ok(index) => index,
+                    Err(err) => return err,
+                };
+                chunk.copy_from_slice(cache.data(index));
+                cache.release(index);
+            }
+            DiskResult::Success
+        })
+    }
+
+    fn write_blocks(&self, block: usize, buffer: &[u8]) -> DiskResult {
+        exclusive(|cache| {
+            let count = match check_range(self, block, buffer.len()) {
+                Ok(count) => count,
+                Err(err) => return err,
+            };
+
+            // 大块写入直接写设备，同时更新已缓存的副本
+            if count > BYPASS_BLOCKS {
+                let result = self.inner.write_blocks(block, buffer);
+                if result != DiskResult::Success {
+                    return result;
+                }
+                for (i, chunk) in buffer.chunks_exact(BLOCK_SIZE).enumerate() {
+                    if let Some(index) = cache.lookup(self.inner, block + i) {
+                        cache.data(index).copy_from_slice(chunk);
+                        cache.buffers[index].dirty = false;
+                    }
+                }
+                return DiskResult::Success;
+            }
+
+            for (i, chunk) in buffer.chunks_exact(BLOCK_SIZE).enumerate() {
+                let index = match cache.get(self.inner, block + i, false) {
+                    Ok(index) => index,
+                    Err(err) => return err,
+                };
+                cache.data(index).copy_from_slice(chunk);
+                cache.mark_dirty(index);
+                cache.release(index);
+            }
+            DiskResult::Success
+        })
+    }
+
+    fn block_count(&self) -> usize {
+        self.inner.block_count()
+    }
+
+    fn block_size(&self) -> usize {
+        self.inner.block_size()
+    }
+
+    // 写回这个设备的脏块，再让设备落盘
+    fn flush(&self) -> DiskResult {
+        match exclusive(|cache| cache.write_back(Some(self.inner), None)) {
+            DiskResult::Success => self.inner.flush(),
+            err => err,
+        }
+    }
+
+    fn extent(&self) -> (*const (), usize, usize) {
+        self.inner.extent()
+    }
+
+    fn is_busy(&self) -> bool {
+        self.inner.is_busy()
+    }
+}
+
+// 挂上缓存的设备
+static mut CACHED_DEVICES: [Option<CachedDevice>; MAX_CACHED_DEVICES] = [None, None, None, None];
+
+fn cached_devices() -> &'static mut [Option<CachedDevice>; MAX_CACHED_DEVICES] {
+    unsafe { &mut *core::ptr::addr_of_mut!(CACHED_DEVICES) }
+}
+
+// 全局函数
+// 返回经过缓存访问device的块设备，同一个设备只建立一次
+pub fn cached(device: &'static dyn BlockDevice) -> Option<&'static dyn BlockDevice> {
+    let slots = cached_devices();
+    let index = match slots
+        .iter()
+        .position(|slot| matches!(slot, Some(cached) if same_device(cached.inner, device)))
+    {
+        Some(index) => index,
+        None => {
+            let index = slots.iter().position(|slot| slot.is_none())?;
+            slots[index] = Some(CachedDevice { inner: device });
+            index
+        }
+    };
+    slots[index]
+        .as_ref()
+        .map(|cached| cached as &'static dyn BlockDevice)
+}
+
+// 读取一个块并固定在缓存中，用完后调用release
+pub fn bread(device: &'static dyn BlockDevice, block: usize) -> Result<usize, DiskResult> {
+    exclusive(|cache| cache.get(device, block, true))
+}
+
+pub fn buffer(index: usize) -> &'static mut [u8; BLOCK_SIZE] {
+    cache().data(index)
+}
+
+pub fn mark_dirty(index: usize) {
+    exclusive(|cache| cache.mark_dirty(index));
+}
+
+pub fn release(index: usize) {
+    exclusive(|cache| cache.release(index));
+}
+
+// 写回所有脏块并让设备落盘
+pub fn sync() -> DiskResult {
+    let mut result = exclusive(|cache| cache.write_back(None, None));
+    for cached in cached_devices().iter().flatten() {
+        let flushed = cached.inner.flush();
+        if flushed != DiskResult::Success {
+            result = flushed;
+        }
+    }
+    result
+}
+
+// 定期写回：只写回变脏超过 WRITEBACK_INTERVAL 秒的块
+// 由定时器中断调用，每秒最多扫描一次缓存；被打断的代码正在使用缓存时留到下一个节拍
+pub fn writeback_expired() {
+    if ACTIVE.load(Ordering::SeqCst) != 0 {
+        return;
+    }
+    let now = rtc::now();
+    let cache = cache();
+    if now == cache.last_writeback {
+        return;
+    }
+    cache.last_writeback = now;
+    let _ = cache.write_back(None, Some(now.saturating_sub(WRITEBACK_INTERVAL)));
+}
+
+// 卸载后丢弃设备的缓存块
+pub fn invalidate(device: &dyn BlockDevice) {
+    exclusive(|cache| cache.invalidate(device));
+}
+
+pub fn stats() -> CacheStats {
+    cache().stats()
+}
+
+// 打印缓存统计
+pub fn print_stats() {
+    let stats = stats();
+    let (used, dirty, pinned) = cache().usage();
+    stdio::print("Buffers: ");
+    stdio::print_dec(used);
+    stdio::print("/");
+    stdio::print_dec(CACHE_BUFFERS);
+    stdio::print(" used, ");
+    stdio::print_dec(dirty);
+    stdio::print(" dirty, ");
+    stdio::print_dec(pinned);
+    stdio::println(" pinned");
+
+    let lookups = stats.hits + stats.misses;
+    stdio::print("Hits: ");
+    stdio::print_dec(stats.hits);
+    stdio::print("  Misses: ");
+    stdio::print_dec(stats.misses);
+    stdio::print("  Hit rate: ");
+    stdio::print_dec((stats.hits * 100).checked_div(lookups).unwrap_or(0));
+    stdio::println("%");
+
+    stdio::print("Write-backs: ");
+    stdio::print_dec(stats.writebacks);
+    stdio::print("  Evictions: ");
+    stdio::print_dec(stats.evictions);
+    stdio::println("");
+}
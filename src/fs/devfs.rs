@@ -0,0 +1,222 @@
+// 设备文件系统
+// 第16章：文件系统 - /dev 下的字符设备：null、zero、console、random
+
+use crate::drivers::{rng, tty};
+use crate::fs::vfs::{
+    DirEntry, FileSystem, FsError, Inode, InodeId, InodeType, Metadata, MAX_INODES,
+};
+
+// 根目录的inode为0，设备的inode为DEVICES下标加1
+const ROOT_INODE: InodeId = 0;
+
+// 字符设备：读写函数返回传输的字节数
+struct Device {
+    name: &'static str,
+    read: fn(&mut [u8]) -> usize,
+    write: fn(&[u8]) -> usize,
+}
+
+fn read_null(_buffer: &mut [u8]) -> usize {
+    0
+}
+
+fn read_zero(buffer: &mut [u8]) -> usize {
+    buffer.fill(0);
+    buffer.len()
+}
+
+// 写入的数据直接丢弃
+fn write_discard(data: &[u8]) -> usize {
+    data.len()
+}
+
+const DEVICES: [Device; 4] = [
+    Device {
+        name: "null",
+        read: read_null,
+        write: write_discard,
+    },
+    Device {
+        name: "zero",
+        read: read_zero,
+        write: write_discard,
+    },
+    Device {
+        name: "console",
+        read: tty::read,
+        write: tty::write,
+    },
+    Device {
+        name: "random",
+        read: rng::fill_random,
+        write: write_discard,
+    },
+];
+
+fn device(inode: InodeId) -> Result<&'static Device, FsError> {
+    match inode.checked_sub(1) {
+        Some(index) => DEVICES.get(index as usize).ok_or(FsError::NotFound),
+        None => Err(FsError::IsADirectory),
+    }
+}
+
+pub struct DevFs {
+    mounted: bool,
+}
+
+impl DevFs {
+    pub const fn new() -> Self {
+        Self { mounted: false }
+    }
+
+    fn metadata(&mut self, inode: InodeId) -> Result<Metadata, FsError> {
+        if inode == ROOT_INODE {
+            let mut metadata = Metadata::new(inode, InodeType::Directory);
+            metadata.read_only = true;
+            return Ok(metadata);
+        }
+        device(inode)?;
+        Ok(Metadata::new(inode, InodeType::CharDevice))
+    }
+
+    fn lookup(&mut self, dir: InodeId, name: &str) -> Result<InodeId, FsError> {
+        if dir != ROOT_INODE {
+            return Err(FsError::NotADirectory);
+        }
+        match DEVICES.iter().position(|device| device.name == name) {
+            Some(index) => Ok(index as InodeId + 1),
+            None => Err(FsError::NotFound),
+        }
+    }
+
+    fn read_dir(&mut self, dir: InodeId, cursor: &mut usize) -> Result<Option<DirEntry>, FsError> {
+        if dir != ROOT_INODE {
+            return Err(FsError::NotADirectory);
+        }
+        let Some(device) = DEVICES.get(*cursor) else {
+            return Ok(None);
+        };
+        *cursor += 1;
+        Ok(Some(DirEntry::new(
+            *cursor as InodeId,
+            InodeType::CharDevice,
+            device.name,
+        )))
+    }
+
+    // 字符设备没有读写位置，忽略offset
+    fn read(
+        &mut self,
+        inode: InodeId,
+        _offset: usize,
+        buffer: &mut [u8],
+    ) -> Result<usize, FsError> {
+        Ok((device(inode)?.read)(buffer))
+    }
+
+    fn write(&mut self, inode: InodeId, _offset: usize, data: &[u8]) -> Result<usize, FsError> {
+        Ok((device(inode)?.write)(data))
+    }
+
+    // 以写方式打开设备时 (如 > /dev/null) 会截断，什么也不做
+    fn truncate(&mut self, inode: InodeId, _length: usize) -> Result<(), FsError> {
+        device(inode).map(|_| ())
+    }
+}
+
+impl Default for DevFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FileSystem for DevFs {
+    fn name(&self) -> &'static str {
+        "devfs"
+    }
+
+    fn root(&self) -> InodeId {
+        ROOT_INODE
+    }
+
+    fn inode(&mut self, id: InodeId) -> Result<&'static mut dyn Inode, FsError> {
+        let inode = inodes()
+            .iter_mut()
+            .find(|inode| !inode.used)
+            .ok_or(FsError::TooManyFiles)?;
+        *inode = DevInode { used: true, id };
+        Ok(inode)
+    }
+
+    fn unmount(&mut self) -> Result<(), FsError> {
+        self.mounted = false;
+        Ok(())
+    }
+}
+
+// 交给VFS的inode对象，在INODES表中分配
+struct DevInode {
+    used: bool,
+    id: InodeId,
+}
+
+impl DevInode {
+    const EMPTY: DevInode = DevInode { used: false, id: 0 };
+}
+
+impl Inode for DevInode {
+    fn id(&self) -> InodeId {
+        self.id
+    }
+
+    fn metadata(&mut self) -> Result<Metadata, FsError> {
+        devfs().metadata(self.id)
+    }
+
+    fn lookup(&mut self, name: &str) -> Result<InodeId, FsError> {
+        devfs().lookup(self.id, name)
+    }
+
+    fn read_dir(&mut self, cursor: &mut usize) -> Result<Option<DirEntry>, FsError> {
+        devfs().read_dir(self.id, cursor)
+    }
+
+    fn read_at(&mut self, offset: usize, buffer: &mut [u8]) -> Result<usize, FsError> {
+        devfs().read(self.id, offset, buffer)
+    }
+
+    fn write_at(&mut self, offset: usize, data: &[u8]) -> Result<usize, FsError> {
+        devfs().write(self.id, offset, data)
+    }
+
+    fn truncate(&mut self, length: usize) -> Result<(), FsError> {
+        devfs().truncate(self.id, length)
+    }
+
+    fn release(&mut self) {
+        self.used = false;
+    }
+}
+
+static mut INODES: [DevInode; MAX_INODES] = [DevInode::EMPTY; MAX_INODES];
+
+fn inodes() -> &'static mut [DevInode; MAX_INODES] {
+    unsafe { &mut *core::ptr::addr_of_mut!(INODES) }
+}
+
+// 只有一个实例
+static mut DEVFS: DevFs = DevFs::new();
+
+fn devfs() -> &'static mut DevFs {
+    unsafe { &mut *core::ptr::addr_of_mut!(DEVFS) }
+}
+
+// 全局函数
+pub fn instance(_source: &str) -> Result<&'static mut dyn FileSystem, FsError> {
+    let devfs = devfs();
+    if devfs.mounted {
+        return Err(FsError::Busy);
+    }
+    devfs.mounted = true;
+    Ok(devfs)
+}
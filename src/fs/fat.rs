@@ -2,23 +2,128 @@
 // 实现简单的FAT文件系统
 
 use crate::common::types::ToString;
-use crate::drivers::disk::*;
-use crate::drivers::uart;
+use crate::drivers::block::{self, BlockDevice, DiskResult, NullDevice, BLOCK_SIZE};
+use crate::drivers::{rtc, uart};
+use crate::fs::bcache;
+use crate::fs::vfs::{DirEntry, FileSystem, Inode, InodeId, InodeType, Metadata, MAX_INODES};
+use crate::kernel::memory;
+
+pub use crate::fs::vfs::FsError;
 
 // FAT文件系统常量
 const FAT_SIGNATURE: u16 = 0xAA55;
 const BOOT_SECTOR_SIZE: usize = 512;
-const FAT_ENTRY_SIZE: usize = 2; // FAT16
-const ROOT_DIR_ENTRIES: usize = 512;
 const DIR_ENTRY_SIZE: usize = 32;
+const ENTRIES_PER_SECTOR: usize = BLOCK_SIZE / DIR_ENTRY_SIZE;
+
+// FAT类型由簇数决定
+const FAT12_MAX_CLUSTERS: usize = 4085;
+const FAT16_MAX_CLUSTERS: usize = 65525;
+const FAT32_ENTRY_MASK: u32 = 0x0FFF_FFFF; // FAT32表项只使用低28位
+
+// FAT[1] 中的卷状态标志：干净卸载、无磁盘错误 (FAT12没有)
+const FAT16_CLEAN_SHUTDOWN: u32 = 0x8000;
+const FAT16_NO_ERRORS: u32 = 0x4000;
+const FAT32_CLEAN_SHUTDOWN: u32 = 0x0800_0000;
+const FAT32_NO_ERRORS: u32 = 0x0400_0000;
+
+// FAT32 FSInfo扇区：签名和空闲簇提示的偏移
+const FSINFO_LEAD_SIGNATURE: u32 = 0x4161_5252;
+const FSINFO_STRUCT_SIGNATURE: u32 = 0x6141_7272;
+const FSINFO_TRAIL_SIGNATURE: u32 = 0xAA55_0000;
+const FSINFO_FREE_COUNT: usize = 488;
+const FSINFO_NEXT_FREE: usize = 492;
+const FSINFO_UNKNOWN: u32 = 0xFFFF_FFFF;
+
+// 目录项首字节的特殊值
+const ENTRY_END: u8 = 0x00; // 目录结束
+const ENTRY_DELETED: u8 = 0xE5; // 已删除
+const ENTRY_KANJI_E5: u8 = 0x05; // 首字符实际为0xE5
+
+// 长文件名 (VFAT)：每个目录项存13个UCS-2字符，最多20项
+pub const MAX_NAME_LEN: usize = 255; // 名称UTF-8字节数上限
+const LFN_CHARS_PER_ENTRY: usize = 13;
+const LFN_MAX_ENTRIES: usize = 20;
+const LFN_MAX_CHARS: usize = 255;
+const LFN_LAST_ENTRY: u8 = 0x40; // 序号中的最后一项标志
+const LFN_CHAR_OFFSETS: [usize; LFN_CHARS_PER_ENTRY] =
+    [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+// 短文件名目录项偏移12：基本名、扩展名以小写显示 (Windows NT)
+const NT_LOWER_BASE: u8 = 0x08;
+const NT_LOWER_EXT: u8 = 0x10;
+
+// 子目录中指向自身和父目录的目录项名称
+const DOT_NAME: [u8; 11] = *b".          ";
+const DOTDOT_NAME: [u8; 11] = *b"..         ";
 
 // 文件属性
-const ATTR_READ_ONLY: u8 = 0x01;
-const ATTR_HIDDEN: u8 = 0x02;
-const ATTR_SYSTEM: u8 = 0x04;
-const ATTR_VOLUME_ID: u8 = 0x08;
-const ATTR_DIRECTORY: u8 = 0x10;
-const ATTR_ARCHIVE: u8 = 0x20;
+pub const ATTR_READ_ONLY: u8 = 0x01;
+pub const ATTR_HIDDEN: u8 = 0x02;
+pub const ATTR_SYSTEM: u8 = 0x04;
+pub const ATTR_VOLUME_ID: u8 = 0x08;
+pub const ATTR_DIRECTORY: u8 = 0x10;
+pub const ATTR_ARCHIVE: u8 = 0x20;
+pub const ATTR_LONG_NAME: u8 = ATTR_READ_ONLY | ATTR_HIDDEN | ATTR_SYSTEM | ATTR_VOLUME_ID;
+
+// FAT类型
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+impl FatType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FatType::Fat12 => "FAT12",
+            FatType::Fat16 => "FAT16",
+            FatType::Fat32 => "FAT32",
+        }
+    }
+
+    // 表项的有效位，同时也是写入时使用的簇链结束标记
+    fn mask(&self) -> u32 {
+        match self {
+            FatType::Fat12 => 0xFFF,
+            FatType::Fat16 => 0xFFFF,
+            FatType::Fat32 => FAT32_ENTRY_MASK,
+        }
+    }
+
+    // 坏簇标记，比它大的值都是簇链结束
+    fn bad_cluster(&self) -> u32 {
+        self.mask() - 8
+    }
+
+    // count个表项占用的字节数 (FAT12每项1.5字节)
+    fn table_size(&self, count: usize) -> usize {
+        match self {
+            FatType::Fat12 => (count * 3).div_ceil(2),
+            FatType::Fat16 => count * 2,
+            FatType::Fat32 => count * 4,
+        }
+    }
+
+    // 第cluster项在FAT中的字节偏移和读写宽度
+    fn entry_position(&self, cluster: usize) -> (usize, usize) {
+        match self {
+            FatType::Fat12 => (cluster + cluster / 2, 2),
+            FatType::Fat16 => (cluster * 2, 2),
+            FatType::Fat32 => (cluster * 4, 4),
+        }
+    }
+
+    // FAT[1] 中的 (干净卸载, 无错误) 标志位
+    fn status_flags(&self) -> (u32, u32) {
+        match self {
+            FatType::Fat12 => (0, 0),
+            FatType::Fat16 => (FAT16_CLEAN_SHUTDOWN, FAT16_NO_ERRORS),
+            FatType::Fat32 => (FAT32_CLEAN_SHUTDOWN, FAT32_NO_ERRORS),
+        }
+    }
+}
 
 // 文件类型
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -28,25 +133,257 @@ pub enum FileType {
     Unknown,
 }
 
+// FAT日期时间：日期 年(1980起)7位|月4位|日5位，时间 时5位|分6位|秒/2 5位
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FatDateTime {
+    pub date: u16,
+    pub time: u16,
+}
+
+impl FatDateTime {
+    pub const fn new() -> Self {
+        Self { date: 0, time: 0 }
+    }
+
+    pub fn year(&self) -> usize {
+        1980 + (self.date >> 9) as usize
+    }
+
+    pub fn month(&self) -> usize {
+        ((self.date >> 5) & 0x0F) as usize
+    }
+
+    pub fn day(&self) -> usize {
+        (self.date & 0x1F) as usize
+    }
+
+    pub fn hour(&self) -> usize {
+        (self.time >> 11) as usize
+    }
+
+    pub fn minute(&self) -> usize {
+        ((self.time >> 5) & 0x3F) as usize
+    }
+
+    pub fn second(&self) -> usize {
+        ((self.time & 0x1F) * 2) as usize
+    }
+
+    // 由Unix时间换算，超出FAT可表示范围 (1980-2107) 时取边界值
+    pub fn from_unix_time(seconds: u64) -> Self {
+        let days = seconds / 86400;
+        let secs = seconds % 86400;
+
+        // 公历日期换算 (以0000-03-01为起点的400年周期)
+        let z = days + 719468;
+        let era = z / 146097;
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        if year < 1980 {
+            return Self {
+                date: (1 << 5) | 1,
+                time: 0,
+            };
+        }
+        let year = year.min(2107) - 1980;
+        Self {
+            date: ((year << 9) | (month << 5) | day) as u16,
+            time: (((secs / 3600) << 11) | ((secs / 60 % 60) << 5) | (secs % 60 / 2)) as u16,
+        }
+    }
+
+    pub fn now() -> Self {
+        Self::from_unix_time(rtc::now())
+    }
+
+    // 换算为Unix时间，from_unix_time的逆运算；没有记录时间 (全0) 时返回0
+    pub fn to_unix_time(&self) -> u64 {
+        if self.date == 0 {
+            return 0;
+        }
+        let year = self.year() as u64;
+        let month = self.month().clamp(1, 12) as u64;
+        let day = self.day().max(1) as u64;
+
+        let y = if month <= 2 { year - 1 } else { year };
+        let era = y / 400;
+        let yoe = y - era * 400;
+        let mp = (month + 9) % 12;
+        let doy = (153 * mp + 2) / 5 + day - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146097 + doe - 719468;
+        days * 86400 + (self.hour() * 3600 + self.minute() * 60 + self.second()) as u64
+    }
+}
+
+impl Default for FatDateTime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 // 文件信息
 #[derive(Debug, Clone, Copy)]
 pub struct FileInfo {
-    pub name: [u8; 11],
+    pub name: [u8; 11], // 8.3短文件名
     pub attributes: u8,
+    pub case_flags: u8,
     pub size: u32,
-    pub first_cluster: u16,
+    pub first_cluster: u32,
     pub file_type: FileType,
+    pub created: FatDateTime,
+    pub modified: FatDateTime,
+    // 显示用的名称：长文件名，没有时为格式化后的8.3名称
+    pub long_name: [u8; MAX_NAME_LEN],
+    pub long_name_len: usize,
+    // 目录项在磁盘上的位置 (扇区号和扇区内偏移)，用于写回
+    pub entry_sector: usize,
+    pub entry_offset: usize,
+    // 所在目录的首簇、目录项序号和它前面的长文件名目录项个数，用于删除
+    pub dir_cluster: u32,
+    pub entry_index: usize,
+    pub lfn_entries: usize,
 }
 
 impl FileInfo {
-    pub fn new() -> Self {
+    pub const fn new() -> Self {
         Self {
             name: [0; 11],
             attributes: 0,
+            case_flags: 0,
             size: 0,
             first_cluster: 0,
             file_type: FileType::Unknown,
+            created: FatDateTime::new(),
+            modified: FatDateTime::new(),
+            long_name: [0; MAX_NAME_LEN],
+            long_name_len: 0,
+            entry_sector: 0,
+            entry_offset: 0,
+            dir_cluster: 0,
+            entry_index: 0,
+            lfn_entries: 0,
+        }
+    }
+
+    // 根目录没有目录项，用首簇0表示
+    pub const fn root() -> Self {
+        let mut file_info = Self::new();
+        file_info.name = *b"/          ";
+        file_info.attributes = ATTR_DIRECTORY;
+        file_info.file_type = FileType::Directory;
+        file_info.long_name[0] = b'/';
+        file_info.long_name_len = 1;
+        file_info
+    }
+
+    // 解码32字节的短文件名目录项
+    pub fn from_bytes(entry: &[u8]) -> Self {
+        let read_u16 = |offset: usize| u16::from_le_bytes([entry[offset], entry[offset + 1]]);
+
+        let mut name = [0u8; 11];
+        name.copy_from_slice(&entry[..11]);
+        if name[0] == ENTRY_KANJI_E5 {
+            name[0] = 0xE5;
+        }
+        let attributes = entry[11];
+        let mut file_info = Self {
+            name,
+            attributes,
+            case_flags: entry[12],
+            size: u32::from_le_bytes([entry[28], entry[29], entry[30], entry[31]]),
+            // 首簇高16位在偏移20 (FAT32)
+            first_cluster: ((read_u16(20) as u32) << 16) | read_u16(26) as u32,
+            file_type: if attributes & ATTR_DIRECTORY != 0 {
+                FileType::Directory
+            } else if attributes & ATTR_VOLUME_ID == 0 {
+                FileType::Regular
+            } else {
+                FileType::Unknown
+            },
+            created: FatDateTime {
+                date: read_u16(16),
+                time: read_u16(14),
+            },
+            modified: FatDateTime {
+                date: read_u16(24),
+                time: read_u16(22),
+            },
+            long_name: [0; MAX_NAME_LEN],
+            long_name_len: 0,
+            entry_sector: 0,
+            entry_offset: 0,
+            dir_cluster: 0,
+            entry_index: 0,
+            lfn_entries: 0,
+        };
+        file_info.set_short_display_name();
+        file_info
+    }
+
+    // 显示名称设为8.3名称，按NT标志转换大小写
+    fn set_short_display_name(&mut self) {
+        let base_len = self.name[..8].iter().take_while(|&&c| c != b' ').count();
+        let mut name = [0u8; 12];
+        let len = self.format_name(&mut name);
+        for (i, c) in name[..len].iter_mut().enumerate() {
+            let flag = if i < base_len {
+                NT_LOWER_BASE
+            } else {
+                NT_LOWER_EXT
+            };
+            if self.case_flags & flag != 0 {
+                c.make_ascii_lowercase();
+            }
+        }
+        self.long_name[..len].copy_from_slice(&name[..len]);
+        self.long_name_len = len;
+    }
+
+    // 把UCS-2长文件名转换为UTF-8，太长时保留8.3名称并返回false
+    fn set_long_name(&mut self, chars: &[u16]) -> bool {
+        let mut len = 0;
+        for c in core::char::decode_utf16(chars.iter().copied()) {
+            let c = c.unwrap_or(core::char::REPLACEMENT_CHARACTER);
+            if len + c.len_utf8() > MAX_NAME_LEN {
+                return false;
+            }
+            c.encode_utf8(&mut self.long_name[len..]);
+            len += c.len_utf8();
+        }
+        if len == 0 {
+            return false;
+        }
+        self.long_name_len = len;
+        true
+    }
+
+    // 显示用的名称
+    pub fn name_str(&self) -> &str {
+        core::str::from_utf8(&self.long_name[..self.long_name_len]).unwrap_or("???")
+    }
+
+    // 编码为32字节目录项，保留项中其他字段不变
+    pub fn to_bytes(&self, entry: &mut [u8]) {
+        entry[..11].copy_from_slice(&self.name);
+        if entry[0] == 0xE5 {
+            entry[0] = ENTRY_KANJI_E5;
         }
+        entry[11] = self.attributes;
+        entry[12] = self.case_flags;
+        entry[14..16].copy_from_slice(&self.created.time.to_le_bytes());
+        entry[16..18].copy_from_slice(&self.created.date.to_le_bytes());
+        entry[22..24].copy_from_slice(&self.modified.time.to_le_bytes());
+        entry[24..26].copy_from_slice(&self.modified.date.to_le_bytes());
+        entry[20..22].copy_from_slice(&((self.first_cluster >> 16) as u16).to_le_bytes());
+        entry[26..28].copy_from_slice(&(self.first_cluster as u16).to_le_bytes());
+        entry[28..32].copy_from_slice(&self.size.to_le_bytes());
     }
 
     pub fn is_directory(&self) -> bool {
@@ -56,6 +393,24 @@ impl FileInfo {
     pub fn is_regular_file(&self) -> bool {
         !self.is_directory() && (self.attributes & ATTR_VOLUME_ID) == 0
     }
+
+    // 把8.3名称格式化为 "NAME.EXT"，返回长度 (out至少12字节)
+    pub fn format_name(&self, out: &mut [u8]) -> usize {
+        let mut len = 0;
+        for &c in self.name[..8].iter().take_while(|&&c| c != b' ') {
+            out[len] = c;
+            len += 1;
+        }
+        if self.name[8] != b' ' {
+            out[len] = b'.';
+            len += 1;
+            for &c in self.name[8..].iter().take_while(|&&c| c != b' ') {
+                out[len] = c;
+                len += 1;
+            }
+        }
+        len
+    }
 }
 
 // 文件句柄
@@ -63,6 +418,11 @@ pub struct FileHandle {
     pub file_info: FileInfo,
     pub current_position: usize,
     pub open: bool,
+    // 最近访问的簇及其在簇链中的序号，顺序读取时不必从头遍历
+    cluster: u32,
+    cluster_index: usize,
+    // 大小或簇链已修改，关闭或flush时写回目录项
+    dirty: bool,
 }
 
 impl FileHandle {
@@ -71,50 +431,154 @@ impl FileHandle {
             file_info: FileInfo::new(),
             current_position: 0,
             open: false,
+            cluster: 0,
+            cluster_index: 0,
+            dirty: false,
+        }
+    }
+}
+
+// 目录句柄：按目录项序号顺序读取
+pub struct DirHandle {
+    pub first_cluster: u32, // 目录首簇，0表示根目录
+    index: usize,           // 下一个目录项的序号
+    // index所在的簇及其在簇链中的序号
+    cluster: u32,
+    cluster_index: usize,
+    // 已读入buffer的扇区号，0表示尚未读取
+    sector: usize,
+    buffer: [u8; BLOCK_SIZE],
+    end: bool,
+    // 正在拼接的长文件名：已读到的目录项数、下一个期望的序号和校验和
+    lfn: [u16; LFN_MAX_ENTRIES * LFN_CHARS_PER_ENTRY],
+    lfn_count: usize,
+    lfn_next: usize,
+    lfn_checksum: u8,
+}
+
+impl DirHandle {
+    pub fn new(first_cluster: u32) -> Self {
+        Self {
+            first_cluster,
+            index: 0,
+            cluster: 0,
+            cluster_index: 0,
+            sector: 0,
+            buffer: [0; BLOCK_SIZE],
+            end: false,
+            lfn: [0; LFN_MAX_ENTRIES * LFN_CHARS_PER_ENTRY],
+            lfn_count: 0,
+            lfn_next: 0,
+            lfn_checksum: 0,
+        }
+    }
+
+    // 移动到第index个目录项
+    fn seek(&mut self, index: usize) {
+        self.index = index;
+        self.end = false;
+        self.lfn_count = 0;
+    }
+
+    // 收集一个长文件名目录项，序号从大到小排列，最后一项 (0x40) 在最前
+    fn push_lfn(&mut self, entry: &[u8]) {
+        let order = (entry[0] & !LFN_LAST_ENTRY) as usize;
+        if order == 0 || order > LFN_MAX_ENTRIES {
+            self.lfn_count = 0;
+            return;
+        }
+        if entry[0] & LFN_LAST_ENTRY != 0 {
+            self.lfn_count = order;
+            self.lfn_checksum = entry[13];
+        } else if self.lfn_count == 0 || order != self.lfn_next || entry[13] != self.lfn_checksum {
+            // 序列不完整或校验和不一致，丢弃
+            self.lfn_count = 0;
+            return;
+        }
+
+        let start = (order - 1) * LFN_CHARS_PER_ENTRY;
+        for (i, &offset) in LFN_CHAR_OFFSETS.iter().enumerate() {
+            self.lfn[start + i] = u16::from_le_bytes([entry[offset], entry[offset + 1]]);
+        }
+        self.lfn_next = order - 1;
+    }
+
+    // 取出属于短文件名目录项short_name的长文件名：(UCS-2字符, 目录项个数)
+    fn take_lfn(&mut self, short_name: &[u8]) -> Option<(&[u16], usize)> {
+        let count = core::mem::take(&mut self.lfn_count);
+        if count == 0 || self.lfn_next != 0 || lfn_checksum(short_name) != self.lfn_checksum {
+            return None;
         }
+        let chars = &self.lfn[..count * LFN_CHARS_PER_ENTRY];
+        let len = chars
+            .iter()
+            .position(|&c| c == 0x0000 || c == 0xFFFF)
+            .unwrap_or(chars.len());
+        Some((&chars[..len], count))
     }
 }
 
-// FAT文件系统
-pub struct FatFileSystem {
+// FAT文件系统，挂载在一个块设备上
+pub struct FatFileSystem<'a> {
+    device: &'a dyn BlockDevice,
     pub boot_sector: [u8; BOOT_SECTOR_SIZE],
-    pub fat_table: [u16; 1024], // 简化的FAT表
-    pub root_directory: [FileInfo; ROOT_DIR_ENTRIES],
+    fat_table: *mut u8, // 内存中的FAT，按卷的簇数分配
+    fat_table_size: usize,
+    fat_dirty: Option<(usize, usize)>, // 已修改的FAT扇区范围
+    next_free: usize,                  // 下次分配簇时开始查找的位置
+    free_count: Option<usize>,         // 空闲簇数，未知时为None
+    fsinfo_sector: usize,              // FAT32 FSInfo扇区，0表示没有
     pub initialized: bool,
+    pub fat_type: FatType,
+    pub root_cluster: u32, // FAT32根目录首簇，FAT12/16为0
     pub total_sectors: usize,
     pub sectors_per_cluster: usize,
-    pub fat_sectors: usize,
+    pub reserved_sectors: usize,
+    pub num_fats: usize,
+    pub root_entry_count: usize,
+    pub fat_sectors: usize, // 每个FAT副本的扇区数
     pub root_dir_sectors: usize,
     pub data_sectors: usize,
+    pub cluster_count: usize,
 }
 
-impl FatFileSystem {
+impl<'a> FatFileSystem<'a> {
     pub const fn new() -> Self {
         Self {
+            device: &NullDevice,
             boot_sector: [0; BOOT_SECTOR_SIZE],
-            fat_table: [0; 1024],
-            root_directory: [FileInfo {
-                name: [0; 11],
-                attributes: 0,
-                size: 0,
-                first_cluster: 0,
-                file_type: FileType::Unknown,
-            }; ROOT_DIR_ENTRIES],
+            fat_table: core::ptr::null_mut(),
+            fat_table_size: 0,
+            fat_dirty: None,
+            next_free: 2,
+            free_count: None,
+            fsinfo_sector: 0,
             initialized: false,
+            fat_type: FatType::Fat16,
+            root_cluster: 0,
             total_sectors: 0,
             sectors_per_cluster: 0,
+            reserved_sectors: 0,
+            num_fats: 0,
+            root_entry_count: 0,
             fat_sectors: 0,
             root_dir_sectors: 0,
             data_sectors: 0,
+            cluster_count: 0,
         }
     }
 
-    // 初始化文件系统
-    pub fn init(&mut self) -> bool {
+    // 在块设备上挂载文件系统
+    pub fn mount(&mut self, device: &'a dyn BlockDevice) -> bool {
         uart::println("Initializing FAT file system...");
+        if device.block_size() != BLOCK_SIZE {
+            uart::println("Unsupported block size");
+            return false;
+        }
+        self.device = device;
 
         // 读取引导扇区
-        if read_disk_block(0, &mut self.boot_sector) != DiskResult::Success {
+        if self.device.read_blocks(0, &mut self.boot_sector) != DiskResult::Success {
             uart::println("Failed to read boot sector");
             return false;
         }
@@ -131,6 +595,9 @@ impl FatFileSystem {
             return false;
         }
 
+        // FAT32的空闲簇提示
+        self.load_fsinfo();
+
         // 读取根目录
         if !self.load_root_directory() {
             uart::println("Failed to load root directory");
@@ -142,7 +609,15 @@ impl FatFileSystem {
         true
     }
 
-    // 解析引导扇区
+    fn bpb_u16(&self, offset: usize) -> usize {
+        u16::from_le_bytes([self.boot_sector[offset], self.boot_sector[offset + 1]]) as usize
+    }
+
+    fn bpb_u32(&self, offset: usize) -> usize {
+        read_u32(&self.boot_sector, offset) as usize
+    }
+
+    // 解析引导扇区中的BPB (BIOS参数块)
     fn parse_boot_sector(&mut self) -> bool {
         // 检查FAT签名
         let signature = u16::from_le_bytes([self.boot_sector[510], self.boot_sector[511]]);
@@ -152,13 +627,81 @@ impl FatFileSystem {
         }
 
         // 解析文件系统参数
-        self.total_sectors =
-            u16::from_le_bytes([self.boot_sector[19], self.boot_sector[20]]) as usize;
+        let bytes_per_sector = self.bpb_u16(11);
+        if bytes_per_sector != BLOCK_SIZE {
+            uart::println("Unsupported sector size");
+            return false;
+        }
         self.sectors_per_cluster = self.boot_sector[13] as usize;
-        self.fat_sectors =
-            u16::from_le_bytes([self.boot_sector[22], self.boot_sector[23]]) as usize;
-        self.root_dir_sectors = ((ROOT_DIR_ENTRIES * DIR_ENTRY_SIZE) + BLOCK_SIZE - 1) / BLOCK_SIZE;
-        self.data_sectors = self.total_sectors - 1 - self.fat_sectors - self.root_dir_sectors;
+        self.reserved_sectors = self.bpb_u16(14);
+        self.num_fats = self.boot_sector[16] as usize;
+        self.root_entry_count = self.bpb_u16(17);
+        // 16位字段为0时，扇区总数和FAT大小在32位字段中 (偏移32、36)
+        self.total_sectors = match self.bpb_u16(19) {
+            0 => self.bpb_u32(32),
+            count => count,
+        };
+        self.fat_sectors = match self.bpb_u16(22) {
+            0 => self.bpb_u32(36),
+            count => count,
+        };
+        if self.sectors_per_cluster == 0
+            || !self.sectors_per_cluster.is_power_of_two()
+            || self.reserved_sectors == 0
+            || self.num_fats == 0
+            || self.fat_sectors == 0
+        {
+            uart::println("Invalid BIOS parameter block");
+            return false;
+        }
+
+        self.root_dir_sectors = (self.root_entry_count * DIR_ENTRY_SIZE).div_ceil(BLOCK_SIZE);
+        let data_start = self.data_start();
+        if data_start >= self.total_sectors {
+            uart::println("Invalid BIOS parameter block");
+            return false;
+        }
+        self.data_sectors = self.total_sectors - data_start;
+        self.cluster_count = self.data_sectors / self.sectors_per_cluster;
+        if self.cluster_count == 0 {
+            uart::println("Volume has no data clusters");
+            return false;
+        }
+
+        // FAT类型只由簇数决定
+        self.fat_type = if self.cluster_count < FAT12_MAX_CLUSTERS {
+            FatType::Fat12
+        } else if self.cluster_count < FAT16_MAX_CLUSTERS {
+            FatType::Fat16
+        } else {
+            FatType::Fat32
+        };
+        if self.fat_type == FatType::Fat32 {
+            // FAT32的根目录是从root_cluster开始的簇链
+            self.root_cluster = self.bpb_u32(44) as u32;
+            self.fsinfo_sector = self.bpb_u16(48);
+            let root = self.root_cluster as usize;
+            if self.root_entry_count != 0 || root < 2 || root >= self.cluster_count + 2 {
+                uart::println("Invalid FAT32 root directory");
+                return false;
+            }
+        } else {
+            self.root_cluster = 0;
+            self.fsinfo_sector = 0;
+            if self.root_entry_count == 0 {
+                uart::println("Invalid BIOS parameter block");
+                return false;
+            }
+        }
+
+        // FAT表必须能容纳所有簇 (加上两个保留项)
+        if self.fat_type.table_size(self.cluster_count + 2) > self.fat_sectors * BLOCK_SIZE {
+            uart::println("FAT too small for volume");
+            return false;
+        }
+
+        uart::print("FAT type: ");
+        uart::println(self.fat_type.as_str());
 
         uart::print("Total sectors: ");
         uart::println(&self.total_sectors.to_string());
@@ -168,172 +711,1681 @@ impl FatFileSystem {
         true
     }
 
-    // 加载FAT表
-    fn load_fat_table(&mut self) -> bool {
-        uart::println("Loading FAT table...");
+    // 第一个FAT副本的起始扇区
+    fn fat_start(&self) -> usize {
+        self.reserved_sectors
+    }
 
-        // 简化实现，不实际读取FAT表
-        for i in 0..self.fat_table.len() {
-            self.fat_table[i] = 0xFFFF; // 标记为未使用
-        }
+    // 根目录区的起始扇区 (紧跟在所有FAT副本之后)
+    fn root_dir_start(&self) -> usize {
+        self.reserved_sectors + self.num_fats * self.fat_sectors
+    }
 
-        uart::println("FAT table loaded");
-        true
+    // 数据区的起始扇区
+    fn data_start(&self) -> usize {
+        self.root_dir_start() + self.root_dir_sectors
     }
 
-    // 加载根目录
-    fn load_root_directory(&mut self) -> bool {
-        uart::println("Loading root directory...");
+    // 内存中的FAT表
+    fn fat_bytes(&self) -> &[u8] {
+        if self.fat_table.is_null() {
+            return &[];
+        }
+        unsafe { core::slice::from_raw_parts(self.fat_table, self.fat_table_size) }
+    }
 
-        // 简化实现，创建一些示例文件
-        let mut file_info = FileInfo::new();
-        file_info.name = [
-            b'H', b'E', b'L', b'L', b'O', b' ', b' ', b' ', b'T', b'X', b'T',
-        ];
-        file_info.attributes = ATTR_ARCHIVE;
-        file_info.size = 13;
-        file_info.first_cluster = 2;
-        file_info.file_type = FileType::Regular;
-        self.root_directory[0] = file_info;
-
-        let mut dir_info = FileInfo::new();
-        dir_info.name = [
-            b'T', b'E', b'S', b'T', b' ', b' ', b' ', b' ', b'D', b'I', b'R',
-        ];
-        dir_info.attributes = ATTR_DIRECTORY;
-        dir_info.size = 0;
-        dir_info.first_cluster = 3;
-        dir_info.file_type = FileType::Directory;
-        self.root_directory[1] = dir_info;
-
-        uart::println("Root directory loaded");
-        true
+    fn fat_bytes_mut(&mut self) -> &mut [u8] {
+        if self.fat_table.is_null() {
+            return &mut [];
+        }
+        unsafe { core::slice::from_raw_parts_mut(self.fat_table, self.fat_table_size) }
     }
 
-    // 列出根目录
-    pub fn list_directory(&self) {
-        uart::println("Directory listing:");
+    // 读取FAT表项
+    fn fat_entry(&self, cluster: usize) -> u32 {
+        let (offset, width) = self.fat_type.entry_position(cluster);
+        let table = self.fat_bytes();
+        if offset + width > table.len() {
+            return 0;
+        }
+        match self.fat_type {
+            FatType::Fat12 => {
+                // 奇数项在高12位，偶数项在低12位
+                let value = u16::from_le_bytes([table[offset], table[offset + 1]]) as u32;
+                if cluster & 1 == 1 {
+                    value >> 4
+                } else {
+                    value & 0xFFF
+                }
+            }
+            FatType::Fat16 => u16::from_le_bytes([table[offset], table[offset + 1]]) as u32,
+            FatType::Fat32 => read_u32(table, offset) & FAT32_ENTRY_MASK,
+        }
+    }
 
-        for (i, file_info) in self.root_directory.iter().enumerate() {
-            if file_info.name[0] != 0 && file_info.name[0] != 0xE5 {
-                uart::print("  ");
-                uart::print(&i.to_string());
-                uart::print(": ");
+    // 修改FAT表项，记录需要写回的扇区
+    fn set_fat_entry(&mut self, cluster: usize, value: u32) {
+        let fat_type = self.fat_type;
+        let (offset, width) = fat_type.entry_position(cluster);
+        let table = self.fat_bytes_mut();
+        if offset + width > table.len() {
+            return;
+        }
+        match fat_type {
+            FatType::Fat12 => {
+                // 与相邻表项共用一个字节，保留另一半
+                let old = u16::from_le_bytes([table[offset], table[offset + 1]]);
+                let value = (value & 0xFFF) as u16;
+                let new = if cluster & 1 == 1 {
+                    (old & 0x000F) | (value << 4)
+                } else {
+                    (old & 0xF000) | value
+                };
+                table[offset..offset + 2].copy_from_slice(&new.to_le_bytes());
+            }
+            FatType::Fat16 => {
+                table[offset..offset + 2].copy_from_slice(&(value as u16).to_le_bytes());
+            }
+            FatType::Fat32 => {
+                // 高4位保留
+                let old = read_u32(table, offset);
+                let new = (old & !FAT32_ENTRY_MASK) | (value & FAT32_ENTRY_MASK);
+                table[offset..offset + 4].copy_from_slice(&new.to_le_bytes());
+            }
+        }
 
-                // 打印文件名
-                let name_str = core::str::from_utf8(&file_info.name[..8]).unwrap_or("???");
-                uart::print(name_str);
+        let first = offset / BLOCK_SIZE;
+        let last = (offset + width - 1) / BLOCK_SIZE;
+        self.fat_dirty = Some(match self.fat_dirty {
+            Some((start, end)) => (start.min(first), end.max(last)),
+            None => (first, last),
+        });
+    }
 
-                if file_info.name[8] != b' ' {
-                    uart::put_char(b'.');
-                    let ext_str = core::str::from_utf8(&file_info.name[8..11]).unwrap_or("???");
-                    uart::print(ext_str);
+    // 把修改过的FAT扇区写入所有FAT副本
+    fn flush_fat(&mut self) -> Result<(), FsError> {
+        let (first, last) = match self.fat_dirty {
+            Some(range) => range,
+            None => return Ok(()),
+        };
+        let table = unsafe {
+            core::slice::from_raw_parts(
+                self.fat_table,
+                self.fat_table_size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE,
+            )
+        };
+        for copy in 0..self.num_fats {
+            let start = self.fat_start() + copy * self.fat_sectors;
+            for i in first..=last {
+                let sector = &table[i * BLOCK_SIZE..(i + 1) * BLOCK_SIZE];
+                if self.device.write_blocks(start + i, sector) != DiskResult::Success {
+                    return Err(FsError::IoError);
                 }
+            }
+        }
+        self.fat_dirty = None;
+        self.write_fsinfo()
+    }
+
+    // 写出修改过的FAT，再让块设备 (和块缓存) 把数据落盘
+    pub fn sync(&mut self) -> Result<(), FsError> {
+        self.flush_fat()?;
+        match self.device.flush() {
+            DiskResult::Success => Ok(()),
+            _ => Err(FsError::IoError),
+        }
+    }
 
-                uart::print(" (");
-                uart::print(&file_info.size.to_string());
-                uart::print(" bytes, ");
+    // 读取FAT32 FSInfo中的空闲簇数和下一个空闲簇提示
+    fn load_fsinfo(&mut self) {
+        if self.fsinfo_sector == 0 {
+            return;
+        }
+        let mut sector = [0u8; BLOCK_SIZE];
+        if self.device.read_blocks(self.fsinfo_sector, &mut sector) != DiskResult::Success
+            || !fsinfo_valid(&sector)
+        {
+            uart::println("Warning: invalid FSInfo sector");
+            self.fsinfo_sector = 0;
+            return;
+        }
 
-                if file_info.is_directory() {
-                    uart::print("DIR");
-                } else {
-                    uart::print("FILE");
-                }
+        // 提示可能过时，只在合理范围内采用
+        let free_count = read_u32(&sector, FSINFO_FREE_COUNT);
+        if free_count != FSINFO_UNKNOWN && free_count as usize <= self.cluster_count {
+            self.free_count = Some(free_count as usize);
+        }
+        let next_free = read_u32(&sector, FSINFO_NEXT_FREE) as usize;
+        if (2..self.cluster_count + 2).contains(&next_free) {
+            self.next_free = next_free;
+        }
+    }
 
-                uart::println(")");
-            }
+    // 把空闲簇数和下一个空闲簇写回FSInfo
+    fn write_fsinfo(&self) -> Result<(), FsError> {
+        if self.fsinfo_sector == 0 {
+            return Ok(());
+        }
+        let mut sector = [0u8; BLOCK_SIZE];
+        if self.device.read_blocks(self.fsinfo_sector, &mut sector) != DiskResult::Success {
+            return Err(FsError::IoError);
+        }
+        if !fsinfo_valid(&sector) {
+            return Ok(());
+        }
+        let free_count = self.free_count.map_or(FSINFO_UNKNOWN, |count| count as u32);
+        sector[FSINFO_FREE_COUNT..FSINFO_FREE_COUNT + 4].copy_from_slice(&free_count.to_le_bytes());
+        sector[FSINFO_NEXT_FREE..FSINFO_NEXT_FREE + 4]
+            .copy_from_slice(&(self.next_free as u32).to_le_bytes());
+        if self.device.write_blocks(self.fsinfo_sector, &sector) != DiskResult::Success {
+            return Err(FsError::IoError);
         }
+        Ok(())
     }
 
-    // 打开文件
-    pub fn open_file(&self, filename: &str) -> Option<FileHandle> {
-        uart::print("Opening file: ");
-        uart::println(filename);
+    // 分配一个空闲簇并标记为链尾，prev不为0时把它接到prev之后
+    fn allocate_cluster(&mut self, prev: u32) -> Result<u32, FsError> {
+        let total = self.cluster_count;
+        let start = self.next_free.clamp(2, total + 1);
+        let cluster = (0..total)
+            .map(|i| 2 + (start - 2 + i) % total)
+            .find(|&cluster| self.fat_entry(cluster) == 0)
+            .ok_or(FsError::NoSpace)?;
 
-        // 在根目录中查找文件
-        for file_info in &self.root_directory {
-            if file_info.name[0] != 0 && file_info.name[0] != 0xE5 {
-                // 比较文件名
-                let name_str = core::str::from_utf8(&file_info.name[..8]).unwrap_or("");
-                if name_str == filename {
-                    let mut handle = FileHandle::new();
-                    handle.file_info = *file_info;
-                    handle.open = true;
-                    uart::println("File opened successfully");
-                    return Some(handle);
-                }
-            }
+        self.set_fat_entry(cluster, self.fat_type.mask());
+        if prev >= 2 {
+            self.set_fat_entry(prev as usize, cluster as u32);
         }
-
-        uart::println("File not found");
-        None
+        self.next_free = cluster + 1;
+        if let Some(count) = self.free_count.as_mut() {
+            *count = count.saturating_sub(1);
+        }
+        Ok(cluster as u32)
     }
 
-    // 读取文件
-    pub fn read_file(&self, handle: &mut FileHandle, buffer: &mut [u8]) -> usize {
-        if !handle.open {
-            return 0;
+    // 释放从start开始的整条簇链
+    fn free_chain(&mut self, start: u32) {
+        let mut cluster = start;
+        for _ in 0..self.cluster_count {
+            if cluster < 2 {
+                break;
+            }
+            let next = self.next_cluster(cluster).unwrap_or(0);
+            self.set_fat_entry(cluster as usize, 0);
+            self.next_free = self.next_free.min(cluster as usize);
+            if let Some(count) = self.free_count.as_mut() {
+                *count += 1;
+            }
+            cluster = next;
         }
+    }
 
-        let remaining = handle.file_info.size as usize - handle.current_position;
-        let to_read = if buffer.len() < remaining {
-            buffer.len()
+    // 簇链中的下一个簇，链结束或遇到坏簇时返回None
+    fn next_cluster(&self, cluster: u32) -> Option<u32> {
+        let next = self.fat_entry(cluster as usize);
+        if next < 2
+            || next >= self.fat_type.bad_cluster()
+            || next as usize >= self.cluster_count + 2
+        {
+            None
         } else {
-            remaining
-        };
+            Some(next)
+        }
+    }
 
-        if to_read == 0 {
-            return 0;
+    // 簇的第一个扇区
+    // 簇号从2开始，损坏的目录项或FAT中超出范围的簇号返回Corrupted
+    fn cluster_to_sector(&self, cluster: u32) -> Result<usize, FsError> {
+        let index = (cluster as usize)
+            .checked_sub(2)
+            .filter(|&index| index < self.cluster_count)
+            .ok_or(FsError::Corrupted)?;
+        Ok(self.data_start() + index * self.sectors_per_cluster)
+    }
+
+    fn cluster_size(&self) -> usize {
+        self.sectors_per_cluster * BLOCK_SIZE
+    }
+
+    // 找到文件第index个簇，从句柄缓存的位置或文件开头沿簇链前进
+    fn cluster_at(&self, handle: &mut FileHandle, index: usize) -> Result<u32, FsError> {
+        if handle.cluster == 0 || handle.cluster_index > index {
+            handle.cluster = handle.file_info.first_cluster;
+            handle.cluster_index = 0;
+        }
+        if handle.cluster < 2 {
+            return Err(FsError::Corrupted);
+        }
+        while handle.cluster_index < index {
+            handle.cluster = self
+                .next_cluster(handle.cluster)
+                .ok_or(FsError::Corrupted)?;
+            handle.cluster_index += 1;
         }
+        Ok(handle.cluster)
+    }
+
+    // 找到写入位置所在的簇，簇链不够长时分配新簇
+    fn cluster_for_write(&mut self, handle: &mut FileHandle, index: usize) -> Result<u32, FsError> {
+        if handle.file_info.first_cluster == 0 {
+            let cluster = self.allocate_cluster(0)?;
+            handle.file_info.first_cluster = cluster;
+            handle.cluster = cluster;
+            handle.cluster_index = 0;
+            handle.dirty = true;
+        }
+        if handle.cluster == 0 || handle.cluster_index > index {
+            handle.cluster = handle.file_info.first_cluster;
+            handle.cluster_index = 0;
+        }
+        while handle.cluster_index < index {
+            handle.cluster = match self.next_cluster(handle.cluster) {
+                Some(next) => next,
+                None => self.allocate_cluster(handle.cluster)?,
+            };
+            handle.cluster_index += 1;
+        }
+        Ok(handle.cluster)
+    }
+
+    // 加载FAT表：读取第一个可读的FAT副本
+    fn load_fat_table(&mut self) -> bool {
+        uart::println("Loading FAT table...");
 
-        uart::print("Reading ");
-        uart::print(&to_read.to_string());
-        uart::print(" bytes from file...");
+        // 只需要覆盖实际存在的簇
+        let size = self.fat_type.table_size(self.cluster_count + 2);
+        let sectors = size.div_ceil(BLOCK_SIZE);
+        let buffer = match memory::allocate(sectors * BLOCK_SIZE) {
+            Some(buffer) => buffer,
+            None => {
+                uart::println("Out of memory for FAT table");
+                return false;
+            }
+        };
+        let table = unsafe { core::slice::from_raw_parts_mut(buffer, sectors * BLOCK_SIZE) };
 
-        // 简化实现，不实际读取文件内容
-        for i in 0..to_read {
-            buffer[i] = b'A' + (i % 26) as u8;
+        let loaded = (0..self.num_fats).any(|copy| {
+            let start = self.fat_start() + copy * self.fat_sectors;
+            self.device.read_blocks(start, table) == DiskResult::Success
+        });
+        if !loaded {
+            uart::println("Failed to read any FAT copy");
+            memory::deallocate(buffer, sectors * BLOCK_SIZE);
+            return false;
         }
+        self.fat_table = buffer;
+        self.fat_table_size = size;
 
-        handle.current_position += to_read;
-        uart::println(" done");
+        // FAT[0] 低字节是介质描述符，其余位全为1
+        let mask = self.fat_type.mask();
+        let media = self.boot_sector[21] as u32;
+        if self.fat_entry(0) != (mask & !0xFF) | media {
+            uart::println("FAT[0] does not match media descriptor");
+            self.release_fat_table();
+            return false;
+        }
+        // FAT[1] 是簇链结束标记，FAT16/32的最高两位记录卷状态
+        let entry1 = self.fat_entry(1);
+        let (clean, no_errors) = self.fat_type.status_flags();
+        if (entry1 | clean | no_errors) <= self.fat_type.bad_cluster() {
+            uart::println("FAT[1] is not an end-of-chain marker");
+            self.release_fat_table();
+            return false;
+        }
+        if clean != 0 && entry1 & clean == 0 {
+            uart::println("Warning: volume was not cleanly unmounted");
+        }
+        if no_errors != 0 && entry1 & no_errors == 0 {
+            uart::println("Warning: volume has recorded disk errors");
+        }
 
-        to_read
+        uart::print("FAT table loaded: ");
+        uart::print_dec(self.cluster_count);
+        uart::println(" clusters");
+        true
     }
 
-    // 关闭文件
-    pub fn close_file(&mut self, handle: &mut FileHandle) {
-        if handle.open {
-            handle.open = false;
-            uart::println("File closed");
+    // 释放内存中的FAT表，分配时按整扇区取整
+    fn release_fat_table(&mut self) {
+        if !self.fat_table.is_null() {
+            memory::deallocate(
+                self.fat_table,
+                self.fat_table_size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE,
+            );
         }
+        self.fat_table = core::ptr::null_mut();
+        self.fat_table_size = 0;
     }
-}
 
-// 全局文件系统
-pub static mut FS: FatFileSystem = FatFileSystem::new();
+    // 检查根目录可读
+    fn load_root_directory(&mut self) -> bool {
+        uart::println("Loading root directory...");
 
-// 全局函数
-pub fn init_filesystem() -> bool {
-    unsafe { FS.init() }
-}
+        let start = if self.root_cluster == 0 {
+            self.root_dir_start()
+        } else {
+            match self.cluster_to_sector(self.root_cluster) {
+                Ok(sector) => sector,
+                Err(_) => return false,
+            }
+        };
+        let mut sector = [0u8; BLOCK_SIZE];
+        if self.device.read_blocks(start, &mut sector) != DiskResult::Success {
+            return false;
+        }
 
-pub fn list_files() {
-    unsafe {
-        FS.list_directory();
+        if self.root_cluster == 0 {
+            uart::print("Root directory loaded: ");
+            uart::print_dec(self.root_entry_count);
+            uart::println(" entries");
+        } else {
+            uart::print("Root directory loaded: cluster ");
+            uart::print_dec(self.root_cluster as usize);
+            uart::println("");
+        }
+        true
     }
-}
 
-pub fn open_file(filename: &str) -> Option<FileHandle> {
-    unsafe { FS.open_file(filename) }
-}
+    // 目录中第index个目录项的位置 (扇区号, 扇区内偏移)，目录结束时返回None
+    // 目录项所在扇区被读入dir.buffer
+    fn dir_slot(&self, dir: &mut DirHandle) -> Result<Option<(usize, usize)>, FsError> {
+        if dir.end {
+            return Ok(None);
+        }
+        let entries_per_sector = BLOCK_SIZE / DIR_ENTRY_SIZE;
+        let sector_index = dir.index / entries_per_sector;
+        // FAT32的根目录也是簇链
+        let chain = if dir.first_cluster == 0 {
+            self.root_cluster
+        } else {
+            dir.first_cluster
+        };
+        let sector = if chain == 0 {
+            // FAT12/16根目录：固定大小的连续区域
+            if dir.index >= self.root_entry_count {
+                dir.end = true;
+                return Ok(None);
+            }
+            self.root_dir_start() + sector_index
+        } else {
+            // 子目录：沿簇链前进
+            let index = sector_index / self.sectors_per_cluster;
+            if dir.cluster == 0 || dir.cluster_index > index {
+                dir.cluster = chain;
+                dir.cluster_index = 0;
+            }
+            while dir.cluster_index < index {
+                match self.next_cluster(dir.cluster) {
+                    Some(next) => {
+                        dir.cluster = next;
+                        dir.cluster_index += 1;
+                    }
+                    None => {
+                        dir.end = true;
+                        return Ok(None);
+                    }
+                }
+            }
+            self.cluster_to_sector(dir.cluster)? + sector_index % self.sectors_per_cluster
+        };
 
-pub fn read_file(handle: &mut FileHandle, buffer: &mut [u8]) -> usize {
-    unsafe { FS.read_file(handle, buffer) }
-}
+        if dir.sector != sector {
+            if self.device.read_blocks(sector, &mut dir.buffer) != DiskResult::Success {
+                return Err(FsError::IoError);
+            }
+            dir.sector = sector;
+        }
+        let offset = (dir.index % entries_per_sector) * DIR_ENTRY_SIZE;
+        dir.index += 1;
+        Ok(Some((sector, offset)))
+    }
+
+    // 读取下一个目录项，目录结束时返回None
+    // 拼接前面的长文件名，跳过已删除项和卷标，遇到结束标记时停止
+    pub fn readdir(&self, dir: &mut DirHandle) -> Result<Option<FileInfo>, FsError> {
+        while let Some((sector, offset)) = self.dir_slot(dir)? {
+            let mut entry = [0u8; DIR_ENTRY_SIZE];
+            entry.copy_from_slice(&dir.buffer[offset..offset + DIR_ENTRY_SIZE]);
+            if entry[0] == ENTRY_END {
+                dir.end = true;
+                break;
+            }
+
+            let attributes = entry[11];
+            if entry[0] == ENTRY_DELETED {
+                dir.lfn_count = 0;
+                continue;
+            }
+            if attributes & ATTR_LONG_NAME == ATTR_LONG_NAME {
+                dir.push_lfn(&entry);
+                continue;
+            }
+            if attributes & ATTR_VOLUME_ID != 0 {
+                dir.lfn_count = 0;
+                continue;
+            }
+
+            let mut file_info = FileInfo::from_bytes(&entry);
+            file_info.entry_sector = sector;
+            file_info.entry_offset = offset;
+            file_info.dir_cluster = dir.first_cluster;
+            file_info.entry_index = dir.index - 1;
+            if let Some((chars, count)) = dir.take_lfn(&entry[..11]) {
+                file_info.set_long_name(chars);
+                file_info.lfn_entries = count;
+            }
+            return Ok(Some(file_info));
+        }
+        Ok(None)
+    }
+
+    // 逐个访问目录 (首簇为dir，0表示根目录) 中的文件和目录，f返回false时停止
+    pub fn for_each_entry(
+        &self,
+        dir: u32,
+        mut f: impl FnMut(&FileInfo) -> bool,
+    ) -> Result<(), FsError> {
+        let mut handle = DirHandle::new(dir);
+        while let Some(file_info) = self.readdir(&mut handle)? {
+            if !f(&file_info) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // 在目录中按名称查找，长文件名和8.3名称都可以匹配，不区分大小写
+    fn lookup(&self, dir: u32, component: &str) -> Result<Option<FileInfo>, FsError> {
+        let short_name = to_short_name(component).ok();
+        let mut handle = DirHandle::new(dir);
+        while let Some(file_info) = self.readdir(&mut handle)? {
+            if file_info.name_str().eq_ignore_ascii_case(component)
+                || short_name.is_some_and(|name| file_info.name.eq_ignore_ascii_case(&name))
+            {
+                return Ok(Some(file_info));
+            }
+        }
+        Ok(None)
+    }
+
+    // 目录中是否已有这个8.3名称
+    fn short_name_exists(&self, dir: u32, name: &[u8; 11]) -> Result<bool, FsError> {
+        let mut handle = DirHandle::new(dir);
+        while let Some(file_info) = self.readdir(&mut handle)? {
+            if &file_info.name == name {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    // 为长文件名生成目录中唯一的8.3别名，如 "Long File.txt" -> LONGFI~1.TXT
+    fn generate_alias(&self, dir: u32, name: &str) -> Result<[u8; 11], FsError> {
+        let (base, ext) = match name.rfind('.') {
+            Some(dot) if dot > 0 => (&name[..dot], &name[dot + 1..]),
+            _ => (name, ""),
+        };
+        let mut base_chars = [b'_'; 8];
+        let base_len = alias_chars(base, &mut base_chars).max(1);
+        let mut alias = [b' '; 11];
+        alias_chars(ext, &mut alias[8..]);
+
+        for n in 1..1_000_000usize {
+            // 序号尾巴 "~n"，基本名截短为它让出位置
+            let mut tail = [0u8; 8];
+            let mut tail_len = 0;
+            let mut value = n;
+            while value > 0 {
+                tail[7 - tail_len] = b'0' + (value % 10) as u8;
+                tail_len += 1;
+                value /= 10;
+            }
+            tail_len += 1;
+            tail[8 - tail_len] = b'~';
+
+            let keep = base_len.min(8 - tail_len);
+            alias[..8].fill(b' ');
+            alias[..keep].copy_from_slice(&base_chars[..keep]);
+            alias[keep..keep + tail_len].copy_from_slice(&tail[8 - tail_len..]);
+            if !self.short_name_exists(dir, &alias)? {
+                return Ok(alias);
+            }
+        }
+        Err(FsError::AlreadyExists)
+    }
+
+    // 在目录中找count个连续的空闲目录项 (已删除或结束标记)，返回第一项的序号
+    // 子目录空间不够时在末尾扩展新簇
+    fn find_free_run(&mut self, dir: u32, count: usize) -> Result<usize, FsError> {
+        let mut handle = DirHandle::new(dir);
+        let mut start = 0;
+        let mut run = 0;
+        while let Some((_, offset)) = self.dir_slot(&mut handle)? {
+            let first = handle.buffer[offset];
+            if first == ENTRY_END || first == ENTRY_DELETED {
+                if run == 0 {
+                    start = handle.index - 1;
+                }
+                run += 1;
+                if run == count {
+                    return Ok(start);
+                }
+            } else {
+                run = 0;
+            }
+        }
+        if dir == 0 && self.root_cluster == 0 {
+            return Err(FsError::NoSpace);
+        }
+
+        if run == 0 {
+            start = handle.index;
+        }
+        let entries_per_cluster = self.cluster_size() / DIR_ENTRY_SIZE;
+        let mut last = handle.cluster;
+        while run < count {
+            last = self.allocate_cluster(last)?;
+            self.zero_cluster(last)?;
+            run += entries_per_cluster;
+        }
+        self.flush_fat()?;
+        Ok(start)
+    }
+
+    // 写入目录中第index个目录项，返回它的位置 (扇区号, 扇区内偏移)
+    fn write_slot(
+        &self,
+        dir: &mut DirHandle,
+        index: usize,
+        entry: &[u8],
+    ) -> Result<(usize, usize), FsError> {
+        dir.seek(index);
+        let (sector, offset) = self.dir_slot(dir)?.ok_or(FsError::Corrupted)?;
+        dir.buffer[offset..offset + DIR_ENTRY_SIZE].copy_from_slice(entry);
+        if self.device.write_blocks(sector, &dir.buffer) != DiskResult::Success {
+            return Err(FsError::IoError);
+        }
+        Ok((sector, offset))
+    }
+
+    // 把簇清零 (新目录簇中的目录项都是结束标记)
+    fn zero_cluster(&self, cluster: u32) -> Result<(), FsError> {
+        let zeros = [0u8; BLOCK_SIZE];
+        let start = self.cluster_to_sector(cluster)?;
+        for i in 0..self.sectors_per_cluster {
+            if self.device.write_blocks(start + i, &zeros) != DiskResult::Success {
+                return Err(FsError::IoError);
+            }
+        }
+        Ok(())
+    }
+
+    // 把文件信息写回它的目录项
+    fn write_entry(&self, file_info: &FileInfo) -> Result<(), FsError> {
+        let mut sector = [0u8; BLOCK_SIZE];
+        if self.device.read_blocks(file_info.entry_sector, &mut sector) != DiskResult::Success {
+            return Err(FsError::IoError);
+        }
+        let offset = file_info.entry_offset;
+        file_info.to_bytes(&mut sector[offset..offset + DIR_ENTRY_SIZE]);
+        if self.device.write_blocks(file_info.entry_sector, &sector) != DiskResult::Success {
+            return Err(FsError::IoError);
+        }
+        Ok(())
+    }
+
+    // 把目录项和它前面的长文件名目录项标记为已删除
+    fn remove_entry(&self, file_info: &FileInfo) -> Result<(), FsError> {
+        let mut handle = DirHandle::new(file_info.dir_cluster);
+        let first = file_info.entry_index - file_info.lfn_entries;
+        for index in first..=file_info.entry_index {
+            handle.seek(index);
+            let (sector, offset) = self.dir_slot(&mut handle)?.ok_or(FsError::Corrupted)?;
+            handle.buffer[offset] = ENTRY_DELETED;
+            if self.device.write_blocks(sector, &handle.buffer) != DiskResult::Success {
+                return Err(FsError::IoError);
+            }
+        }
+        Ok(())
+    }
+
+    // 在目录dir中新建目录项，名称不是8.3格式时加上长文件名目录项
+    fn create_entry(
+        &mut self,
+        dir: u32,
+        name: &str,
+        attributes: u8,
+        first_cluster: u32,
+    ) -> Result<FileInfo, FsError> {
+        let mut chars = [0u16; LFN_MAX_CHARS];
+        let (short_name, case_flags, lfn_len) = match short_name_for(name) {
+            Some((short_name, case_flags)) => (short_name, case_flags, 0),
+            None => (
+                self.generate_alias(dir, name)?,
+                0,
+                encode_long_name(name, &mut chars)?,
+            ),
+        };
+        let lfn_entries = lfn_len.div_ceil(LFN_CHARS_PER_ENTRY);
+        let start = self.find_free_run(dir, lfn_entries + 1)?;
+
+        let now = FatDateTime::now();
+        let mut file_info = FileInfo::new();
+        file_info.name = short_name;
+        file_info.attributes = attributes;
+        file_info.case_flags = case_flags;
+        file_info.first_cluster = first_cluster;
+        file_info.file_type = if attributes & ATTR_DIRECTORY != 0 {
+            FileType::Directory
+        } else {
+            FileType::Regular
+        };
+        file_info.created = now;
+        file_info.modified = now;
+        file_info.dir_cluster = dir;
+        file_info.entry_index = start + lfn_entries;
+        file_info.lfn_entries = lfn_entries;
+        if lfn_entries == 0 || !file_info.set_long_name(&chars[..lfn_len]) {
+            file_info.set_short_display_name();
+        }
+
+        // 长文件名目录项按序号从大到小写在短文件名目录项前面
+        let mut handle = DirHandle::new(dir);
+        let checksum = lfn_checksum(&short_name);
+        for i in 0..lfn_entries {
+            let order = lfn_entries - i;
+            let mut entry = [0u8; DIR_ENTRY_SIZE];
+            entry[0] = order as u8 | if i == 0 { LFN_LAST_ENTRY } else { 0 };
+            entry[11] = ATTR_LONG_NAME;
+            entry[13] = checksum;
+            for (j, &offset) in LFN_CHAR_OFFSETS.iter().enumerate() {
+                // 名称后跟一个0，剩余部分填0xFFFF
+                let k = (order - 1) * LFN_CHARS_PER_ENTRY + j;
+                let c = match k.cmp(&lfn_len) {
+                    core::cmp::Ordering::Less => chars[k],
+                    core::cmp::Ordering::Equal => 0x0000,
+                    core::cmp::Ordering::Greater => 0xFFFF,
+                };
+                entry[offset..offset + 2].copy_from_slice(&c.to_le_bytes());
+            }
+            self.write_slot(&mut handle, start + i, &entry)?;
+        }
+
+        let mut entry = [0u8; DIR_ENTRY_SIZE];
+        file_info.to_bytes(&mut entry);
+        let (sector, offset) = self.write_slot(&mut handle, file_info.entry_index, &entry)?;
+        file_info.entry_sector = sector;
+        file_info.entry_offset = offset;
+        Ok(file_info)
+    }
+
+    // 写入文件：从current_position开始写，需要时扩展簇链
+    pub fn write_file(&mut self, handle: &mut FileHandle, data: &[u8]) -> Result<usize, FsError> {
+        if !handle.open {
+            return Err(FsError::NotFound);
+        }
+        if handle.file_info.attributes & ATTR_READ_ONLY != 0 {
+            return Err(FsError::ReadOnly);
+        }
+        // 写入从当前位置开始 (可能在文件末尾之后)，写完后的大小不能超过u32
+        let end = handle.current_position.checked_add(data.len());
+        if end.is_none_or(|end| end > u32::MAX as usize) {
+            return Err(FsError::NoSpace);
+        }
+
+        // 写入位置超过文件末尾时，中间部分补0
+        let size = handle.file_info.size as usize;
+        if handle.current_position > size {
+            let target = handle.current_position;
+            handle.current_position = size;
+            let zeros = [0u8; BLOCK_SIZE];
+            while handle.current_position < target {
+                let count = (target - handle.current_position).min(BLOCK_SIZE);
+                self.write_file(handle, &zeros[..count])?;
+            }
+        }
+
+        let cluster_size = self.cluster_size();
+        let mut sector_buffer = [0u8; BLOCK_SIZE];
+        let mut done = 0;
+        while done < data.len() {
+            let position = handle.current_position;
+            let cluster = self.cluster_for_write(handle, position / cluster_size)?;
+            let sector = self.cluster_to_sector(cluster)? + (position % cluster_size) / BLOCK_SIZE;
+            let offset = position % BLOCK_SIZE;
+            let count = (BLOCK_SIZE - offset).min(data.len() - done);
+
+            // 不是整扇区写入时先读出原内容
+            if count < BLOCK_SIZE
+                && self.device.read_blocks(sector, &mut sector_buffer) != DiskResult::Success
+            {
+                return Err(FsError::IoError);
+            }
+            sector_buffer[offset..offset + count].copy_from_slice(&data[done..done + count]);
+            if self.device.write_blocks(sector, &sector_buffer) != DiskResult::Success {
+                return Err(FsError::IoError);
+            }
+
+            done += count;
+            handle.current_position += count;
+            if handle.current_position > handle.file_info.size as usize {
+                handle.file_info.size = handle.current_position as u32;
+            }
+            handle.dirty = true;
+        }
+        Ok(done)
+    }
+
+    // 截断或扩展文件到length字节，释放多余的簇
+    pub fn truncate(&mut self, handle: &mut FileHandle, length: usize) -> Result<(), FsError> {
+        if !handle.open {
+            return Err(FsError::NotFound);
+        }
+        if handle.file_info.attributes & ATTR_READ_ONLY != 0 {
+            return Err(FsError::ReadOnly);
+        }
+
+        let size = handle.file_info.size as usize;
+        if length > size {
+            // 扩展：在末尾写入0
+            let position = handle.current_position;
+            handle.current_position = length;
+            self.write_file(handle, &[])?;
+            handle.current_position = position;
+            return Ok(());
+        }
+
+        let keep = length.div_ceil(self.cluster_size());
+        let first = handle.file_info.first_cluster;
+        if keep == 0 {
+            if first >= 2 {
+                self.free_chain(first);
+            }
+            handle.file_info.first_cluster = 0;
+        } else if first >= 2 {
+            let last = self.cluster_at(handle, keep - 1)?;
+            if let Some(rest) = self.next_cluster(last) {
+                self.free_chain(rest);
+            }
+            self.set_fat_entry(last as usize, self.fat_type.mask());
+        }
+        handle.cluster = 0;
+        handle.cluster_index = 0;
+        handle.file_info.size = length as u32;
+        handle.dirty = true;
+        Ok(())
+    }
+
+    // 把文件大小、首簇和修改时间写回目录项，并同步FAT
+    pub fn flush_file(&mut self, handle: &mut FileHandle) -> Result<(), FsError> {
+        if handle.dirty {
+            handle.file_info.modified = FatDateTime::now();
+            handle.file_info.attributes |= ATTR_ARCHIVE;
+            self.write_entry(&handle.file_info)?;
+            handle.dirty = false;
+        }
+        self.flush_fat()
+    }
+
+    // 删除文件：目录项标记为0xE5并释放簇链
+    pub fn delete_file(&mut self, file_info: &FileInfo) -> Result<(), FsError> {
+        if file_info.is_directory() {
+            return Err(FsError::IsADirectory);
+        }
+
+        self.remove_entry(file_info)?;
+        if file_info.first_cluster >= 2 {
+            self.free_chain(file_info.first_cluster);
+        }
+        self.flush_fat()
+    }
+
+    // 重命名或移动文件和目录，old_dir和new_dir是新旧目录的首簇
+    pub fn rename(
+        &mut self,
+        old_dir: u32,
+        old_name: &str,
+        new_dir: u32,
+        name: &str,
+    ) -> Result<(), FsError> {
+        let mut file_info = self.lookup(old_dir, old_name)?.ok_or(FsError::NotFound)?;
+
+        if let Some(existing) = self.lookup(new_dir, name)? {
+            let same_entry = existing.entry_sector == file_info.entry_sector
+                && existing.entry_offset == file_info.entry_offset;
+            if !same_entry {
+                return Err(FsError::AlreadyExists);
+            }
+        }
+
+        // 同一目录内的8.3名称之间改名，直接改写目录项
+        if old_dir == new_dir && file_info.lfn_entries == 0 {
+            if let Some((short_name, case_flags)) = short_name_for(name) {
+                file_info.name = short_name;
+                file_info.case_flags = case_flags;
+                return self.write_entry(&file_info);
+            }
+        }
+
+        let cluster = file_info.first_cluster;
+        if old_dir != new_dir && file_info.is_directory() && self.is_ancestor(cluster, new_dir)? {
+            // 不能把目录移动到它自己的子目录中
+            return Err(FsError::InvalidArgument);
+        }
+
+        // 建立新目录项，保留大小和时间，再删除旧目录项
+        let mut moved = self.create_entry(new_dir, name, file_info.attributes, cluster)?;
+        moved.size = file_info.size;
+        moved.created = file_info.created;
+        moved.modified = file_info.modified;
+        self.write_entry(&moved)?;
+        self.remove_entry(&file_info)?;
+
+        // 移动的目录中 .. 指向新的父目录
+        if moved.is_directory() && old_dir != new_dir {
+            if let Some(mut parent) = self.lookup(cluster, "..")? {
+                parent.first_cluster = new_dir;
+                self.write_entry(&parent)?;
+            }
+        }
+        Ok(())
+    }
+
+    // dir是否等于ancestor或位于其下 (沿 .. 向上查找)
+    fn is_ancestor(&self, ancestor: u32, dir: u32) -> Result<bool, FsError> {
+        let mut current = dir;
+        for _ in 0..self.cluster_count {
+            if current == ancestor {
+                return Ok(true);
+            }
+            if current == 0 {
+                break;
+            }
+            current = match self.lookup(current, "..")? {
+                Some(parent) => parent.first_cluster,
+                None => break,
+            };
+        }
+        Ok(false)
+    }
+
+    // 在目录dir中创建目录：分配一个簇并写入 . 和 .. 目录项
+    pub fn create_directory(&mut self, dir: u32, name: &str) -> Result<FileInfo, FsError> {
+        if self.lookup(dir, name)?.is_some() {
+            return Err(FsError::AlreadyExists);
+        }
+
+        let cluster = self.allocate_cluster(0)?;
+        let result = self
+            .init_directory(cluster, dir)
+            .and_then(|_| self.create_entry(dir, name, ATTR_DIRECTORY, cluster));
+        if result.is_err() {
+            self.free_chain(cluster);
+        }
+        self.flush_fat()?;
+        result
+    }
+
+    // 初始化新目录的第一个簇
+    fn init_directory(&self, cluster: u32, parent: u32) -> Result<(), FsError> {
+        self.zero_cluster(cluster)?;
+
+        let now = FatDateTime::now();
+        let mut dot = FileInfo::new();
+        dot.name = DOT_NAME;
+        dot.attributes = ATTR_DIRECTORY;
+        dot.first_cluster = cluster;
+        dot.created = now;
+        dot.modified = now;
+        let mut dotdot = dot;
+        dotdot.name = DOTDOT_NAME;
+        dotdot.first_cluster = parent;
+
+        let mut sector = [0u8; BLOCK_SIZE];
+        dot.to_bytes(&mut sector[..DIR_ENTRY_SIZE]);
+        dotdot.to_bytes(&mut sector[DIR_ENTRY_SIZE..2 * DIR_ENTRY_SIZE]);
+        if self
+            .device
+            .write_blocks(self.cluster_to_sector(cluster)?, &sector)
+            != DiskResult::Success
+        {
+            return Err(FsError::IoError);
+        }
+        Ok(())
+    }
+
+    // 删除空目录
+    pub fn remove_directory(&mut self, file_info: &FileInfo) -> Result<(), FsError> {
+        if !file_info.is_directory() {
+            return Err(FsError::NotADirectory);
+        }
+        if file_info.first_cluster == 0 {
+            // 根目录
+            return Err(FsError::InvalidArgument);
+        }
+
+        let mut empty = true;
+        self.for_each_entry(file_info.first_cluster, |entry| {
+            empty = entry.name == DOT_NAME || entry.name == DOTDOT_NAME;
+            empty
+        })?;
+        if !empty {
+            return Err(FsError::NotEmpty);
+        }
+
+        self.remove_entry(file_info)?;
+        self.free_chain(file_info.first_cluster);
+        self.flush_fat()
+    }
+
+    // 读取文件：从current_position开始沿簇链读取，返回读取的字节数
+    pub fn read_file(&self, handle: &mut FileHandle, buffer: &mut [u8]) -> usize {
+        if !handle.open {
+            return 0;
+        }
+
+        let size = handle.file_info.size as usize;
+        let to_read = buffer
+            .len()
+            .min(size.saturating_sub(handle.current_position));
+        let cluster_size = self.cluster_size();
+        let mut sector_buffer = [0u8; BLOCK_SIZE];
+        let mut done = 0;
+
+        while done < to_read {
+            let position = handle.current_position;
+            let cluster = match self.cluster_at(handle, position / cluster_size) {
+                Ok(cluster) => cluster,
+                Err(_) => break,
+            };
+            let sector = match self.cluster_to_sector(cluster) {
+                Ok(sector) => sector + (position % cluster_size) / BLOCK_SIZE,
+                Err(_) => break,
+            };
+            if self.device.read_blocks(sector, &mut sector_buffer) != DiskResult::Success {
+                break;
+            }
+
+            let offset = position % BLOCK_SIZE;
+            let count = (BLOCK_SIZE - offset).min(to_read - done);
+            buffer[done..done + count].copy_from_slice(&sector_buffer[offset..offset + count]);
+            done += count;
+            handle.current_position += count;
+        }
+
+        done
+    }
+
+    // 卸载：写回修改，释放FAT表
+    pub fn unmount(&mut self) -> Result<(), FsError> {
+        let result = self.sync();
+        self.release_fat_table();
+        *self = Self::new();
+        result
+    }
+
+    // 按inode编号 (目录项所在扇区和扇区内序号) 读出目录项
+    fn entry_by_id(&self, id: InodeId) -> Result<FileInfo, FsError> {
+        if !self.initialized {
+            return Err(FsError::NotInitialized);
+        }
+        if id == ROOT_INODE {
+            return Ok(FileInfo::root());
+        }
+        let sector = (id / ENTRIES_PER_SECTOR as u64) as usize;
+        let offset = (id % ENTRIES_PER_SECTOR as u64) as usize * DIR_ENTRY_SIZE;
+        let mut buffer = [0u8; BLOCK_SIZE];
+        if self.device.read_blocks(sector, &mut buffer) != DiskResult::Success {
+            return Err(FsError::IoError);
+        }
+        let entry = &buffer[offset..offset + DIR_ENTRY_SIZE];
+        if entry[0] == ENTRY_END || entry[0] == ENTRY_DELETED {
+            // 文件已被删除
+            return Err(FsError::NotFound);
+        }
+        let mut file_info = FileInfo::from_bytes(entry);
+        file_info.entry_sector = sector;
+        file_info.entry_offset = offset;
+        Ok(file_info)
+    }
+
+    // 目录的首簇，不是目录时返回NotADirectory
+    fn dir_cluster(&self, dir: InodeId) -> Result<u32, FsError> {
+        let file_info = self.entry_by_id(dir)?;
+        if !file_info.is_directory() {
+            return Err(FsError::NotADirectory);
+        }
+        Ok(file_info.first_cluster)
+    }
+
+    // 打开inode对应的文件，读写位置在offset
+    fn handle_at(&self, id: InodeId, offset: usize) -> Result<FileHandle, FsError> {
+        let mut handle = FileHandle::new();
+        handle.file_info = self.entry_by_id(id)?;
+        if handle.file_info.is_directory() {
+            return Err(FsError::IsADirectory);
+        }
+        handle.current_position = offset;
+        handle.open = true;
+        Ok(handle)
+    }
+}
+
+// inode编号：根目录没有目录项，编号为0；其他文件用目录项的位置编号
+// 目录项所在扇区总在引导扇区之后，不会与根目录冲突
+// 重命名会移动目录项，删除后位置可能被新文件重用，所以VFS不允许删除或重命名使用中的inode
+const ROOT_INODE: InodeId = 0;
+
+fn inode_id(file_info: &FileInfo) -> InodeId {
+    if file_info.is_directory() && file_info.first_cluster == 0 {
+        return ROOT_INODE;
+    }
+    (file_info.entry_sector * ENTRIES_PER_SECTOR + file_info.entry_offset / DIR_ENTRY_SIZE)
+        as InodeId
+}
+
+fn inode_type(file_info: &FileInfo) -> InodeType {
+    if file_info.is_directory() {
+        InodeType::Directory
+    } else {
+        InodeType::Regular
+    }
+}
+
+// 把 "name.ext" 转换为大写、空格填充的11字节8.3名称
+pub fn to_short_name(filename: &str) -> Result<[u8; 11], FsError> {
+    if filename == "." || filename == ".." {
+        return Err(FsError::InvalidName);
+    }
+    let (base, ext) = match filename.rfind('.') {
+        Some(dot) => (&filename[..dot], &filename[dot + 1..]),
+        None => (filename, ""),
+    };
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 {
+        return Err(FsError::InvalidName);
+    }
+
+    let mut name = [b' '; 11];
+    for (i, &c) in base.as_bytes().iter().enumerate() {
+        name[i] = short_name_char(c)?;
+    }
+    for (i, &c) in ext.as_bytes().iter().enumerate() {
+        name[8 + i] = short_name_char(c)?;
+    }
+    Ok(name)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+// 检查FSInfo扇区的三个签名
+fn fsinfo_valid(sector: &[u8]) -> bool {
+    read_u32(sector, 0) == FSINFO_LEAD_SIGNATURE
+        && read_u32(sector, 484) == FSINFO_STRUCT_SIGNATURE
+        && read_u32(sector, 508) == FSINFO_TRAIL_SIGNATURE
+}
+
+// 名称能直接作为8.3名称保存时返回 (8.3名称, NT大小写标志)
+// 基本名或扩展名内大小写混合时需要长文件名
+fn short_name_for(name: &str) -> Option<([u8; 11], u8)> {
+    let short_name = to_short_name(name).ok()?;
+    let (base, ext) = match name.rfind('.') {
+        Some(dot) => (&name[..dot], &name[dot + 1..]),
+        None => (name, ""),
+    };
+    let lower = |part: &str| {
+        let has_lower = part.bytes().any(|c| c.is_ascii_lowercase());
+        let has_upper = part.bytes().any(|c| c.is_ascii_uppercase());
+        match (has_lower, has_upper) {
+            (true, true) => None,
+            (has_lower, _) => Some(has_lower),
+        }
+    };
+    let mut case_flags = 0;
+    if lower(base)? {
+        case_flags |= NT_LOWER_BASE;
+    }
+    if lower(ext)? {
+        case_flags |= NT_LOWER_EXT;
+    }
+    Some((short_name, case_flags))
+}
+
+// 把长文件名编码为UCS-2 (UTF-16)，检查非法字符，返回字符数
+fn encode_long_name(name: &str, out: &mut [u16; LFN_MAX_CHARS]) -> Result<usize, FsError> {
+    if name.ends_with('.') || name.ends_with(' ') {
+        return Err(FsError::InvalidName);
+    }
+    let mut len = 0;
+    for c in name.chars() {
+        if (c as u32) < 0x20 || "\"*/:<>?\\|".contains(c) {
+            return Err(FsError::InvalidName);
+        }
+        let mut units = [0u16; 2];
+        for &unit in c.encode_utf16(&mut units).iter() {
+            if len == LFN_MAX_CHARS {
+                return Err(FsError::InvalidName);
+            }
+            out[len] = unit;
+            len += 1;
+        }
+    }
+    if len == 0 {
+        return Err(FsError::InvalidName);
+    }
+    Ok(len)
+}
+
+// 别名中的字符：去掉空格和点，大写，8.3中不允许的字符换成 '_'，返回长度
+fn alias_chars(part: &str, out: &mut [u8]) -> usize {
+    let mut len = 0;
+    for c in part.chars().filter(|&c| c != ' ' && c != '.') {
+        if len == out.len() {
+            break;
+        }
+        out[len] = if c.is_ascii() {
+            short_name_char(c as u8).unwrap_or(b'_')
+        } else {
+            b'_'
+        };
+        len += 1;
+    }
+    len
+}
+
+// 长文件名目录项中的短文件名校验和
+fn lfn_checksum(short_name: &[u8]) -> u8 {
+    short_name.iter().fold(0u8, |sum, &c| {
+        ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(c)
+    })
+}
+
+// 8.3名称中允许的字符，小写字母转换为大写
+fn short_name_char(c: u8) -> Result<u8, FsError> {
+    match c {
+        b'a'..=b'z' => Ok(c.to_ascii_uppercase()),
+        b'A'..=b'Z' | b'0'..=b'9' => Ok(c),
+        b'!' | b'#' | b'$' | b'%' | b'&' | b'\'' | b'(' | b')' | b'-' | b'@' | b'^' | b'_'
+        | b'`' | b'{' | b'}' | b'~' => Ok(c),
+        _ => Err(FsError::InvalidName),
+    }
+}
+
+// 写入后还没有写回目录项的文件个数上限，表满时立即写回
+const MAX_DIRTY_FILES: usize = 8;
+
+// 挂载到VFS上的FAT卷
+pub struct FatVolume {
+    fs: FatFileSystem<'static>,
+    mounted: bool,
+    // 大小或簇链已修改的文件，最后一次关闭或sync时写回目录项和FAT
+    dirty: [Option<(InodeId, FileInfo)>; MAX_DIRTY_FILES],
+    index: usize, // 在VOLUMES中的下标
+}
+
+impl FatVolume {
+    pub const fn new() -> Self {
+        Self {
+            fs: FatFileSystem::new(),
+            mounted: false,
+            dirty: [None; MAX_DIRTY_FILES],
+            index: 0,
+        }
+    }
+
+    fn cached(&self, inode: InodeId) -> Option<FileInfo> {
+        self.dirty
+            .iter()
+            .flatten()
+            .find(|(id, _)| *id == inode)
+            .map(|(_, file_info)| *file_info)
+    }
+
+    // 打开inode，使用还没有写回的文件信息
+    fn handle_at(&self, inode: InodeId, offset: usize) -> Result<FileHandle, FsError> {
+        let mut handle = self.fs.handle_at(inode, offset)?;
+        if let Some(file_info) = self.cached(inode) {
+            handle.file_info = file_info;
+            handle.dirty = true;
+        }
+        Ok(handle)
+    }
+
+    // 记下修改后的文件信息
+    fn keep_dirty(&mut self, inode: InodeId, handle: &mut FileHandle) -> Result<(), FsError> {
+        if !handle.dirty {
+            return Ok(());
+        }
+        let index = self
+            .dirty
+            .iter()
+            .position(|slot| slot.is_some_and(|(id, _)| id == inode))
+            .or_else(|| self.dirty.iter().position(|slot| slot.is_none()));
+        match index {
+            Some(index) => {
+                self.dirty[index] = Some((inode, handle.file_info));
+                Ok(())
+            }
+            None => self.fs.flush_file(handle),
+        }
+    }
+
+    // 把inode缓存的修改写回目录项，失败时留在表中以后重试
+    fn flush_inode(&mut self, inode: InodeId) -> Result<(), FsError> {
+        let index = match self
+            .dirty
+            .iter()
+            .position(|slot| slot.is_some_and(|(id, _)| id == inode))
+        {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+        let mut handle = self.handle_at(inode, 0)?;
+        self.fs.flush_file(&mut handle)?;
+        self.dirty[index] = None;
+        Ok(())
+    }
+
+    fn flush_all(&mut self) -> Result<(), FsError> {
+        let mut result = Ok(());
+        for index in 0..MAX_DIRTY_FILES {
+            if let Some((inode, _)) = self.dirty[index] {
+                let flushed = self.flush_inode(inode);
+                if result.is_ok() {
+                    result = flushed;
+                }
+            }
+        }
+        result
+    }
+
+    fn metadata(&mut self, inode: InodeId) -> Result<Metadata, FsError> {
+        let file_info = match self.cached(inode) {
+            Some(file_info) => file_info,
+            None => self.fs.entry_by_id(inode)?,
+        };
+        let mut metadata = Metadata::new(inode, inode_type(&file_info));
+        metadata.size = file_info.size as usize;
+        metadata.read_only = file_info.attributes & ATTR_READ_ONLY != 0;
+        metadata.created = file_info.created.to_unix_time();
+        metadata.modified = file_info.modified.to_unix_time();
+        Ok(metadata)
+    }
+
+    fn lookup(&mut self, dir: InodeId, name: &str) -> Result<InodeId, FsError> {
+        let cluster = self.fs.dir_cluster(dir)?;
+        match self.fs.lookup(cluster, name)? {
+            Some(file_info) => Ok(inode_id(&file_info)),
+            None => Err(FsError::NotFound),
+        }
+    }
+
+    // cursor是目录项序号
+    fn read_dir(&mut self, dir: InodeId, cursor: &mut usize) -> Result<Option<DirEntry>, FsError> {
+        let mut handle = DirHandle::new(self.fs.dir_cluster(dir)?);
+        handle.seek(*cursor);
+        while let Some(file_info) = self.fs.readdir(&mut handle)? {
+            *cursor = handle.index;
+            if file_info.name == DOT_NAME || file_info.name == DOTDOT_NAME {
+                continue;
+            }
+            let kind = inode_type(&file_info);
+            return Ok(Some(DirEntry::new(
+                inode_id(&file_info),
+                kind,
+                file_info.name_str(),
+            )));
+        }
+        *cursor = handle.index;
+        Ok(None)
+    }
+
+    fn read(&mut self, inode: InodeId, offset: usize, buffer: &mut [u8]) -> Result<usize, FsError> {
+        let mut handle = self.handle_at(inode, offset)?;
+        Ok(self.fs.read_file(&mut handle, buffer))
+    }
+
+    // 目录项在关闭或sync时才写回；部分写入失败时也要记下已经扩展的大小
+    fn write(&mut self, inode: InodeId, offset: usize, data: &[u8]) -> Result<usize, FsError> {
+        let mut handle = self.handle_at(inode, offset)?;
+        let result = self.fs.write_file(&mut handle, data);
+        self.keep_dirty(inode, &mut handle)?;
+        result
+    }
+
+    fn truncate(&mut self, inode: InodeId, length: usize) -> Result<(), FsError> {
+        let mut handle = self.handle_at(inode, 0)?;
+        let result = self.fs.truncate(&mut handle, length);
+        self.keep_dirty(inode, &mut handle)?;
+        result
+    }
+
+    fn create(&mut self, dir: InodeId, name: &str, kind: InodeType) -> Result<InodeId, FsError> {
+        let cluster = self.fs.dir_cluster(dir)?;
+        let file_info = match kind {
+            InodeType::Directory => self.fs.create_directory(cluster, name)?,
+            InodeType::Regular => {
+                if self.fs.lookup(cluster, name)?.is_some() {
+                    return Err(FsError::AlreadyExists);
+                }
+                let file_info = self.fs.create_entry(cluster, name, ATTR_ARCHIVE, 0)?;
+                self.fs.flush_fat()?;
+                file_info
+            }
+            _ => return Err(FsError::NotSupported),
+        };
+        Ok(inode_id(&file_info))
+    }
+
+    fn remove(&mut self, dir: InodeId, name: &str) -> Result<(), FsError> {
+        // 删除和重命名会移动目录项，先写回缓存的修改
+        self.flush_all()?;
+        let cluster = self.fs.dir_cluster(dir)?;
+        let file_info = self.fs.lookup(cluster, name)?.ok_or(FsError::NotFound)?;
+        if file_info.is_directory() {
+            self.fs.remove_directory(&file_info)
+        } else {
+            self.fs.delete_file(&file_info)
+        }
+    }
+
+    fn rename(
+        &mut self,
+        old_dir: InodeId,
+        old_name: &str,
+        new_dir: InodeId,
+        new_name: &str,
+    ) -> Result<(), FsError> {
+        self.flush_all()?;
+        let old_cluster = self.fs.dir_cluster(old_dir)?;
+        let new_cluster = self.fs.dir_cluster(new_dir)?;
+        self.fs.rename(old_cluster, old_name, new_cluster, new_name)
+    }
+
+    fn close(&mut self, inode: InodeId) -> Result<(), FsError> {
+        self.flush_inode(inode)
+    }
+}
+
+impl Default for FatVolume {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FileSystem for FatVolume {
+    fn name(&self) -> &'static str {
+        "fat"
+    }
+
+    fn root(&self) -> InodeId {
+        ROOT_INODE
+    }
+
+    fn inode(&mut self, id: InodeId) -> Result<&'static mut dyn Inode, FsError> {
+        let inode = inodes()
+            .iter_mut()
+            .find(|inode| !inode.used)
+            .ok_or(FsError::TooManyFiles)?;
+        *inode = FatInode {
+            used: true,
+            volume: self.index,
+            id,
+        };
+        Ok(inode)
+    }
+
+    fn sync(&mut self) -> Result<(), FsError> {
+        let flushed = self.flush_all();
+        self.fs.sync().and(flushed)
+    }
+
+    fn unmount(&mut self) -> Result<(), FsError> {
+        self.flush_all()?;
+        self.mounted = false;
+        // 卸载后丢弃卷的缓存块，之后挂载同一设备或直接写设备时不会读到旧数据
+        let device = self.fs.device;
+        self.fs.unmount()?;
+        bcache::invalidate(device);
+        Ok(())
+    }
+}
+
+// 交给VFS的inode对象，在INODES表中分配
+struct FatInode {
+    used: bool,
+    volume: usize,
+    id: InodeId,
+}
+
+impl FatInode {
+    const EMPTY: FatInode = FatInode {
+        used: false,
+        volume: 0,
+        id: 0,
+    };
+
+    fn fs(&self) -> &'static mut FatVolume {
+        &mut volumes()[self.volume]
+    }
+}
+
+impl Inode for FatInode {
+    fn id(&self) -> InodeId {
+        self.id
+    }
+
+    fn metadata(&mut self) -> Result<Metadata, FsError> {
+        self.fs().metadata(self.id)
+    }
+
+    fn lookup(&mut self, name: &str) -> Result<InodeId, FsError> {
+        self.fs().lookup(self.id, name)
+    }
+
+    fn read_dir(&mut self, cursor: &mut usize) -> Result<Option<DirEntry>, FsError> {
+        self.fs().read_dir(self.id, cursor)
+    }
+
+    fn read_at(&mut self, offset: usize, buffer: &mut [u8]) -> Result<usize, FsError> {
+        self.fs().read(self.id, offset, buffer)
+    }
+
+    fn write_at(&mut self, offset: usize, data: &[u8]) -> Result<usize, FsError> {
+        self.fs().write(self.id, offset, data)
+    }
+
+    fn truncate(&mut self, length: usize) -> Result<(), FsError> {
+        self.fs().truncate(self.id, length)
+    }
+
+    fn create(&mut self, name: &str, kind: InodeType) -> Result<InodeId, FsError> {
+        self.fs().create(self.id, name, kind)
+    }
+
+    fn remove(&mut self, name: &str) -> Result<(), FsError> {
+        self.fs().remove(self.id, name)
+    }
+
+    fn rename(&mut self, old_name: &str, new_dir: InodeId, new_name: &str) -> Result<(), FsError> {
+        self.fs().rename(self.id, old_name, new_dir, new_name)
+    }
+
+    fn close(&mut self) -> Result<(), FsError> {
+        self.fs().close(self.id)
+    }
+
+    fn release(&mut self) {
+        self.used = false;
+    }
+}
+
+static mut INODES: [FatInode; MAX_INODES] = [FatInode::EMPTY; MAX_INODES];
+
+fn inodes() -> &'static mut [FatInode; MAX_INODES] {
+    unsafe { &mut *core::ptr::addr_of_mut!(INODES) }
+}
+
+// 可以同时挂载的FAT卷数
+const MAX_VOLUMES: usize = 2;
+
+static mut VOLUMES: [FatVolume; MAX_VOLUMES] = [FatVolume::new(), FatVolume::new()];
+
+fn volumes() -> &'static mut [FatVolume; MAX_VOLUMES] {
+    unsafe { &mut *core::ptr::addr_of_mut!(VOLUMES) }
+}
+
+// 全局函数
+// 挂载块设备source (如 virtio0p1) 上的FAT卷
+// 与已挂载的卷重叠的设备 (同一设备，或者磁盘和它的分区) 不能挂载
+pub fn mount_volume(source: &str) -> Result<&'static mut dyn FileSystem, FsError> {
+    let device = block::lookup(source).ok_or(FsError::NoDevice)?;
+    let volumes = volumes();
+    let busy = volumes
+        .iter()
+        .any(|volume| volume.mounted && block::overlaps(volume.fs.device, device));
+    if busy {
+        return Err(FsError::Busy);
+    }
+    let (index, volume) = volumes
+        .iter_mut()
+        .enumerate()
+        .find(|(_, volume)| !volume.mounted)
+        .ok_or(FsError::Busy)?;
+    if !volume.fs.mount(device) {
+        volume.fs = FatFileSystem::new();
+        return Err(FsError::InvalidArgument);
+    }
+    volume.dirty = [None; MAX_DIRTY_FILES];
+    volume.index = index;
+    volume.mounted = true;
+    Ok(volume)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_names() {
+        assert_eq!(to_short_name("readme.txt"), Ok(*b"README  TXT"));
+        assert_eq!(to_short_name("KERNEL"), Ok(*b"KERNEL     "));
+        assert_eq!(to_short_name("toolongname.txt"), Err(FsError::InvalidName));
+        assert_eq!(to_short_name("a.text"), Err(FsError::InvalidName));
+        assert_eq!(to_short_name("a b.txt"), Err(FsError::InvalidName));
+        assert_eq!(to_short_name(".."), Err(FsError::InvalidName));
+    }
+
+    #[test]
+    fn case_flags() {
+        assert_eq!(short_name_for("README.TXT"), Some((*b"README  TXT", 0)));
+        assert_eq!(
+            short_name_for("readme.txt"),
+            Some((*b"README  TXT", NT_LOWER_BASE | NT_LOWER_EXT))
+        );
+        assert_eq!(
+            short_name_for("readme.TXT"),
+            Some((*b"README  TXT", NT_LOWER_BASE))
+        );
+        // 基本名内大小写混合只能用长文件名保存
+        assert_eq!(short_name_for("ReadMe.txt"), None);
+        assert_eq!(short_name_for("long file name.txt"), None);
+    }
+
+    #[test]
+    fn long_name_encoding() {
+        let mut chars = [0u16; LFN_MAX_CHARS];
+        assert_eq!(encode_long_name("Hello world.txt", &mut chars), Ok(15));
+        assert_eq!(chars[0], b'H' as u16);
+        // 基本平面以外的字符占两个UTF-16单元
+        assert_eq!(encode_long_name("\u{e9}\u{1f600}", &mut chars), Ok(3));
+        assert_eq!(&chars[..3], &[0xe9, 0xd83d, 0xde00]);
+        assert_eq!(
+            encode_long_name("a:b", &mut chars),
+            Err(FsError::InvalidName)
+        );
+        assert_eq!(
+            encode_long_name("name.", &mut chars),
+            Err(FsError::InvalidName)
+        );
+        assert_eq!(encode_long_name("", &mut chars), Err(FsError::InvalidName));
+        let long = "x".repeat(LFN_MAX_CHARS + 1);
+        assert_eq!(
+            encode_long_name(&long, &mut chars),
+            Err(FsError::InvalidName)
+        );
+    }
+
+    #[test]
+    fn alias_characters() {
+        let mut out = [b' '; 8];
+        assert_eq!(alias_chars("my file.tar", &mut out), 8);
+        assert_eq!(&out, b"MYFILETA");
+        let mut out = [b' '; 3];
+        assert_eq!(alias_chars("t+\u{e9}", &mut out), 3);
+        assert_eq!(&out, b"T__");
+    }
+
+    #[test]
+    fn checksum() {
+        assert_eq!(lfn_checksum(b"README  TXT"), 0x73);
+    }
+
+    // 按create_entry的方式生成长文件名目录项，序号从大到小
+    fn lfn_entries(name: &[u16], short_name: &[u8; 11]) -> std::vec::Vec<[u8; DIR_ENTRY_SIZE]> {
+        let count = name.len().div_ceil(LFN_CHARS_PER_ENTRY);
+        (0..count)
+            .map(|i| {
+                let order = count - i;
+                let mut entry = [0u8; DIR_ENTRY_SIZE];
+                entry[0] = order as u8 | if i == 0 { LFN_LAST_ENTRY } else { 0 };
+                entry[11] = ATTR_LONG_NAME;
+                entry[13] = lfn_checksum(short_name);
+                for (j, &offset) in LFN_CHAR_OFFSETS.iter().enumerate() {
+                    let k = (order - 1) * LFN_CHARS_PER_ENTRY + j;
+                    let c = match k.cmp(&name.len()) {
+                        core::cmp::Ordering::Less => name[k],
+                        core::cmp::Ordering::Equal => 0x0000,
+                        core::cmp::Ordering::Greater => 0xFFFF,
+                    };
+                    entry[offset..offset + 2].copy_from_slice(&c.to_le_bytes());
+                }
+                entry
+            })
+            .collect()
+    }
+
+    #[test]
+    fn long_name_round_trip() {
+        let mut chars = [0u16; LFN_MAX_CHARS];
+        let len = encode_long_name("A rather long file name.text", &mut chars).unwrap();
+        let short_name = *b"ARATHE~1TEX";
+        let entries = lfn_entries(&chars[..len], &short_name);
+        assert_eq!(entries.len(), 3);
+
+        let mut handle = DirHandle::new(0);
+        for entry in &entries {
+            handle.push_lfn(entry);
+        }
+        assert_eq!(handle.take_lfn(&short_name), Some((&chars[..len], 3)));
+        // 取出后清空
+        assert_eq!(handle.take_lfn(&short_name), None);
+
+        // 校验和对不上 (短文件名目录项被不支持长文件名的系统改过)
+        for entry in &entries {
+            handle.push_lfn(entry);
+        }
+        assert_eq!(handle.take_lfn(b"OTHER   TXT"), None);
 
-pub fn close_file(handle: &mut FileHandle) {
-    unsafe {
-        FS.close_file(handle);
+        // 缺少中间的一项
+        handle.push_lfn(&entries[0]);
+        handle.push_lfn(&entries[2]);
+        assert_eq!(handle.take_lfn(&short_name), None);
     }
 }
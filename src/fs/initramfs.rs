@@ -0,0 +1,569 @@
+// initramfs
+// 第16章：文件系统 - QEMU -initrd 传入的cpio (newc格式) 归档，作为只读的内存文件系统
+
+use crate::drivers::uart;
+use crate::fs::vfs::{
+    DirEntry, FileSystem, FsError, Inode, InodeId, InodeType, Metadata, MAX_INODES,
+};
+
+// newc头部：6字节魔数加13个8位十六进制字段，文件名和数据都按4字节对齐
+const NEWC_MAGIC: &[u8; 6] = b"070701";
+const NEWC_CRC_MAGIC: &[u8; 6] = b"070702";
+const HEADER_SIZE: usize = 110;
+const TRAILER: &str = "TRAILER!!!";
+
+// 头部字段的位置 (魔数之后每个字段8字节)
+const FIELD_MODE: usize = 1;
+const FIELD_MTIME: usize = 5;
+const FIELD_FILESIZE: usize = 6;
+const FIELD_NAMESIZE: usize = 11;
+
+// 文件类型 (mode的高位)
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+
+// 路径和符号链接的限制
+const MAX_PATH: usize = 256;
+const MAX_SYMLINKS: usize = 8;
+
+// 归档中的一个文件
+#[derive(Clone, Copy)]
+pub struct Entry {
+    pub name: &'static str, // 去掉开头的 "/" 和 "./"
+    pub mode: u32,
+    pub mtime: u32,
+    pub data: &'static [u8],
+}
+
+impl Entry {
+    pub fn is_directory(&self) -> bool {
+        self.mode & S_IFMT == S_IFDIR
+    }
+
+    pub fn is_regular_file(&self) -> bool {
+        self.mode & S_IFMT == S_IFREG
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.mode & S_IFMT == S_IFLNK
+    }
+}
+
+// 解析8位十六进制字段
+fn hex_field(header: &[u8], index: usize) -> Option<u32> {
+    let start = NEWC_MAGIC.len() + index * 8;
+    let text = core::str::from_utf8(header.get(start..start + 8)?).ok()?;
+    u32::from_str_radix(text, 16).ok()
+}
+
+fn align4(value: usize) -> usize {
+    (value + 3) & !3
+}
+
+// 去掉开头的 "/" 和 "./"，归档根目录本身是空串
+fn normalize(path: &str) -> &str {
+    let mut path = path;
+    loop {
+        if let Some(rest) = path.strip_prefix('/') {
+            path = rest;
+        } else if let Some(rest) = path.strip_prefix("./") {
+            path = rest;
+        } else if path == "." {
+            return "";
+        } else {
+            return path.trim_end_matches('/');
+        }
+    }
+}
+
+// 把target接在dir后面写入buffer，同时消去 "." 和 ".." 段；".." 不会越过归档根目录
+fn join_path<'a>(dir: &str, target: &str, buffer: &'a mut [u8]) -> Option<&'a str> {
+    let mut len = 0;
+    for part in dir.split('/').chain(target.split('/')) {
+        match part {
+            "" | "." => {}
+            ".." => len = buffer[..len].iter().rposition(|&c| c == b'/').unwrap_or(0),
+            _ => {
+                if len > 0 {
+                    *buffer.get_mut(len)? = b'/';
+                    len += 1;
+                }
+                buffer
+                    .get_mut(len..len + part.len())?
+                    .copy_from_slice(part.as_bytes());
+                len += part.len();
+            }
+        }
+    }
+    core::str::from_utf8(&buffer[..len]).ok()
+}
+
+// cpio归档
+pub struct Cpio {
+    data: &'static [u8],
+}
+
+impl Cpio {
+    // 开头不是newc魔数时返回None
+    pub fn new(data: &'static [u8]) -> Option<Self> {
+        let magic = data.get(..NEWC_MAGIC.len())?;
+        (magic == NEWC_MAGIC || magic == NEWC_CRC_MAGIC).then_some(Self { data })
+    }
+
+    // 解析offset处的文件，返回文件和下一个头部的位置，遇到结尾标记时返回None
+    fn entry_at(&self, offset: usize) -> Option<(Entry, usize)> {
+        let header = self.data.get(offset..offset.checked_add(HEADER_SIZE)?)?;
+        if &header[..6] != NEWC_MAGIC && &header[..6] != NEWC_CRC_MAGIC {
+            return None;
+        }
+        let mode = hex_field(header, FIELD_MODE)?;
+        let mtime = hex_field(header, FIELD_MTIME)?;
+        let file_size = hex_field(header, FIELD_FILESIZE)? as usize;
+        let name_size = hex_field(header, FIELD_NAMESIZE)? as usize;
+
+        // 文件名包含结尾的NUL
+        let name_start = offset + HEADER_SIZE;
+        let name = self
+            .data
+            .get(name_start..name_start.checked_add(name_size)?.checked_sub(1)?)?;
+        let name = core::str::from_utf8(name).ok()?;
+        if name == TRAILER {
+            return None;
+        }
+
+        let data_start = align4(name_start + name_size);
+        let data = self
+            .data
+            .get(data_start..data_start.checked_add(file_size)?)?;
+        let entry = Entry {
+            name: normalize(name),
+            mode,
+            mtime,
+            data,
+        };
+        Some((entry, align4(data_start + file_size)))
+    }
+
+    // 遍历所有文件，f的参数为头部位置和文件，返回false时停止
+    pub fn for_each(&self, mut f: impl FnMut(usize, &Entry) -> bool) {
+        let mut offset = 0;
+        while let Some((entry, next)) = self.entry_at(offset) {
+            if !f(offset, &entry) {
+                return;
+            }
+            offset = next;
+        }
+    }
+
+    // 查找文件 (不跟随符号链接)，返回头部位置和文件
+    // 同名文件出现多次时以最后一个为准，与解压归档的结果一致
+    pub fn find(&self, path: &str) -> Option<(usize, Entry)> {
+        let path = normalize(path);
+        let mut found = None;
+        self.for_each(|offset, entry| {
+            if entry.name == path {
+                found = Some((offset, *entry));
+            }
+            true
+        });
+        found
+    }
+
+    // 查找文件，跟随最后一段的符号链接
+    pub fn lookup(&self, path: &str) -> Option<(usize, Entry)> {
+        let mut buffer = [0u8; MAX_PATH];
+        let (mut offset, mut entry) = self.find(path)?;
+        for _ in 0..MAX_SYMLINKS {
+            if !entry.is_symlink() {
+                return Some((offset, entry));
+            }
+            let target = core::str::from_utf8(entry.data).ok()?;
+            // 相对链接相对于链接所在的目录
+            let dir = if target.starts_with('/') {
+                ""
+            } else {
+                entry.name.rsplit_once('/').map_or("", |(dir, _)| dir)
+            };
+            let target = join_path(dir, target, &mut buffer)?;
+            (offset, entry) = self.find(target)?;
+        }
+        None
+    }
+}
+
+// 挂载到VFS上的只读initramfs
+// 归档根目录的inode为0，其他文件的inode为头部位置加1
+const ROOT_INODE: InodeId = 0;
+
+pub struct InitramfsFs {
+    mounted: bool,
+}
+
+impl InitramfsFs {
+    pub const fn new() -> Self {
+        Self { mounted: false }
+    }
+
+    fn entry(&self, inode: InodeId) -> Result<Entry, FsError> {
+        let cpio = initramfs().ok_or(FsError::NotInitialized)?;
+        match inode.checked_sub(1) {
+            Some(offset) => cpio
+                .entry_at(offset as usize)
+                .map(|(entry, _)| entry)
+                .ok_or(FsError::NotFound),
+            None => Ok(ROOT_ENTRY),
+        }
+    }
+}
+
+impl Default for InitramfsFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 归档中不一定有根目录本身
+const ROOT_ENTRY: Entry = Entry {
+    name: "",
+    mode: S_IFDIR,
+    mtime: 0,
+    data: &[],
+};
+
+fn inode_type(entry: &Entry) -> InodeType {
+    if entry.is_directory() {
+        InodeType::Directory
+    } else if entry.is_symlink() {
+        InodeType::Symlink
+    } else {
+        InodeType::Regular
+    }
+}
+
+// 文件所在目录的路径
+fn parent_of(name: &str) -> &str {
+    name.rsplit_once('/').map_or("", |(dir, _)| dir)
+}
+
+impl InitramfsFs {
+    fn metadata(&mut self, inode: InodeId) -> Result<Metadata, FsError> {
+        let entry = self.entry(inode)?;
+        let mut metadata = Metadata::new(inode, inode_type(&entry));
+        metadata.size = entry.data.len();
+        metadata.read_only = true;
+        metadata.created = entry.mtime as u64;
+        metadata.modified = entry.mtime as u64;
+        Ok(metadata)
+    }
+
+    // 跟随符号链接，使 /bin/sh -> busybox 这样的链接可以直接执行
+    fn lookup(&mut self, dir: InodeId, name: &str) -> Result<InodeId, FsError> {
+        let parent = self.entry(dir)?;
+        if !parent.is_directory() {
+            return Err(FsError::NotADirectory);
+        }
+        let mut buffer = [0u8; MAX_PATH];
+        let len = parent.name.len() + 1 + name.len();
+        let path = buffer.get_mut(..len).ok_or(FsError::InvalidName)?;
+        path[..parent.name.len()].copy_from_slice(parent.name.as_bytes());
+        path[parent.name.len()] = b'/';
+        path[parent.name.len() + 1..].copy_from_slice(name.as_bytes());
+        let path = core::str::from_utf8(path).map_err(|_| FsError::InvalidName)?;
+
+        let cpio = initramfs().ok_or(FsError::NotInitialized)?;
+        match cpio.lookup(path) {
+            Some((_, entry)) if entry.name.is_empty() => Ok(ROOT_INODE),
+            Some((offset, _)) => Ok(offset as InodeId + 1),
+            None => Err(FsError::NotFound),
+        }
+    }
+
+    // cursor是下一个要检查的头部位置
+    fn read_dir(&mut self, dir: InodeId, cursor: &mut usize) -> Result<Option<DirEntry>, FsError> {
+        let parent = self.entry(dir)?;
+        if !parent.is_directory() {
+            return Err(FsError::NotADirectory);
+        }
+        let cpio = initramfs().ok_or(FsError::NotInitialized)?;
+        while let Some((entry, next)) = cpio.entry_at(*cursor) {
+            let offset = *cursor;
+            *cursor = next;
+            // 跳过被后面同名文件覆盖的项
+            if !entry.name.is_empty()
+                && parent_of(entry.name) == parent.name
+                && cpio.find(entry.name).map(|(found, _)| found) == Some(offset)
+            {
+                let name = &entry.name[entry.name.rfind('/').map_or(0, |i| i + 1)..];
+                let inode = offset as InodeId + 1;
+                return Ok(Some(DirEntry::new(inode, inode_type(&entry), name)));
+            }
+        }
+        Ok(None)
+    }
+
+    // 符号链接读出的是目标路径
+    fn read(&mut self, inode: InodeId, offset: usize, buffer: &mut [u8]) -> Result<usize, FsError> {
+        let entry = self.entry(inode)?;
+        if entry.is_directory() {
+            return Err(FsError::IsADirectory);
+        }
+        let data = entry.data.get(offset..).unwrap_or(&[]);
+        let count = data.len().min(buffer.len());
+        buffer[..count].copy_from_slice(&data[..count]);
+        Ok(count)
+    }
+}
+
+impl FileSystem for InitramfsFs {
+    fn name(&self) -> &'static str {
+        "initramfs"
+    }
+
+    fn root(&self) -> InodeId {
+        ROOT_INODE
+    }
+
+    fn inode(&mut self, id: InodeId) -> Result<&'static mut dyn Inode, FsError> {
+        let inode = inodes()
+            .iter_mut()
+            .find(|inode| !inode.used)
+            .ok_or(FsError::TooManyFiles)?;
+        *inode = InitramfsInode { used: true, id };
+        Ok(inode)
+    }
+
+    fn unmount(&mut self) -> Result<(), FsError> {
+        self.mounted = false;
+        Ok(())
+    }
+}
+
+// 交给VFS的inode对象，在INODES表中分配
+struct InitramfsInode {
+    used: bool,
+    id: InodeId,
+}
+
+impl InitramfsInode {
+    const EMPTY: InitramfsInode = InitramfsInode { used: false, id: 0 };
+}
+
+impl Inode for InitramfsInode {
+    fn id(&self) -> InodeId {
+        self.id
+    }
+
+    fn metadata(&mut self) -> Result<Metadata, FsError> {
+        initramfs_fs().metadata(self.id)
+    }
+
+    fn lookup(&mut self, name: &str) -> Result<InodeId, FsError> {
+        initramfs_fs().lookup(self.id, name)
+    }
+
+    fn read_dir(&mut self, cursor: &mut usize) -> Result<Option<DirEntry>, FsError> {
+        initramfs_fs().read_dir(self.id, cursor)
+    }
+
+    fn read_at(&mut self, offset: usize, buffer: &mut [u8]) -> Result<usize, FsError> {
+        initramfs_fs().read(self.id, offset, buffer)
+    }
+
+    fn release(&mut self) {
+        self.used = false;
+    }
+}
+
+static mut INODES: [InitramfsInode; MAX_INODES] = [InitramfsInode::EMPTY; MAX_INODES];
+
+fn inodes() -> &'static mut [InitramfsInode; MAX_INODES] {
+    unsafe { &mut *core::ptr::addr_of_mut!(INODES) }
+}
+
+// 全局initramfs
+static mut INITRAMFS: Option<Cpio> = None;
+
+fn initramfs() -> Option<&'static Cpio> {
+    unsafe { (*core::ptr::addr_of!(INITRAMFS)).as_ref() }
+}
+
+// 全局函数
+// 使用 [start, end) 处的cpio归档，不是cpio格式时返回false
+pub fn init(start: usize, end: usize) -> bool {
+    let data = unsafe { core::slice::from_raw_parts(start as *const u8, end - start) };
+    let cpio = match Cpio::new(data) {
+        Some(cpio) => cpio,
+        None => return false,
+    };
+    unsafe { *core::ptr::addr_of_mut!(INITRAMFS) = Some(cpio) };
+    true
+}
+
+pub fn is_loaded() -> bool {
+    initramfs().is_some()
+}
+
+pub fn lookup(path: &str) -> Option<Entry> {
+    initramfs()?.lookup(path).map(|(_, entry)| entry)
+}
+
+pub fn for_each_file(mut f: impl FnMut(&Entry)) {
+    if let Some(cpio) = initramfs() {
+        cpio.for_each(|_, entry| {
+            f(entry);
+            true
+        });
+    }
+}
+
+pub fn file_count() -> usize {
+    let mut count = 0;
+    for_each_file(|_| count += 1);
+    count
+}
+
+// 列出归档中的文件
+pub fn list_files() {
+    for_each_file(|entry| {
+        if entry.name.is_empty() {
+            return;
+        }
+        uart::print(if entry.is_directory() {
+            "d "
+        } else if entry.is_symlink() {
+            "l "
+        } else {
+            "- "
+        });
+        uart::print_dec_padded(entry.data.len(), 8);
+        uart::print(" /");
+        uart::println(entry.name);
+    });
+}
+
+static mut INITRAMFS_FS: InitramfsFs = InitramfsFs::new();
+
+fn initramfs_fs() -> &'static mut InitramfsFs {
+    unsafe { &mut *core::ptr::addr_of_mut!(INITRAMFS_FS) }
+}
+
+// 挂载initramfs，只能挂载一次
+pub fn instance(_source: &str) -> Result<&'static mut dyn FileSystem, FsError> {
+    if !is_loaded() {
+        return Err(FsError::NoDevice);
+    }
+    let fs = initramfs_fs();
+    if fs.mounted {
+        return Err(FsError::Busy);
+    }
+    fs.mounted = true;
+    Ok(fs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::boxed::Box;
+    use std::format;
+    use std::vec::Vec;
+
+    // 按newc格式追加一个文件
+    fn push_entry(archive: &mut Vec<u8>, name: &str, mode: u32, data: &[u8]) {
+        let header = format!(
+            "070701{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}",
+            0,
+            mode,
+            0,
+            0,
+            1,
+            1234,
+            data.len(),
+            0,
+            0,
+            0,
+            0,
+            name.len() + 1,
+            0
+        );
+        archive.extend_from_slice(header.as_bytes());
+        archive.extend_from_slice(name.as_bytes());
+        archive.push(0);
+        archive.resize(align4(archive.len()), 0);
+        archive.extend_from_slice(data);
+        archive.resize(align4(archive.len()), 0);
+    }
+
+    fn archive(entries: &[(&str, u32, &[u8])]) -> Cpio {
+        let mut archive = Vec::new();
+        for &(name, mode, data) in entries {
+            push_entry(&mut archive, name, mode, data);
+        }
+        push_entry(&mut archive, TRAILER, 0, b"");
+        Cpio::new(Box::leak(archive.into_boxed_slice())).unwrap()
+    }
+
+    #[test]
+    fn rejects_other_formats() {
+        assert!(Cpio::new(b"070707").is_none());
+        assert!(Cpio::new(b"07").is_none());
+    }
+
+    #[test]
+    fn finds_files_and_directories() {
+        let cpio = archive(&[
+            (".", S_IFDIR | 0o755, b""),
+            ("./bin", S_IFDIR | 0o755, b""),
+            ("./bin/init", S_IFREG | 0o755, b"\x7fELF"),
+        ]);
+        let (_, entry) = cpio.find("/bin/init").unwrap();
+        assert_eq!(entry.name, "bin/init");
+        assert!(entry.is_regular_file());
+        assert_eq!(entry.data, b"\x7fELF");
+        assert_eq!(entry.mtime, 1234);
+        assert!(cpio.find("bin/").unwrap().1.is_directory());
+        assert!(cpio.find("/").unwrap().1.is_directory());
+        assert!(cpio.find("/bin/sh").is_none());
+    }
+
+    #[test]
+    fn last_duplicate_wins() {
+        let cpio = archive(&[
+            ("etc/motd", S_IFREG | 0o644, b"old"),
+            ("etc/motd", S_IFREG | 0o644, b"new"),
+        ]);
+        assert_eq!(cpio.find("etc/motd").unwrap().1.data, b"new");
+    }
+
+    #[test]
+    fn follows_symlinks() {
+        let cpio = archive(&[
+            ("bin/busybox", S_IFREG | 0o755, b"binary"),
+            ("bin/sh", S_IFLNK | 0o777, b"busybox"),
+            ("sbin/init", S_IFLNK | 0o777, b"../bin/sh"),
+            ("init", S_IFLNK | 0o777, b"/sbin/init"),
+            ("loop", S_IFLNK | 0o777, b"loop"),
+        ]);
+        assert_eq!(cpio.lookup("init").unwrap().1.name, "bin/busybox");
+        assert!(cpio.find("init").unwrap().1.is_symlink());
+        assert!(cpio.lookup("loop").is_none());
+    }
+
+    #[test]
+    fn truncated_archive_stops() {
+        let mut data = Vec::new();
+        push_entry(&mut data, "file", S_IFREG | 0o644, b"contents");
+        data.truncate(data.len() - 8);
+        let cpio = Cpio::new(Box::leak(data.into_boxed_slice())).unwrap();
+        assert!(cpio.find("file").is_none());
+    }
+
+    #[test]
+    fn joins_paths() {
+        let mut buffer = [0u8; MAX_PATH];
+        assert_eq!(join_path("a/b", "../c/./d", &mut buffer), Some("a/c/d"));
+        assert_eq!(join_path("", "../../x", &mut buffer), Some("x"));
+        assert_eq!(normalize("./a/b/"), "a/b");
+    }
+}
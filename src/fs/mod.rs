@@ -1,4 +1,10 @@
 // 文件系统
 // 第16章：文件系统
 
+pub mod bcache;
+pub mod devfs;
 pub mod fat;
+pub mod initramfs;
+pub mod procfs;
+pub mod ramfs;
+pub mod vfs;
@@ -0,0 +1,275 @@
+// 进程信息文件系统
+// 第16章：文件系统 - /proc 下的只读文件，每次读取时生成内容
+
+use crate::drivers::partition;
+use crate::fs::vfs::{
+    self, DirEntry, FileSystem, FsError, Inode, InodeId, InodeType, Metadata, MAX_INODES,
+};
+use crate::kernel::{cmdline, memory};
+
+// 根目录的inode为0，文件的inode为FILES下标加1
+const ROOT_INODE: InodeId = 0;
+
+// 生成的文件内容上限，超出部分截断
+const MAX_FILE_SIZE: usize = 2048;
+
+// 文件内容缓冲区
+struct Text {
+    buffer: [u8; MAX_FILE_SIZE],
+    len: usize,
+}
+
+impl Text {
+    const fn new() -> Self {
+        Self {
+            buffer: [0; MAX_FILE_SIZE],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, s: &str) {
+        let count = s.len().min(MAX_FILE_SIZE - self.len);
+        self.buffer[self.len..self.len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.len += count;
+    }
+
+    // 十进制数，右对齐到width
+    fn push_dec(&mut self, value: usize, width: usize) {
+        let mut digits = [0u8; 20];
+        let mut len = 0;
+        let mut value = value;
+        loop {
+            digits[len] = b'0' + (value % 10) as u8;
+            len += 1;
+            value /= 10;
+            if value == 0 {
+                break;
+            }
+        }
+        for _ in len..width {
+            self.push(" ");
+        }
+        for i in (0..len).rev() {
+            self.push(core::str::from_utf8(&digits[i..i + 1]).unwrap_or("?"));
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+}
+
+fn generate_cmdline(text: &mut Text) {
+    text.push(cmdline::as_str());
+    text.push("\n");
+}
+
+fn generate_meminfo(text: &mut Text) {
+    text.push("MemFree:   ");
+    text.push_dec(memory::available() / 1024, 8);
+    text.push(" kB\n");
+}
+
+// 与 /proc/mounts 相同的格式：设备 挂载点 类型
+fn generate_mounts(text: &mut Text) {
+    vfs::for_each_mount(|source, path, fstype| {
+        text.push(source);
+        text.push(" ");
+        text.push(path);
+        text.push(" ");
+        text.push(fstype);
+        text.push("\n");
+    });
+}
+
+// 与 /proc/partitions 相同的格式，大小以1KB为单位
+fn generate_partitions(text: &mut Text) {
+    text.push("major minor  #blocks  name\n");
+    partition::for_each_partition(|entry| {
+        text.push("    0 ");
+        text.push_dec(entry.number, 5);
+        text.push(" ");
+        text.push_dec(entry.count / 2, 8);
+        text.push("  ");
+        let mut name = [0u8; 16];
+        text.push(partition::partition_name(entry.number, &mut name));
+        text.push("\n");
+    });
+}
+
+// 文件名和生成内容的函数
+type Generator = fn(&mut Text);
+
+const FILES: [(&str, Generator); 4] = [
+    ("cmdline", generate_cmdline),
+    ("meminfo", generate_meminfo),
+    ("mounts", generate_mounts),
+    ("partitions", generate_partitions),
+];
+
+fn file(inode: InodeId) -> Result<Generator, FsError> {
+    match inode.checked_sub(1) {
+        Some(index) => FILES
+            .get(index as usize)
+            .map(|(_, generate)| *generate)
+            .ok_or(FsError::NotFound),
+        None => Err(FsError::IsADirectory),
+    }
+}
+
+pub struct ProcFs {
+    mounted: bool,
+    // 生成内容用的缓冲区，放在这里而不是栈上
+    text: Text,
+}
+
+impl ProcFs {
+    pub const fn new() -> Self {
+        Self {
+            mounted: false,
+            text: Text::new(),
+        }
+    }
+
+    fn generate(&mut self, inode: InodeId) -> Result<&[u8], FsError> {
+        let generate = file(inode)?;
+        self.text.len = 0;
+        generate(&mut self.text);
+        Ok(self.text.as_bytes())
+    }
+
+    // 文件大小为当前生成内容的长度
+    fn metadata(&mut self, inode: InodeId) -> Result<Metadata, FsError> {
+        let mut metadata = if inode == ROOT_INODE {
+            Metadata::new(inode, InodeType::Directory)
+        } else {
+            let mut metadata = Metadata::new(inode, InodeType::Regular);
+            metadata.size = self.generate(inode)?.len();
+            metadata
+        };
+        metadata.read_only = true;
+        Ok(metadata)
+    }
+
+    fn lookup(&mut self, dir: InodeId, name: &str) -> Result<InodeId, FsError> {
+        if dir != ROOT_INODE {
+            return Err(FsError::NotADirectory);
+        }
+        match FILES.iter().position(|(file, _)| *file == name) {
+            Some(index) => Ok(index as InodeId + 1),
+            None => Err(FsError::NotFound),
+        }
+    }
+
+    fn read_dir(&mut self, dir: InodeId, cursor: &mut usize) -> Result<Option<DirEntry>, FsError> {
+        if dir != ROOT_INODE {
+            return Err(FsError::NotADirectory);
+        }
+        let Some((name, _)) = FILES.get(*cursor) else {
+            return Ok(None);
+        };
+        *cursor += 1;
+        Ok(Some(DirEntry::new(
+            *cursor as InodeId,
+            InodeType::Regular,
+            name,
+        )))
+    }
+
+    fn read(&mut self, inode: InodeId, offset: usize, buffer: &mut [u8]) -> Result<usize, FsError> {
+        let data = self.generate(inode)?;
+        let data = data.get(offset..).unwrap_or(&[]);
+        let count = data.len().min(buffer.len());
+        buffer[..count].copy_from_slice(&data[..count]);
+        Ok(count)
+    }
+}
+
+impl Default for ProcFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FileSystem for ProcFs {
+    fn name(&self) -> &'static str {
+        "procfs"
+    }
+
+    fn root(&self) -> InodeId {
+        ROOT_INODE
+    }
+
+    fn inode(&mut self, id: InodeId) -> Result<&'static mut dyn Inode, FsError> {
+        let inode = inodes()
+            .iter_mut()
+            .find(|inode| !inode.used)
+            .ok_or(FsError::TooManyFiles)?;
+        *inode = ProcInode { used: true, id };
+        Ok(inode)
+    }
+
+    fn unmount(&mut self) -> Result<(), FsError> {
+        self.mounted = false;
+        Ok(())
+    }
+}
+
+// 交给VFS的inode对象，在INODES表中分配
+struct ProcInode {
+    used: bool,
+    id: InodeId,
+}
+
+impl ProcInode {
+    const EMPTY: ProcInode = ProcInode { used: false, id: 0 };
+}
+
+impl Inode for ProcInode {
+    fn id(&self) -> InodeId {
+        self.id
+    }
+
+    fn metadata(&mut self) -> Result<Metadata, FsError> {
+        procfs().metadata(self.id)
+    }
+
+    fn lookup(&mut self, name: &str) -> Result<InodeId, FsError> {
+        procfs().lookup(self.id, name)
+    }
+
+    fn read_dir(&mut self, cursor: &mut usize) -> Result<Option<DirEntry>, FsError> {
+        procfs().read_dir(self.id, cursor)
+    }
+
+    fn read_at(&mut self, offset: usize, buffer: &mut [u8]) -> Result<usize, FsError> {
+        procfs().read(self.id, offset, buffer)
+    }
+
+    fn release(&mut self) {
+        self.used = false;
+    }
+}
+
+static mut INODES: [ProcInode; MAX_INODES] = [ProcInode::EMPTY; MAX_INODES];
+
+fn inodes() -> &'static mut [ProcInode; MAX_INODES] {
+    unsafe { &mut *core::ptr::addr_of_mut!(INODES) }
+}
+
+// 只有一个实例
+static mut PROCFS: ProcFs = ProcFs::new();
+
+fn procfs() -> &'static mut ProcFs {
+    unsafe { &mut *core::ptr::addr_of_mut!(PROCFS) }
+}
+
+// 全局函数
+pub fn instance(_source: &str) -> Result<&'static mut dyn FileSystem, FsError> {
+    let procfs = procfs();
+    if procfs.mounted {
+        return Err(FsError::Busy);
+    }
+    procfs.mounted = true;
+    Ok(procfs)
+}
@@ -0,0 +1,420 @@
+// 内存文件系统
+// 第16章：文件系统 - 文件内容放在堆上，卸载后丢失 (用于 /tmp，以及没有根设备时的根目录)
+
+use crate::drivers::rtc;
+use crate::fs::vfs::{
+    DirEntry, FileSystem, FsError, Inode, InodeId, InodeType, Metadata, MAX_INODES,
+};
+use crate::kernel::memory;
+
+// 每个实例的节点数和名称长度上限
+const MAX_NODES: usize = 64;
+const MAX_NAME: usize = 64;
+// 文件第一次写入时分配的最小空间
+const MIN_CAPACITY: usize = 64;
+// 可以同时挂载的实例数
+const MAX_INSTANCES: usize = 2;
+
+// 根目录是0号节点，节点下标就是inode编号
+const ROOT_INODE: usize = 0;
+
+// 文件或目录
+struct Node {
+    used: bool,
+    kind: InodeType,
+    parent: usize,
+    name: [u8; MAX_NAME],
+    name_len: usize,
+    data: *mut u8,
+    size: usize,
+    capacity: usize,
+    created: u64,
+    modified: u64,
+}
+
+impl Node {
+    const fn empty() -> Self {
+        Self {
+            used: false,
+            kind: InodeType::Regular,
+            parent: ROOT_INODE,
+            name: [0; MAX_NAME],
+            name_len: 0,
+            data: core::ptr::null_mut(),
+            size: 0,
+            capacity: 0,
+            created: 0,
+            modified: 0,
+        }
+    }
+
+    fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len]).unwrap_or("")
+    }
+
+    fn set_name(&mut self, name: &str) -> Result<(), FsError> {
+        if name.is_empty() || name.len() > MAX_NAME {
+            return Err(FsError::InvalidName);
+        }
+        self.name[..name.len()].copy_from_slice(name.as_bytes());
+        self.name_len = name.len();
+        Ok(())
+    }
+
+    fn contents(&self) -> &[u8] {
+        if self.data.is_null() {
+            return &[];
+        }
+        unsafe { core::slice::from_raw_parts(self.data, self.size) }
+    }
+
+    // 保证至少有capacity字节空间，按两倍扩展，原内容复制到新的空间
+    fn reserve(&mut self, capacity: usize) -> Result<(), FsError> {
+        if capacity <= self.capacity {
+            return Ok(());
+        }
+        let capacity = capacity.max(self.capacity * 2).max(MIN_CAPACITY);
+        let data = memory::allocate(capacity).ok_or(FsError::NoSpace)?;
+        if !self.data.is_null() {
+            unsafe { memory::memcpy(data, self.data, self.size) };
+            memory::deallocate(self.data, self.capacity);
+        }
+        self.data = data;
+        self.capacity = capacity;
+        Ok(())
+    }
+
+    fn release(&mut self) {
+        if !self.data.is_null() {
+            memory::deallocate(self.data, self.capacity);
+        }
+        *self = Node::empty();
+    }
+}
+
+pub struct RamFs {
+    nodes: [Node; MAX_NODES],
+    mounted: bool,
+    index: usize, // 在INSTANCES中的下标
+}
+
+impl RamFs {
+    pub const fn new() -> Self {
+        const EMPTY: Node = Node::empty();
+        Self {
+            nodes: [EMPTY; MAX_NODES],
+            mounted: false,
+            index: 0,
+        }
+    }
+
+    fn node(&mut self, inode: InodeId) -> Result<&mut Node, FsError> {
+        match self.nodes.get_mut(inode as usize) {
+            Some(node) if node.used => Ok(node),
+            _ => Err(FsError::NotFound),
+        }
+    }
+
+    fn directory(&mut self, inode: InodeId) -> Result<&mut Node, FsError> {
+        let node = self.node(inode)?;
+        if node.kind != InodeType::Directory {
+            return Err(FsError::NotADirectory);
+        }
+        Ok(node)
+    }
+
+    fn file(&mut self, inode: InodeId) -> Result<&mut Node, FsError> {
+        let node = self.node(inode)?;
+        if node.kind == InodeType::Directory {
+            return Err(FsError::IsADirectory);
+        }
+        Ok(node)
+    }
+
+    // 在目录dir中按名称查找，返回节点下标
+    fn find(&self, dir: usize, name: &str) -> Option<usize> {
+        (1..MAX_NODES).find(|&index| {
+            let node = &self.nodes[index];
+            node.used && node.parent == dir && node.name() == name
+        })
+    }
+
+    // 初始化根目录
+    fn format(&mut self) {
+        let now = rtc::now();
+        let root = &mut self.nodes[ROOT_INODE];
+        *root = Node::empty();
+        root.used = true;
+        root.kind = InodeType::Directory;
+        root.created = now;
+        root.modified = now;
+    }
+
+    fn metadata(&mut self, inode: InodeId) -> Result<Metadata, FsError> {
+        let node = self.node(inode)?;
+        let mut metadata = Metadata::new(inode, node.kind);
+        metadata.size = node.size;
+        metadata.created = node.created;
+        metadata.modified = node.modified;
+        Ok(metadata)
+    }
+
+    fn lookup(&mut self, dir: InodeId, name: &str) -> Result<InodeId, FsError> {
+        self.directory(dir)?;
+        match self.find(dir as usize, name) {
+            Some(index) => Ok(index as InodeId),
+            None => Err(FsError::NotFound),
+        }
+    }
+
+    // cursor是下一个要检查的节点下标
+    fn read_dir(&mut self, dir: InodeId, cursor: &mut usize) -> Result<Option<DirEntry>, FsError> {
+        self.directory(dir)?;
+        let start = (*cursor).max(1);
+        for index in start..MAX_NODES {
+            let node = &self.nodes[index];
+            if node.used && node.parent == dir as usize {
+                *cursor = index + 1;
+                return Ok(Some(DirEntry::new(
+                    index as InodeId,
+                    node.kind,
+                    node.name(),
+                )));
+            }
+        }
+        *cursor = MAX_NODES;
+        Ok(None)
+    }
+
+    fn read(&mut self, inode: InodeId, offset: usize, buffer: &mut [u8]) -> Result<usize, FsError> {
+        let contents = self.file(inode)?.contents();
+        let data = contents.get(offset..).unwrap_or(&[]);
+        let count = data.len().min(buffer.len());
+        buffer[..count].copy_from_slice(&data[..count]);
+        Ok(count)
+    }
+
+    // 写入位置超过文件末尾时，中间部分补0
+    fn write(&mut self, inode: InodeId, offset: usize, data: &[u8]) -> Result<usize, FsError> {
+        let node = self.file(inode)?;
+        let end = offset.checked_add(data.len()).ok_or(FsError::NoSpace)?;
+        node.reserve(end)?;
+        unsafe {
+            if offset > node.size {
+                memory::memset(node.data.add(node.size), 0, offset - node.size);
+            }
+            memory::memcpy(node.data.add(offset), data.as_ptr(), data.len());
+        }
+        node.size = node.size.max(end);
+        node.modified = rtc::now();
+        Ok(data.len())
+    }
+
+    fn truncate(&mut self, inode: InodeId, length: usize) -> Result<(), FsError> {
+        let node = self.file(inode)?;
+        if length > node.size {
+            node.reserve(length)?;
+            unsafe { memory::memset(node.data.add(node.size), 0, length - node.size) };
+        }
+        node.size = length;
+        node.modified = rtc::now();
+        Ok(())
+    }
+
+    fn create(&mut self, dir: InodeId, name: &str, kind: InodeType) -> Result<InodeId, FsError> {
+        self.directory(dir)?;
+        if self.find(dir as usize, name).is_some() {
+            return Err(FsError::AlreadyExists);
+        }
+        let index = self
+            .nodes
+            .iter()
+            .position(|node| !node.used)
+            .ok_or(FsError::NoSpace)?;
+        let now = rtc::now();
+        let node = &mut self.nodes[index];
+        node.set_name(name)?;
+        node.used = true;
+        node.kind = kind;
+        node.parent = dir as usize;
+        node.created = now;
+        node.modified = now;
+        Ok(index as InodeId)
+    }
+
+    fn remove(&mut self, dir: InodeId, name: &str) -> Result<(), FsError> {
+        self.directory(dir)?;
+        let index = self.find(dir as usize, name).ok_or(FsError::NotFound)?;
+        let has_children = self
+            .nodes
+            .iter()
+            .skip(1)
+            .any(|node| node.used && node.parent == index);
+        if has_children {
+            return Err(FsError::NotEmpty);
+        }
+        self.nodes[index].release();
+        Ok(())
+    }
+
+    fn rename(
+        &mut self,
+        old_dir: InodeId,
+        old_name: &str,
+        new_dir: InodeId,
+        new_name: &str,
+    ) -> Result<(), FsError> {
+        self.directory(old_dir)?;
+        self.directory(new_dir)?;
+        let index = self
+            .find(old_dir as usize, old_name)
+            .ok_or(FsError::NotFound)?;
+        match self.find(new_dir as usize, new_name) {
+            Some(existing) if existing != index => return Err(FsError::AlreadyExists),
+            _ => {}
+        }
+
+        // 不能把目录移动到它自己的子目录中
+        let mut ancestor = new_dir as usize;
+        while ancestor != ROOT_INODE {
+            if ancestor == index {
+                return Err(FsError::InvalidArgument);
+            }
+            ancestor = self.nodes[ancestor].parent;
+        }
+
+        let node = &mut self.nodes[index];
+        node.set_name(new_name)?;
+        node.parent = new_dir as usize;
+        Ok(())
+    }
+}
+
+impl Default for RamFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FileSystem for RamFs {
+    fn name(&self) -> &'static str {
+        "ramfs"
+    }
+
+    fn root(&self) -> InodeId {
+        ROOT_INODE as InodeId
+    }
+
+    fn inode(&mut self, id: InodeId) -> Result<&'static mut dyn Inode, FsError> {
+        let inode = inodes()
+            .iter_mut()
+            .find(|inode| !inode.used)
+            .ok_or(FsError::TooManyFiles)?;
+        *inode = RamInode {
+            used: true,
+            instance: self.index,
+            id,
+        };
+        Ok(inode)
+    }
+
+    // 释放所有文件内容
+    fn unmount(&mut self) -> Result<(), FsError> {
+        for node in self.nodes.iter_mut() {
+            node.release();
+        }
+        self.mounted = false;
+        Ok(())
+    }
+}
+
+// 交给VFS的inode对象，在INODES表中分配
+struct RamInode {
+    used: bool,
+    instance: usize,
+    id: InodeId,
+}
+
+impl RamInode {
+    const EMPTY: RamInode = RamInode {
+        used: false,
+        instance: 0,
+        id: 0,
+    };
+
+    fn fs(&self) -> &'static mut RamFs {
+        &mut instances()[self.instance]
+    }
+}
+
+impl Inode for RamInode {
+    fn id(&self) -> InodeId {
+        self.id
+    }
+
+    fn metadata(&mut self) -> Result<Metadata, FsError> {
+        self.fs().metadata(self.id)
+    }
+
+    fn lookup(&mut self, name: &str) -> Result<InodeId, FsError> {
+        self.fs().lookup(self.id, name)
+    }
+
+    fn read_dir(&mut self, cursor: &mut usize) -> Result<Option<DirEntry>, FsError> {
+        self.fs().read_dir(self.id, cursor)
+    }
+
+    fn read_at(&mut self, offset: usize, buffer: &mut [u8]) -> Result<usize, FsError> {
+        self.fs().read(self.id, offset, buffer)
+    }
+
+    fn write_at(&mut self, offset: usize, data: &[u8]) -> Result<usize, FsError> {
+        self.fs().write(self.id, offset, data)
+    }
+
+    fn truncate(&mut self, length: usize) -> Result<(), FsError> {
+        self.fs().truncate(self.id, length)
+    }
+
+    fn create(&mut self, name: &str, kind: InodeType) -> Result<InodeId, FsError> {
+        self.fs().create(self.id, name, kind)
+    }
+
+    fn remove(&mut self, name: &str) -> Result<(), FsError> {
+        self.fs().remove(self.id, name)
+    }
+
+    fn rename(&mut self, old_name: &str, new_dir: InodeId, new_name: &str) -> Result<(), FsError> {
+        self.fs().rename(self.id, old_name, new_dir, new_name)
+    }
+
+    fn release(&mut self) {
+        self.used = false;
+    }
+}
+
+static mut INODES: [RamInode; MAX_INODES] = [RamInode::EMPTY; MAX_INODES];
+
+fn inodes() -> &'static mut [RamInode; MAX_INODES] {
+    unsafe { &mut *core::ptr::addr_of_mut!(INODES) }
+}
+
+static mut INSTANCES: [RamFs; MAX_INSTANCES] = [RamFs::new(), RamFs::new()];
+
+fn instances() -> &'static mut [RamFs; MAX_INSTANCES] {
+    unsafe { &mut *core::ptr::addr_of_mut!(INSTANCES) }
+}
+
+// 全局函数
+// 每次挂载都是一个新的空文件系统
+pub fn new_instance(_source: &str) -> Result<&'static mut dyn FileSystem, FsError> {
+    let (index, ramfs) = instances()
+        .iter_mut()
+        .enumerate()
+        .find(|(_, ramfs)| !ramfs.mounted)
+        .ok_or(FsError::Busy)?;
+    ramfs.format();
+    ramfs.index = index;
+    ramfs.mounted = true;
+    Ok(ramfs)
+}
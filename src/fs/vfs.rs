@@ -0,0 +1,983 @@
+// 虚拟文件系统
+// 第16章：文件系统 - 挂载表把路径分派到各个文件系统 (FAT、ramfs、devfs、procfs、initramfs)
+
+use crate::fs::{devfs, fat, initramfs, procfs, ramfs};
+use crate::kernel::stdio;
+
+// 挂载表和路径的限制
+pub const MAX_MOUNTS: usize = 8;
+// 同时打开的inode个数上限，同一个inode打开多次只占一项
+const MAX_OPEN_INODES: usize = 32;
+// 同时打开的文件个数上限
+pub const MAX_OPEN_FILES: usize = 64;
+pub const MAX_PATH: usize = 256;
+pub const MAX_NAME_LEN: usize = 255;
+const MAX_SOURCE: usize = 32;
+
+// 打开标志 (取值与Linux相同)
+pub const O_RDONLY: usize = 0;
+pub const O_WRONLY: usize = 1;
+pub const O_RDWR: usize = 2;
+pub const O_ACCMODE: usize = 3;
+pub const O_CREAT: usize = 0o100;
+pub const O_EXCL: usize = 0o200;
+pub const O_TRUNC: usize = 0o1000;
+pub const O_APPEND: usize = 0o2000;
+pub const O_DIRECTORY: usize = 0o200000;
+
+// seek的whence
+pub const SEEK_SET: usize = 0;
+pub const SEEK_CUR: usize = 1;
+pub const SEEK_END: usize = 2;
+
+// 读目录时挂载点目录项的游标标志：文件系统自己的目录项读完后，继续列出挂在这个目录下的挂载点
+const MOUNT_CURSOR: usize = 1 << 62;
+
+// 文件系统错误
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FsError {
+    NotInitialized,
+    NotFound,
+    AlreadyExists,
+    NotADirectory,
+    IsADirectory,
+    InvalidName,
+    NoSpace,
+    ReadOnly,
+    IoError,
+    InvalidArgument,
+    Corrupted,
+    NotEmpty,
+    Busy,
+    NotSupported,
+    NoDevice,
+    CrossDevice,
+    TooManyFiles,
+}
+
+impl FsError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FsError::NotInitialized => "file system not initialized",
+            FsError::NotFound => "no such file or directory",
+            FsError::AlreadyExists => "file exists",
+            FsError::NotADirectory => "not a directory",
+            FsError::IsADirectory => "is a directory",
+            FsError::InvalidName => "invalid file name",
+            FsError::NoSpace => "no space left on device",
+            FsError::ReadOnly => "read-only file system",
+            FsError::IoError => "I/O error",
+            FsError::InvalidArgument => "invalid argument",
+            FsError::Corrupted => "file system corrupted",
+            FsError::NotEmpty => "directory not empty",
+            FsError::Busy => "device or resource busy",
+            FsError::NotSupported => "operation not supported",
+            FsError::NoDevice => "no such device",
+            FsError::CrossDevice => "cross-device link",
+            FsError::TooManyFiles => "too many open files",
+        }
+    }
+}
+
+// 文件系统内的inode编号，由各文件系统自己分配
+pub type InodeId = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InodeType {
+    Regular,
+    Directory,
+    CharDevice,
+    Symlink,
+    Fifo,
+}
+
+impl InodeType {
+    // ls -l 第一列的类型字符
+    pub fn as_char(&self) -> u8 {
+        match self {
+            InodeType::Regular => b'-',
+            InodeType::Directory => b'd',
+            InodeType::CharDevice => b'c',
+            InodeType::Symlink => b'l',
+            InodeType::Fifo => b'p',
+        }
+    }
+}
+
+// 文件属性，时间为Unix时间 (秒)
+#[derive(Debug, Clone, Copy)]
+pub struct Metadata {
+    pub inode: InodeId,
+    pub kind: InodeType,
+    pub size: usize,
+    pub read_only: bool,
+    pub created: u64,
+    pub modified: u64,
+}
+
+impl Metadata {
+    pub const fn new(inode: InodeId, kind: InodeType) -> Self {
+        Self {
+            inode,
+            kind,
+            size: 0,
+            read_only: false,
+            created: 0,
+            modified: 0,
+        }
+    }
+
+    pub fn is_directory(&self) -> bool {
+        self.kind == InodeType::Directory
+    }
+
+    pub fn is_regular_file(&self) -> bool {
+        self.kind == InodeType::Regular
+    }
+}
+
+// 目录项
+#[derive(Clone, Copy)]
+pub struct DirEntry {
+    pub inode: InodeId,
+    pub kind: InodeType,
+    name: [u8; MAX_NAME_LEN],
+    name_len: usize,
+}
+
+impl DirEntry {
+    // 名称超过MAX_NAME_LEN时截断
+    pub fn new(inode: InodeId, kind: InodeType, name: &str) -> Self {
+        let mut len = name.len().min(MAX_NAME_LEN);
+        while !name.is_char_boundary(len) {
+            len -= 1;
+        }
+        let mut entry = Self {
+            inode,
+            kind,
+            name: [0; MAX_NAME_LEN],
+            name_len: len,
+        };
+        entry.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+        entry
+    }
+
+    pub fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len]).unwrap_or("???")
+    }
+}
+
+// 文件系统：提供根目录和inode对象，路径解析和挂载点由VFS处理
+pub trait FileSystem {
+    // 文件系统类型名称
+    fn name(&self) -> &'static str;
+    // 根目录的inode
+    fn root(&self) -> InodeId;
+    // 取得编号为id的inode对象，对象来自文件系统自己的静态表，用完后调用release归还
+    fn inode(&mut self, id: InodeId) -> Result<&'static mut dyn Inode, FsError>;
+
+    // 把缓存的修改写回设备
+    fn sync(&mut self) -> Result<(), FsError> {
+        Ok(())
+    }
+
+    // 卸载：写回修改并释放文件系统占用的资源
+    fn unmount(&mut self) -> Result<(), FsError> {
+        self.sync()
+    }
+}
+
+// 文件系统中的文件或目录
+// 只读文件系统不必实现修改操作
+pub trait Inode {
+    fn id(&self) -> InodeId;
+    fn metadata(&mut self) -> Result<Metadata, FsError>;
+    // 在这个目录中查找name
+    fn lookup(&mut self, name: &str) -> Result<InodeId, FsError>;
+    // 从cursor处读取下一个目录项并推进cursor (从0开始)，读完时返回None
+    // 不返回 "." 和 ".."
+    fn read_dir(&mut self, cursor: &mut usize) -> Result<Option<DirEntry>, FsError>;
+    // 从offset处读取，返回读取的字节数，0表示文件结束
+    fn read_at(&mut self, offset: usize, buffer: &mut [u8]) -> Result<usize, FsError>;
+
+    // 从offset处写入，需要时扩展文件
+    fn write_at(&mut self, _offset: usize, _data: &[u8]) -> Result<usize, FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    fn truncate(&mut self, _length: usize) -> Result<(), FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    // 在这个目录中新建文件或目录，返回新inode
+    fn create(&mut self, _name: &str, _kind: InodeType) -> Result<InodeId, FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    // 删除这个目录中的文件或空目录
+    fn remove(&mut self, _name: &str) -> Result<(), FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    // 把这个目录中的old_name重命名或移动到同一文件系统的new_dir目录中
+    fn rename(
+        &mut self,
+        _old_name: &str,
+        _new_dir: InodeId,
+        _new_name: &str,
+    ) -> Result<(), FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    // 最后一个打开的文件关闭，可以写回为它缓存的修改
+    fn close(&mut self) -> Result<(), FsError> {
+        Ok(())
+    }
+
+    // 把对象归还给文件系统的inode表，之后不能再使用
+    fn release(&mut self);
+}
+
+// 打开的文件：读写位置和打开标志，读写通过文件系统的inode对象进行
+pub trait File {
+    // 文件在VFS中的位置
+    fn location(&self) -> InodeRef;
+    fn kind(&self) -> InodeType;
+    fn flags(&self) -> usize;
+    // 读写位置，目录为读目录的游标
+    fn offset(&self) -> usize;
+    fn metadata(&mut self) -> Result<Metadata, FsError>;
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, FsError>;
+    fn write(&mut self, data: &[u8]) -> Result<usize, FsError>;
+    fn truncate(&mut self, length: usize) -> Result<(), FsError>;
+    // 移动读写位置，返回新位置
+    fn seek(&mut self, offset: isize, whence: usize) -> Result<usize, FsError>;
+    // 读取下一个目录项，返回目录项和它在VFS中的位置
+    fn read_dir(&mut self) -> Result<Option<(DirEntry, InodeRef)>, FsError>;
+    // 关闭后文件对象归还到打开文件表，不能再使用
+    fn close(&mut self) -> Result<(), FsError>;
+
+    fn readable(&self) -> bool {
+        self.flags() & O_ACCMODE != O_WRONLY
+    }
+
+    fn writable(&self) -> bool {
+        matches!(self.flags() & O_ACCMODE, O_WRONLY | O_RDWR)
+    }
+}
+
+// 每个文件系统的inode对象表大小：每个打开的文件一个，再加上路径解析等临时使用的
+pub const MAX_INODES: usize = MAX_OPEN_FILES + 2;
+
+// 可以挂载的文件系统类型，参数是source (不需要设备的文件系统忽略它)
+type MountFn = fn(&str) -> Result<&'static mut dyn FileSystem, FsError>;
+
+const FS_TYPES: [(&str, MountFn); 5] = [
+    ("fat", fat::mount_volume),
+    ("ramfs", ramfs::new_instance),
+    ("devfs", devfs::instance),
+    ("procfs", procfs::instance),
+    ("initramfs", initramfs::instance),
+];
+
+// VFS中对inode的引用：挂载表下标和文件系统内的编号
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InodeRef {
+    pub mount: usize,
+    pub id: InodeId,
+}
+
+impl InodeRef {
+    pub fn metadata(&self) -> Result<Metadata, FsError> {
+        mounts().with_inode(*self, |inode| inode.metadata())
+    }
+}
+
+// 挂载点
+struct Mount {
+    fs: &'static mut dyn FileSystem,
+    path: [u8; MAX_PATH],
+    path_len: usize,
+    source: [u8; MAX_SOURCE],
+    source_len: usize,
+    // 挂载点所在的目录，根文件系统为None
+    parent: Option<InodeRef>,
+}
+
+impl Mount {
+    fn path(&self) -> &str {
+        core::str::from_utf8(&self.path[..self.path_len]).unwrap_or("/")
+    }
+
+    fn source(&self) -> &str {
+        core::str::from_utf8(&self.source[..self.source_len]).unwrap_or("")
+    }
+
+    // 挂载点路径的最后一段
+    fn name(&self) -> &str {
+        let path = self.path();
+        &path[path.rfind('/').map_or(0, |i| i + 1)..]
+    }
+}
+
+// 挂载表
+pub struct MountTable {
+    mounts: [Option<Mount>; MAX_MOUNTS],
+    // 打开的inode和打开次数
+    open_inodes: [Option<(InodeRef, usize)>; MAX_OPEN_INODES],
+}
+
+impl MountTable {
+    pub const fn new() -> Self {
+        const EMPTY: Option<Mount> = None;
+        Self {
+            mounts: [EMPTY; MAX_MOUNTS],
+            open_inodes: [None; MAX_OPEN_INODES],
+        }
+    }
+
+    fn retain_inode(&mut self, inode: InodeRef) -> Result<(), FsError> {
+        if let Some((_, count)) = self
+            .open_inodes
+            .iter_mut()
+            .flatten()
+            .find(|(open, _)| *open == inode)
+        {
+            *count += 1;
+            return Ok(());
+        }
+        let slot = self
+            .open_inodes
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(FsError::TooManyFiles)?;
+        *slot = Some((inode, 1));
+        Ok(())
+    }
+
+    // 释放一次打开，返回是否是最后一次
+    fn release_inode(&mut self, inode: InodeRef) -> bool {
+        for slot in self.open_inodes.iter_mut() {
+            if let Some((open, count)) = slot {
+                if *open == inode {
+                    *count -= 1;
+                    if *count == 0 {
+                        *slot = None;
+                        return true;
+                    }
+                    return false;
+                }
+            }
+        }
+        false
+    }
+
+    // inode是否被打开的文件或挂载点引用；有的文件系统 (FAT) 的inode编号取决于目录项的位置，
+    // 这样的inode被删除或重命名后编号会失效或被新文件重用，所以使用中的inode不能删除或重命名
+    fn in_use(&self, inode: InodeRef) -> bool {
+        self.open_inodes
+            .iter()
+            .flatten()
+            .any(|(open, _)| *open == inode)
+            || self
+                .mounts
+                .iter()
+                .flatten()
+                .any(|mount| mount.parent == Some(inode))
+    }
+
+    fn fs(&mut self, mount: usize) -> Result<&mut dyn FileSystem, FsError> {
+        match self.mounts.get_mut(mount) {
+            Some(Some(entry)) => Ok(&mut *entry.fs),
+            _ => Err(FsError::NotFound),
+        }
+    }
+
+    // 取得inode对象执行f，然后归还
+    fn with_inode<T>(
+        &mut self,
+        inode: InodeRef,
+        f: impl FnOnce(&mut dyn Inode) -> Result<T, FsError>,
+    ) -> Result<T, FsError> {
+        let object = self.fs(inode.mount)?.inode(inode.id)?;
+        let result = f(&mut *object);
+        object.release();
+        result
+    }
+
+    // 按最长前缀找到路径 (已规范化) 所在的挂载点，返回 (下标, 挂载点内的剩余路径)
+    fn find<'p>(&self, path: &'p str) -> Option<(usize, &'p str)> {
+        let mut best: Option<(usize, usize)> = None;
+        for (index, mount) in self.mounts.iter().enumerate() {
+            let Some(mount) = mount else { continue };
+            let prefix = mount.path();
+            let matched = prefix == "/"
+                || path == prefix
+                || (path.starts_with(prefix) && path.as_bytes()[prefix.len()] == b'/');
+            let len = if prefix == "/" { 0 } else { prefix.len() };
+            if matched && best.is_none_or(|(_, best_len)| len >= best_len) {
+                best = Some((index, len));
+            }
+        }
+        best.map(|(index, len)| (index, &path[len..]))
+    }
+
+    fn find_exact(&self, path: &str) -> Option<usize> {
+        self.mounts
+            .iter()
+            .position(|mount| mount.as_ref().is_some_and(|mount| mount.path() == path))
+    }
+
+    // 解析路径，沿途跨过挂载点
+    fn resolve(&mut self, path: &str) -> Result<InodeRef, FsError> {
+        let mut buffer = [0u8; MAX_PATH];
+        let path = normalize(path, &mut buffer)?;
+        let (mount, rest) = self.find(path).ok_or(FsError::NotInitialized)?;
+        let mut inode = InodeRef {
+            mount,
+            id: self.fs(mount)?.root(),
+        };
+        for name in rest.split('/').filter(|name| !name.is_empty()) {
+            inode.id = self.with_inode(inode, |dir| dir.lookup(name))?;
+        }
+        Ok(inode)
+    }
+
+    fn mount(&mut self, source: &str, target: &str, fstype: &str) -> Result<(), FsError> {
+        let mut buffer = [0u8; MAX_PATH];
+        let target = normalize(target, &mut buffer)?;
+        if self.find_exact(target).is_some() {
+            return Err(FsError::Busy);
+        }
+        if source.len() > MAX_SOURCE {
+            return Err(FsError::InvalidName);
+        }
+
+        // 根目录以外的挂载点必须位于已存在的目录中，挂载点本身不必存在
+        let parent = match target.rfind('/') {
+            _ if target == "/" => None,
+            Some(i) => {
+                let parent = self.resolve(&target[..i.max(1)])?;
+                if !parent.metadata()?.is_directory() {
+                    return Err(FsError::NotADirectory);
+                }
+                Some(parent)
+            }
+            None => return Err(FsError::InvalidArgument),
+        };
+        let slot = self
+            .mounts
+            .iter()
+            .position(|mount| mount.is_none())
+            .ok_or(FsError::NoSpace)?;
+        let create = FS_TYPES
+            .iter()
+            .find(|(name, _)| *name == fstype)
+            .map(|(_, create)| *create)
+            .ok_or(FsError::NotSupported)?;
+
+        let mut mount = Mount {
+            fs: create(source)?,
+            path: [0; MAX_PATH],
+            path_len: target.len(),
+            source: [0; MAX_SOURCE],
+            source_len: source.len(),
+            parent,
+        };
+        mount.path[..target.len()].copy_from_slice(target.as_bytes());
+        mount.source[..source.len()].copy_from_slice(source.as_bytes());
+        self.mounts[slot] = Some(mount);
+        Ok(())
+    }
+
+    // 还有打开的文件或下面还挂着别的文件系统时返回Busy
+    fn umount(&mut self, target: &str) -> Result<(), FsError> {
+        let mut buffer = [0u8; MAX_PATH];
+        let target = normalize(target, &mut buffer)?;
+        let index = self.find_exact(target).ok_or(FsError::InvalidArgument)?;
+        let busy = self
+            .mounts
+            .iter()
+            .flatten()
+            .any(|mount| mount.parent.is_some_and(|parent| parent.mount == index))
+            || self
+                .open_inodes
+                .iter()
+                .flatten()
+                .any(|(inode, _)| inode.mount == index);
+        let mount = self.mounts[index]
+            .as_mut()
+            .ok_or(FsError::InvalidArgument)?;
+        if busy {
+            return Err(FsError::Busy);
+        }
+        mount.fs.unmount()?;
+        self.mounts[index] = None;
+        Ok(())
+    }
+
+    // dir中的下一个挂载点目录项，被文件系统中同名文件遮住的也要列出
+    fn next_mount_entry(
+        &mut self,
+        dir: InodeRef,
+        cursor: &mut usize,
+    ) -> Option<(DirEntry, InodeRef)> {
+        while *cursor < MAX_MOUNTS {
+            let index = *cursor;
+            *cursor += 1;
+            let mut entry = match &self.mounts[index] {
+                Some(mount) if mount.parent == Some(dir) => {
+                    DirEntry::new(0, InodeType::Directory, mount.name())
+                }
+                _ => continue,
+            };
+            let shadowed = self
+                .with_inode(dir, |parent| parent.lookup(entry.name()))
+                .is_ok();
+            if shadowed {
+                continue;
+            }
+            let root = self.fs(index).ok()?.root();
+            entry.inode = root;
+            return Some((
+                entry,
+                InodeRef {
+                    mount: index,
+                    id: root,
+                },
+            ));
+        }
+        None
+    }
+}
+
+impl Default for MountTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 规范化路径：合并重复的 '/'，去掉 "."，处理 ".."，结果以 '/' 开头
+// 没有当前目录，相对路径从根目录开始
+pub fn normalize<'b>(path: &str, buffer: &'b mut [u8; MAX_PATH]) -> Result<&'b str, FsError> {
+    let mut len = 0;
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                while len > 0 && buffer[len - 1] != b'/' {
+                    len -= 1;
+                }
+                len = len.saturating_sub(1);
+            }
+            name => {
+                if len + 1 + name.len() > MAX_PATH {
+                    return Err(FsError::InvalidName);
+                }
+                buffer[len] = b'/';
+                buffer[len + 1..len + 1 + name.len()].copy_from_slice(name.as_bytes());
+                len += 1 + name.len();
+            }
+        }
+    }
+    if len == 0 {
+        buffer[0] = b'/';
+        len = 1;
+    }
+    core::str::from_utf8(&buffer[..len]).map_err(|_| FsError::InvalidName)
+}
+
+// 把规范化的路径分成父目录和最后一段
+fn split_parent(path: &str) -> Result<(&str, &str), FsError> {
+    match path.rfind('/') {
+        _ if path == "/" => Err(FsError::InvalidName),
+        Some(0) => Ok(("/", &path[1..])),
+        Some(i) => Ok((&path[..i], &path[i + 1..])),
+        None => Err(FsError::InvalidName),
+    }
+}
+
+// VFS打开的文件，持有文件系统的inode对象
+// 在files()表中分配，inode为None的项是空闲的
+struct OpenFile {
+    inode: Option<&'static mut dyn Inode>,
+    location: InodeRef,
+    kind: InodeType,
+    offset: usize,
+    flags: usize,
+}
+
+impl OpenFile {
+    const EMPTY: OpenFile = OpenFile {
+        inode: None,
+        location: InodeRef { mount: 0, id: 0 },
+        kind: InodeType::Regular,
+        offset: 0,
+        flags: 0,
+    };
+
+    fn inode(&mut self) -> Result<&mut dyn Inode, FsError> {
+        match self.inode.as_mut() {
+            Some(inode) => Ok(&mut **inode),
+            None => Err(FsError::InvalidArgument),
+        }
+    }
+}
+
+impl File for OpenFile {
+    fn location(&self) -> InodeRef {
+        self.location
+    }
+
+    fn kind(&self) -> InodeType {
+        self.kind
+    }
+
+    fn flags(&self) -> usize {
+        self.flags
+    }
+
+    fn offset(&self) -> usize {
+        self.offset
+    }
+
+    fn metadata(&mut self) -> Result<Metadata, FsError> {
+        self.inode()?.metadata()
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, FsError> {
+        if self.kind == InodeType::Directory {
+            return Err(FsError::IsADirectory);
+        }
+        if !self.readable() {
+            return Err(FsError::InvalidArgument);
+        }
+        let offset = self.offset;
+        let count = self.inode()?.read_at(offset, buffer)?;
+        self.offset += count;
+        Ok(count)
+    }
+
+    // O_APPEND时每次都写到文件末尾
+    fn write(&mut self, data: &[u8]) -> Result<usize, FsError> {
+        if !self.writable() {
+            return Err(FsError::InvalidArgument);
+        }
+        if self.flags & O_APPEND != 0 {
+            self.offset = self.metadata()?.size;
+        }
+        let offset = self.offset;
+        let count = self.inode()?.write_at(offset, data)?;
+        self.offset += count;
+        Ok(count)
+    }
+
+    fn truncate(&mut self, length: usize) -> Result<(), FsError> {
+        if !self.writable() {
+            return Err(FsError::InvalidArgument);
+        }
+        self.inode()?.truncate(length)
+    }
+
+    // 允许超过文件末尾
+    fn seek(&mut self, offset: isize, whence: usize) -> Result<usize, FsError> {
+        let base = match whence {
+            SEEK_SET => 0,
+            SEEK_CUR => self.offset,
+            SEEK_END => self.metadata()?.size,
+            _ => return Err(FsError::InvalidArgument),
+        };
+        // 目录的位置是读目录的游标，只能回到之前offset()返回的位置或查询当前位置
+        let tell = whence == SEEK_CUR && offset == 0;
+        if self.kind == InodeType::Directory && whence != SEEK_SET && !tell {
+            return Err(FsError::InvalidArgument);
+        }
+        self.offset = base
+            .checked_add_signed(offset)
+            .ok_or(FsError::InvalidArgument)?;
+        Ok(self.offset)
+    }
+
+    // 挂载点返回被挂载文件系统的根目录
+    fn read_dir(&mut self) -> Result<Option<(DirEntry, InodeRef)>, FsError> {
+        if self.kind != InodeType::Directory {
+            return Err(FsError::NotADirectory);
+        }
+        let table = mounts();
+        let dir = self.location;
+        if self.offset & MOUNT_CURSOR == 0 {
+            let mut cursor = self.offset;
+            let entry = self.inode()?.read_dir(&mut cursor)?;
+            if let Some(entry) = entry {
+                self.offset = cursor;
+                let mut inode = InodeRef {
+                    mount: dir.mount,
+                    id: entry.inode,
+                };
+                // 目录项被挂载点遮住时换成挂载的根目录
+                if let Some(index) = table.mounts.iter().position(|mount| {
+                    mount.as_ref().is_some_and(|mount| {
+                        mount.parent == Some(dir) && mount.name() == entry.name()
+                    })
+                }) {
+                    let root = table.fs(index)?.root();
+                    inode = InodeRef {
+                        mount: index,
+                        id: root,
+                    };
+                }
+                return Ok(Some((entry, inode)));
+            }
+            self.offset = MOUNT_CURSOR;
+        }
+        let mut cursor = self.offset & !MOUNT_CURSOR;
+        let entry = table.next_mount_entry(dir, &mut cursor);
+        self.offset = MOUNT_CURSOR | cursor;
+        Ok(entry)
+    }
+
+    fn close(&mut self) -> Result<(), FsError> {
+        let inode = self.inode.take().ok_or(FsError::InvalidArgument)?;
+        let result = if mounts().release_inode(self.location) {
+            inode.close()
+        } else {
+            Ok(())
+        };
+        inode.release();
+        result
+    }
+}
+
+// 打开文件表
+static mut FILES: [OpenFile; MAX_OPEN_FILES] = [OpenFile::EMPTY; MAX_OPEN_FILES];
+
+fn files() -> &'static mut [OpenFile; MAX_OPEN_FILES] {
+    unsafe { &mut *core::ptr::addr_of_mut!(FILES) }
+}
+
+// 全局挂载表
+static mut MOUNTS: MountTable = MountTable::new();
+
+fn mounts() -> &'static mut MountTable {
+    unsafe { &mut *core::ptr::addr_of_mut!(MOUNTS) }
+}
+
+// 全局函数
+// 把source上类型为fstype的文件系统挂载到target
+pub fn mount(source: &str, target: &str, fstype: &str) -> Result<(), FsError> {
+    mounts().mount(source, target, fstype)
+}
+
+pub fn umount(target: &str) -> Result<(), FsError> {
+    mounts().umount(target)
+}
+
+// 挂载在target上的文件系统类型
+pub fn mount_type(target: &str) -> Option<&'static str> {
+    let mut buffer = [0u8; MAX_PATH];
+    let table = mounts();
+    let index = table.find_exact(normalize(target, &mut buffer).ok()?)?;
+    table.fs(index).ok().map(|fs| fs.name())
+}
+
+// 遍历挂载表：(source, 挂载点, 文件系统类型)
+pub fn for_each_mount(mut f: impl FnMut(&str, &str, &str)) {
+    for mount in mounts().mounts.iter().flatten() {
+        f(mount.source(), mount.path(), mount.fs.name());
+    }
+}
+
+pub fn list_mounts() {
+    for_each_mount(|source, path, fstype| {
+        stdio::print(source);
+        stdio::print(" on ");
+        stdio::print(path);
+        stdio::print(" type ");
+        stdio::println(fstype);
+    });
+}
+
+pub fn lookup(path: &str) -> Result<InodeRef, FsError> {
+    mounts().resolve(path)
+}
+
+pub fn stat(path: &str) -> Result<Metadata, FsError> {
+    lookup(path)?.metadata()
+}
+
+// 打开文件，flags为O_*的组合
+pub fn open(path: &str, flags: usize) -> Result<&'static mut dyn File, FsError> {
+    let table = mounts();
+    let location = match table.resolve(path) {
+        Ok(_) if flags & O_CREAT != 0 && flags & O_EXCL != 0 => return Err(FsError::AlreadyExists),
+        Ok(inode) => inode,
+        Err(FsError::NotFound) if flags & O_CREAT != 0 => {
+            let mut buffer = [0u8; MAX_PATH];
+            let (parent, name) = split_parent(normalize(path, &mut buffer)?)?;
+            let dir = table.resolve(parent)?;
+            let id = table.with_inode(dir, |dir| dir.create(name, InodeType::Regular))?;
+            InodeRef {
+                mount: dir.mount,
+                id,
+            }
+        }
+        Err(err) => return Err(err),
+    };
+
+    let slot = files()
+        .iter_mut()
+        .find(|file| file.inode.is_none())
+        .ok_or(FsError::TooManyFiles)?;
+    let inode = table.fs(location.mount)?.inode(location.id)?;
+    let metadata = match inode.metadata() {
+        Ok(metadata) => metadata,
+        Err(err) => {
+            inode.release();
+            return Err(err);
+        }
+    };
+    let file = OpenFile {
+        inode: None,
+        location,
+        kind: metadata.kind,
+        offset: 0,
+        flags,
+    };
+    let checked = if flags & O_DIRECTORY != 0 && !metadata.is_directory() {
+        Err(FsError::NotADirectory)
+    } else if file.writable() && metadata.is_directory() {
+        Err(FsError::IsADirectory)
+    } else if file.writable() && metadata.read_only {
+        Err(FsError::ReadOnly)
+    } else {
+        table.retain_inode(location)
+    };
+    if let Err(err) = checked {
+        inode.release();
+        return Err(err);
+    }
+    *slot = OpenFile {
+        inode: Some(inode),
+        ..file
+    };
+    if flags & O_TRUNC != 0 && slot.writable() && metadata.is_regular_file() {
+        if let Err(err) = slot.truncate(0) {
+            let _ = close(slot);
+            return Err(err);
+        }
+    }
+    Ok(slot)
+}
+
+// 关闭文件，inode的最后一个打开的文件关闭时由文件系统写回修改
+pub fn close(file: &mut dyn File) -> Result<(), FsError> {
+    file.close()
+}
+
+// 在父目录中新建文件或目录
+fn create(path: &str, kind: InodeType) -> Result<InodeRef, FsError> {
+    let table = mounts();
+    let mut buffer = [0u8; MAX_PATH];
+    let path = normalize(path, &mut buffer)?;
+    if table.find_exact(path).is_some() {
+        return Err(FsError::AlreadyExists);
+    }
+    let (parent, name) = split_parent(path)?;
+    let dir = table.resolve(parent)?;
+    let id = table.with_inode(dir, |dir| dir.create(name, kind))?;
+    Ok(InodeRef {
+        mount: dir.mount,
+        id,
+    })
+}
+
+pub fn create_dir(path: &str) -> Result<(), FsError> {
+    create(path, InodeType::Directory).map(|_| ())
+}
+
+// 删除文件 (directory为false) 或空目录，挂载点不能删除
+fn remove(path: &str, directory: bool) -> Result<(), FsError> {
+    let table = mounts();
+    let mut buffer = [0u8; MAX_PATH];
+    let path = normalize(path, &mut buffer)?;
+    if table.find_exact(path).is_some() {
+        return Err(FsError::Busy);
+    }
+    let inode = table.resolve(path)?;
+    if table.in_use(inode) {
+        return Err(FsError::Busy);
+    }
+    let metadata = inode.metadata()?;
+    match (directory, metadata.is_directory()) {
+        (true, false) => return Err(FsError::NotADirectory),
+        (false, true) => return Err(FsError::IsADirectory),
+        _ => {}
+    }
+    let (parent, name) = split_parent(path)?;
+    let dir = table.resolve(parent)?;
+    table.with_inode(dir, |dir| dir.remove(name))
+}
+
+pub fn remove_file(path: &str) -> Result<(), FsError> {
+    remove(path, false)
+}
+
+pub fn remove_dir(path: &str) -> Result<(), FsError> {
+    remove(path, true)
+}
+
+// 重命名，新旧路径必须在同一个文件系统中
+pub fn rename(old_path: &str, new_path: &str) -> Result<(), FsError> {
+    let table = mounts();
+    let mut old_buffer = [0u8; MAX_PATH];
+    let mut new_buffer = [0u8; MAX_PATH];
+    let old_path = normalize(old_path, &mut old_buffer)?;
+    let new_path = normalize(new_path, &mut new_buffer)?;
+    if table.find_exact(old_path).is_some() || table.find_exact(new_path).is_some() {
+        return Err(FsError::Busy);
+    }
+    // 被替换的目标也不能在使用中
+    let old_inode = table.resolve(old_path)?;
+    let replaced = match table.resolve(new_path) {
+        Ok(inode) => Some(inode),
+        Err(FsError::NotFound) => None,
+        Err(err) => return Err(err),
+    };
+    if table.in_use(old_inode) || replaced.is_some_and(|inode| table.in_use(inode)) {
+        return Err(FsError::Busy);
+    }
+    let (old_parent, old_name) = split_parent(old_path)?;
+    let (new_parent, new_name) = split_parent(new_path)?;
+    let old_dir = table.resolve(old_parent)?;
+    let new_dir = table.resolve(new_parent)?;
+    if old_dir.mount != new_dir.mount {
+        return Err(FsError::CrossDevice);
+    }
+    table.with_inode(old_dir, |dir| dir.rename(old_name, new_dir.id, new_name))
+}
+
+// 遍历目录，f的参数为目录项和它的inode
+pub fn for_each_entry(path: &str, mut f: impl FnMut(&DirEntry, InodeRef)) -> Result<(), FsError> {
+    let dir = open(path, O_RDONLY | O_DIRECTORY)?;
+    let result = loop {
+        match dir.read_dir() {
+            Ok(Some((entry, inode))) => f(&entry, inode),
+            Ok(None) => break Ok(()),
+            Err(err) => break Err(err),
+        }
+    };
+    let closed = close(dir);
+    result.and(closed)
+}
+
+// 所有文件系统写回，返回遇到的第一个错误
+pub fn sync_all() -> Result<(), FsError> {
+    let mut result = Ok(());
+    for mount in mounts().mounts.iter_mut().flatten() {
+        let synced = mount.fs.sync();
+        if result.is_ok() {
+            result = synced;
+        }
+    }
+    result
+}
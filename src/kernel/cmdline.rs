@@ -0,0 +1,254 @@
+// 内核命令行
+// 来自设备树 /chosen/bootargs (QEMU -append)，格式为空格分隔的 key=value 或单独的标志
+
+use crate::drivers::uart;
+use crate::kernel::fdt::MAX_BOOTARGS;
+
+// 默认的init：内置shell
+pub const DEFAULT_INIT: &str = "/bin/sh";
+
+// initramfs中默认的init程序
+pub const DEFAULT_RDINIT: &str = "/init";
+
+// 默认的根设备：第一个virtio块设备
+pub const DEFAULT_ROOT: &str = "virtio0";
+
+// 日志级别 (loglevel=error|warn|info|debug 或 0-7)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl LogLevel {
+    pub fn parse(value: &str) -> Option<LogLevel> {
+        match value {
+            "error" => Some(LogLevel::Error),
+            "warn" => Some(LogLevel::Warn),
+            "info" => Some(LogLevel::Info),
+            "debug" => Some(LogLevel::Debug),
+            // 与Linux的数字级别对应：0-3错误，4警告，5-6信息，7调试
+            _ => match value.parse::<u8>().ok()? {
+                0..=3 => Some(LogLevel::Error),
+                4 => Some(LogLevel::Warn),
+                5 | 6 => Some(LogLevel::Info),
+                _ => Some(LogLevel::Debug),
+            },
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+        }
+    }
+}
+
+// 内核恐慌后的动作 (panic=halt|reboot|poweroff)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PanicAction {
+    Halt,
+    Reboot,
+    Poweroff,
+}
+
+impl PanicAction {
+    pub fn parse(value: &str) -> Option<PanicAction> {
+        match value {
+            "halt" => Some(PanicAction::Halt),
+            "reboot" => Some(PanicAction::Reboot),
+            "poweroff" => Some(PanicAction::Poweroff),
+            _ => None,
+        }
+    }
+}
+
+// 解析后的命令行
+pub struct Cmdline {
+    raw: [u8; MAX_BOOTARGS],
+    len: usize,
+    log_level: LogLevel,
+    panic_action: PanicAction,
+}
+
+impl Cmdline {
+    pub const fn new() -> Self {
+        Self {
+            raw: [0; MAX_BOOTARGS],
+            len: 0,
+            log_level: LogLevel::Info,
+            panic_action: PanicAction::Halt,
+        }
+    }
+
+    // 保存命令行并解析类型化的选项，无法识别的值给出警告并使用默认值
+    pub fn parse(&mut self, args: &str) {
+        // 过长时截断，不能把多字节字符切成两半，否则as_str会得到空串
+        let mut len = args.len().min(MAX_BOOTARGS);
+        while !args.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.len = len;
+        self.raw[..self.len].copy_from_slice(&args.as_bytes()[..self.len]);
+
+        self.log_level = LogLevel::Info;
+        if self.has("quiet") {
+            self.log_level = LogLevel::Warn;
+        }
+        if let Some(value) = self.get("loglevel") {
+            match LogLevel::parse(value) {
+                Some(level) => self.log_level = level,
+                None => warn_invalid("loglevel", value),
+            }
+        }
+
+        self.panic_action = PanicAction::Halt;
+        if let Some(value) = self.get("panic") {
+            match PanicAction::parse(value) {
+                Some(action) => self.panic_action = action,
+                None => warn_invalid("panic", value),
+            }
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.raw[..self.len]).unwrap_or("")
+    }
+
+    // key=value 的值，同一个键出现多次时以最后一次为准
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.as_str()
+            .split_ascii_whitespace()
+            .rev()
+            .filter_map(|word| word.split_once('='))
+            .find(|(name, _)| *name == key)
+            .map(|(_, value)| value)
+    }
+
+    // 是否给出了标志 (不带值)
+    pub fn has(&self, flag: &str) -> bool {
+        self.as_str()
+            .split_ascii_whitespace()
+            .any(|word| word == flag)
+    }
+}
+
+impl Default for Cmdline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn warn_invalid(key: &str, value: &str) {
+    uart::print("cmdline: invalid value for ");
+    uart::print(key);
+    uart::print(": ");
+    uart::println(value);
+}
+
+// 全局命令行
+static mut CMDLINE: Cmdline = Cmdline::new();
+
+fn cmdline() -> &'static mut Cmdline {
+    unsafe { &mut *core::ptr::addr_of_mut!(CMDLINE) }
+}
+
+// 全局函数
+pub fn init(args: &str) {
+    cmdline().parse(args);
+}
+
+pub fn as_str() -> &'static str {
+    cmdline().as_str()
+}
+
+pub fn get(key: &str) -> Option<&'static str> {
+    cmdline().get(key)
+}
+
+pub fn has(flag: &str) -> bool {
+    cmdline().has(flag)
+}
+
+// init=：启动的第一个程序
+pub fn init_path() -> &'static str {
+    get("init").unwrap_or(DEFAULT_INIT)
+}
+
+// rdinit=：initramfs中的init程序 (没有给出init=时使用)
+pub fn rdinit_path() -> &'static str {
+    get("rdinit").unwrap_or(DEFAULT_RDINIT)
+}
+
+// root=：根文件系统所在的设备
+pub fn root() -> &'static str {
+    get("root").unwrap_or(DEFAULT_ROOT)
+}
+
+pub fn log_level() -> LogLevel {
+    cmdline().log_level
+}
+
+// 当前日志级别是否输出level级别的信息
+pub fn log_enabled(level: LogLevel) -> bool {
+    level <= cmdline().log_level
+}
+
+pub fn panic_action() -> PanicAction {
+    cmdline().panic_action
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::String;
+
+    fn parse(args: &str) -> Cmdline {
+        let mut cmdline = Cmdline::new();
+        cmdline.parse(args);
+        cmdline
+    }
+
+    #[test]
+    fn keys_and_flags() {
+        let cmdline = parse("root=virtio0p1  init=/bin/sh quiet rw");
+        assert_eq!(cmdline.get("root"), Some("virtio0p1"));
+        assert_eq!(cmdline.get("init"), Some("/bin/sh"));
+        assert_eq!(cmdline.get("rdinit"), None);
+        assert!(cmdline.has("quiet"));
+        assert!(cmdline.has("rw"));
+        assert!(!cmdline.has("root"));
+    }
+
+    #[test]
+    fn last_value_wins() {
+        let cmdline = parse("loglevel=debug root=a root=b loglevel=3");
+        assert_eq!(cmdline.get("root"), Some("b"));
+        assert_eq!(cmdline.log_level, LogLevel::Error);
+    }
+
+    #[test]
+    fn typed_options() {
+        assert_eq!(parse("").log_level, LogLevel::Info);
+        assert_eq!(parse("quiet").log_level, LogLevel::Warn);
+        assert_eq!(parse("quiet loglevel=7").log_level, LogLevel::Debug);
+        assert_eq!(parse("panic=reboot").panic_action, PanicAction::Reboot);
+        assert_eq!(parse("").panic_action, PanicAction::Halt);
+        assert_eq!(LogLevel::parse("5"), Some(LogLevel::Info));
+        assert_eq!(LogLevel::parse("verbose"), None);
+        assert_eq!(PanicAction::parse("explode"), None);
+    }
+
+    #[test]
+    fn truncates_on_char_boundary() {
+        // 截断位置落在 "é" 的两个字节之间
+        let mut args = "a".repeat(MAX_BOOTARGS - 1);
+        args.push_str("éx");
+        assert_eq!(parse(&args).as_str().len(), MAX_BOOTARGS - 1);
+    }
+}
@@ -0,0 +1,230 @@
+// 第13章：用户模式
+// ELF 可执行文件加载
+
+use crate::fs::vfs;
+use crate::kernel::memory::{self, PAGE_SIZE};
+use crate::kernel::paging::{self, AddressSpace};
+
+// ELF 常量
+const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ET_EXEC: u16 = 2;
+const EM_RISCV: u16 = 243;
+const PT_LOAD: u32 = 1;
+const ELF_HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+
+// 最多加载的 PT_LOAD 段数
+pub const MAX_SEGMENTS: usize = 8;
+
+// 段权限
+const PF_X: u32 = 0x1;
+const PF_W: u32 = 0x2;
+const PF_R: u32 = 0x4;
+
+// 加载错误
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ElfError {
+    NotFound,
+    IoError,
+    BadMagic,
+    Unsupported,
+    BadProgramHeader,
+    OutOfMemory,
+}
+
+impl ElfError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ElfError::NotFound => "no such file",
+            ElfError::IoError => "I/O error",
+            ElfError::BadMagic => "not an ELF file",
+            ElfError::Unsupported => {
+                "unsupported ELF (need 64-bit little-endian RISC-V executable)"
+            }
+            ElfError::BadProgramHeader => "bad program header",
+            ElfError::OutOfMemory => "out of memory",
+        }
+    }
+}
+
+// 为一个段分配的物理页
+#[derive(Debug, Clone, Copy)]
+pub struct Segment {
+    pub base: usize,
+    pub pages: usize,
+}
+
+// 加载结果
+#[derive(Debug, Clone, Copy)]
+pub struct ElfImage {
+    pub entry: usize,
+    pub segments: [Segment; MAX_SEGMENTS],
+    pub count: usize,
+}
+
+impl ElfImage {
+    const fn new(entry: usize) -> Self {
+        Self {
+            entry,
+            segments: [Segment { base: 0, pages: 0 }; MAX_SEGMENTS],
+            count: 0,
+        }
+    }
+
+    // 释放所有段的内存 (进程退出或加载失败时)
+    pub fn release(&mut self) {
+        for segment in &self.segments[..self.count] {
+            memory::deallocate_pages(segment.base as *mut u8, segment.pages);
+        }
+        self.count = 0;
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> usize {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(bytes) as usize
+}
+
+// 把 PT_LOAD 段复制到新分配的内存，并在进程的地址空间中映射到段的虚拟地址
+// 失败时已经分配的段被释放
+pub fn load(data: &[u8], space: &mut AddressSpace) -> Result<ElfImage, ElfError> {
+    let mut image = ElfImage::new(0);
+    let result = load_segments(data, space, &mut image);
+    if result.is_err() {
+        image.release();
+    }
+    result.map(|_| image)
+}
+
+fn load_segments(
+    data: &[u8],
+    space: &mut AddressSpace,
+    image: &mut ElfImage,
+) -> Result<(), ElfError> {
+    if data.len() < ELF_HEADER_SIZE || data[..4] != ELF_MAGIC {
+        return Err(ElfError::BadMagic);
+    }
+    if data[4] != ELFCLASS64
+        || data[5] != ELFDATA2LSB
+        || read_u16(data, 16) != ET_EXEC
+        || read_u16(data, 18) != EM_RISCV
+    {
+        return Err(ElfError::Unsupported);
+    }
+
+    image.entry = read_u64(data, 24);
+    let ph_offset = read_u64(data, 32);
+    let ph_entry_size = read_u16(data, 54) as usize;
+    let ph_count = read_u16(data, 56) as usize;
+    let ph_end = ph_entry_size
+        .checked_mul(ph_count)
+        .and_then(|size| size.checked_add(ph_offset))
+        .unwrap_or(usize::MAX);
+    if ph_entry_size < PROGRAM_HEADER_SIZE || ph_end > data.len() {
+        return Err(ElfError::BadProgramHeader);
+    }
+
+    for i in 0..ph_count {
+        let ph = ph_offset + i * ph_entry_size;
+        if read_u32(data, ph) != PT_LOAD {
+            continue;
+        }
+
+        let flags = read_u32(data, ph + 4);
+        let offset = read_u64(data, ph + 8);
+        let vaddr = read_u64(data, ph + 16);
+        let file_size = read_u64(data, ph + 32);
+        let mem_size = read_u64(data, ph + 40);
+        let file_end = offset.saturating_add(file_size);
+        if file_size > mem_size || file_end > data.len() {
+            return Err(ElfError::BadProgramHeader);
+        }
+
+        if image.count == MAX_SEGMENTS {
+            return Err(ElfError::BadProgramHeader);
+        }
+
+        // 按页对齐分配，段内偏移保持不变
+        let page_offset = vaddr & (PAGE_SIZE - 1);
+        let pages = page_offset
+            .checked_add(mem_size)
+            .ok_or(ElfError::BadProgramHeader)?
+            .div_ceil(PAGE_SIZE);
+        let size = pages * PAGE_SIZE;
+        let base = memory::allocate_pages(pages).ok_or(ElfError::OutOfMemory)? as usize;
+        image.segments[image.count] = Segment { base, pages };
+        image.count += 1;
+
+        unsafe {
+            memory::memset(base as *mut u8, 0, size);
+            memory::memcpy(
+                (base + page_offset) as *mut u8,
+                data[offset..].as_ptr(),
+                file_size,
+            );
+        }
+
+        let mut pte_flags = paging::PTE_U;
+        if flags & PF_R != 0 {
+            pte_flags |= paging::PTE_R;
+        }
+        if flags & PF_W != 0 {
+            pte_flags |= paging::PTE_W;
+        }
+        if flags & PF_X != 0 {
+            pte_flags |= paging::PTE_X;
+        }
+        // 与已经加载的段重叠
+        if !space.map(vaddr - page_offset, base, size, pte_flags) {
+            return Err(ElfError::BadProgramHeader);
+        }
+    }
+
+    if image.count == 0 {
+        return Err(ElfError::BadProgramHeader);
+    }
+    Ok(())
+}
+
+// 从文件系统读取并加载ELF文件
+pub fn load_file(path: &str, space: &mut AddressSpace) -> Result<ElfImage, ElfError> {
+    let metadata = vfs::stat(path).map_err(|_| ElfError::NotFound)?;
+    if !metadata.is_regular_file() {
+        return Err(ElfError::NotFound);
+    }
+
+    let size = metadata.size;
+    let buffer = memory::allocate(size).ok_or(ElfError::OutOfMemory)?;
+    let data = unsafe { core::slice::from_raw_parts_mut(buffer, size) };
+
+    let file = vfs::open(path, vfs::O_RDONLY).map_err(|_| ElfError::NotFound)?;
+    let mut total = 0;
+    while total < size {
+        match file.read(&mut data[total..]) {
+            Ok(0) | Err(_) => break,
+            Ok(count) => total += count,
+        }
+    }
+    let _ = vfs::close(file);
+
+    let result = if total == size {
+        load(data, space)
+    } else {
+        Err(ElfError::IoError)
+    };
+    memory::deallocate(buffer, size);
+    result
+}
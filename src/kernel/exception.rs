@@ -3,24 +3,275 @@
 
 use crate::arch::riscv::*;
 use crate::common::types::ToString;
-use crate::drivers::uart;
+use crate::drivers::{clint, plic, uart};
 
-// 异常处理函数
+// 陷阱帧：32个通用寄存器 (regs[0]不用) 和mepc
+// 内核中的陷阱保存在栈上，用户进程的上下文保存在进程控制块中
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct TrapFrame {
+    pub regs: [usize; 32],
+    pub pc: usize,
+}
+
+impl TrapFrame {
+    pub const fn new() -> Self {
+        Self {
+            regs: [0; 32],
+            pc: 0,
+        }
+    }
+}
+
+impl Default for TrapFrame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 陷阱帧在栈上占的字节数，保持16字节对齐
+#[cfg(target_arch = "riscv64")]
+const TRAP_FRAME_SIZE: usize = 34 * 8;
+// enter_user在内核栈上保存的内容：ra、s0-s11和用户上下文指针
+#[cfg(target_arch = "riscv64")]
+const KERNEL_CONTEXT_SIZE: usize = 14 * 8;
+
+// 陷阱入口。mscratch在内核中为0，运行用户进程时是enter_user保存内核上下文的栈位置
+// 内核中的陷阱：在当前栈上保存所有通用寄存器和mepc，调用handle_exception，
+// 再恢复寄存器并用mret返回被打断的代码 (mepc可能被处理函数修改)
+// 用户进程的陷阱：把寄存器和mepc存入用户上下文，恢复内核上下文，从enter_user返回
+#[cfg(target_arch = "riscv64")]
+core::arch::global_asm!(
+    ".balign 4",
+    ".global trap_entry",
+    "trap_entry:",
+    "csrrw sp, mscratch, sp",
+    "bnez sp, 1f",
+    "csrrw sp, mscratch, sp",
+    "addi sp, sp, -{frame}",
+    "sd x1, 1*8(sp)",
+    "sd x3, 3*8(sp)",
+    "sd x4, 4*8(sp)",
+    "sd x5, 5*8(sp)",
+    "sd x6, 6*8(sp)",
+    "sd x7, 7*8(sp)",
+    "sd x8, 8*8(sp)",
+    "sd x9, 9*8(sp)",
+    "sd x10, 10*8(sp)",
+    "sd x11, 11*8(sp)",
+    "sd x12, 12*8(sp)",
+    "sd x13, 13*8(sp)",
+    "sd x14, 14*8(sp)",
+    "sd x15, 15*8(sp)",
+    "sd x16, 16*8(sp)",
+    "sd x17, 17*8(sp)",
+    "sd x18, 18*8(sp)",
+    "sd x19, 19*8(sp)",
+    "sd x20, 20*8(sp)",
+    "sd x21, 21*8(sp)",
+    "sd x22, 22*8(sp)",
+    "sd x23, 23*8(sp)",
+    "sd x24, 24*8(sp)",
+    "sd x25, 25*8(sp)",
+    "sd x26, 26*8(sp)",
+    "sd x27, 27*8(sp)",
+    "sd x28, 28*8(sp)",
+    "sd x29, 29*8(sp)",
+    "sd x30, 30*8(sp)",
+    "sd x31, 31*8(sp)",
+    // 被打断时的sp
+    "addi t0, sp, {frame}",
+    "sd t0, 2*8(sp)",
+    "csrr t0, mepc",
+    "sd t0, 32*8(sp)",
+    "mv a0, sp",
+    "call handle_exception",
+    "ld t0, 32*8(sp)",
+    "csrw mepc, t0",
+    "ld x1, 1*8(sp)",
+    "ld x3, 3*8(sp)",
+    "ld x4, 4*8(sp)",
+    "ld x5, 5*8(sp)",
+    "ld x6, 6*8(sp)",
+    "ld x7, 7*8(sp)",
+    "ld x8, 8*8(sp)",
+    "ld x9, 9*8(sp)",
+    "ld x10, 10*8(sp)",
+    "ld x11, 11*8(sp)",
+    "ld x12, 12*8(sp)",
+    "ld x13, 13*8(sp)",
+    "ld x14, 14*8(sp)",
+    "ld x15, 15*8(sp)",
+    "ld x16, 16*8(sp)",
+    "ld x17, 17*8(sp)",
+    "ld x18, 18*8(sp)",
+    "ld x19, 19*8(sp)",
+    "ld x20, 20*8(sp)",
+    "ld x21, 21*8(sp)",
+    "ld x22, 22*8(sp)",
+    "ld x23, 23*8(sp)",
+    "ld x24, 24*8(sp)",
+    "ld x25, 25*8(sp)",
+    "ld x26, 26*8(sp)",
+    "ld x27, 27*8(sp)",
+    "ld x28, 28*8(sp)",
+    "ld x29, 29*8(sp)",
+    "ld x30, 30*8(sp)",
+    "ld x31, 31*8(sp)",
+    "addi sp, sp, {frame}",
+    "mret",
+    // 来自用户进程：sp是内核上下文，mscratch是用户sp
+    "1:",
+    "sd a0, -8(sp)",
+    "ld a0, 13*8(sp)",
+    "sd x1, 1*8(a0)",
+    "sd x3, 3*8(a0)",
+    "sd x4, 4*8(a0)",
+    "sd x5, 5*8(a0)",
+    "sd x6, 6*8(a0)",
+    "sd x7, 7*8(a0)",
+    "sd x8, 8*8(a0)",
+    "sd x9, 9*8(a0)",
+    "sd x11, 11*8(a0)",
+    "sd x12, 12*8(a0)",
+    "sd x13, 13*8(a0)",
+    "sd x14, 14*8(a0)",
+    "sd x15, 15*8(a0)",
+    "sd x16, 16*8(a0)",
+    "sd x17, 17*8(a0)",
+    "sd x18, 18*8(a0)",
+    "sd x19, 19*8(a0)",
+    "sd x20, 20*8(a0)",
+    "sd x21, 21*8(a0)",
+    "sd x22, 22*8(a0)",
+    "sd x23, 23*8(a0)",
+    "sd x24, 24*8(a0)",
+    "sd x25, 25*8(a0)",
+    "sd x26, 26*8(a0)",
+    "sd x27, 27*8(a0)",
+    "sd x28, 28*8(a0)",
+    "sd x29, 29*8(a0)",
+    "sd x30, 30*8(a0)",
+    "sd x31, 31*8(a0)",
+    "ld t0, -8(sp)",
+    "sd t0, 10*8(a0)",
+    "csrr t0, mscratch",
+    "sd t0, 2*8(a0)",
+    "csrr t0, mepc",
+    "sd t0, 32*8(a0)",
+    "csrw mscratch, zero",
+    "ld ra, 0*8(sp)",
+    "ld s0, 1*8(sp)",
+    "ld s1, 2*8(sp)",
+    "ld s2, 3*8(sp)",
+    "ld s3, 4*8(sp)",
+    "ld s4, 5*8(sp)",
+    "ld s5, 6*8(sp)",
+    "ld s6, 7*8(sp)",
+    "ld s7, 8*8(sp)",
+    "ld s8, 9*8(sp)",
+    "ld s9, 10*8(sp)",
+    "ld s10, 11*8(sp)",
+    "ld s11, 12*8(sp)",
+    "addi sp, sp, {kernel}",
+    "ret",
+    // enter_user(context)：保存内核上下文，载入用户寄存器，mret进入mstatus.MPP指定的模式
+    ".balign 4",
+    ".global enter_user",
+    "enter_user:",
+    "addi sp, sp, -{kernel}",
+    "sd ra, 0*8(sp)",
+    "sd s0, 1*8(sp)",
+    "sd s1, 2*8(sp)",
+    "sd s2, 3*8(sp)",
+    "sd s3, 4*8(sp)",
+    "sd s4, 5*8(sp)",
+    "sd s5, 6*8(sp)",
+    "sd s6, 7*8(sp)",
+    "sd s7, 8*8(sp)",
+    "sd s8, 9*8(sp)",
+    "sd s9, 10*8(sp)",
+    "sd s10, 11*8(sp)",
+    "sd s11, 12*8(sp)",
+    "sd a0, 13*8(sp)",
+    "csrw mscratch, sp",
+    "ld t0, 32*8(a0)",
+    "csrw mepc, t0",
+    "ld x1, 1*8(a0)",
+    "ld x2, 2*8(a0)",
+    "ld x3, 3*8(a0)",
+    "ld x4, 4*8(a0)",
+    "ld x5, 5*8(a0)",
+    "ld x6, 6*8(a0)",
+    "ld x7, 7*8(a0)",
+    "ld x8, 8*8(a0)",
+    "ld x9, 9*8(a0)",
+    "ld x11, 11*8(a0)",
+    "ld x12, 12*8(a0)",
+    "ld x13, 13*8(a0)",
+    "ld x14, 14*8(a0)",
+    "ld x15, 15*8(a0)",
+    "ld x16, 16*8(a0)",
+    "ld x17, 17*8(a0)",
+    "ld x18, 18*8(a0)",
+    "ld x19, 19*8(a0)",
+    "ld x20, 20*8(a0)",
+    "ld x21, 21*8(a0)",
+    "ld x22, 22*8(a0)",
+    "ld x23, 23*8(a0)",
+    "ld x24, 24*8(a0)",
+    "ld x25, 25*8(a0)",
+    "ld x26, 26*8(a0)",
+    "ld x27, 27*8(a0)",
+    "ld x28, 28*8(a0)",
+    "ld x29, 29*8(a0)",
+    "ld x30, 30*8(a0)",
+    "ld x31, 31*8(a0)",
+    "ld x10, 10*8(a0)",
+    "mret",
+    frame = const TRAP_FRAME_SIZE,
+    kernel = const KERNEL_CONTEXT_SIZE,
+);
+
+extern "C" {
+    fn trap_entry();
+    fn enter_user(context: *mut TrapFrame);
+}
+
+/// 切换到用户进程运行，直到发生陷阱 (系统调用、异常或中断) 时返回，
+/// 返回后mcause和mtval说明陷阱原因
+///
+/// # Safety
+/// 调用前要关闭中断并设置好mstatus.MPP、MPIE和satp
+pub unsafe fn run_user(context: &mut TrapFrame) {
+    enter_user(context);
+}
+
+// 异常处理函数，由trap_entry调用
 #[no_mangle]
-pub extern "C" fn handle_exception() {
+pub extern "C" fn handle_exception(frame: &mut TrapFrame) {
     let mcause = unsafe { read_mcause() };
-    let mepc = unsafe { read_mepc() };
     let mtval = unsafe { read_mtval() };
 
     // 检查是异常还是中断
     if mcause & (1 << 63) != 0 {
-        // 中断
+        // 中断：返回被打断的指令继续执行
         let interrupt_code = mcause & 0x7FFFFFFF;
         handle_interrupt(interrupt_code);
     } else {
         // 异常
         let exception_code = mcause;
-        handle_exception_code(exception_code, mepc, mtval);
+        handle_exception_code(exception_code, frame, mtval);
+    }
+}
+
+// mepc处指令的长度：低两位不是11的是16位压缩指令
+fn instruction_len(pc: usize) -> usize {
+    let low = unsafe { core::ptr::read_volatile(pc as *const u16) };
+    if low & 0b11 == 0b11 {
+        4
+    } else {
+        2
     }
 }
 
@@ -28,12 +279,14 @@ pub extern "C" fn handle_exception() {
 fn handle_interrupt(code: usize) {
     match code {
         INTERRUPT_MACHINE_TIMER => {
-            uart::println("Timer interrupt");
-            // 处理定时器中断
+            // 处理定时器中断：设置下一个节拍，累计当前进程的CPU时间，写回停留太久的脏块
+            clint::CLINT.set_next_tick();
+            crate::kernel::process::tick();
+            crate::fs::bcache::writeback_expired();
         }
         INTERRUPT_MACHINE_EXTERNAL => {
-            uart::println("External interrupt");
-            // 处理外部中断
+            // 外部中断由PLIC分发 (不能在这里打印，UART中断也走这条路径)
+            plic::handle_external_interrupt();
         }
         _ => {
             uart::print("Unknown interrupt: ");
@@ -42,86 +295,59 @@ fn handle_interrupt(code: usize) {
     }
 }
 
-// 处理异常
-fn handle_exception_code(code: usize, mepc: usize, mtval: usize) {
-    uart::print("Exception: ");
-
+// 异常名称
+pub fn exception_name(code: usize) -> &'static str {
     match code {
-        EXCEPTION_INSTRUCTION_MISALIGNED => {
-            uart::println("Instruction address misaligned");
-        }
-        EXCEPTION_INSTRUCTION_ACCESS_FAULT => {
-            uart::println("Instruction access fault");
-        }
-        EXCEPTION_ILLEGAL_INSTRUCTION => {
-            uart::println("Illegal instruction");
-        }
-        EXCEPTION_BREAKPOINT => {
-            uart::println("Breakpoint");
-        }
-        EXCEPTION_LOAD_ADDRESS_MISALIGNED => {
-            uart::println("Load address misaligned");
-        }
-        EXCEPTION_LOAD_ACCESS_FAULT => {
-            uart::println("Load access fault");
-        }
-        EXCEPTION_STORE_ADDRESS_MISALIGNED => {
-            uart::println("Store address misaligned");
-        }
-        EXCEPTION_STORE_ACCESS_FAULT => {
-            uart::println("Store access fault");
-        }
-        EXCEPTION_ECALL_FROM_USER => {
-            uart::println("Environment call from user mode");
-            handle_syscall(mepc);
-        }
-        EXCEPTION_ECALL_FROM_SUPERVISOR => {
-            uart::println("Environment call from supervisor mode");
-        }
-        EXCEPTION_ECALL_FROM_MACHINE => {
-            uart::println("Environment call from machine mode");
-        }
-        _ => {
-            uart::print("Unknown exception: ");
-            uart::println(&code.to_string());
-        }
+        EXCEPTION_INSTRUCTION_MISALIGNED => "Instruction address misaligned",
+        EXCEPTION_INSTRUCTION_ACCESS_FAULT => "Instruction access fault",
+        EXCEPTION_ILLEGAL_INSTRUCTION => "Illegal instruction",
+        EXCEPTION_BREAKPOINT => "Breakpoint",
+        EXCEPTION_LOAD_ADDRESS_MISALIGNED => "Load address misaligned",
+        EXCEPTION_LOAD_ACCESS_FAULT => "Load access fault",
+        EXCEPTION_STORE_ADDRESS_MISALIGNED => "Store address misaligned",
+        EXCEPTION_STORE_ACCESS_FAULT => "Store access fault",
+        EXCEPTION_ECALL_FROM_USER => "Environment call from user mode",
+        EXCEPTION_ECALL_FROM_SUPERVISOR => "Environment call from supervisor mode",
+        EXCEPTION_ECALL_FROM_MACHINE => "Environment call from machine mode",
+        EXCEPTION_INSTRUCTION_PAGE_FAULT => "Instruction page fault",
+        EXCEPTION_LOAD_PAGE_FAULT => "Load page fault",
+        EXCEPTION_STORE_PAGE_FAULT => "Store page fault",
+        _ => "Unknown exception",
     }
+}
 
+// 处理内核中的异常：ecall和ebreak跳过该指令继续执行，其他异常返回后会再次触发，无法恢复
+// (用户进程的异常由process::run处理)
+fn handle_exception_code(code: usize, frame: &mut TrapFrame, mtval: usize) {
+    let mepc = frame.pc;
+    uart::print("Exception: ");
+    uart::print(exception_name(code));
+    uart::print(" (");
+    uart::print(&code.to_string());
+    uart::println(")");
     uart::print("MEPC: 0x");
     uart::println(&mepc.to_string());
     uart::print("MTVAL: 0x");
     uart::println(&mtval.to_string());
-}
-
-// 处理系统调用
-fn handle_syscall(mepc: usize) {
-    // 从a7寄存器读取系统调用号
-    let syscall_num: usize;
-    unsafe {
-        core::arch::asm!("mv {}, a7", out(reg) syscall_num);
-    }
 
-    match syscall_num {
-        SYS_WRITE => {
-            uart::println("System call: write");
-            // 处理write系统调用
-        }
-        SYS_EXIT => {
-            uart::println("System call: exit");
-            // 处理exit系统调用
-        }
-        _ => {
-            uart::print("Unknown syscall: ");
-            uart::println(&syscall_num.to_string());
+    match code {
+        EXCEPTION_BREAKPOINT | EXCEPTION_ECALL_FROM_SUPERVISOR | EXCEPTION_ECALL_FROM_MACHINE => {
+            frame.pc += instruction_len(mepc)
         }
+        _ => panic!("unhandled exception"),
     }
 }
 
 // 设置异常处理向量
 pub fn init_exception_handling() {
     unsafe {
-        // 设置异常处理向量地址
-        write_mtvec(handle_exception as usize);
+        // 设置异常处理向量地址，mscratch为0表示陷阱来自内核
+        #[cfg(target_arch = "riscv64")]
+        core::arch::asm!("csrw mscratch, zero");
+        write_mtvec(trap_entry as usize);
+
+        // 允许U模式访问内存 (由进程的页表限制)
+        init_pmp();
 
         // 启用机器模式中断
         write_mie(read_mie() | (1 << INTERRUPT_MACHINE_TIMER) | (1 << INTERRUPT_MACHINE_EXTERNAL));
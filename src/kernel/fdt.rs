@@ -0,0 +1,497 @@
+// 扁平设备树 (FDT) 解析
+// QEMU启动时在a1中传入设备树地址，这里不分配内存，直接在原始数据上遍历
+
+use crate::drivers::virtio::VIRTIO_MMIO_COUNT;
+
+// FDT 头部
+const FDT_MAGIC: u32 = 0xd00dfeed;
+const FDT_HEADER_SIZE: usize = 40;
+const HEADER_TOTAL_SIZE: usize = 4;
+const HEADER_OFF_STRUCT: usize = 8;
+const HEADER_OFF_STRINGS: usize = 12;
+const HEADER_SIZE_STRINGS: usize = 32;
+const HEADER_SIZE_STRUCT: usize = 36;
+
+// 结构块中的标记
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+const FDT_END: u32 = 9;
+
+// 节点嵌套深度、路径和字符串属性的长度上限
+const MAX_DEPTH: usize = 16;
+const MAX_PATH: usize = 128;
+pub const MAX_BOOTARGS: usize = 256;
+
+// 未指定时 #address-cells 和 #size-cells 的默认值
+const DEFAULT_ADDRESS_CELLS: u32 = 2;
+const DEFAULT_SIZE_CELLS: u32 = 1;
+
+// 结构块中的一个元素
+pub enum Token<'a> {
+    BeginNode(&'a str),
+    Property(&'a str, &'a [u8]),
+    EndNode,
+}
+
+pub struct Fdt<'a> {
+    structure: &'a [u8],
+    strings: &'a [u8],
+}
+
+impl<'a> Fdt<'a> {
+    // 检查头部并划出结构块和字符串块
+    pub fn new(blob: &'a [u8]) -> Option<Self> {
+        if be32(blob, 0)? != FDT_MAGIC {
+            return None;
+        }
+        let off_struct = be32(blob, HEADER_OFF_STRUCT)? as usize;
+        let size_struct = be32(blob, HEADER_SIZE_STRUCT)? as usize;
+        let off_strings = be32(blob, HEADER_OFF_STRINGS)? as usize;
+        let size_strings = be32(blob, HEADER_SIZE_STRINGS)? as usize;
+        Some(Self {
+            structure: blob.get(off_struct..off_struct.checked_add(size_struct)?)?,
+            strings: blob.get(off_strings..off_strings.checked_add(size_strings)?)?,
+        })
+    }
+
+    // 从物理地址上的设备树构造 (QEMU传入的地址)，返回 (设备树, 总大小)
+    pub fn from_addr(addr: usize) -> Option<(Fdt<'static>, usize)> {
+        if addr == 0 || !addr.is_multiple_of(4) {
+            return None;
+        }
+        let header = unsafe { core::slice::from_raw_parts(addr as *const u8, FDT_HEADER_SIZE) };
+        if be32(header, 0)? != FDT_MAGIC {
+            return None;
+        }
+        let total_size = be32(header, HEADER_TOTAL_SIZE)? as usize;
+        if total_size < FDT_HEADER_SIZE {
+            return None;
+        }
+        let blob = unsafe { core::slice::from_raw_parts(addr as *const u8, total_size) };
+        Some((Fdt::new(blob)?, total_size))
+    }
+
+    // 按顺序遍历结构块，格式错误时返回false
+    pub fn walk(&self, mut f: impl FnMut(Token<'a>)) -> bool {
+        self.walk_tokens(&mut f).is_some()
+    }
+
+    fn walk_tokens(&self, f: &mut impl FnMut(Token<'a>)) -> Option<()> {
+        let data = self.structure;
+        let mut pos = 0;
+        loop {
+            let token = be32(data, pos)?;
+            pos += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name = cstr(data, pos)?;
+                    pos = align4(pos + name.len() + 1);
+                    f(Token::BeginNode(name));
+                }
+                FDT_END_NODE => f(Token::EndNode),
+                FDT_PROP => {
+                    let len = be32(data, pos)? as usize;
+                    let name_offset = be32(data, pos + 4)? as usize;
+                    pos += 8;
+                    let value = data.get(pos..pos.checked_add(len)?)?;
+                    pos = align4(pos + len);
+                    f(Token::Property(cstr(self.strings, name_offset)?, value));
+                }
+                FDT_NOP => {}
+                FDT_END => return Some(()),
+                _ => return None,
+            }
+        }
+    }
+}
+
+// 读取大端32位数
+fn be32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+// 读取以NUL结尾的字符串
+fn cstr(data: &[u8], offset: usize) -> Option<&str> {
+    let bytes = data.get(offset..)?;
+    let len = bytes.iter().position(|&c| c == 0)?;
+    core::str::from_utf8(&bytes[..len]).ok()
+}
+
+fn align4(value: usize) -> usize {
+    (value + 3) & !3
+}
+
+// 读取由cells个32位单元组成的数 (最多2个)
+fn read_cells(data: &[u8], index: usize, cells: u32) -> Option<usize> {
+    let mut value = 0usize;
+    for i in 0..cells as usize {
+        value = (value << 32) | be32(data, (index + i) * 4)? as usize;
+    }
+    Some(value)
+}
+
+// 32位或64位的地址属性
+fn prop_addr(value: &[u8]) -> Option<usize> {
+    match value.len() {
+        4 | 8 => read_cells(value, 0, value.len() as u32 / 4),
+        _ => None,
+    }
+}
+
+// 字符串属性 (去掉结尾的NUL)
+fn prop_str(value: &[u8]) -> &str {
+    let len = value.iter().position(|&c| c == 0).unwrap_or(value.len());
+    core::str::from_utf8(&value[..len]).unwrap_or("")
+}
+
+// compatible 属性是多个NUL分隔的字符串，任一匹配即可
+fn compatible_with(value: &[u8], names: &[&str]) -> bool {
+    value
+        .split(|&c| c == 0)
+        .any(|item| names.iter().any(|name| item == name.as_bytes()))
+}
+
+// 节点名去掉 @单元地址 后的部分
+fn base_name(name: &str) -> &str {
+    name.split('@').next().unwrap_or(name)
+}
+
+// 我们关心的设备类型
+#[derive(Clone, Copy, PartialEq)]
+enum NodeKind {
+    Other,
+    Uart,
+    Clint,
+    Plic,
+    Virtio,
+    Syscon,
+}
+
+// 正在遍历的节点的状态
+#[derive(Clone, Copy)]
+struct NodeState {
+    kind: NodeKind,
+    memory: bool,
+    cpu: bool,
+    reg: Option<(usize, usize)>,
+    clock_frequency: Option<u32>,
+    is_stdout: bool,
+    // 子节点reg属性的格式
+    address_cells: u32,
+    size_cells: u32,
+    path_len: usize,
+}
+
+impl NodeState {
+    const fn new() -> Self {
+        Self {
+            kind: NodeKind::Other,
+            memory: false,
+            cpu: false,
+            reg: None,
+            clock_frequency: None,
+            is_stdout: false,
+            address_cells: DEFAULT_ADDRESS_CELLS,
+            size_cells: DEFAULT_SIZE_CELLS,
+            path_len: 0,
+        }
+    }
+}
+
+// 从设备树得到的启动信息
+pub struct BootInfo {
+    pub hart_id: usize,
+    pub dtb_address: usize,
+    pub dtb_size: usize,
+    pub memory_base: usize,
+    pub memory_size: usize,
+    pub hart_count: usize,
+    pub uart_base: Option<usize>,
+    pub uart_clock: Option<u32>,
+    pub clint_base: Option<usize>,
+    pub plic_base: Option<usize>,
+    pub syscon_base: Option<usize>,
+    pub initrd: Option<(usize, usize)>, // QEMU -initrd 加载的位置 [start, end)
+    virtio_bases: [usize; VIRTIO_MMIO_COUNT],
+    virtio_count: usize,
+    bootargs: [u8; MAX_BOOTARGS],
+    bootargs_len: usize,
+    stdout_path: [u8; MAX_PATH],
+    stdout_path_len: usize,
+}
+
+impl BootInfo {
+    pub const fn new() -> Self {
+        Self {
+            hart_id: 0,
+            dtb_address: 0,
+            dtb_size: 0,
+            memory_base: 0,
+            memory_size: 0,
+            hart_count: 0,
+            uart_base: None,
+            uart_clock: None,
+            clint_base: None,
+            plic_base: None,
+            syscon_base: None,
+            initrd: None,
+            virtio_bases: [0; VIRTIO_MMIO_COUNT],
+            virtio_count: 0,
+            bootargs: [0; MAX_BOOTARGS],
+            bootargs_len: 0,
+            stdout_path: [0; MAX_PATH],
+            stdout_path_len: 0,
+        }
+    }
+
+    pub fn bootargs(&self) -> &str {
+        core::str::from_utf8(&self.bootargs[..self.bootargs_len]).unwrap_or("")
+    }
+
+    pub fn stdout_path(&self) -> &str {
+        core::str::from_utf8(&self.stdout_path[..self.stdout_path_len]).unwrap_or("")
+    }
+
+    // virtio-mmio 设备地址，从小到大排列
+    pub fn virtio_bases(&self) -> &[usize] {
+        &self.virtio_bases[..self.virtio_count]
+    }
+
+    // 解析设备树，格式错误时返回false
+    pub fn parse(&mut self, fdt: &Fdt) -> bool {
+        self.parse_chosen(fdt) && self.parse_devices(fdt)
+    }
+
+    // 第一遍：/chosen 中的启动参数、initrd位置和标准输出路径 (可能是 /aliases 中的别名)
+    fn parse_chosen(&mut self, fdt: &Fdt) -> bool {
+        let mut depth = 0;
+        let mut in_chosen = false;
+        let mut stdout = [0u8; MAX_PATH];
+        let mut stdout_len = 0;
+        let mut initrd_start = None;
+        let mut initrd_end = None;
+        let ok = fdt.walk(|token| match token {
+            Token::BeginNode(name) => {
+                depth += 1;
+                in_chosen = depth == 2 && name == "chosen";
+            }
+            Token::EndNode => {
+                depth -= 1;
+                in_chosen = false;
+            }
+            Token::Property(name, value) if in_chosen => {
+                let text = prop_str(value);
+                if name == "bootargs" {
+                    self.bootargs_len = copy_str(&mut self.bootargs, text);
+                } else if name == "stdout-path" {
+                    // 去掉 ":115200n8" 这样的选项
+                    let path = text.split(':').next().unwrap_or("");
+                    stdout_len = copy_str(&mut stdout, path);
+                } else if name == "linux,initrd-start" {
+                    initrd_start = prop_addr(value);
+                } else if name == "linux,initrd-end" {
+                    initrd_end = prop_addr(value);
+                }
+            }
+            Token::Property(..) => {}
+        });
+        if !ok {
+            return false;
+        }
+        if let (Some(start), Some(end)) = (initrd_start, initrd_end) {
+            self.initrd = (start < end).then_some((start, end));
+        }
+
+        let path = core::str::from_utf8(&stdout[..stdout_len]).unwrap_or("");
+        if path.is_empty() || path.starts_with('/') {
+            self.stdout_path_len = copy_str(&mut self.stdout_path, path);
+            return true;
+        }
+
+        let mut depth = 0;
+        let mut in_aliases = false;
+        fdt.walk(|token| match token {
+            Token::BeginNode(name) => {
+                depth += 1;
+                in_aliases = depth == 2 && name == "aliases";
+            }
+            Token::EndNode => {
+                depth -= 1;
+                in_aliases = false;
+            }
+            Token::Property(name, value) if in_aliases && name == path => {
+                self.stdout_path_len = copy_str(&mut self.stdout_path, prop_str(value));
+            }
+            Token::Property(..) => {}
+        })
+    }
+
+    // 第二遍：内存、CPU和设备节点
+    fn parse_devices(&mut self, fdt: &Fdt) -> bool {
+        let mut stack = [NodeState::new(); MAX_DEPTH];
+        let mut depth = 0usize;
+        let mut path = [0u8; MAX_PATH];
+        let mut path_len = 0;
+        let mut uart_is_stdout = false;
+        let mut overflow = false;
+
+        let ok = fdt.walk(|token| match token {
+            Token::BeginNode(name) => {
+                if depth >= MAX_DEPTH {
+                    overflow = true;
+                    depth += 1;
+                    return;
+                }
+                // 根节点的路径是空串，子节点依次追加 "/名字"
+                if depth > 0 && path_len + 1 + name.len() <= MAX_PATH {
+                    path[path_len] = b'/';
+                    path[path_len + 1..path_len + 1 + name.len()].copy_from_slice(name.as_bytes());
+                    path_len += 1 + name.len();
+                }
+                let mut node = NodeState::new();
+                node.path_len = path_len;
+                node.memory = base_name(name) == "memory";
+                node.is_stdout = self.stdout_path_len > 0
+                    && path[..path_len] == self.stdout_path[..self.stdout_path_len];
+                stack[depth] = node;
+                depth += 1;
+            }
+            Token::Property(name, value) => {
+                if depth == 0 || depth > MAX_DEPTH {
+                    return;
+                }
+                let parent = if depth >= 2 {
+                    stack[depth - 2]
+                } else {
+                    NodeState::new()
+                };
+                let node = &mut stack[depth - 1];
+                match name {
+                    "#address-cells" => {
+                        node.address_cells = be32(value, 0).unwrap_or(DEFAULT_ADDRESS_CELLS)
+                    }
+                    "#size-cells" => node.size_cells = be32(value, 0).unwrap_or(DEFAULT_SIZE_CELLS),
+                    "device_type" => {
+                        node.memory |= prop_str(value) == "memory";
+                        node.cpu = prop_str(value) == "cpu";
+                    }
+                    "reg" => {
+                        let address = read_cells(value, 0, parent.address_cells);
+                        let size =
+                            read_cells(value, parent.address_cells as usize, parent.size_cells);
+                        node.reg = address.map(|address| (address, size.unwrap_or(0)));
+                    }
+                    "clock-frequency" => node.clock_frequency = be32(value, 0),
+                    "compatible" => {
+                        node.kind = if compatible_with(value, &["ns16550a", "ns16550"]) {
+                            NodeKind::Uart
+                        } else if compatible_with(value, &["riscv,clint0", "sifive,clint0"]) {
+                            NodeKind::Clint
+                        } else if compatible_with(value, &["riscv,plic0", "sifive,plic-1.0.0"]) {
+                            NodeKind::Plic
+                        } else if compatible_with(value, &["virtio,mmio"]) {
+                            NodeKind::Virtio
+                        } else if compatible_with(value, &["sifive,test0"]) {
+                            NodeKind::Syscon
+                        } else {
+                            NodeKind::Other
+                        };
+                    }
+                    _ => {}
+                }
+            }
+            Token::EndNode => {
+                if depth == 0 {
+                    return;
+                }
+                depth -= 1;
+                if depth >= MAX_DEPTH {
+                    return;
+                }
+                let node = stack[depth];
+                self.add_node(&node, &mut uart_is_stdout);
+                // 回到父节点的路径
+                path_len = if depth > 0 {
+                    stack[depth - 1].path_len
+                } else {
+                    0
+                };
+            }
+        });
+
+        self.virtio_bases[..self.virtio_count].sort_unstable();
+        ok && !overflow
+    }
+
+    // 一个节点遍历完后登记它描述的资源
+    fn add_node(&mut self, node: &NodeState, uart_is_stdout: &mut bool) {
+        if node.cpu {
+            self.hart_count += 1;
+        }
+        let (base, size) = match node.reg {
+            Some(reg) => reg,
+            None => return,
+        };
+        if node.memory && self.memory_size == 0 {
+            self.memory_base = base;
+            self.memory_size = size;
+        }
+        match node.kind {
+            // 优先使用 stdout-path 指向的串口
+            NodeKind::Uart if self.uart_base.is_none() || (node.is_stdout && !*uart_is_stdout) => {
+                self.uart_base = Some(base);
+                self.uart_clock = node.clock_frequency;
+                *uart_is_stdout = node.is_stdout;
+            }
+            NodeKind::Clint if self.clint_base.is_none() => self.clint_base = Some(base),
+            NodeKind::Plic if self.plic_base.is_none() => self.plic_base = Some(base),
+            NodeKind::Syscon if self.syscon_base.is_none() => self.syscon_base = Some(base),
+            NodeKind::Virtio if self.virtio_count < VIRTIO_MMIO_COUNT => {
+                self.virtio_bases[self.virtio_count] = base;
+                self.virtio_count += 1;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Default for BootInfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 复制字符串到定长缓冲区，返回复制的长度
+fn copy_str(buffer: &mut [u8], text: &str) -> usize {
+    let len = text.len().min(buffer.len());
+    buffer[..len].copy_from_slice(&text.as_bytes()[..len]);
+    len
+}
+
+// 全局启动信息
+static mut BOOT_INFO: BootInfo = BootInfo::new();
+
+fn boot_info_mut() -> &'static mut BootInfo {
+    unsafe { &mut *core::ptr::addr_of_mut!(BOOT_INFO) }
+}
+
+// 全局函数
+// 解析QEMU传入的设备树，没有设备树或格式错误时返回false
+pub fn init(hart_id: usize, dtb: usize) -> bool {
+    let info = boot_info_mut();
+    info.hart_id = hart_id;
+    let (fdt, size) = match Fdt::from_addr(dtb) {
+        Some(fdt) => fdt,
+        None => return false,
+    };
+    info.dtb_address = dtb;
+    info.dtb_size = size;
+    info.parse(&fdt)
+}
+
+pub fn boot_info() -> &'static BootInfo {
+    boot_info_mut()
+}
@@ -0,0 +1,362 @@
+// 打开文件表
+// 文件描述符指向这里的打开文件对象，对象按引用计数共享
+
+use crate::drivers::tty;
+use crate::fs::vfs::{self, DirEntry, File, FsError, InodeRef, InodeType, Metadata};
+use crate::kernel::{pipe, process};
+
+// 打开文件表大小和每个进程的文件描述符数量
+pub const MAX_OPEN_FILES: usize = 64;
+pub const MAX_FDS: usize = 16;
+
+// 文件操作错误
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileError {
+    BadDescriptor,
+    TooManyFiles,
+    BrokenPipe,
+    WouldBlock,
+    NotReadable,
+    NotWritable,
+    NotSeekable,
+    Fs(FsError),
+}
+
+impl FileError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileError::BadDescriptor => "bad file descriptor",
+            FileError::TooManyFiles => "too many open files",
+            FileError::BrokenPipe => "broken pipe",
+            FileError::WouldBlock => "resource temporarily unavailable",
+            FileError::NotReadable => "file not open for reading",
+            FileError::NotWritable => "file not open for writing",
+            FileError::NotSeekable => "illegal seek",
+            FileError::Fs(err) => err.as_str(),
+        }
+    }
+}
+
+impl From<FsError> for FileError {
+    fn from(err: FsError) -> Self {
+        FileError::Fs(err)
+    }
+}
+
+// 打开文件的类型
+pub enum FileKind {
+    Console,
+    PipeRead(usize),
+    PipeWrite(usize),
+    Vfs(&'static mut dyn File),
+}
+
+// 打开文件对象
+pub struct OpenFile {
+    pub kind: FileKind,
+    pub readable: bool,
+    pub writable: bool,
+    pub nonblocking: bool, // 管道没有数据或已满时直接返回WouldBlock
+    refs: usize,
+}
+
+pub struct FileTable {
+    files: [Option<OpenFile>; MAX_OPEN_FILES],
+}
+
+impl FileTable {
+    pub const fn new() -> Self {
+        const EMPTY: Option<OpenFile> = None;
+        Self {
+            files: [EMPTY; MAX_OPEN_FILES],
+        }
+    }
+
+    // 登记一个打开文件，引用计数为1
+    pub fn install(&mut self, kind: FileKind, readable: bool, writable: bool) -> Option<usize> {
+        let index = self.files.iter().position(|file| file.is_none())?;
+        self.files[index] = Some(OpenFile {
+            kind,
+            readable,
+            writable,
+            nonblocking: false,
+            refs: 1,
+        });
+        Some(index)
+    }
+
+    pub fn get(&mut self, index: usize) -> Result<&mut OpenFile, FileError> {
+        match self.files.get_mut(index) {
+            Some(Some(file)) => Ok(file),
+            _ => Err(FileError::BadDescriptor),
+        }
+    }
+
+    // 增加一个引用 (dup、fork、重定向时)
+    pub fn retain(&mut self, index: usize) -> bool {
+        match self.get(index) {
+            Ok(file) => {
+                file.refs += 1;
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    // 释放一个引用，最后一个引用释放时关闭底层对象
+    pub fn release(&mut self, index: usize) {
+        let file = match self.get(index) {
+            Ok(file) => file,
+            Err(_) => return,
+        };
+        file.refs -= 1;
+        if file.refs > 0 {
+            return;
+        }
+
+        if let Some(file) = self.files[index].take() {
+            match file.kind {
+                FileKind::Console => {}
+                FileKind::PipeRead(id) => pipe::close_pipe(id, false),
+                FileKind::PipeWrite(id) => pipe::close_pipe(id, true),
+                FileKind::Vfs(file) => {
+                    // 写回失败的修改留在文件系统中，sync时重试
+                    let _ = vfs::close(file);
+                }
+            }
+        }
+    }
+}
+
+impl Default for FileTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 全局打开文件表
+static mut FILES: FileTable = FileTable::new();
+
+fn files() -> &'static mut FileTable {
+    unsafe { &mut *core::ptr::addr_of_mut!(FILES) }
+}
+
+// 等待管道就绪时让其他进程运行，并处理终端输入
+// 用户进程不能在内核中等待，返回WouldBlock，由系统调用重新执行
+fn wait_for_io(nonblocking: bool) -> Result<(), FileError> {
+    if nonblocking || process::current_pid().is_some() {
+        return Err(FileError::WouldBlock);
+    }
+    tty::poll_input();
+    process::schedule();
+    core::hint::spin_loop();
+    Ok(())
+}
+
+// 全局函数
+pub fn open_console() -> Option<usize> {
+    files().install(FileKind::Console, true, true)
+}
+
+// 创建管道，返回 (读端, 写端)
+pub fn open_pipe() -> Result<(usize, usize), FileError> {
+    install_pipe(pipe::create_pipe().ok_or(FileError::TooManyFiles)?)
+}
+
+// 创建使用大缓冲区的管道，用于依次执行的内置命令之间
+pub fn open_spooled_pipe() -> Result<(usize, usize), FileError> {
+    install_pipe(pipe::create_spooled_pipe().ok_or(FileError::TooManyFiles)?)
+}
+
+fn install_pipe(id: usize) -> Result<(usize, usize), FileError> {
+    let read_end = match files().install(FileKind::PipeRead(id), true, false) {
+        Some(index) => index,
+        None => {
+            pipe::close_pipe(id, false);
+            pipe::close_pipe(id, true);
+            return Err(FileError::TooManyFiles);
+        }
+    };
+    match files().install(FileKind::PipeWrite(id), false, true) {
+        Some(write_end) => Ok((read_end, write_end)),
+        None => {
+            files().release(read_end);
+            pipe::close_pipe(id, true);
+            Err(FileError::TooManyFiles)
+        }
+    }
+}
+
+// 以只读方式打开文件系统中的文件
+pub fn open_read(path: &str) -> Result<usize, FileError> {
+    let file = vfs::open(path, vfs::O_RDONLY)?;
+    if file.kind() == InodeType::Directory {
+        let _ = vfs::close(file);
+        return Err(FileError::Fs(FsError::IsADirectory));
+    }
+    install_vfs(file)
+}
+
+// 以写方式打开文件：append时追加到末尾，否则创建或截断
+pub fn open_write(path: &str, append: bool) -> Result<usize, FileError> {
+    let mode = if append { vfs::O_APPEND } else { vfs::O_TRUNC };
+    install_vfs(vfs::open(path, vfs::O_WRONLY | vfs::O_CREAT | mode)?)
+}
+
+// 按open(2)的flags打开文件或目录
+pub fn open(path: &str, flags: usize) -> Result<usize, FileError> {
+    install_vfs(vfs::open(path, flags)?)
+}
+
+// 登记VFS打开的文件，表满时关闭它
+fn install_vfs(file: &'static mut dyn File) -> Result<usize, FileError> {
+    let (readable, writable) = (file.readable(), file.writable());
+    let table = files();
+    let index = table
+        .files
+        .iter()
+        .position(|file| file.is_none())
+        .ok_or(FileError::TooManyFiles);
+    match index {
+        Ok(index) => {
+            table.files[index] = Some(OpenFile {
+                kind: FileKind::Vfs(file),
+                readable,
+                writable,
+                nonblocking: false,
+                refs: 1,
+            });
+            Ok(index)
+        }
+        Err(err) => {
+            let _ = vfs::close(file);
+            Err(err)
+        }
+    }
+}
+
+pub fn retain(index: usize) -> bool {
+    files().retain(index)
+}
+
+pub fn release(index: usize) {
+    files().release(index);
+}
+
+// 打开文件是否是终端 (ioctl只对终端有效)
+pub fn is_console(index: usize) -> bool {
+    matches!(
+        files().get(index),
+        Ok(OpenFile {
+            kind: FileKind::Console,
+            ..
+        })
+    )
+}
+
+// 管道的写者是否因为缓冲区满丢弃过数据
+pub fn pipe_overflowed(index: usize) -> bool {
+    match files().get(index) {
+        Ok(OpenFile {
+            kind: FileKind::PipeRead(id) | FileKind::PipeWrite(id),
+            ..
+        }) => pipe::pipe_overflowed(*id),
+        _ => false,
+    }
+}
+
+// 设置非阻塞模式
+pub fn set_nonblocking(index: usize, nonblocking: bool) -> bool {
+    match files().get(index) {
+        Ok(file) => {
+            file.nonblocking = nonblocking;
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+// 读取，管道为空时阻塞等待
+pub fn read(index: usize, buffer: &mut [u8]) -> Result<usize, FileError> {
+    let file = files().get(index)?;
+    if !file.readable {
+        return Err(FileError::NotReadable);
+    }
+    let nonblocking = file.nonblocking;
+    match &mut file.kind {
+        FileKind::Console => {
+            if process::current_pid().is_some() && !buffer.is_empty() && !tty::input_ready() {
+                return Err(FileError::WouldBlock);
+            }
+            Ok(tty::read(buffer))
+        }
+        FileKind::PipeRead(id) => {
+            let id = *id;
+            loop {
+                match pipe::read_pipe(id, buffer) {
+                    Err(FileError::WouldBlock) => wait_for_io(nonblocking)?,
+                    result => return result,
+                }
+            }
+        }
+        FileKind::PipeWrite(_) => Err(FileError::NotReadable),
+        FileKind::Vfs(file) => Ok(file.read(buffer)?),
+    }
+}
+
+// 写入，管道满时阻塞直到全部写完
+pub fn write(index: usize, data: &[u8]) -> Result<usize, FileError> {
+    let file = files().get(index)?;
+    if !file.writable {
+        return Err(FileError::NotWritable);
+    }
+    let nonblocking = file.nonblocking;
+    match &mut file.kind {
+        FileKind::Console => Ok(tty::write(data)),
+        FileKind::PipeWrite(id) => {
+            let id = *id;
+            let mut written = 0;
+            while written < data.len() {
+                match pipe::write_pipe(id, &data[written..]) {
+                    Ok(count) => written += count,
+                    // 不能等待时先返回已写入的部分
+                    Err(FileError::WouldBlock) => match wait_for_io(nonblocking) {
+                        Ok(()) => {}
+                        Err(err) if written == 0 => return Err(err),
+                        Err(_) => break,
+                    },
+                    Err(err) if written == 0 => return Err(err),
+                    Err(_) => break,
+                }
+            }
+            Ok(written)
+        }
+        FileKind::PipeRead(_) => Err(FileError::NotWritable),
+        FileKind::Vfs(file) => Ok(file.write(data)?),
+    }
+}
+
+// 移动读写位置，返回新位置；控制台和管道不能移动
+pub fn seek(index: usize, offset: isize, whence: usize) -> Result<usize, FileError> {
+    match &mut files().get(index)?.kind {
+        FileKind::Vfs(file) => Ok(file.seek(offset, whence)?),
+        _ => Err(FileError::NotSeekable),
+    }
+}
+
+// 打开文件的属性，控制台和管道没有对应的inode
+pub fn stat(index: usize) -> Result<Metadata, FileError> {
+    match &mut files().get(index)?.kind {
+        FileKind::Console => Ok(Metadata::new(0, InodeType::CharDevice)),
+        FileKind::PipeRead(_) | FileKind::PipeWrite(_) => Ok(Metadata::new(0, InodeType::Fifo)),
+        FileKind::Vfs(file) => Ok(file.metadata()?),
+    }
+}
+
+// 读取下一个目录项，读完时返回None
+pub fn read_dir(index: usize) -> Result<Option<(DirEntry, InodeRef)>, FileError> {
+    match &mut files().get(index)?.kind {
+        FileKind::Vfs(file) => Ok(file.read_dir()?),
+        _ => Err(FileError::Fs(FsError::NotADirectory)),
+    }
+}
@@ -49,6 +49,17 @@ impl Allocator {
         Some(ptr as *mut u8)
     }
 
+    // 分配按页对齐的连续页
+    pub fn allocate_pages(&mut self, count: usize) -> Option<*mut u8> {
+        let start = (self.next_free + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        let end = start.checked_add(count.checked_mul(PAGE_SIZE)?)?;
+        if end > self.heap_end {
+            return None;
+        }
+        self.next_free = end;
+        Some(start as *mut u8)
+    }
+
     // 释放内存 (简单实现，不实际释放)
     pub fn deallocate(&mut self, _ptr: *mut u8, _size: usize) {
         // 简化实现，不实际释放内存
@@ -63,21 +74,33 @@ impl Allocator {
 // 全局内存分配器
 pub static mut ALLOCATOR: Allocator = Allocator::new();
 
+fn allocator() -> &'static mut Allocator {
+    unsafe { &mut *core::ptr::addr_of_mut!(ALLOCATOR) }
+}
+
 // 全局分配函数
 pub fn init_memory(start: usize, size: usize) {
-    unsafe {
-        ALLOCATOR.init(start, size);
-    }
+    allocator().init(start, size);
 }
 
 pub fn allocate(size: usize) -> Option<*mut u8> {
-    unsafe { ALLOCATOR.allocate(size) }
+    allocator().allocate(size)
 }
 
 pub fn deallocate(ptr: *mut u8, size: usize) {
-    unsafe {
-        ALLOCATOR.deallocate(ptr, size);
-    }
+    allocator().deallocate(ptr, size);
+}
+
+pub fn allocate_pages(count: usize) -> Option<*mut u8> {
+    allocator().allocate_pages(count)
+}
+
+pub fn deallocate_pages(ptr: *mut u8, count: usize) {
+    deallocate(ptr, count * PAGE_SIZE);
+}
+
+pub fn available() -> usize {
+    allocator().available()
 }
 
 // 简单的字符串分配
@@ -2,29 +2,101 @@
 // 第01章：入门 - 基本内核结构
 
 use crate::common::types::ToString;
-use crate::drivers::uart;
+use crate::drivers::{partition, uart};
+use crate::fs::vfs;
+use crate::kernel::cmdline::LogLevel;
+use core::sync::atomic::{AtomicBool, Ordering};
 
+pub mod cmdline;
 pub mod conclusion;
+pub mod elf;
 pub mod exception;
+pub mod fdt;
+pub mod file;
 pub mod memory;
 pub mod paging;
+pub mod pipe;
 pub mod process;
+pub mod stdio;
 pub mod syscall;
 pub mod usermode;
 
-pub fn init() {
+// 第一个进入内核的hart负责初始化
+static BOOT_HART_CLAIMED: AtomicBool = AtomicBool::new(false);
+
+// 旧的固定堆区域，没有设备树时使用
+const FALLBACK_HEAP_START: usize = 0x20000000;
+const FALLBACK_HEAP_SIZE: usize = 1024 * 1024;
+
+extern "C" {
+    static _heap_start: u8;
+}
+
+pub fn init(hart_id: usize, dtb: usize) {
+    // 其余hart (-smp N) 停下等待
+    if BOOT_HART_CLAIMED.swap(true, Ordering::AcqRel) {
+        park_hart();
+    }
+
+    // 解析设备树，按其中的地址配置串口、PLIC、CLINT和virtio总线
+    let has_fdt = fdt::init(hart_id, dtb);
+    let info = fdt::boot_info();
+    if let Some(base) = info.uart_base {
+        uart::UART.set_base(base);
+    }
+    if let Some(base) = info.plic_base {
+        crate::drivers::plic::PLIC.configure(base, hart_id);
+    }
+    if let Some(base) = info.clint_base {
+        crate::drivers::clint::CLINT.configure(base, hart_id);
+    }
+    if let Some(base) = info.syscon_base {
+        crate::drivers::power::SYSCON.set_base(base);
+    }
+    crate::drivers::virtio::set_mmio_slots(info.virtio_bases());
+
     // 第05章：Hello World - 初始化UART并输出Hello World
-    uart::UART.init();
+    // 设备树中的时钟算不出有效除数时使用默认时钟
+    let configured = info
+        .uart_clock
+        .is_some_and(|clock| uart::UART.configure(clock, uart::UART_BAUD_RATE));
+    if !configured {
+        uart::UART.init();
+    }
     uart::println("Hello, Rust OS!");
     uart::println("Welcome to OS in 1000 Lines!");
 
+    // 内核命令行 (QEMU -append)
+    cmdline::init(info.bootargs());
+
+    if has_fdt {
+        if cmdline::log_enabled(LogLevel::Info) {
+            print_boot_info(info);
+        }
+    } else {
+        uart::println("No device tree found, using default QEMU virt layout");
+    }
+
     // 第08章：异常处理 - 初始化异常处理
     exception::init_exception_handling();
     uart::println("Exception handling initialized");
 
+    // 定时器中断：用户进程的时间片和CPU时间统计
+    crate::drivers::clint::init_timer();
+
+    // 初始化PLIC，之后串口输出改为中断驱动，避免大量日志阻塞CPU
+    crate::drivers::plic::init_plic();
+    uart::UART.enable_tx_interrupt();
+
+    // 终端行规程：串口输入经过回显和行编辑后交给程序
+    crate::drivers::tty::init_tty();
+
     // 第09章：内存分配 - 初始化内存分配器
-    // 假设从0x20000000开始有1MB的堆内存
-    memory::init_memory(0x20000000, 1024 * 1024);
+    // 堆从内核映像末尾一直到内存末尾 (避开设备树)，没有设备树时使用旧的固定区域
+    match heap_region(info) {
+        Some((start, end)) => memory::init_memory(start, end - start),
+        None => memory::init_memory(FALLBACK_HEAP_START, FALLBACK_HEAP_SIZE),
+    }
 
     // 测试内存分配
     if let Some(ptr) = memory::allocate(1024) {
@@ -49,7 +121,9 @@ pub fn init() {
     }
 
     // 列出所有进程
-    process::list_processes();
+    if cmdline::log_enabled(LogLevel::Info) {
+        process::list_processes();
+    }
 
     // 第11章：页表 - 初始化虚拟内存管理
     if paging::init_paging() {
@@ -64,13 +138,25 @@ pub fn init() {
 
     // 第13章：用户模式 - 初始化用户态管理
     usermode::init_user_mode();
-    usermode::list_user_programs();
+    if cmdline::log_enabled(LogLevel::Info) {
+        usermode::list_user_programs();
+    }
 
     // 第14章：系统调用 - 初始化系统调用处理
     syscall::init_syscalls();
 
-    // 第15章：磁盘I/O - 初始化磁盘驱动
-    if crate::drivers::disk::init_disk() == crate::drivers::disk::DiskResult::Success {
+    // 第15章：磁盘I/O - 注册驱动，枚举virtio-mmio总线并绑定设备
+    crate::drivers::disk::register();
+    crate::drivers::rng::register();
+    crate::drivers::console::register();
+    crate::drivers::net::register();
+    crate::drivers::input::register();
+    crate::drivers::driver::probe_devices();
+    if cmdline::log_enabled(LogLevel::Info) {
+        crate::drivers::driver::list_devices();
+    }
+
+    if crate::drivers::disk::is_ready() {
         uart::println("Disk driver initialized successfully");
 
         // 测试磁盘读写
@@ -84,31 +170,225 @@ pub fn init() {
         uart::println("Failed to initialize disk driver");
     }
 
-    // 第16章：文件系统 - 初始化文件系统
-    if crate::fs::fat::init_filesystem() {
-        uart::println("File system initialized successfully");
-        crate::fs::fat::list_files();
+    // 扫描磁盘分区表，分区经过块缓存访问磁盘
+    let disk = crate::drivers::disk::block_device();
+    partition::scan(crate::fs::bcache::cached(disk).unwrap_or(disk));
+    if cmdline::log_enabled(LogLevel::Info) {
+        partition::list_partitions();
+    }
+
+    // QEMU -initrd：cpio归档作为initramfs，其他镜像作为RAM盘ram0
+    if let Some((start, end)) = info.initrd {
+        load_initrd(start, end);
+    }
+
+    // 第16章：文件系统 - 挂载 root= 指定的根文件系统和 /dev、/proc、/tmp
+    mount_filesystems();
+
+    // 第17章：结语 - 总结和展望
+    if cmdline::log_enabled(LogLevel::Info) {
+        conclusion::print_conclusion();
+    }
+
+    // 第12章：应用程序 - 启动 init= 指定的程序 (默认是shell)，作为启动的最后一步
+    // 没有给出 init= 而根文件系统是initramfs且其中有 rdinit= 指定的程序时运行它
+    let init = match cmdline::get("init") {
+        None if vfs::mount_type("/") == Some("initramfs")
+            && vfs::stat(cmdline::rdinit_path()).is_ok() =>
+        {
+            cmdline::rdinit_path()
+        }
+        _ => cmdline::init_path(),
+    };
+    if init == cmdline::DEFAULT_INIT {
+        uart::println("Starting shell...");
+        crate::user::shell::run_shell();
+    } else {
+        crate::user::shell::run_init(init);
+    }
+}
+
+// 挂载根文件系统，再挂载 /dev、/proc 和 /tmp
+fn mount_filesystems() {
+    mount_root();
+    for (source, target, fstype) in [
+        ("devfs", "/dev", "devfs"),
+        ("proc", "/proc", "procfs"),
+        ("tmpfs", "/tmp", "ramfs"),
+    ] {
+        if let Err(err) = vfs::mount(source, target, fstype) {
+            uart::print("Failed to mount ");
+            uart::print(target);
+            uart::print(": ");
+            uart::println(err.as_str());
+        }
+    }
+    if cmdline::log_enabled(LogLevel::Info) {
+        vfs::list_mounts();
+    }
+}
 
-        // 测试文件操作
-        if let Some(mut file) = crate::fs::fat::open_file("HELLO") {
-            let mut buffer = [0u8; 100];
-            let bytes_read = crate::fs::fat::read_file(&mut file, &mut buffer);
-            uart::print("Read ");
-            uart::print(&bytes_read.to_string());
-            uart::println(" bytes from file");
-            crate::fs::fat::close_file(&mut file);
+// 挂载根文件系统
+fn mount_root() {
+    // 没有给出 root= 时initramfs就是根文件系统
+    if cmdline::get("root").is_none() && crate::fs::initramfs::is_loaded() {
+        match vfs::mount("initramfs", "/", "initramfs") {
+            Ok(()) => {
+                uart::println("Using initramfs as root file system");
+                return;
+            }
+            Err(err) => {
+                uart::print("Failed to mount initramfs: ");
+                uart::println(err.as_str());
+            }
         }
+    }
+
+    let root = cmdline::root();
+    if root == "none" {
+        uart::println("No root device (root=none)");
     } else {
-        uart::println("Failed to initialize file system");
+        // 默认根设备有分区表时挂载第一个分区
+        let mut name = [0u8; 16];
+        let source = match (root, partition::first_partition()) {
+            (cmdline::DEFAULT_ROOT, Some(number)) => partition::partition_name(number, &mut name),
+            _ => root,
+        };
+        match vfs::mount(source, "/", "fat") {
+            Ok(()) => {
+                uart::println("File system initialized successfully");
+                read_test_file();
+                return;
+            }
+            Err(err) => {
+                uart::print("Failed to mount root device ");
+                uart::print(source);
+                uart::print(": ");
+                uart::println(err.as_str());
+            }
+        }
     }
 
-    // 第12章：应用程序 - 启动shell
-    uart::println("Starting shell...");
-    // crate::user::shell::run_shell(); // 注释掉以避免阻塞
+    // 没有可用的根设备时用空的ramfs作为根目录
+    uart::println("Using ramfs as root file system");
+    if let Err(err) = vfs::mount("rootfs", "/", "ramfs") {
+        uart::print("Failed to mount ramfs: ");
+        uart::println(err.as_str());
+    }
+}
 
-    // 第17章：结语 - 总结和展望
-    conclusion::print_conclusion();
+// 测试文件操作
+fn read_test_file() {
+    if let Ok(file) = vfs::open("/HELLO.TXT", vfs::O_RDONLY) {
+        let mut buffer = [0u8; 100];
+        let bytes_read = file.read(&mut buffer).unwrap_or(0);
+        uart::print("Read ");
+        uart::print(&bytes_read.to_string());
+        uart::println(" bytes from file");
+        let _ = vfs::close(file);
+    }
+}
+
+// 使用QEMU -initrd加载的数据
+fn load_initrd(start: usize, end: usize) {
+    if crate::fs::initramfs::init(start, end) {
+        uart::print("initramfs: ");
+        uart::print_dec(crate::fs::initramfs::file_count());
+        uart::println(" files");
+        if cmdline::log_enabled(LogLevel::Debug) {
+            crate::fs::initramfs::list_files();
+        }
+    } else {
+        let blocks = crate::drivers::ramdisk::init(start, end - start);
+        uart::print("RAM disk ");
+        uart::print(crate::drivers::ramdisk::NAME);
+        uart::print(": ");
+        uart::print_dec(blocks);
+        uart::println(" blocks");
+    }
+}
+
+// 停下当前hart
+fn park_hart() -> ! {
+    loop {
+        #[cfg(target_arch = "riscv64")]
+        unsafe {
+            core::arch::asm!("wfi")
+        };
+    }
+}
+
+// 根据设备树计算堆区域 [start, end)
+fn heap_region(info: &fdt::BootInfo) -> Option<(usize, usize)> {
+    if info.memory_size == 0 {
+        return None;
+    }
+    let start = core::ptr::addr_of!(_heap_start) as usize;
+    let start = (start + memory::PAGE_SIZE - 1) & !(memory::PAGE_SIZE - 1);
+    let mut end = info.memory_base + info.memory_size;
+    // 设备树和initrd都在内存高处，堆在它们之前结束
+    let reserved = [
+        Some(info.dtb_address),
+        info.initrd.map(|(initrd, _)| initrd),
+    ];
+    for address in reserved.into_iter().flatten() {
+        if address >= start && address < end {
+            end = address & !(memory::PAGE_SIZE - 1);
+        }
+    }
+    (start < end).then_some((start, end))
+}
+
+// 打印设备树中读到的配置
+fn print_boot_info(info: &fdt::BootInfo) {
+    uart::print("Device tree at 0x");
+    uart::print_hex(info.dtb_address);
+    uart::print(", ");
+    uart::print_dec(info.hart_count);
+    uart::print(" hart(s), booting on hart ");
+    uart::print_dec(info.hart_id);
+    uart::println("");
+
+    uart::print("Memory: 0x");
+    uart::print_hex(info.memory_base);
+    uart::print(", ");
+    uart::print_dec(info.memory_size / (1024 * 1024));
+    uart::println(" MB");
 
-    // 第07章：内核恐慌 - 演示panic处理
-    // panic!("This is a kernel panic test");
+    let devices = [
+        ("UART", info.uart_base),
+        ("CLINT", info.clint_base),
+        ("PLIC", info.plic_base),
+    ];
+    for (name, base) in devices {
+        if let Some(base) = base {
+            uart::print(name);
+            uart::print(" at 0x");
+            uart::print_hex(base);
+            uart::println("");
+        }
+    }
+    if let Some((start, end)) = info.initrd {
+        uart::print("initrd at 0x");
+        uart::print_hex(start);
+        uart::print(", ");
+        uart::print_dec((end - start) / 1024);
+        uart::println(" KB");
+    }
+    uart::print("virtio-mmio slots: ");
+    uart::print_dec(info.virtio_bases().len());
+    uart::println("");
+
+    if !info.bootargs().is_empty() {
+        uart::print("Kernel command line: ");
+        uart::println(info.bootargs());
+    }
+    if cmdline::log_enabled(LogLevel::Debug) {
+        uart::print("Log level: ");
+        uart::println(cmdline::log_level().as_str());
+        uart::print("init=");
+        uart::print(cmdline::init_path());
+        uart::print(" root=");
+        uart::println(cmdline::root());
+    }
 }
@@ -1,16 +1,17 @@
 // 第11章：页表
 // 实现RISC-V虚拟内存管理
 
+use crate::arch::riscv::SATP_MODE_SV39;
 use crate::common::types::ToString;
 use crate::drivers::uart;
-use crate::kernel::memory;
+use crate::kernel::memory::{self, PAGE_SIZE};
 
 // 页表项标志
 const PTE_V: usize = 1 << 0; // 有效位
-const PTE_R: usize = 1 << 1; // 可读
-const PTE_W: usize = 1 << 2; // 可写
-const PTE_X: usize = 1 << 3; // 可执行
-const PTE_U: usize = 1 << 4; // 用户可访问
+pub const PTE_R: usize = 1 << 1; // 可读
+pub const PTE_W: usize = 1 << 2; // 可写
+pub const PTE_X: usize = 1 << 3; // 可执行
+pub const PTE_U: usize = 1 << 4; // 用户可访问
 const PTE_G: usize = 1 << 5; // 全局
 const PTE_A: usize = 1 << 6; // 访问位
 const PTE_D: usize = 1 << 7; // 脏位
@@ -46,16 +47,22 @@ impl PageTableEntry {
         (self.pte & PTE_U) != 0
     }
 
+    // 物理页号在第10位开始 (Sv39)
     pub fn get_physical_address(&self) -> usize {
-        self.pte & 0xFFFFFFFFFFF00000
+        (self.pte >> 10) << 12
     }
 
     pub fn set_physical_address(&mut self, addr: usize) {
-        self.pte = (self.pte & 0x0000000000000FFF) | (addr & 0xFFFFFFFFFFF00000);
+        self.pte = (self.pte & 0x3FF) | ((addr >> 12) << 10);
     }
 
     pub fn set_flags(&mut self, flags: usize) {
-        self.pte = (self.pte & 0xFFFFFFFFFFF00000) | (flags & 0xFFF);
+        self.pte = (self.pte & !0x3FF) | (flags & 0x3FF);
+    }
+
+    // 叶子项：R、W、X中至少有一位，否则指向下一级页表
+    pub fn is_leaf(&self) -> bool {
+        (self.pte & (PTE_R | PTE_W | PTE_X)) != 0
     }
 
     pub fn set_valid(&mut self, valid: bool) {
@@ -174,6 +181,112 @@ impl PageTable {
     }
 }
 
+// Sv39地址空间：三级页表，每级512项，供U模式的进程使用
+// 内核运行在M模式，访问内存不经过页表，用户地址由translate转换
+#[derive(Debug, Clone, Copy)]
+pub struct AddressSpace {
+    root: *mut PageTable,
+}
+
+// 分配一个清零的页表页
+fn allocate_table() -> Option<*mut PageTable> {
+    let page = memory::allocate_pages(1)?;
+    unsafe { memory::memset(page, 0, PAGE_SIZE) };
+    Some(page as *mut PageTable)
+}
+
+// 虚拟地址第level级的页表下标
+fn vpn(vaddr: usize, level: usize) -> usize {
+    (vaddr >> (12 + 9 * level)) & 0x1FF
+}
+
+impl AddressSpace {
+    pub fn new() -> Option<Self> {
+        Some(Self {
+            root: allocate_table()?,
+        })
+    }
+
+    // satp的值：Sv39模式和根页表的物理页号
+    pub fn satp(&self) -> usize {
+        SATP_MODE_SV39 | (self.root as usize >> 12)
+    }
+
+    // 找到vaddr的叶子页表项，create时分配缺少的中间页表
+    fn walk(&self, vaddr: usize, create: bool) -> Option<&'static mut PageTableEntry> {
+        let mut table = unsafe { &mut *self.root };
+        for level in (1..3).rev() {
+            let entry = table.get_entry_mut(vpn(vaddr, level));
+            if !entry.is_valid() {
+                if !create {
+                    return None;
+                }
+                let next = allocate_table()?;
+                entry.pte = 0;
+                entry.set_physical_address(next as usize);
+                entry.set_valid(true);
+            } else if entry.is_leaf() {
+                return None;
+            }
+            table = unsafe { &mut *(entry.get_physical_address() as *mut PageTable) };
+        }
+        Some(table.get_entry_mut(vpn(vaddr, 0)))
+    }
+
+    // 把从vaddr开始的size字节映射到paddr (都按页对齐)，已经映射过的页返回false
+    pub fn map(&mut self, vaddr: usize, paddr: usize, size: usize, flags: usize) -> bool {
+        for offset in (0..size).step_by(PAGE_SIZE) {
+            let entry = match self.walk(vaddr + offset, true) {
+                Some(entry) if !entry.is_valid() => entry,
+                _ => return false,
+            };
+            entry.set_physical_address(paddr + offset);
+            // 预先设置访问位和脏位，硬件不需要更新页表
+            entry.set_flags(flags | PTE_A | PTE_D);
+            entry.set_valid(true);
+        }
+        true
+    }
+
+    // 用户地址范围 [vaddr, vaddr+len) 对应的物理地址
+    // 每一页都必须对U模式可访问 (write时可写)，并且在物理上连续
+    pub fn translate(&self, vaddr: usize, len: usize, write: bool) -> Option<usize> {
+        let end = vaddr.checked_add(len.max(1))?;
+        let base = vaddr & !(PAGE_SIZE - 1);
+        let mut paddr = None;
+        for page in (base..end).step_by(PAGE_SIZE) {
+            let entry = self.walk(page, false)?;
+            if !entry.is_valid() || !entry.is_user_accessible() || (write && !entry.is_writable()) {
+                return None;
+            }
+            let frame = entry.get_physical_address();
+            match paddr {
+                None => paddr = Some(frame + (vaddr - base)),
+                Some(start) if frame == start - (vaddr - base) + (page - base) => {}
+                Some(_) => return None,
+            }
+        }
+        paddr
+    }
+
+    // 释放所有页表页 (不释放映射的物理页)
+    pub fn destroy(&mut self) {
+        free_table(self.root, 2);
+    }
+}
+
+fn free_table(table: *mut PageTable, level: usize) {
+    if level > 0 {
+        let entries = unsafe { &(*table).entries };
+        for entry in entries.iter() {
+            if entry.is_valid() && !entry.is_leaf() {
+                free_table(entry.get_physical_address() as *mut PageTable, level - 1);
+            }
+        }
+    }
+    memory::deallocate_pages(table as *mut u8, 1);
+}
+
 // 虚拟内存管理器
 pub struct VirtualMemoryManager {
     pub root_page_table: Option<*mut PageTable>,
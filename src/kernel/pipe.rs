@@ -0,0 +1,206 @@
+// 管道
+// 进程间的单向字节流，由打开文件表引用读端和写端
+
+use crate::fs::fat::FsError;
+use crate::kernel::file::FileError;
+use core::ptr::addr_of_mut;
+
+// 管道缓冲区大小和管道数量上限
+const PIPE_BUFFER_SIZE: usize = 512;
+const MAX_PIPES: usize = 16;
+
+// 内置命令之间的管道使用大缓冲区：两条内置命令依次执行，
+// 写者结束之前读者不会运行，缓冲区要装下写者的全部输出
+const SPOOL_SIZE: usize = 16 * 1024;
+const MAX_SPOOLS: usize = 3; // 一条命令管道最多4条命令，3个管道
+
+static mut SPOOLS: [[u8; SPOOL_SIZE]; MAX_SPOOLS] = [[0; SPOOL_SIZE]; MAX_SPOOLS];
+
+fn spools() -> &'static mut [[u8; SPOOL_SIZE]; MAX_SPOOLS] {
+    unsafe { &mut *addr_of_mut!(SPOOLS) }
+}
+
+pub struct Pipe {
+    buffer: [u8; PIPE_BUFFER_SIZE],
+    spool: Option<usize>, // 使用的大缓冲区编号
+    head: usize,
+    len: usize,
+    readers: usize,   // 打开的读端数量
+    writers: usize,   // 打开的写端数量
+    overflowed: bool, // 大缓冲区写满后丢弃过数据
+}
+
+impl Pipe {
+    const fn new(spool: Option<usize>) -> Self {
+        Self {
+            buffer: [0; PIPE_BUFFER_SIZE],
+            spool,
+            head: 0,
+            len: 0,
+            readers: 1,
+            writers: 1,
+            overflowed: false,
+        }
+    }
+
+    fn storage(&mut self) -> &mut [u8] {
+        match self.spool {
+            Some(index) => &mut spools()[index],
+            None => &mut self.buffer,
+        }
+    }
+
+    // 读取：管道为空时，若写端已全部关闭返回0 (EOF)，否则需要等待
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, FileError> {
+        if self.len == 0 {
+            return if self.writers == 0 {
+                Ok(0)
+            } else {
+                Err(FileError::WouldBlock)
+            };
+        }
+
+        let count = buffer.len().min(self.len);
+        let head = self.head;
+        let storage = self.storage();
+        let capacity = storage.len();
+        for (i, byte) in buffer[..count].iter_mut().enumerate() {
+            *byte = storage[(head + i) % capacity];
+        }
+        self.head = (head + count) % capacity;
+        self.len -= count;
+        Ok(count)
+    }
+
+    // 写入：读端已全部关闭时失败，缓冲区满时需要等待
+    // 大缓冲区满时读者不可能在写者结束前取走数据，直接失败
+    fn write(&mut self, data: &[u8]) -> Result<usize, FileError> {
+        if self.readers == 0 {
+            return Err(FileError::BrokenPipe);
+        }
+        let (head, len) = (self.head, self.len);
+        let storage = self.storage();
+        let capacity = storage.len();
+        if len == capacity {
+            if self.spool.is_some() {
+                self.overflowed = true;
+                return Err(FileError::Fs(FsError::NoSpace));
+            }
+            return Err(FileError::WouldBlock);
+        }
+
+        let count = data.len().min(capacity - len);
+        for (i, &byte) in data[..count].iter().enumerate() {
+            storage[(head + len + i) % capacity] = byte;
+        }
+        self.len += count;
+        Ok(count)
+    }
+}
+
+// 管道表
+pub struct PipeTable {
+    pipes: [Option<Pipe>; MAX_PIPES],
+    spools_used: [bool; MAX_SPOOLS],
+}
+
+impl PipeTable {
+    pub const fn new() -> Self {
+        const EMPTY: Option<Pipe> = None;
+        Self {
+            pipes: [EMPTY; MAX_PIPES],
+            spools_used: [false; MAX_SPOOLS],
+        }
+    }
+
+    pub fn create(&mut self) -> Option<usize> {
+        let index = self.pipes.iter().position(|pipe| pipe.is_none())?;
+        self.pipes[index] = Some(Pipe::new(None));
+        Some(index)
+    }
+
+    // 创建使用大缓冲区的管道
+    pub fn create_spooled(&mut self) -> Option<usize> {
+        let index = self.pipes.iter().position(|pipe| pipe.is_none())?;
+        let spool = self.spools_used.iter().position(|used| !used)?;
+        self.spools_used[spool] = true;
+        self.pipes[index] = Some(Pipe::new(Some(spool)));
+        Some(index)
+    }
+
+    // 写者的数据是否因为大缓冲区满而被丢弃过
+    pub fn overflowed(&self, id: usize) -> bool {
+        matches!(self.pipes.get(id), Some(Some(pipe)) if pipe.overflowed)
+    }
+
+    pub fn read(&mut self, id: usize, buffer: &mut [u8]) -> Result<usize, FileError> {
+        match self.pipes.get_mut(id) {
+            Some(Some(pipe)) => pipe.read(buffer),
+            _ => Err(FileError::BadDescriptor),
+        }
+    }
+
+    pub fn write(&mut self, id: usize, data: &[u8]) -> Result<usize, FileError> {
+        match self.pipes.get_mut(id) {
+            Some(Some(pipe)) => pipe.write(data),
+            _ => Err(FileError::BadDescriptor),
+        }
+    }
+
+    // 关闭一端，两端都关闭后释放管道
+    pub fn close(&mut self, id: usize, write_end: bool) {
+        if let Some(slot) = self.pipes.get_mut(id) {
+            if let Some(pipe) = slot {
+                if write_end {
+                    pipe.writers = pipe.writers.saturating_sub(1);
+                } else {
+                    pipe.readers = pipe.readers.saturating_sub(1);
+                }
+                if pipe.readers == 0 && pipe.writers == 0 {
+                    if let Some(spool) = pipe.spool {
+                        self.spools_used[spool] = false;
+                    }
+                    *slot = None;
+                }
+            }
+        }
+    }
+}
+
+impl Default for PipeTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 全局管道表
+static mut PIPES: PipeTable = PipeTable::new();
+
+fn pipes() -> &'static mut PipeTable {
+    unsafe { &mut *core::ptr::addr_of_mut!(PIPES) }
+}
+
+// 全局函数
+pub fn create_pipe() -> Option<usize> {
+    pipes().create()
+}
+
+pub fn create_spooled_pipe() -> Option<usize> {
+    pipes().create_spooled()
+}
+
+pub fn pipe_overflowed(id: usize) -> bool {
+    pipes().overflowed(id)
+}
+
+pub fn read_pipe(id: usize, buffer: &mut [u8]) -> Result<usize, FileError> {
+    pipes().read(id, buffer)
+}
+
+pub fn write_pipe(id: usize, data: &[u8]) -> Result<usize, FileError> {
+    pipes().write(id, data)
+}
+
+pub fn close_pipe(id: usize, write_end: bool) {
+    pipes().close(id, write_end);
+}
@@ -1,9 +1,32 @@
 // 第10章：进程管理
 // 实现简单的进程调度
 
+use crate::arch::riscv::*;
 use crate::common::types::ToString;
 use crate::drivers::uart;
-use crate::kernel::memory;
+use crate::kernel::elf::{self, ElfError, ElfImage};
+use crate::kernel::exception::{self, TrapFrame};
+use crate::kernel::file::{self, MAX_FDS};
+use crate::kernel::memory::{self, PAGE_SIZE};
+use crate::kernel::paging::{self, AddressSpace};
+use crate::kernel::stdio;
+use crate::kernel::syscall::{self, SyscallArgs};
+
+// 信号编号 (与Linux一致)
+pub const SIGINT: usize = 2;
+pub const SIGILL: usize = 4;
+pub const SIGKILL: usize = 9;
+pub const SIGSEGV: usize = 11;
+pub const SIGTERM: usize = 15;
+pub const SIGCHLD: usize = 17;
+pub const SIGCONT: usize = 18;
+pub const SIGSTOP: usize = 19;
+
+// 用户程序的栈大小
+pub const USER_STACK_SIZE: usize = 16 * 1024;
+
+// 进程名最大长度
+pub const PROCESS_NAME_LEN: usize = 16;
 
 // 进程状态
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -23,11 +46,23 @@ pub struct Process {
     pub program_counter: usize,
     pub stack: *mut u8,
     pub stack_size: usize,
+    pub pending_signals: u32,
+    pub parent: usize, // 父进程PID，0表示内核
+    pub name: [u8; PROCESS_NAME_LEN],
+    pub cpu_time: usize, // 运行过的时钟节拍数
+    pub exit_code: i32,
+    pub fds: [Option<usize>; MAX_FDS], // 文件描述符，指向打开文件表
+    pub context: TrapFrame,            // 用户态寄存器，进入用户态时恢复
+    pub space: Option<AddressSpace>,   // 用户地址空间，没有时进程不会被调度
+    pub image: Option<ElfImage>,       // 加载的程序段，退出时释放
+    pub restart: bool,                 // 系统调用需要等待，重新执行ecall
 }
 
 impl Process {
     pub fn new(pid: usize, stack_size: usize) -> Option<Self> {
-        if let Some(stack_ptr) = memory::allocate(stack_size) {
+        // 栈按页分配，可以映射到用户地址空间
+        let stack_size = stack_size.div_ceil(PAGE_SIZE) * PAGE_SIZE;
+        if let Some(stack_ptr) = memory::allocate_pages(stack_size / PAGE_SIZE) {
             Some(Self {
                 pid,
                 state: ProcessState::Ready,
@@ -35,6 +70,16 @@ impl Process {
                 program_counter: 0,
                 stack: stack_ptr,
                 stack_size,
+                pending_signals: 0,
+                parent: 0,
+                name: [0; PROCESS_NAME_LEN],
+                cpu_time: 0,
+                exit_code: 0,
+                fds: [None; MAX_FDS],
+                context: TrapFrame::new(),
+                space: None,
+                image: None,
+                restart: false,
             })
         } else {
             None
@@ -43,11 +88,122 @@ impl Process {
 
     pub fn destroy(&mut self) {
         if !self.stack.is_null() {
-            memory::deallocate(self.stack, self.stack_size);
+            memory::deallocate_pages(self.stack, self.stack_size / PAGE_SIZE);
             self.stack = core::ptr::null_mut();
         }
+        if let Some(mut image) = self.image.take() {
+            image.release();
+        }
+        if let Some(mut space) = self.space.take() {
+            space.destroy();
+        }
+        for fd in self.fds.iter_mut() {
+            if let Some(index) = fd.take() {
+                file::release(index);
+            }
+        }
         self.state = ProcessState::Terminated;
     }
+
+    pub fn set_name(&mut self, name: &str) {
+        let len = name.len().min(PROCESS_NAME_LEN);
+        self.name = [0; PROCESS_NAME_LEN];
+        self.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+    }
+
+    pub fn name_str(&self) -> &str {
+        let len = self
+            .name
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(PROCESS_NAME_LEN);
+        core::str::from_utf8(&self.name[..len]).unwrap_or("?")
+    }
+
+    // 把文件描述符指向一个打开文件，原来打开的文件被释放
+    pub fn set_fd(&mut self, fd: usize, index: usize) -> bool {
+        if fd >= MAX_FDS || !file::retain(index) {
+            return false;
+        }
+        if let Some(old) = self.fds[fd].replace(index) {
+            file::release(old);
+        }
+        true
+    }
+
+    // 把不小于min的最小空闲文件描述符指向打开文件
+    pub fn alloc_fd(&mut self, index: usize, min: usize) -> Option<usize> {
+        let fd = (min..MAX_FDS).find(|&fd| self.fds[fd].is_none())?;
+        if self.set_fd(fd, index) {
+            Some(fd)
+        } else {
+            None
+        }
+    }
+
+    // 关闭文件描述符，释放它引用的打开文件
+    pub fn close_fd(&mut self, fd: usize) -> bool {
+        match self.fds.get_mut(fd).and_then(|fd| fd.take()) {
+            Some(index) => {
+                file::release(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // 在栈顶布置参数：argc, argv[0..n], NULL, envp[0..m], NULL (RISC-V psABI)
+    pub fn push_args(&mut self, argv: &[&str], envp: &[&str]) -> bool {
+        const MAX_ARGV: usize = 16;
+        const MAX_ENVP: usize = 32;
+        if argv.len() > MAX_ARGV || envp.len() > MAX_ENVP {
+            return false;
+        }
+
+        let bottom = self.stack as usize;
+        let mut sp = self.stack_pointer;
+        let mut pointers = [0usize; MAX_ARGV + MAX_ENVP];
+
+        // 先复制字符串
+        for (i, arg) in argv.iter().chain(envp.iter()).enumerate() {
+            let size = arg.len() + 1;
+            if sp - bottom < size {
+                return false;
+            }
+            sp -= size;
+            unsafe {
+                memory::memcpy(sp as *mut u8, arg.as_ptr(), arg.len());
+                *((sp + arg.len()) as *mut u8) = 0;
+            }
+            pointers[i] = sp;
+        }
+
+        // 再放置指针数组，栈指针保持16字节对齐
+        let words = 1 + argv.len() + 1 + envp.len() + 1;
+        sp = (sp - words * 8) & !0xF;
+        if sp < bottom {
+            return false;
+        }
+        unsafe {
+            let slots = sp as *mut usize;
+            *slots = argv.len();
+            for (i, &pointer) in pointers[..argv.len()].iter().enumerate() {
+                *slots.add(1 + i) = pointer;
+            }
+            *slots.add(1 + argv.len()) = 0;
+            let env_slots = slots.add(2 + argv.len());
+            for (i, &pointer) in pointers[argv.len()..argv.len() + envp.len()]
+                .iter()
+                .enumerate()
+            {
+                *env_slots.add(i) = pointer;
+            }
+            *env_slots.add(envp.len()) = 0;
+        }
+
+        self.stack_pointer = sp;
+        true
+    }
 }
 
 // 进程调度器
@@ -55,6 +211,7 @@ pub struct Scheduler {
     processes: [Option<Process>; 16], // 最多16个进程
     current_pid: Option<usize>,
     next_pid: usize,
+    last_run: usize, // 上次运行的进程槽位，轮转调度从它的下一个开始
 }
 
 impl Scheduler {
@@ -63,6 +220,7 @@ impl Scheduler {
             processes: [None; 16],
             current_pid: None,
             next_pid: 1,
+            last_run: 0,
         }
     }
 
@@ -102,37 +260,139 @@ impl Scheduler {
         false
     }
 
-    // 调度下一个进程
-    pub fn schedule(&mut self) -> Option<usize> {
-        // 简单的轮转调度
-        let start = if let Some(current) = self.current_pid {
-            (current % self.processes.len()) + 1
-        } else {
-            0
+    // 创建执行指定入口的进程，并在栈上准备参数
+    pub fn spawn(
+        &mut self,
+        name: &str,
+        entry: usize,
+        parent: usize,
+        stack_size: usize,
+        argv: &[&str],
+        envp: &[&str],
+    ) -> Option<usize> {
+        let pid = self.create_process(stack_size)?;
+        let process = self.get_process_mut(pid)?;
+        process.set_name(name);
+        process.program_counter = entry;
+        process.parent = parent;
+        // 标准输入、输出和错误默认指向控制台，内核启动的进程继承被重定向的stdio
+        if let Some(console) = file::open_console() {
+            for fd in 0..3 {
+                process.set_fd(fd, console);
+            }
+            file::release(console);
+        }
+        if parent == 0 {
+            for fd in 0..3 {
+                if let Some(index) = stdio::get_fd(fd) {
+                    process.set_fd(fd, index);
+                }
+            }
+        }
+        if !process.push_args(argv, envp) {
+            self.terminate_process(pid);
+            return None;
+        }
+        process.context.regs[REG_SP] = process.stack_pointer;
+        process.context.pc = entry;
+        Some(pid)
+    }
+
+    // 进程退出：释放资源但保留PCB (僵尸状态)，等待父进程回收退出码
+    pub fn exit_process(&mut self, pid: usize, exit_code: i32) -> bool {
+        let parent = match self.get_process_mut(pid) {
+            Some(process) if process.state != ProcessState::Terminated => {
+                process.destroy();
+                process.exit_code = exit_code;
+                process.parent
+            }
+            _ => return false,
         };
+        if self.current_pid == Some(pid) {
+            self.current_pid = None;
+        }
+        if parent != 0 {
+            self.send_signal(parent, SIGCHLD);
+        }
+        true
+    }
 
-        for i in 0..self.processes.len() {
-            let index = (start + i) % self.processes.len();
-            if let Some(process) = &self.processes[index] {
-                if process.state == ProcessState::Ready {
-                    // 设置当前进程为就绪状态
-                    if let Some(current) = self.current_pid {
-                        for j in 0..self.processes.len() {
-                            if let Some(p) = &mut self.processes[j] {
-                                if p.pid == current {
-                                    p.state = ProcessState::Ready;
-                                    break;
-                                }
-                            }
-                        }
-                    }
+    // 回收一个已退出的进程，pid为None时回收parent的任意子进程
+    pub fn wait(&mut self, pid: Option<usize>, parent: usize) -> Option<(usize, i32)> {
+        for slot in self.processes.iter_mut() {
+            if let Some(process) = slot {
+                let matches = match pid {
+                    Some(pid) => process.pid == pid,
+                    None => process.parent == parent,
+                };
+                if matches && process.state == ProcessState::Terminated {
+                    let result = (process.pid, process.exit_code);
+                    *slot = None;
+                    return Some(result);
+                }
+            }
+        }
+        None
+    }
 
-                    // 设置新进程为运行状态
-                    if let Some(p) = &mut self.processes[index] {
-                        p.state = ProcessState::Running;
-                        self.current_pid = Some(p.pid);
-                        return Some(p.pid);
+    // 发送信号：记录为待处理，暂不支持自定义处理函数，按默认动作处理
+    pub fn send_signal(&mut self, pid: usize, signal: usize) -> bool {
+        if signal == 0 || signal >= 32 {
+            return false;
+        }
+        match self.get_process_mut(pid) {
+            Some(process) if process.state != ProcessState::Terminated => {
+                process.pending_signals |= 1 << signal;
+                match signal {
+                    SIGSTOP => process.state = ProcessState::Blocked,
+                    SIGCONT if process.state == ProcessState::Blocked => {
+                        process.state = ProcessState::Ready
                     }
+                    _ => {}
+                }
+            }
+            _ => return false,
+        }
+
+        match signal {
+            SIGINT | SIGKILL | SIGTERM => self.exit_process(pid, 128 + signal as i32),
+            _ => true,
+        }
+    }
+
+    // 时钟节拍：累计当前进程的CPU时间
+    pub fn tick(&mut self) {
+        if let Some(pid) = self.current_pid {
+            if let Some(process) = self.get_process_mut(pid) {
+                process.cpu_time += 1;
+            }
+        }
+    }
+
+    pub fn current_pid(&self) -> Option<usize> {
+        self.current_pid
+    }
+
+    // 遍历所有进程
+    pub fn for_each(&self, mut f: impl FnMut(&Process)) {
+        for process in self.processes.iter().flatten() {
+            f(process);
+        }
+    }
+
+    pub fn set_current(&mut self, pid: Option<usize>) {
+        self.current_pid = pid;
+    }
+
+    // 轮转选择下一个可以运行的进程：就绪并且加载了用户程序
+    pub fn next_runnable(&mut self) -> Option<usize> {
+        let count = self.processes.len();
+        for i in 1..=count {
+            let index = (self.last_run + i) % count;
+            if let Some(process) = &self.processes[index] {
+                if process.state == ProcessState::Ready && process.space.is_some() {
+                    self.last_run = index;
+                    return Some(process.pid);
                 }
             }
         }
@@ -186,7 +446,11 @@ impl Scheduler {
 }
 
 // 全局调度器
-pub static mut SCHEDULER: Scheduler = Scheduler::new();
+static mut SCHEDULER: Scheduler = Scheduler::new();
+
+fn scheduler() -> &'static mut Scheduler {
+    unsafe { &mut *core::ptr::addr_of_mut!(SCHEDULER) }
+}
 
 // 全局函数
 pub fn init_scheduler() {
@@ -194,19 +458,224 @@ pub fn init_scheduler() {
 }
 
 pub fn create_process(stack_size: usize) -> Option<usize> {
-    unsafe { SCHEDULER.create_process(stack_size) }
+    scheduler().create_process(stack_size)
 }
 
 pub fn terminate_process(pid: usize) -> bool {
-    unsafe { SCHEDULER.terminate_process(pid) }
+    scheduler().terminate_process(pid)
+}
+
+pub fn send_signal(pid: usize, signal: usize) -> bool {
+    scheduler().send_signal(pid, signal)
+}
+
+pub fn spawn(
+    name: &str,
+    entry: usize,
+    parent: usize,
+    stack_size: usize,
+    argv: &[&str],
+    envp: &[&str],
+) -> Option<usize> {
+    scheduler().spawn(name, entry, parent, stack_size, argv, envp)
 }
 
+// 从文件系统加载ELF程序并创建进程，进程名取路径的最后一段
+pub fn exec(path: &str, argv: &[&str], envp: &[&str], parent: usize) -> Result<usize, ElfError> {
+    let mut space = AddressSpace::new().ok_or(ElfError::OutOfMemory)?;
+    let mut image = match elf::load_file(path, &mut space) {
+        Ok(image) => image,
+        Err(err) => {
+            space.destroy();
+            return Err(err);
+        }
+    };
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let pid = match spawn(name, image.entry, parent, USER_STACK_SIZE, argv, envp) {
+        Some(pid) => pid,
+        None => {
+            image.release();
+            space.destroy();
+            return Err(ElfError::OutOfMemory);
+        }
+    };
+
+    // 用户栈映射到与物理地址相同的用户地址，push_args写入的指针在用户态同样有效
+    let process = scheduler()
+        .get_process_mut(pid)
+        .ok_or(ElfError::OutOfMemory)?;
+    let stack = process.stack as usize;
+    let flags = paging::PTE_U | paging::PTE_R | paging::PTE_W;
+    let mapped = space.map(stack, stack, process.stack_size, flags);
+    // 交给进程后，退出或失败时由destroy释放
+    process.space = Some(space);
+    process.image = Some(image);
+    if !mapped {
+        terminate_process(pid);
+        return Err(ElfError::BadProgramHeader);
+    }
+    Ok(pid)
+}
+
+// 在U模式运行进程，直到它被中断、需要等待、停止或退出；系统调用在这里处理
+fn run(pid: usize) {
+    let previous = current_pid();
+    loop {
+        let (mut context, satp) = match scheduler().get_process_mut(pid) {
+            Some(process) if process.state == ProcessState::Ready => match process.space {
+                Some(space) => {
+                    process.state = ProcessState::Running;
+                    (process.context, space.satp())
+                }
+                None => break,
+            },
+            _ => break,
+        };
+        scheduler().set_current(Some(pid));
+
+        // 进入用户态期间不能在内核中处理中断 (trap_entry按mscratch区分陷阱来源)
+        let (cause, value) = unsafe {
+            let irq_enabled = read_mie_global();
+            write_mie_global(false);
+            write_satp(satp);
+            write_mpp(PRIVILEGE_USER);
+            write_mpie(true);
+            exception::run_user(&mut context);
+            let cause = (read_mcause(), read_mtval());
+            // 打断用户进程的中断仍然挂起，打开中断后由内核的陷阱入口处理
+            write_mie_global(irq_enabled);
+            cause
+        };
+
+        let process = match scheduler().get_process_mut(pid) {
+            Some(process) => process,
+            None => break,
+        };
+        process.context = context;
+        if cause & (1 << 63) != 0 {
+            // 中断：让出CPU
+            if process.state == ProcessState::Running {
+                process.state = ProcessState::Ready;
+            }
+            break;
+        }
+
+        if cause != EXCEPTION_ECALL_FROM_USER {
+            let signal = if cause == EXCEPTION_ILLEGAL_INSTRUCTION {
+                SIGILL
+            } else {
+                SIGSEGV
+            };
+            uart::print("Process ");
+            uart::print_dec(pid);
+            uart::print(": ");
+            uart::print(exception::exception_name(cause));
+            uart::print(" at 0x");
+            uart::print_hex(context.pc);
+            uart::print(" (address 0x");
+            uart::print_hex(value);
+            uart::println(")");
+            exit_process(pid, 128 + signal as i32);
+            break;
+        }
+
+        process.context.pc += 4;
+        let result = syscall::handle_syscall(SyscallArgs::from_frame(&context));
+        let process = match scheduler().get_process_mut(pid) {
+            Some(process) => process,
+            None => break,
+        };
+        if core::mem::take(&mut process.restart) {
+            // 系统调用需要等待 (管道或终端没有数据)，下次运行时重新执行ecall
+            process.context.pc -= 4;
+            if process.state == ProcessState::Running {
+                process.state = ProcessState::Ready;
+            }
+            break;
+        }
+        process.context.regs[10] = result;
+        if process.state != ProcessState::Running {
+            break;
+        }
+        process.state = ProcessState::Ready;
+    }
+    scheduler().set_current(previous);
+}
+
+// 当前系统调用需要等待，返回用户态前退回到ecall，进程下次运行时重新执行
+pub fn restart_syscall() {
+    if let Some(process) = scheduler()
+        .current_pid()
+        .and_then(|pid| scheduler().get_process_mut(pid))
+    {
+        process.restart = true;
+    }
+}
+
+// 把进程的用户地址范围转换成内核可以访问的物理地址
+pub fn translate(pid: usize, vaddr: usize, len: usize, write: bool) -> Option<usize> {
+    scheduler()
+        .get_process(pid)
+        .and_then(|process| process.space)
+        .and_then(|space| space.translate(vaddr, len, write))
+}
+
+pub fn exit_process(pid: usize, exit_code: i32) -> bool {
+    scheduler().exit_process(pid, exit_code)
+}
+
+pub fn wait(pid: Option<usize>, parent: usize) -> Option<(usize, i32)> {
+    scheduler().wait(pid, parent)
+}
+
+pub fn set_fd(pid: usize, fd: usize, index: usize) -> bool {
+    scheduler()
+        .get_process_mut(pid)
+        .is_some_and(|process| process.set_fd(fd, index))
+}
+
+pub fn alloc_fd(pid: usize, index: usize, min: usize) -> Option<usize> {
+    scheduler()
+        .get_process_mut(pid)
+        .and_then(|process| process.alloc_fd(index, min))
+}
+
+pub fn close_fd(pid: usize, fd: usize) -> bool {
+    scheduler()
+        .get_process_mut(pid)
+        .is_some_and(|process| process.close_fd(fd))
+}
+
+// 查找进程的文件描述符对应的打开文件
+pub fn get_fd(pid: usize, fd: usize) -> Option<usize> {
+    scheduler()
+        .get_process(pid)
+        .and_then(|process| process.fds.get(fd).copied().flatten())
+}
+
+pub fn process_state(pid: usize) -> Option<ProcessState> {
+    scheduler().get_process(pid).map(|process| process.state)
+}
+
+pub fn current_pid() -> Option<usize> {
+    scheduler().current_pid()
+}
+
+pub fn tick() {
+    scheduler().tick();
+}
+
+pub fn for_each_process(f: impl FnMut(&Process)) {
+    scheduler().for_each(f);
+}
+
+// 选择下一个就绪的进程运行一段时间，返回它的PID；没有可运行的进程时返回None
 pub fn schedule() -> Option<usize> {
-    unsafe { SCHEDULER.schedule() }
+    let pid = scheduler().next_runnable()?;
+    run(pid);
+    Some(pid)
 }
 
 pub fn list_processes() {
-    unsafe {
-        SCHEDULER.list_processes();
-    }
+    scheduler().list_processes();
 }
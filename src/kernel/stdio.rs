@@ -0,0 +1,153 @@
+// 内核上下文的标准输入输出
+// shell的内置命令没有进程，它们的文件描述符保存在这里：0-2可以被重定向到文件或管道，
+// 其余的由内核上下文的openat、dup等系统调用使用
+
+use crate::drivers::tty;
+use crate::kernel::file::{self, FileError, MAX_FDS};
+
+pub const STDIN: usize = 0;
+pub const STDOUT: usize = 1;
+pub const STDERR: usize = 2;
+
+// 内核上下文的文件描述符表，0-2为None时表示终端
+static mut FDS: [Option<usize>; MAX_FDS] = [None; MAX_FDS];
+
+fn fds() -> &'static mut [Option<usize>; MAX_FDS] {
+    unsafe { &mut *core::ptr::addr_of_mut!(FDS) }
+}
+
+// 文件描述符对应的打开文件，没有重定向时返回None
+pub fn get_fd(fd: usize) -> Option<usize> {
+    fds().get(fd).copied().flatten()
+}
+
+// 重定向文件描述符，返回原来的打开文件；调用者负责打开文件表中的引用
+pub fn redirect(fd: usize, index: Option<usize>) -> Option<usize> {
+    match fds().get_mut(fd) {
+        Some(slot) => core::mem::replace(slot, index),
+        None => None,
+    }
+}
+
+// 与进程的文件描述符相同：文件描述符持有打开文件的一个引用
+pub fn set_fd(fd: usize, index: usize) -> bool {
+    if fd >= MAX_FDS || !file::retain(index) {
+        return false;
+    }
+    if let Some(old) = fds()[fd].replace(index) {
+        file::release(old);
+    }
+    true
+}
+
+// 把不小于min的最小空闲文件描述符指向打开文件 (0-2保留给标准输入输出)
+pub fn alloc_fd(index: usize, min: usize) -> Option<usize> {
+    let fd = (min.max(STDERR + 1)..MAX_FDS).find(|&fd| fds()[fd].is_none())?;
+    if set_fd(fd, index) {
+        Some(fd)
+    } else {
+        None
+    }
+}
+
+// 关闭文件描述符；标准输入输出关闭后回到终端
+pub fn close_fd(fd: usize) -> bool {
+    match fds().get_mut(fd).and_then(|fd| fd.take()) {
+        Some(index) => {
+            file::release(index);
+            true
+        }
+        None => false,
+    }
+}
+
+pub fn read(fd: usize, buffer: &mut [u8]) -> Result<usize, FileError> {
+    match get_fd(fd) {
+        Some(index) => file::read(index, buffer),
+        None => Ok(tty::read(buffer)),
+    }
+}
+
+pub fn write(fd: usize, data: &[u8]) -> Result<usize, FileError> {
+    match get_fd(fd) {
+        Some(index) => file::write(index, data),
+        None => Ok(tty::write(data)),
+    }
+}
+
+// 与uart的打印函数对应，输出到标准输出
+pub fn print(s: &str) {
+    let _ = write(STDOUT, s.as_bytes());
+}
+
+pub fn println(s: &str) {
+    print(s);
+    put_char(b'\n');
+}
+
+// 错误信息输出到标准错误
+pub fn eprint(s: &str) {
+    let _ = write(STDERR, s.as_bytes());
+}
+
+pub fn eprintln(s: &str) {
+    eprint(s);
+    let _ = write(STDERR, b"\n");
+}
+
+pub fn put_char(c: u8) {
+    let _ = write(STDOUT, &[c]);
+}
+
+pub fn print_dec(value: usize) {
+    print_dec_padded(value, 0);
+}
+
+// 打印十进制数，右对齐到指定宽度
+pub fn print_dec_padded(value: usize, width: usize) {
+    print_number(false, value, width);
+}
+
+pub fn print_int(value: isize) {
+    print_int_padded(value, 0);
+}
+
+pub fn print_int_padded(value: isize, width: usize) {
+    print_number(value < 0, value.unsigned_abs(), width);
+}
+
+fn print_number(negative: bool, value: usize, width: usize) {
+    let mut digits = [b' '; 64];
+    let mut start = digits.len();
+    let mut value = value;
+    loop {
+        start -= 1;
+        digits[start] = b'0' + (value % 10) as u8;
+        value /= 10;
+        if value == 0 {
+            break;
+        }
+    }
+    if negative {
+        start -= 1;
+        digits[start] = b'-';
+    }
+    let start = start.min(digits.len().saturating_sub(width));
+    let _ = write(STDOUT, &digits[start..]);
+}
+
+// 打印十六进制数 (不带0x前缀)
+pub fn print_hex(value: usize) {
+    let mut digits = [0u8; 16];
+    let mut start = digits.len();
+    let mut value = value;
+    loop {
+        start -= 1;
+        digits[start] = b"0123456789abcdef"[value & 0xF];
+        value >>= 4;
+        if value == 0 {
+            break;
+        }
+    }
+    let _ = write(STDOUT, &digits[start..]);
+}
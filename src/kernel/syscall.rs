@@ -2,17 +2,146 @@
 // 实现系统调用接口
 
 use crate::common::types::ToString;
-use crate::drivers::uart;
-use crate::kernel::process;
+use crate::drivers::block::DiskResult;
+use crate::drivers::{tty, uart};
+use crate::fs::vfs::{self, FsError, InodeType, Metadata};
+use crate::kernel::exception::TrapFrame;
+use crate::kernel::file::{self, FileError};
+use crate::kernel::{memory, process, stdio};
 
 // 系统调用号
+pub const SYS_DUP: usize = 23;
+pub const SYS_DUP3: usize = 24;
+pub const SYS_IOCTL: usize = 29;
+pub const SYS_UMOUNT2: usize = 39;
+pub const SYS_MOUNT: usize = 40;
+pub const SYS_OPENAT: usize = 56;
+pub const SYS_CLOSE: usize = 57;
+pub const SYS_GETDENTS64: usize = 61;
+pub const SYS_LSEEK: usize = 62;
+pub const SYS_READ: usize = 63;
 pub const SYS_WRITE: usize = 64;
+pub const SYS_FSTAT: usize = 80;
+pub const SYS_SYNC: usize = 81;
 pub const SYS_EXIT: usize = 93;
 pub const SYS_GETPID: usize = 172;
 pub const SYS_FORK: usize = 220;
 pub const SYS_EXECVE: usize = 221;
 pub const SYS_WAITPID: usize = 260;
 
+// 错误码 (与Linux一致)，系统调用失败时返回 -errno
+pub const ENOENT: usize = 2;
+pub const ESRCH: usize = 3;
+pub const EIO: usize = 5;
+pub const EBADF: usize = 9;
+pub const EAGAIN: usize = 11;
+pub const ENOMEM: usize = 12;
+pub const EFAULT: usize = 14;
+pub const EBUSY: usize = 16;
+pub const EEXIST: usize = 17;
+pub const EXDEV: usize = 18;
+pub const ENODEV: usize = 19;
+pub const ENOTDIR: usize = 20;
+pub const EISDIR: usize = 21;
+pub const EINVAL: usize = 22;
+pub const ENFILE: usize = 23;
+pub const EMFILE: usize = 24;
+pub const ENOTTY: usize = 25;
+pub const ENOSPC: usize = 28;
+pub const ESPIPE: usize = 29;
+pub const EROFS: usize = 30;
+pub const EPIPE: usize = 32;
+pub const ENOSYS: usize = 38;
+pub const ENOTEMPTY: usize = 39;
+pub const EOPNOTSUPP: usize = 95;
+
+// openat的dirfd：相对当前目录 (没有工作目录，相对路径从根目录开始)
+pub const AT_FDCWD: isize = -100;
+// dup3唯一允许的标志，没有exec替换进程映像，接受后忽略
+pub const O_CLOEXEC: usize = 0o2000000;
+
+// st_mode的文件类型位
+const S_IFIFO: u32 = 0o010000;
+const S_IFCHR: u32 = 0o020000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+
+// linux_dirent64的d_type
+const DT_FIFO: u8 = 1;
+const DT_CHR: u8 = 2;
+const DT_DIR: u8 = 4;
+const DT_REG: u8 = 8;
+const DT_LNK: u8 = 10;
+
+// linux_dirent64中d_name之前的字节数：d_ino、d_off、d_reclen、d_type
+const DIRENT_HEADER: usize = 19;
+
+// fstat返回的struct stat (asm-generic布局，RISC-V 64位共128字节)
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct Stat {
+    pub st_dev: u64,
+    pub st_ino: u64,
+    pub st_mode: u32,
+    pub st_nlink: u32,
+    pub st_uid: u32,
+    pub st_gid: u32,
+    pub st_rdev: u64,
+    pub pad1: u64,
+    pub st_size: i64,
+    pub st_blksize: i32,
+    pub pad2: i32,
+    pub st_blocks: i64,
+    pub st_atime: i64,
+    pub st_atime_nsec: u64,
+    pub st_mtime: i64,
+    pub st_mtime_nsec: u64,
+    pub st_ctime: i64,
+    pub st_ctime_nsec: u64,
+    pub unused: [u32; 2],
+}
+
+impl Stat {
+    fn from_metadata(metadata: &Metadata) -> Self {
+        let kind = match metadata.kind {
+            InodeType::Regular => S_IFREG,
+            InodeType::Directory => S_IFDIR,
+            InodeType::CharDevice => S_IFCHR,
+            InodeType::Symlink => S_IFLNK,
+            InodeType::Fifo => S_IFIFO,
+        };
+        let permissions = match (metadata.is_directory(), metadata.read_only) {
+            (true, false) => 0o755,
+            (true, true) => 0o555,
+            (false, false) => 0o644,
+            (false, true) => 0o444,
+        };
+        Self {
+            st_ino: metadata.inode,
+            st_mode: kind | permissions,
+            st_nlink: 1,
+            st_size: metadata.size as i64,
+            st_blksize: 512,
+            st_blocks: metadata.size.div_ceil(512) as i64,
+            st_atime: metadata.modified as i64,
+            st_mtime: metadata.modified as i64,
+            st_ctime: metadata.created as i64,
+            ..Self::default()
+        }
+    }
+}
+
+fn dirent_type(kind: InodeType) -> u8 {
+    match kind {
+        InodeType::Regular => DT_REG,
+        InodeType::Directory => DT_DIR,
+        InodeType::CharDevice => DT_CHR,
+        InodeType::Symlink => DT_LNK,
+        InodeType::Fifo => DT_FIFO,
+    }
+}
+
 // 系统调用参数
 #[derive(Debug)]
 pub struct SyscallArgs {
@@ -26,6 +155,7 @@ pub struct SyscallArgs {
 }
 
 impl SyscallArgs {
+    #[cfg(target_arch = "riscv64")]
     pub fn from_registers() -> Self {
         let (syscall_num, arg0, arg1, arg2, arg3, arg4, arg5): (
             usize,
@@ -66,6 +196,137 @@ impl SyscallArgs {
             arg5,
         }
     }
+
+    // 从用户进程陷入时保存的寄存器读取：a7是调用号，a0-a5是参数
+    pub fn from_frame(frame: &TrapFrame) -> Self {
+        Self {
+            syscall_num: frame.regs[17],
+            arg0: frame.regs[10],
+            arg1: frame.regs[11],
+            arg2: frame.regs[12],
+            arg3: frame.regs[13],
+            arg4: frame.regs[14],
+            arg5: frame.regs[15],
+        }
+    }
+}
+
+// 把用户地址转换成内核可以访问的地址；内核上下文的调用直接使用物理地址
+fn user_ptr(vaddr: usize, len: usize, write: bool) -> Option<usize> {
+    if vaddr == 0 {
+        return None;
+    }
+    match process::current_pid() {
+        Some(pid) => process::translate(pid, vaddr, len, write),
+        None => Some(vaddr),
+    }
+}
+
+fn user_slice<'a>(vaddr: usize, len: usize) -> Option<&'a [u8]> {
+    if len == 0 {
+        return Some(&[]);
+    }
+    let ptr = user_ptr(vaddr, len, false)? as *const u8;
+    Some(unsafe { core::slice::from_raw_parts(ptr, len) })
+}
+
+fn user_slice_mut<'a>(vaddr: usize, len: usize) -> Option<&'a mut [u8]> {
+    if len == 0 {
+        return Some(&mut []);
+    }
+    let ptr = user_ptr(vaddr, len, true)? as *mut u8;
+    Some(unsafe { core::slice::from_raw_parts_mut(ptr, len) })
+}
+
+// 失败的返回值：-errno
+fn error(errno: usize) -> usize {
+    errno.wrapping_neg()
+}
+
+fn fs_errno(err: FsError) -> usize {
+    match err {
+        FsError::NotInitialized | FsError::NoDevice => ENODEV,
+        FsError::NotFound => ENOENT,
+        FsError::AlreadyExists => EEXIST,
+        FsError::NotADirectory => ENOTDIR,
+        FsError::IsADirectory => EISDIR,
+        FsError::InvalidName | FsError::InvalidArgument => EINVAL,
+        FsError::NoSpace => ENOSPC,
+        FsError::ReadOnly => EROFS,
+        FsError::IoError | FsError::Corrupted => EIO,
+        FsError::NotEmpty => ENOTEMPTY,
+        FsError::Busy => EBUSY,
+        FsError::NotSupported => EOPNOTSUPP,
+        FsError::CrossDevice => EXDEV,
+        FsError::TooManyFiles => ENFILE,
+    }
+}
+
+fn file_errno(err: FileError) -> usize {
+    match err {
+        FileError::BadDescriptor | FileError::NotReadable | FileError::NotWritable => EBADF,
+        FileError::TooManyFiles => ENFILE,
+        FileError::BrokenPipe => EPIPE,
+        FileError::WouldBlock => EAGAIN,
+        FileError::NotSeekable => ESPIPE,
+        FileError::Fs(err) => fs_errno(err),
+    }
+}
+
+// 读写文件的结果：需要等待时让进程稍后重新执行系统调用
+fn io_result(result: Result<usize, FileError>) -> usize {
+    match result {
+        Ok(count) => count,
+        Err(FileError::WouldBlock) => {
+            process::restart_syscall();
+            0
+        }
+        Err(err) => error(file_errno(err)),
+    }
+}
+
+// 当前进程的文件描述符对应的打开文件；内核上下文使用stdio中的文件描述符表
+fn current_file(fd: usize) -> Option<usize> {
+    match process::current_pid() {
+        Some(pid) => process::get_fd(pid, fd),
+        None => stdio::get_fd(fd),
+    }
+}
+
+// 分配、设置和关闭当前进程 (或内核上下文) 的文件描述符
+fn alloc_fd(index: usize, min: usize) -> Option<usize> {
+    match process::current_pid() {
+        Some(pid) => process::alloc_fd(pid, index, min),
+        None => stdio::alloc_fd(index, min),
+    }
+}
+
+fn set_fd(fd: usize, index: usize) -> bool {
+    match process::current_pid() {
+        Some(pid) => process::set_fd(pid, fd, index),
+        None => stdio::set_fd(fd, index),
+    }
+}
+
+fn close_fd(fd: usize) -> bool {
+    match process::current_pid() {
+        Some(pid) => process::close_fd(pid, fd),
+        None => stdio::close_fd(fd),
+    }
+}
+
+// 内核上下文没有重定向的0-2是终端
+fn is_terminal(fd: usize) -> bool {
+    process::current_pid().is_none() && fd <= stdio::STDERR && stdio::get_fd(fd).is_none()
+}
+
+// 用户传入的以NUL结尾的字符串，最长MAX_PATH字节
+fn user_str<'a>(ptr: usize) -> Option<&'a str> {
+    // 逐字节转换，字符串可能跨页
+    let len = (0..vfs::MAX_PATH).find(|&i| {
+        user_ptr(ptr + i, 1, false).is_some_and(|p| unsafe { *(p as *const u8) } == 0)
+    })?;
+    core::str::from_utf8(user_slice(ptr, len)?).ok()
 }
 
 // 系统调用处理器
@@ -87,7 +348,19 @@ impl SyscallHandler {
         uart::println(&args.syscall_num.to_string());
 
         match args.syscall_num {
+            SYS_DUP => self.handle_dup(args),
+            SYS_DUP3 => self.handle_dup3(args),
+            SYS_IOCTL => self.handle_ioctl(args),
+            SYS_UMOUNT2 => self.handle_umount(args),
+            SYS_MOUNT => self.handle_mount(args),
+            SYS_OPENAT => self.handle_openat(args),
+            SYS_CLOSE => self.handle_close(args),
+            SYS_GETDENTS64 => self.handle_getdents64(args),
+            SYS_LSEEK => self.handle_lseek(args),
+            SYS_READ => self.handle_read(args),
             SYS_WRITE => self.handle_write(args),
+            SYS_FSTAT => self.handle_fstat(args),
+            SYS_SYNC => self.handle_sync(args),
             SYS_EXIT => self.handle_exit(args),
             SYS_GETPID => self.handle_getpid(args),
             SYS_FORK => self.handle_fork(args),
@@ -96,7 +369,7 @@ impl SyscallHandler {
             _ => {
                 uart::print("Unknown syscall: ");
                 uart::println(&args.syscall_num.to_string());
-                0xFFFFFFFF
+                error(ENOSYS)
             }
         }
     }
@@ -104,7 +377,6 @@ impl SyscallHandler {
     // 处理write系统调用
     fn handle_write(&self, args: SyscallArgs) -> usize {
         let fd = args.arg0;
-        let buf_ptr = args.arg1 as *const u8;
         let count = args.arg2;
 
         uart::print("write(fd=");
@@ -113,40 +385,343 @@ impl SyscallHandler {
         uart::print(&count.to_string());
         uart::println(")");
 
-        if fd == 1 || fd == 2 {
-            // stdout or stderr
-            unsafe {
-                let slice = core::slice::from_raw_parts(buf_ptr, count);
-                for &byte in slice {
-                    uart::put_char(byte);
+        let slice = match user_slice(args.arg1, count) {
+            Some(slice) => slice,
+            None => return error(EFAULT),
+        };
+        if let Some(file) = current_file(fd) {
+            // 经过文件描述符 (可能被重定向到管道或文件)
+            io_result(file::write(file, slice))
+        } else if is_terminal(fd) && fd != stdio::STDIN {
+            // 内核上下文的stdout or stderr
+            tty::write(slice)
+        } else {
+            uart::println("Invalid file descriptor");
+            error(EBADF)
+        }
+    }
+
+    // 处理read系统调用
+    fn handle_read(&self, args: SyscallArgs) -> usize {
+        let fd = args.arg0;
+        let count = args.arg2;
+
+        let slice = match user_slice_mut(args.arg1, count) {
+            Some(slice) => slice,
+            None => return error(EFAULT),
+        };
+        if let Some(file) = current_file(fd) {
+            io_result(file::read(file, slice))
+        } else if is_terminal(fd) && fd == stdio::STDIN {
+            // 内核上下文的stdin，经过终端行规程
+            tty::read(slice)
+        } else {
+            uart::println("Invalid file descriptor");
+            error(EBADF)
+        }
+    }
+
+    // 处理openat系统调用：openat(dirfd, pathname, flags, mode)，mode未使用
+    fn handle_openat(&self, args: SyscallArgs) -> usize {
+        let dirfd = args.arg0 as isize;
+        let flags = args.arg2;
+        let path = match user_str(args.arg1) {
+            Some(path) => path,
+            None => return error(EFAULT),
+        };
+
+        uart::print("openat(path=");
+        uart::print(path);
+        uart::print(", flags=");
+        uart::print_dec(flags);
+        uart::println(")");
+
+        // 不支持相对于目录文件描述符的路径
+        if dirfd != AT_FDCWD && !path.starts_with('/') {
+            uart::println("dirfd not supported");
+            return error(EBADF);
+        }
+
+        let index = match file::open(path, flags) {
+            Ok(index) => index,
+            Err(err) => {
+                uart::println(err.as_str());
+                return error(file_errno(err));
+            }
+        };
+        // 文件描述符持有引用后释放打开时的引用，分配失败时文件被关闭
+        let fd = alloc_fd(index, 0);
+        file::release(index);
+        fd.unwrap_or(error(EMFILE))
+    }
+
+    // 处理close系统调用
+    fn handle_close(&self, args: SyscallArgs) -> usize {
+        let fd = args.arg0;
+
+        uart::print("close(fd=");
+        uart::print_dec(fd);
+        uart::println(")");
+
+        if close_fd(fd) {
+            0
+        } else {
+            error(EBADF)
+        }
+    }
+
+    // 处理lseek系统调用，返回新的读写位置
+    fn handle_lseek(&self, args: SyscallArgs) -> usize {
+        let fd = args.arg0;
+        let offset = args.arg1 as isize;
+        let whence = args.arg2;
+
+        let file = match current_file(fd) {
+            Some(file) => file,
+            None => return error(EBADF),
+        };
+        match file::seek(file, offset, whence) {
+            Ok(position) => position,
+            Err(err) => {
+                uart::println(err.as_str());
+                error(file_errno(err))
+            }
+        }
+    }
+
+    // 处理dup系统调用：复制到最小的空闲文件描述符
+    fn handle_dup(&self, args: SyscallArgs) -> usize {
+        let fd = args.arg0;
+
+        uart::print("dup(fd=");
+        uart::print_dec(fd);
+        uart::println(")");
+
+        match current_file(fd) {
+            Some(file) => alloc_fd(file, 0).unwrap_or(error(EMFILE)),
+            None => error(EBADF),
+        }
+    }
+
+    // 处理dup3系统调用：dup3(oldfd, newfd, flags)，newfd原来打开的文件被关闭
+    fn handle_dup3(&self, args: SyscallArgs) -> usize {
+        let old_fd = args.arg0;
+        let new_fd = args.arg1;
+        let flags = args.arg2;
+
+        uart::print("dup3(oldfd=");
+        uart::print_dec(old_fd);
+        uart::print(", newfd=");
+        uart::print_dec(new_fd);
+        uart::println(")");
+
+        if old_fd == new_fd || flags & !O_CLOEXEC != 0 {
+            return error(EINVAL);
+        }
+        match current_file(old_fd) {
+            Some(file) if set_fd(new_fd, file) => new_fd,
+            _ => error(EBADF),
+        }
+    }
+
+    // 处理fstat系统调用：fstat(fd, statbuf)
+    fn handle_fstat(&self, args: SyscallArgs) -> usize {
+        let fd = args.arg0;
+        let statbuf = match user_ptr(args.arg1, core::mem::size_of::<Stat>(), true) {
+            Some(ptr) => ptr as *mut Stat,
+            None => return error(EFAULT),
+        };
+
+        let file = match current_file(fd) {
+            Some(file) => file,
+            None => return error(EBADF),
+        };
+        match file::stat(file) {
+            Ok(metadata) => {
+                unsafe { statbuf.write_unaligned(Stat::from_metadata(&metadata)) };
+                0
+            }
+            Err(err) => {
+                uart::println(err.as_str());
+                error(file_errno(err))
+            }
+        }
+    }
+
+    // 处理getdents64系统调用：getdents64(fd, dirp, count)
+    // 尽量多地填入linux_dirent64记录，返回写入的字节数，0表示目录已读完
+    fn handle_getdents64(&self, args: SyscallArgs) -> usize {
+        let fd = args.arg0;
+        let count = args.arg2;
+        let buffer = match user_slice_mut(args.arg1, count) {
+            Some(buffer) => buffer.as_mut_ptr(),
+            None => return error(EFAULT),
+        };
+
+        let file = match current_file(fd) {
+            Some(file) => file,
+            None => return error(EBADF),
+        };
+
+        let mut written = 0;
+        loop {
+            let position = file::seek(file, 0, vfs::SEEK_CUR).unwrap_or(0);
+            let (entry, inode) = match file::read_dir(file) {
+                Ok(Some(entry)) => entry,
+                Ok(None) => break,
+                Err(err) if written == 0 => {
+                    uart::println(err.as_str());
+                    return error(file_errno(err));
+                }
+                Err(_) => break,
+            };
+
+            // 记录长度包含名字末尾的NUL，按8字节对齐
+            let name = entry.name().as_bytes();
+            let record_len = (DIRENT_HEADER + name.len() + 1).next_multiple_of(8);
+            if written + record_len > count {
+                // 放不下，退回这一项留到下次读取；一项都放不下时缓冲区太小
+                let _ = file::seek(file, position as isize, vfs::SEEK_SET);
+                if written == 0 {
+                    return error(EINVAL);
                 }
+                break;
+            }
+
+            // d_off是下一项的位置，可以传给lseek
+            let next = file::seek(file, 0, vfs::SEEK_CUR).unwrap_or(0);
+            unsafe {
+                let record = buffer.add(written);
+                (record as *mut u64).write_unaligned(inode.id);
+                (record.add(8) as *mut u64).write_unaligned(next as u64);
+                (record.add(16) as *mut u16).write_unaligned(record_len as u16);
+                *record.add(18) = dirent_type(entry.kind);
+                memory::memcpy(record.add(DIRENT_HEADER), name.as_ptr(), name.len());
+                memory::memset(
+                    record.add(DIRENT_HEADER + name.len()),
+                    0,
+                    record_len - DIRENT_HEADER - name.len(),
+                );
+            }
+            written += record_len;
+        }
+        written
+    }
+
+    // 处理ioctl系统调用 (终端模式切换)
+    fn handle_ioctl(&self, args: SyscallArgs) -> usize {
+        let fd = args.arg0;
+        let request = args.arg1;
+        let arg = args.arg2;
+
+        uart::print("ioctl(fd=");
+        uart::print_dec(fd);
+        uart::print(", request=");
+        uart::print_dec(request);
+        uart::println(")");
+
+        // 只有终端支持ioctl
+        let terminal = match current_file(fd) {
+            Some(file) => file::is_console(file),
+            None if is_terminal(fd) => true,
+            None => {
+                uart::println("Invalid file descriptor");
+                return error(EBADF);
+            }
+        };
+        if !terminal {
+            return error(ENOTTY);
+        }
+
+        // TCGETS写入termios，TCSETS读取
+        let size = core::mem::size_of::<tty::Termios>();
+        match user_ptr(arg, size, request == tty::TCGETS) {
+            Some(arg) => tty::ioctl(request, arg).unwrap_or(error(EINVAL)),
+            None => error(EFAULT),
+        }
+    }
+
+    // 处理mount系统调用：mount(source, target, fstype, flags, data)，flags和data未使用
+    fn handle_mount(&self, args: SyscallArgs) -> usize {
+        let (source, target, fstype) = match (
+            user_str(args.arg0),
+            user_str(args.arg1),
+            user_str(args.arg2),
+        ) {
+            (Some(source), Some(target), Some(fstype)) => (source, target, fstype),
+            _ => return error(EFAULT),
+        };
+
+        uart::print("mount(source=");
+        uart::print(source);
+        uart::print(", target=");
+        uart::print(target);
+        uart::print(", type=");
+        uart::print(fstype);
+        uart::println(")");
+
+        match vfs::mount(source, target, fstype) {
+            Ok(()) => 0,
+            Err(err) => {
+                uart::println(err.as_str());
+                error(fs_errno(err))
+            }
+        }
+    }
+
+    // 处理umount2系统调用：umount2(target, flags)，flags未使用
+    fn handle_umount(&self, args: SyscallArgs) -> usize {
+        let target = match user_str(args.arg0) {
+            Some(target) => target,
+            None => return error(EFAULT),
+        };
+
+        uart::print("umount(target=");
+        uart::print(target);
+        uart::println(")");
+
+        match vfs::umount(target) {
+            Ok(()) => 0,
+            Err(err) => {
+                uart::println(err.as_str());
+                error(fs_errno(err))
             }
-            count
+        }
+    }
+
+    // 处理sync系统调用：写出所有文件系统和块缓存中的脏块
+    fn handle_sync(&self, _args: SyscallArgs) -> usize {
+        uart::println("sync()");
+
+        let fs = vfs::sync_all();
+        let cache = crate::fs::bcache::sync();
+        if fs.is_ok() && cache == DiskResult::Success {
+            0
         } else {
-            uart::println("Invalid file descriptor");
-            0xFFFFFFFF
+            error(EIO)
         }
     }
 
     // 处理exit系统调用
     fn handle_exit(&self, args: SyscallArgs) -> usize {
-        let exit_code = args.arg0;
+        // 退出码只保留低8位
+        let exit_code = (args.arg0 & 0xFF) as i32;
 
         uart::print("exit(code=");
-        uart::print(&exit_code.to_string());
+        uart::print_int(exit_code as isize);
         uart::println(")");
 
-        // 终止当前进程
-        process::terminate_process(0); // 假设PID 0是当前进程
-
-        0
+        match process::current_pid() {
+            Some(pid) if process::exit_process(pid, exit_code) => 0,
+            _ => error(ESRCH),
+        }
     }
 
     // 处理getpid系统调用
     fn handle_getpid(&self, _args: SyscallArgs) -> usize {
         uart::println("getpid()");
-        // 返回当前进程ID
-        1 // 简化实现
+        // 内核上下文没有进程，返回0
+        process::current_pid().unwrap_or(0)
     }
 
     // 处理fork系统调用
@@ -159,7 +734,7 @@ impl SyscallHandler {
             uart::println(&pid.to_string());
             pid
         } else {
-            0xFFFFFFFF
+            error(ENOMEM)
         }
     }
 
@@ -174,13 +749,13 @@ impl SyscallHandler {
         uart::println("program)");
 
         // 简化实现，不实际执行程序
-        0xFFFFFFFF
+        error(ENOSYS)
     }
 
     // 处理waitpid系统调用
     fn handle_waitpid(&self, args: SyscallArgs) -> usize {
         let pid = args.arg0;
-        let status = args.arg1 as *mut i32;
+        let status = user_ptr(args.arg1, 4, true).map_or(core::ptr::null_mut(), |p| p as *mut i32);
         let options = args.arg2;
 
         uart::print("waitpid(pid=");
@@ -226,6 +801,32 @@ pub fn sys_write(fd: usize, buf: *const u8, count: usize) -> usize {
     handle_syscall(args)
 }
 
+pub fn sys_read(fd: usize, buf: *mut u8, count: usize) -> usize {
+    let args = SyscallArgs {
+        syscall_num: SYS_READ,
+        arg0: fd,
+        arg1: buf as usize,
+        arg2: count,
+        arg3: 0,
+        arg4: 0,
+        arg5: 0,
+    };
+    handle_syscall(args)
+}
+
+pub fn sys_ioctl(fd: usize, request: usize, arg: usize) -> usize {
+    let args = SyscallArgs {
+        syscall_num: SYS_IOCTL,
+        arg0: fd,
+        arg1: request,
+        arg2: arg,
+        arg3: 0,
+        arg4: 0,
+        arg5: 0,
+    };
+    handle_syscall(args)
+}
+
 pub fn sys_exit(exit_code: usize) -> usize {
     let args = SyscallArgs {
         syscall_num: SYS_EXIT,
@@ -251,3 +852,148 @@ pub fn sys_getpid() -> usize {
     };
     handle_syscall(args)
 }
+
+pub fn sys_sync() -> usize {
+    let args = SyscallArgs {
+        syscall_num: SYS_SYNC,
+        arg0: 0,
+        arg1: 0,
+        arg2: 0,
+        arg3: 0,
+        arg4: 0,
+        arg5: 0,
+    };
+    handle_syscall(args)
+}
+
+// 把字符串复制为以NUL结尾的C字符串，太长时返回空指针
+fn c_string(s: &str, buffer: &mut [u8; vfs::MAX_PATH]) -> usize {
+    if s.len() >= buffer.len() {
+        return 0;
+    }
+    buffer[..s.len()].copy_from_slice(s.as_bytes());
+    buffer[s.len()] = 0;
+    buffer.as_ptr() as usize
+}
+
+pub fn sys_mount(source: &str, target: &str, fstype: &str) -> usize {
+    let mut source_buffer = [0u8; vfs::MAX_PATH];
+    let mut target_buffer = [0u8; vfs::MAX_PATH];
+    let mut fstype_buffer = [0u8; vfs::MAX_PATH];
+    let args = SyscallArgs {
+        syscall_num: SYS_MOUNT,
+        arg0: c_string(source, &mut source_buffer),
+        arg1: c_string(target, &mut target_buffer),
+        arg2: c_string(fstype, &mut fstype_buffer),
+        arg3: 0,
+        arg4: 0,
+        arg5: 0,
+    };
+    handle_syscall(args)
+}
+
+pub fn sys_umount(target: &str) -> usize {
+    let mut target_buffer = [0u8; vfs::MAX_PATH];
+    let args = SyscallArgs {
+        syscall_num: SYS_UMOUNT2,
+        arg0: c_string(target, &mut target_buffer),
+        arg1: 0,
+        arg2: 0,
+        arg3: 0,
+        arg4: 0,
+        arg5: 0,
+    };
+    handle_syscall(args)
+}
+
+pub fn sys_openat(dirfd: isize, path: &str, flags: usize) -> usize {
+    let mut path_buffer = [0u8; vfs::MAX_PATH];
+    let args = SyscallArgs {
+        syscall_num: SYS_OPENAT,
+        arg0: dirfd as usize,
+        arg1: c_string(path, &mut path_buffer),
+        arg2: flags,
+        arg3: 0,
+        arg4: 0,
+        arg5: 0,
+    };
+    handle_syscall(args)
+}
+
+pub fn sys_close(fd: usize) -> usize {
+    let args = SyscallArgs {
+        syscall_num: SYS_CLOSE,
+        arg0: fd,
+        arg1: 0,
+        arg2: 0,
+        arg3: 0,
+        arg4: 0,
+        arg5: 0,
+    };
+    handle_syscall(args)
+}
+
+pub fn sys_lseek(fd: usize, offset: isize, whence: usize) -> usize {
+    let args = SyscallArgs {
+        syscall_num: SYS_LSEEK,
+        arg0: fd,
+        arg1: offset as usize,
+        arg2: whence,
+        arg3: 0,
+        arg4: 0,
+        arg5: 0,
+    };
+    handle_syscall(args)
+}
+
+pub fn sys_dup(fd: usize) -> usize {
+    let args = SyscallArgs {
+        syscall_num: SYS_DUP,
+        arg0: fd,
+        arg1: 0,
+        arg2: 0,
+        arg3: 0,
+        arg4: 0,
+        arg5: 0,
+    };
+    handle_syscall(args)
+}
+
+pub fn sys_dup3(old_fd: usize, new_fd: usize, flags: usize) -> usize {
+    let args = SyscallArgs {
+        syscall_num: SYS_DUP3,
+        arg0: old_fd,
+        arg1: new_fd,
+        arg2: flags,
+        arg3: 0,
+        arg4: 0,
+        arg5: 0,
+    };
+    handle_syscall(args)
+}
+
+pub fn sys_fstat(fd: usize, stat: *mut Stat) -> usize {
+    let args = SyscallArgs {
+        syscall_num: SYS_FSTAT,
+        arg0: fd,
+        arg1: stat as usize,
+        arg2: 0,
+        arg3: 0,
+        arg4: 0,
+        arg5: 0,
+    };
+    handle_syscall(args)
+}
+
+pub fn sys_getdents64(fd: usize, buf: *mut u8, count: usize) -> usize {
+    let args = SyscallArgs {
+        syscall_num: SYS_GETDENTS64,
+        arg0: fd,
+        arg1: buf as usize,
+        arg2: count,
+        arg3: 0,
+        arg4: 0,
+        arg5: 0,
+    };
+    handle_syscall(args)
+}
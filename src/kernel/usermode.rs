@@ -160,9 +160,14 @@ impl UserModeManager {
     fn handle_user_syscall(&mut self, mepc: usize) {
         // 从a7寄存器读取系统调用号
         let syscall_num: usize;
+        #[cfg(target_arch = "riscv64")]
         unsafe {
             core::arch::asm!("mv {}, a7", out(reg) syscall_num);
         }
+        #[cfg(not(target_arch = "riscv64"))]
+        {
+            syscall_num = 0;
+        }
 
         uart::print("User syscall: ");
         uart::println(&syscall_num.to_string());
@@ -1,8 +1,12 @@
 #![no_std]
-#![no_main]
+#![cfg_attr(not(test), no_main)]
 
 // 禁用标准库，使用no_std
 // 这是操作系统内核的基本要求
+// 不依赖硬件的单元测试在主机上运行 (make test)，测试时链接std
+
+#[cfg(test)]
+extern crate std;
 
 pub mod arch;
 pub mod common;
@@ -12,6 +16,6 @@ pub mod kernel;
 pub mod user;
 
 // 内核初始化函数
-pub fn init() {
-    kernel::init();
+pub fn init(hart_id: usize, dtb: usize) {
+    kernel::init(hart_id, dtb);
 }
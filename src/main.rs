@@ -4,10 +4,10 @@
 #![no_std]
 #![no_main]
 
-// 内核入口点
+// 内核入口点：a0 是hart编号，a1 是设备树地址
 #[no_mangle]
-pub extern "C" fn _start() -> ! {
-    rust_os_in_1000::init();
+pub extern "C" fn _start(hart_id: usize, dtb: usize) -> ! {
+    rust_os_in_1000::init(hart_id, dtb);
     loop {}
 }
 
@@ -15,8 +15,11 @@ pub extern "C" fn _start() -> ! {
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
     use rust_os_in_1000::common::types::ToString;
-    use rust_os_in_1000::drivers::uart;
+    use rust_os_in_1000::drivers::{power, uart};
+    use rust_os_in_1000::kernel::cmdline::{self, PanicAction};
 
+    // 切回轮询发送，保证panic信息和缓冲区中的日志都能输出
+    uart::UART.disable_tx_interrupt();
     uart::println("\n*** KERNEL PANIC ***");
 
     if let Some(location) = info.location() {
@@ -31,6 +34,19 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
     uart::print("Message: ");
     uart::println("Panic occurred");
 
-    uart::println("System halted.");
-    loop {}
+    // 按内核命令行的 panic= 处理
+    match cmdline::panic_action() {
+        PanicAction::Reboot => {
+            uart::println("Rebooting...");
+            power::reboot();
+        }
+        PanicAction::Poweroff => {
+            uart::println("Powering off...");
+            power::poweroff();
+        }
+        PanicAction::Halt => {
+            uart::println("System halted.");
+            loop {}
+        }
+    }
 }
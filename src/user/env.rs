@@ -0,0 +1,261 @@
+// 第12章：应用程序
+// Shell变量：赋值、导出和 $VAR 展开
+
+// 变量表大小和名称、值的长度上限
+pub const MAX_VARS: usize = 32;
+pub const VAR_NAME_LEN: usize = 16;
+pub const VAR_VALUE_LEN: usize = 64;
+
+// 导出变量拼成 "NAME=value" 时需要的缓冲区大小
+pub const ENV_BLOCK_SIZE: usize = MAX_VARS * (VAR_NAME_LEN + 1 + VAR_VALUE_LEN);
+
+#[derive(Clone, Copy)]
+struct Variable {
+    name: [u8; VAR_NAME_LEN],
+    name_len: usize,
+    value: [u8; VAR_VALUE_LEN],
+    value_len: usize,
+    exported: bool,
+}
+
+impl Variable {
+    fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len]).unwrap_or("")
+    }
+
+    fn value(&self) -> &str {
+        core::str::from_utf8(&self.value[..self.value_len]).unwrap_or("")
+    }
+}
+
+// 变量表
+pub struct Environment {
+    vars: [Option<Variable>; MAX_VARS],
+}
+
+impl Environment {
+    pub const fn new() -> Self {
+        Self {
+            vars: [None; MAX_VARS],
+        }
+    }
+
+    fn find(&self, name: &str) -> Option<usize> {
+        self.vars
+            .iter()
+            .position(|var| var.is_some_and(|var| var.name() == name))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        let index = self.find(name)?;
+        self.vars[index].as_ref().map(|var| var.value())
+    }
+
+    // 设置变量，保留原有的导出标记；名称非法、值过长或表满时返回false
+    pub fn set(&mut self, name: &str, value: &str) -> bool {
+        if !is_valid_name(name) || value.len() > VAR_VALUE_LEN {
+            return false;
+        }
+        let index = match self.find(name) {
+            Some(index) => index,
+            None => match self.vars.iter().position(|var| var.is_none()) {
+                Some(index) => index,
+                None => return false,
+            },
+        };
+
+        let exported = self.vars[index].is_some_and(|var| var.exported);
+        let mut var = Variable {
+            name: [0; VAR_NAME_LEN],
+            name_len: name.len(),
+            value: [0; VAR_VALUE_LEN],
+            value_len: value.len(),
+            exported,
+        };
+        var.name[..name.len()].copy_from_slice(name.as_bytes());
+        var.value[..value.len()].copy_from_slice(value.as_bytes());
+        self.vars[index] = Some(var);
+        true
+    }
+
+    // 标记为导出，变量不存在时创建空值
+    pub fn export(&mut self, name: &str) -> bool {
+        if self.find(name).is_none() && !self.set(name, "") {
+            return false;
+        }
+        match self.find(name).and_then(|index| self.vars[index].as_mut()) {
+            Some(var) => {
+                var.exported = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn unset(&mut self, name: &str) -> bool {
+        match self.find(name) {
+            Some(index) => {
+                self.vars[index] = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // 遍历变量：(名称, 值, 是否导出)
+    pub fn for_each(&self, mut f: impl FnMut(&str, &str, bool)) {
+        for var in self.vars.iter().flatten() {
+            f(var.name(), var.value(), var.exported);
+        }
+    }
+
+    // 把导出的变量写成 "NAME=value" 放入block，返回个数
+    pub fn exported<'b>(
+        &self,
+        block: &'b mut [u8; ENV_BLOCK_SIZE],
+        envp: &mut [&'b str; MAX_VARS],
+    ) -> usize {
+        let mut ranges = [(0usize, 0usize); MAX_VARS];
+        let mut count = 0;
+        let mut len = 0;
+        for var in self.vars.iter().flatten().filter(|var| var.exported) {
+            let start = len;
+            for &c in var
+                .name()
+                .as_bytes()
+                .iter()
+                .chain(b"=")
+                .chain(var.value().as_bytes())
+            {
+                block[len] = c;
+                len += 1;
+            }
+            ranges[count] = (start, len);
+            count += 1;
+        }
+
+        let block: &'b [u8] = block;
+        for i in 0..count {
+            let (start, end) = ranges[i];
+            envp[i] = core::str::from_utf8(&block[start..end]).unwrap_or("");
+        }
+        count
+    }
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 变量名：字母或下划线开头，后跟字母、数字或下划线
+pub fn is_valid_name(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    !bytes.is_empty()
+        && bytes.len() <= VAR_NAME_LEN
+        && (bytes[0].is_ascii_alphabetic() || bytes[0] == b'_')
+        && bytes
+            .iter()
+            .all(|&c| c.is_ascii_alphanumeric() || c == b'_')
+}
+
+// 解析 NAME=value 形式的赋值
+pub fn parse_assignment(word: &str) -> Option<(&str, &str)> {
+    let (name, value) = word.split_once('=')?;
+    if is_valid_name(name) {
+        Some((name, value))
+    } else {
+        None
+    }
+}
+
+// 展开 $NAME、${NAME} 和 $? 并去掉注释，单引号内保持原样
+// 引号本身保留给分词器处理；结果超出out时返回None
+pub fn expand(input: &[u8], env: &Environment, status: i32, out: &mut [u8]) -> Option<usize> {
+    let mut len = 0;
+    let mut quote: Option<u8> = None;
+    let mut i = 0;
+
+    let mut push = |bytes: &[u8], len: &mut usize| -> Option<()> {
+        let end = *len + bytes.len();
+        out.get_mut(*len..end)?.copy_from_slice(bytes);
+        *len = end;
+        Some(())
+    };
+
+    while i < input.len() {
+        let c = input[i];
+        match quote {
+            Some(b'\'') if c == b'\'' => quote = None,
+            Some(b'\'') => {}
+            Some(_) if c == b'"' => quote = None,
+            None if c == b'\'' || c == b'"' => quote = Some(c),
+            // 词首的 # 开始注释
+            None if c == b'#' && (i == 0 || input[i - 1].is_ascii_whitespace()) => break,
+            _ => {}
+        }
+
+        if c != b'$' || quote == Some(b'\'') {
+            push(&[c], &mut len)?;
+            i += 1;
+            continue;
+        }
+
+        // $? 展开为上一条命令的退出码
+        if input.get(i + 1) == Some(&b'?') {
+            let mut digits = [0u8; 12];
+            let count = format_status(status, &mut digits);
+            push(&digits[..count], &mut len)?;
+            i += 2;
+            continue;
+        }
+
+        // ${NAME} 或 $NAME
+        let braced = input.get(i + 1) == Some(&b'{');
+        let start = if braced { i + 2 } else { i + 1 };
+        let mut end = start;
+        while end < input.len() && (input[end].is_ascii_alphanumeric() || input[end] == b'_') {
+            end += 1;
+        }
+        if end == start || (braced && input.get(end) != Some(&b'}')) {
+            // 不是变量引用，按普通字符处理
+            push(&[c], &mut len)?;
+            i += 1;
+            continue;
+        }
+
+        let name = core::str::from_utf8(&input[start..end]).unwrap_or("");
+        if let Some(value) = env.get(name) {
+            push(value.as_bytes(), &mut len)?;
+        }
+        i = if braced { end + 1 } else { end };
+    }
+    Some(len)
+}
+
+// 把退出码格式化为十进制
+fn format_status(status: i32, out: &mut [u8; 12]) -> usize {
+    let mut digits = [0u8; 11];
+    let mut value = status.unsigned_abs();
+    let mut count = 0;
+    loop {
+        digits[count] = b'0' + (value % 10) as u8;
+        count += 1;
+        value /= 10;
+        if value == 0 {
+            break;
+        }
+    }
+
+    let mut len = 0;
+    if status < 0 {
+        out[0] = b'-';
+        len = 1;
+    }
+    for &digit in digits[..count].iter().rev() {
+        out[len] = digit;
+        len += 1;
+    }
+    len
+}
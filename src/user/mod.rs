@@ -1,4 +1,7 @@
 // 用户程序
 // 第12章：应用程序
 
+pub mod env;
+pub mod readline;
+pub mod script;
 pub mod shell;
@@ -0,0 +1,522 @@
+// 第12章：应用程序
+// 行编辑器：光标移动、历史记录、反向搜索和Tab补全
+
+use crate::drivers::tty;
+
+// 行缓冲区和历史记录的大小
+pub const MAX_LINE: usize = 256;
+const HISTORY_SIZE: usize = 32;
+const MAX_QUERY: usize = 64;
+const MAX_CANDIDATES: usize = 32;
+
+// 控制字符
+const CTRL_A: u8 = 0x01;
+const CTRL_B: u8 = 0x02;
+const CTRL_C: u8 = 0x03;
+const CTRL_D: u8 = 0x04;
+const CTRL_E: u8 = 0x05;
+const CTRL_F: u8 = 0x06;
+const CTRL_G: u8 = 0x07;
+const BACKSPACE: u8 = 0x08;
+const TAB: u8 = 0x09;
+const CTRL_K: u8 = 0x0B;
+const CTRL_L: u8 = 0x0C;
+const CTRL_N: u8 = 0x0E;
+const CTRL_P: u8 = 0x10;
+const CTRL_R: u8 = 0x12;
+const CTRL_U: u8 = 0x15;
+const ESC: u8 = 0x1B;
+const DELETE: u8 = 0x7F;
+
+// 解码后的按键
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Key {
+    Char(u8),
+    Control(u8),
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    Delete,
+    Unknown,
+}
+
+// Tab补全的候选来源
+pub trait Completer {
+    // 对 line[word_start..] 这个词给出候选，通过 add 逐个提交
+    fn complete(&self, line: &str, word_start: usize, add: &mut dyn FnMut(&str));
+}
+
+// 命令历史 (环形缓冲区)
+pub struct History {
+    entries: [[u8; MAX_LINE]; HISTORY_SIZE],
+    lengths: [usize; HISTORY_SIZE],
+    next: usize,
+    count: usize,
+}
+
+impl History {
+    pub const fn new() -> Self {
+        Self {
+            entries: [[0; MAX_LINE]; HISTORY_SIZE],
+            lengths: [0; HISTORY_SIZE],
+            next: 0,
+            count: 0,
+        }
+    }
+
+    // 添加一条记录，忽略空行和与上一条相同的行
+    pub fn push(&mut self, line: &[u8]) {
+        if line.iter().all(|c| c.is_ascii_whitespace()) {
+            return;
+        }
+        if self.get(0) == Some(line) {
+            return;
+        }
+
+        let len = line.len().min(MAX_LINE);
+        self.entries[self.next][..len].copy_from_slice(&line[..len]);
+        self.lengths[self.next] = len;
+        self.next = (self.next + 1) % HISTORY_SIZE;
+        if self.count < HISTORY_SIZE {
+            self.count += 1;
+        }
+    }
+
+    // 按时间倒序获取记录，0是最新的一条
+    pub fn get(&self, age: usize) -> Option<&[u8]> {
+        if age >= self.count {
+            return None;
+        }
+        let index = (self.next + HISTORY_SIZE - 1 - age) % HISTORY_SIZE;
+        Some(&self.entries[index][..self.lengths[index]])
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    // 从 from 开始向更早的记录查找包含 query 的一条
+    fn search(&self, query: &[u8], from: usize) -> Option<usize> {
+        (from..self.count).find(|&age| match self.get(age) {
+            Some(entry) => query.is_empty() || entry.windows(query.len()).any(|w| w == query),
+            None => false,
+        })
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 行编辑器
+pub struct LineEditor {
+    buffer: [u8; MAX_LINE],
+    len: usize,
+    cursor: usize,
+    pub history: History,
+    // 浏览历史时的位置，None表示正在编辑新行
+    history_index: Option<usize>,
+    // 开始浏览历史前正在编辑的内容
+    saved: [u8; MAX_LINE],
+    saved_len: usize,
+}
+
+impl LineEditor {
+    pub const fn new() -> Self {
+        Self {
+            buffer: [0; MAX_LINE],
+            len: 0,
+            cursor: 0,
+            history: History::new(),
+            history_index: None,
+            saved: [0; MAX_LINE],
+            saved_len: 0,
+        }
+    }
+
+    // 读取一行到 out 中 (不含换行)，EOF时返回None
+    pub fn read_line(
+        &mut self,
+        prompt: &str,
+        completer: &dyn Completer,
+        out: &mut [u8],
+    ) -> Option<usize> {
+        // 编辑期间由行编辑器自己处理回显、退格和Ctrl-C
+        let termios = tty::get_termios();
+        let mut raw = termios;
+        raw.c_lflag &= !(tty::ICANON | tty::ECHO | tty::ISIG);
+        tty::set_termios(raw);
+
+        let result = self.edit(prompt, completer);
+        tty::set_termios(termios);
+
+        result.map(|len| {
+            let len = len.min(out.len());
+            out[..len].copy_from_slice(&self.buffer[..len]);
+            len
+        })
+    }
+
+    fn edit(&mut self, prompt: &str, completer: &dyn Completer) -> Option<usize> {
+        self.len = 0;
+        self.cursor = 0;
+        self.history_index = None;
+        write_str(prompt);
+
+        loop {
+            match read_key() {
+                Key::Char(b'\n') => {
+                    write_str("\n");
+                    self.history.push(&self.buffer[..self.len]);
+                    return Some(self.len);
+                }
+                Key::Char(c) => self.insert(c),
+                Key::Control(CTRL_C) => {
+                    // 放弃当前行
+                    write_str("^C\n");
+                    self.len = 0;
+                    self.cursor = 0;
+                    return Some(0);
+                }
+                Key::Control(CTRL_D) => {
+                    if self.len == 0 {
+                        write_str("\n");
+                        return None;
+                    }
+                    self.delete_at_cursor();
+                }
+                Key::Control(BACKSPACE) | Key::Control(DELETE) => self.backspace(),
+                Key::Delete => self.delete_at_cursor(),
+                Key::Control(CTRL_A) | Key::Home => self.cursor = 0,
+                Key::Control(CTRL_E) | Key::End => self.cursor = self.len,
+                Key::Control(CTRL_B) | Key::Left if self.cursor > 0 => self.cursor -= 1,
+                Key::Control(CTRL_F) | Key::Right if self.cursor < self.len => self.cursor += 1,
+                Key::Control(CTRL_K) => self.len = self.cursor,
+                Key::Control(CTRL_U) => {
+                    self.buffer.copy_within(self.cursor..self.len, 0);
+                    self.len -= self.cursor;
+                    self.cursor = 0;
+                }
+                Key::Control(CTRL_L) => write_str("\x1b[2J\x1b[H"),
+                Key::Control(CTRL_P) | Key::Up => self.history_prev(),
+                Key::Control(CTRL_N) | Key::Down => self.history_next(),
+                Key::Control(CTRL_R) if self.reverse_search() => {
+                    self.refresh(prompt);
+                    write_str("\n");
+                    self.history.push(&self.buffer[..self.len]);
+                    return Some(self.len);
+                }
+                Key::Control(TAB) => self.complete(prompt, completer),
+                _ => {}
+            }
+            self.refresh(prompt);
+        }
+    }
+
+    // 在光标处插入字符
+    fn insert(&mut self, c: u8) {
+        if self.len >= MAX_LINE - 1 {
+            return;
+        }
+        self.buffer
+            .copy_within(self.cursor..self.len, self.cursor + 1);
+        self.buffer[self.cursor] = c;
+        self.len += 1;
+        self.cursor += 1;
+    }
+
+    fn insert_str(&mut self, s: &[u8]) {
+        for &c in s {
+            self.insert(c);
+        }
+    }
+
+    // 删除光标前的字符
+    fn backspace(&mut self) {
+        if self.cursor == 0 {
+            return;
+        }
+        self.buffer
+            .copy_within(self.cursor..self.len, self.cursor - 1);
+        self.len -= 1;
+        self.cursor -= 1;
+    }
+
+    // 删除光标处的字符
+    fn delete_at_cursor(&mut self) {
+        if self.cursor >= self.len {
+            return;
+        }
+        self.buffer
+            .copy_within(self.cursor + 1..self.len, self.cursor);
+        self.len -= 1;
+    }
+
+    fn set_line(&mut self, line: &[u8]) {
+        let len = line.len().min(MAX_LINE - 1);
+        self.buffer[..len].copy_from_slice(&line[..len]);
+        self.len = len;
+        self.cursor = len;
+    }
+
+    // 重绘当前行：回到行首，输出提示符和内容，清除行尾，再把光标移回原位
+    fn refresh(&self, prompt: &str) {
+        write_str("\r");
+        write_str(prompt);
+        tty::write(&self.buffer[..self.len]);
+        write_str("\x1b[K");
+        for _ in self.cursor..self.len {
+            tty::write(&[BACKSPACE]);
+        }
+    }
+
+    // 上一条历史
+    fn history_prev(&mut self) {
+        let age = match self.history_index {
+            Some(age) => age + 1,
+            None => 0,
+        };
+        if age >= self.history.len() {
+            return;
+        }
+        if self.history_index.is_none() {
+            self.saved[..self.len].copy_from_slice(&self.buffer[..self.len]);
+            self.saved_len = self.len;
+        }
+
+        let mut entry = [0u8; MAX_LINE];
+        let len = match self.history.get(age) {
+            Some(line) => {
+                entry[..line.len()].copy_from_slice(line);
+                line.len()
+            }
+            None => return,
+        };
+        self.set_line(&entry[..len]);
+        self.history_index = Some(age);
+    }
+
+    // 下一条历史，越过最新一条时恢复正在编辑的行
+    fn history_next(&mut self) {
+        match self.history_index {
+            None => {}
+            Some(0) => {
+                let saved = self.saved;
+                self.set_line(&saved[..self.saved_len]);
+                self.history_index = None;
+            }
+            Some(age) => {
+                let mut entry = [0u8; MAX_LINE];
+                let len = match self.history.get(age - 1) {
+                    Some(line) => {
+                        entry[..line.len()].copy_from_slice(line);
+                        line.len()
+                    }
+                    None => return,
+                };
+                self.set_line(&entry[..len]);
+                self.history_index = Some(age - 1);
+            }
+        }
+    }
+
+    // Ctrl-R 反向增量搜索，回车时返回true表示直接执行匹配的命令
+    fn reverse_search(&mut self) -> bool {
+        let mut query = [0u8; MAX_QUERY];
+        let mut query_len = 0;
+        let mut found = self.history.search(&query[..0], 0);
+
+        loop {
+            write_str("\r(reverse-i-search)`");
+            tty::write(&query[..query_len]);
+            write_str("': ");
+            if let Some(line) = found.and_then(|age| self.history.get(age)) {
+                tty::write(line);
+            }
+            write_str("\x1b[K");
+
+            match read_key() {
+                Key::Char(b'\n') => {
+                    self.accept_search(found);
+                    return true;
+                }
+                Key::Char(c) => {
+                    if query_len < MAX_QUERY {
+                        query[query_len] = c;
+                        query_len += 1;
+                    }
+                    let from = found.unwrap_or(0);
+                    found = self.history.search(&query[..query_len], from);
+                }
+                Key::Control(CTRL_R) => {
+                    let from = found.map_or(0, |age| age + 1);
+                    if let Some(age) = self.history.search(&query[..query_len], from) {
+                        found = Some(age);
+                    }
+                }
+                Key::Control(BACKSPACE) | Key::Control(DELETE) => {
+                    query_len = query_len.saturating_sub(1);
+                    found = self.history.search(&query[..query_len], 0);
+                }
+                Key::Control(CTRL_G) | Key::Control(CTRL_C) => return false,
+                _ => {
+                    // 其他按键结束搜索，把匹配结果放入编辑行
+                    self.accept_search(found);
+                    return false;
+                }
+            }
+        }
+    }
+
+    fn accept_search(&mut self, found: Option<usize>) {
+        let mut entry = [0u8; MAX_LINE];
+        let len = match found.and_then(|age| self.history.get(age)) {
+            Some(line) => {
+                entry[..line.len()].copy_from_slice(line);
+                line.len()
+            }
+            None => return,
+        };
+        self.set_line(&entry[..len]);
+        self.history_index = None;
+    }
+
+    // Tab补全光标前的词
+    fn complete(&mut self, prompt: &str, completer: &dyn Completer) {
+        let word_start = self.buffer[..self.cursor]
+            .iter()
+            .rposition(|c| c.is_ascii_whitespace())
+            .map_or(0, |i| i + 1);
+        let line = match core::str::from_utf8(&self.buffer[..self.cursor]) {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        let word = &line[word_start..];
+
+        // 收集候选
+        let mut candidates = [[0u8; MAX_QUERY]; MAX_CANDIDATES];
+        let mut lengths = [0usize; MAX_CANDIDATES];
+        let mut count = 0;
+        completer.complete(line, word_start, &mut |candidate: &str| {
+            // 空候选不能补全任何字符，忽略
+            let matches = !candidate.is_empty()
+                && candidate.len() >= word.len()
+                && candidate.as_bytes()[..word.len()].eq_ignore_ascii_case(word.as_bytes());
+            if matches && count < MAX_CANDIDATES && candidate.len() <= MAX_QUERY {
+                candidates[count][..candidate.len()].copy_from_slice(candidate.as_bytes());
+                lengths[count] = candidate.len();
+                count += 1;
+            }
+        });
+
+        if count == 0 {
+            return;
+        }
+
+        // 所有候选的最长公共前缀
+        let mut common = lengths[0];
+        for i in 1..count {
+            let mut n = 0;
+            while n < common && n < lengths[i] && candidates[i][n] == candidates[0][n] {
+                n += 1;
+            }
+            common = n;
+        }
+
+        let word_len = word.len();
+        if common > word_len {
+            let mut suffix = [0u8; MAX_QUERY];
+            suffix[..common - word_len].copy_from_slice(&candidates[0][word_len..common]);
+            self.insert_str(&suffix[..common - word_len]);
+        }
+
+        if count == 1 {
+            // 唯一候选，目录以'/'结尾时不加空格
+            if candidates[0][..lengths[0]].last() != Some(&b'/') {
+                self.insert(b' ');
+            }
+        } else if common <= word_len {
+            // 无法继续补全，列出所有候选
+            write_str("\n");
+            for i in 0..count {
+                tty::write(&candidates[i][..lengths[i]]);
+                write_str("  ");
+            }
+            write_str("\n");
+            self.refresh(prompt);
+        }
+    }
+}
+
+impl Default for LineEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn write_str(s: &str) {
+    tty::write(s.as_bytes());
+}
+
+fn read_byte() -> u8 {
+    let mut c = [0u8; 1];
+    while tty::read(&mut c) == 0 {}
+    c[0]
+}
+
+// 读取一个按键，解析ANSI转义序列
+fn read_key() -> Key {
+    let c = read_byte();
+    match c {
+        ESC => {}
+        DELETE => return Key::Control(DELETE),
+        b'\n' => return Key::Char(b'\n'),
+        0..=0x1F => return Key::Control(c),
+        _ => return Key::Char(c),
+    }
+
+    match read_byte() {
+        // ESC O H / ESC O F
+        b'O' => match read_byte() {
+            b'H' => Key::Home,
+            b'F' => Key::End,
+            _ => Key::Unknown,
+        },
+        b'[' => {
+            // CSI：可选的数字参数加上结束字符
+            let mut param = 0u8;
+            loop {
+                let c = read_byte();
+                match c {
+                    b'0'..=b'9' => param = param.wrapping_mul(10).wrapping_add(c - b'0'),
+                    b'A' => return Key::Up,
+                    b'B' => return Key::Down,
+                    b'C' => return Key::Right,
+                    b'D' => return Key::Left,
+                    b'H' => return Key::Home,
+                    b'F' => return Key::End,
+                    b'~' => {
+                        return match param {
+                            1 | 7 => Key::Home,
+                            3 => Key::Delete,
+                            4 | 8 => Key::End,
+                            _ => Key::Unknown,
+                        }
+                    }
+                    b';' => {}
+                    _ => return Key::Unknown,
+                }
+            }
+        }
+        _ => Key::Unknown,
+    }
+}
@@ -0,0 +1,189 @@
+// 第12章：应用程序
+// Shell脚本结构：按换行和分号切分命令，识别 if/else 和 while 关键字
+
+// 一个脚本中的命令和关键字个数上限
+pub const MAX_ITEMS: usize = 128;
+
+// 脚本元素：关键字或一条命令 (源代码中的范围)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Item {
+    If,
+    Then,
+    Else,
+    Fi,
+    While,
+    Do,
+    Done,
+    Command(usize, usize),
+}
+
+impl Item {
+    fn keyword(word: &[u8]) -> Option<Item> {
+        match word {
+            b"if" => Some(Item::If),
+            b"then" => Some(Item::Then),
+            b"else" => Some(Item::Else),
+            b"fi" => Some(Item::Fi),
+            b"while" => Some(Item::While),
+            b"do" => Some(Item::Do),
+            b"done" => Some(Item::Done),
+            _ => None,
+        }
+    }
+}
+
+// if语句各部分的位置
+pub struct IfParts {
+    pub then: usize,
+    pub otherwise: Option<usize>,
+    pub fi: usize,
+}
+
+pub struct Script {
+    items: [Item; MAX_ITEMS],
+    count: usize,
+}
+
+impl Script {
+    pub fn parse(source: &[u8]) -> Result<Self, &'static str> {
+        let mut script = Self {
+            items: [Item::Done; MAX_ITEMS],
+            count: 0,
+        };
+
+        let mut start = 0;
+        let mut i = 0;
+        let mut quote: Option<u8> = None;
+        while i <= source.len() {
+            let c = source.get(i).copied().unwrap_or(b'\n');
+            match quote {
+                Some(q) if c == q => quote = None,
+                Some(_) => {}
+                None if c == b'\'' || c == b'"' => quote = Some(c),
+                None if c == b'#' && (i == start || source[i - 1].is_ascii_whitespace()) => {
+                    // 注释一直到行尾
+                    script.push_segment(source, start, i)?;
+                    while i < source.len() && source[i] != b'\n' {
+                        i += 1;
+                    }
+                    start = i + 1;
+                }
+                None if c == b'\n' || c == b';' => {
+                    script.push_segment(source, start, i)?;
+                    start = i + 1;
+                }
+                None => {}
+            }
+            i += 1;
+        }
+        Ok(script)
+    }
+
+    // 处理一段命令：开头的关键字单独成为元素，剩余部分是一条命令
+    fn push_segment(
+        &mut self,
+        source: &[u8],
+        start: usize,
+        end: usize,
+    ) -> Result<(), &'static str> {
+        let mut start = start;
+        loop {
+            while start < end && source[start].is_ascii_whitespace() {
+                start += 1;
+            }
+            if start >= end {
+                return Ok(());
+            }
+
+            let mut word_end = start;
+            while word_end < end && !source[word_end].is_ascii_whitespace() {
+                word_end += 1;
+            }
+            let item = match Item::keyword(&source[start..word_end]) {
+                Some(keyword) => {
+                    start = word_end;
+                    keyword
+                }
+                None => Item::Command(start, end),
+            };
+
+            if self.count == MAX_ITEMS {
+                return Err("script too long");
+            }
+            self.items[self.count] = item;
+            self.count += 1;
+            if let Item::Command(..) = item {
+                return Ok(());
+            }
+        }
+    }
+
+    pub fn items(&self) -> &[Item] {
+        &self.items[..self.count]
+    }
+
+    // 尚未闭合的 if/while 层数，交互输入时据此判断是否需要续行
+    pub fn open_blocks(&self) -> usize {
+        let mut depth: usize = 0;
+        for item in self.items() {
+            match item {
+                Item::If | Item::While => depth += 1,
+                Item::Fi | Item::Done => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+        depth
+    }
+}
+
+// 从items[start] (If) 开始查找匹配的 then/else/fi
+pub fn find_if(items: &[Item], start: usize) -> Result<IfParts, &'static str> {
+    let mut then = None;
+    let mut otherwise = None;
+    let mut depth = 0;
+    for (i, item) in items.iter().enumerate().skip(start + 1) {
+        match item {
+            Item::If | Item::While => depth += 1,
+            Item::Fi | Item::Done if depth > 0 => depth -= 1,
+            Item::Then if depth == 0 && then.is_none() => then = Some(i),
+            Item::Else if depth == 0 && then.is_some() && otherwise.is_none() => {
+                otherwise = Some(i)
+            }
+            Item::Fi if depth == 0 => {
+                let then = then.ok_or("syntax error: expected 'then'")?;
+                return Ok(IfParts {
+                    then,
+                    otherwise,
+                    fi: i,
+                });
+            }
+            Item::Then | Item::Else | Item::Done if depth == 0 => {
+                return Err("syntax error in 'if'");
+            }
+            _ => {}
+        }
+    }
+    Err("syntax error: expected 'fi'")
+}
+
+// 从items[start] (While) 开始查找匹配的 do/done
+pub fn find_loop(items: &[Item], start: usize) -> Result<(usize, usize), &'static str> {
+    let mut body = None;
+    let mut depth = 0;
+    for (i, item) in items.iter().enumerate().skip(start + 1) {
+        match item {
+            Item::If | Item::While => depth += 1,
+            Item::Fi | Item::Done if depth > 0 => depth -= 1,
+            Item::Do if depth == 0 && body.is_none() => body = Some(i),
+            Item::Done if depth == 0 => {
+                let body = body.ok_or("syntax error: expected 'do'")?;
+                return Ok((body, i));
+            }
+            Item::Do | Item::Fi | Item::Then | Item::Else if depth == 0 => {
+                return Err("syntax error in 'while'");
+            }
+            _ => {}
+        }
+    }
+    Err("syntax error: expected 'done'")
+}
@@ -2,108 +2,1862 @@
 // 实现简单的shell
 
 use crate::common::types::ToString;
-use crate::drivers::uart;
-use crate::kernel::process;
+use crate::drivers::{driver, partition, tty};
+use crate::fs::bcache;
+use crate::fs::fat::FatDateTime;
+use crate::fs::vfs::{self, File, FsError, InodeType, Metadata};
+use crate::kernel::elf::ElfError;
+use crate::kernel::file::{self, FileError};
+use crate::kernel::process::{self, ProcessState};
+use crate::kernel::stdio::{self, STDIN, STDOUT};
+use crate::kernel::syscall;
+use crate::user::env::{self, Environment, ENV_BLOCK_SIZE, MAX_VARS};
+use crate::user::readline::{Completer, LineEditor, MAX_LINE};
+use crate::user::script::{self, Item, Script};
+
+// 参数个数上限
+pub const MAX_ARGS: usize = 16;
+
+// 管道中命令个数上限
+const MAX_STAGES: usize = 4;
+
+// 作业表大小和保存的命令行长度
+const MAX_JOBS: usize = 8;
+const JOB_COMMAND_LEN: usize = 64;
+
+// 启动时自动执行的脚本
+const BOOT_SCRIPT: &str = "/etc/rc";
+
+// 脚本文件大小、嵌套层数和交互输入多行语句的长度上限
+const MAX_SCRIPT_SIZE: usize = 8192;
+const MAX_SCRIPT_DEPTH: usize = 4;
+const BLOCK_INPUT_LEN: usize = 1024;
+
+// 脚本源码缓冲区，每层嵌套一个
+static mut SCRIPT_BUFFERS: [[u8; MAX_SCRIPT_SIZE]; MAX_SCRIPT_DEPTH] =
+    [[0; MAX_SCRIPT_SIZE]; MAX_SCRIPT_DEPTH];
+
+fn script_buffers() -> &'static mut [[u8; MAX_SCRIPT_SIZE]; MAX_SCRIPT_DEPTH] {
+    unsafe { &mut *core::ptr::addr_of_mut!(SCRIPT_BUFFERS) }
+}
+
+// 内置命令及说明，help和Tab补全共用
+const COMMANDS: &[(&str, &str)] = &[
+    ("help", "Show this help message"),
+    ("ps", "List processes with parent, state and CPU time"),
+    ("create", "Create a new process"),
+    (
+        "run",
+        "Run a program from disk (supports |, <, >, >> and trailing &)",
+    ),
+    (
+        "kill",
+        "Send a signal to a process: kill <pid|%job> [signal]",
+    ),
+    ("wait", "Wait for a process or all background jobs"),
+    ("jobs", "List background jobs"),
+    ("fg", "Bring a background job to the foreground"),
+    ("ls", "List a directory with sizes and attributes"),
+    ("cat", "Print files, or standard input without arguments"),
+    (
+        "write",
+        "Create or overwrite a file from arguments or typed input",
+    ),
+    ("rm", "Delete files"),
+    ("mv", "Rename a file: mv <old> <new>"),
+    ("mkdir", "Create directories"),
+    ("rmdir", "Remove empty directories"),
+    ("stat", "Show file metadata"),
+    ("devices", "List virtio devices and their drivers"),
+    ("sync", "Write cached file system data to disk"),
+    ("cache", "Show buffer cache statistics"),
+    ("partitions", "List disk partitions"),
+    (
+        "mount",
+        "List mounts or mount a file system: mount <source> <target> <type>",
+    ),
+    ("umount", "Unmount a file system"),
+    ("echo", "Print arguments"),
+    (
+        "export",
+        "Export variables to programs: export NAME[=value]",
+    ),
+    ("unset", "Remove variables"),
+    ("set", "List shell variables"),
+    ("test", "Evaluate a condition (also '[ ... ]')"),
+    ("true", "Return success"),
+    ("false", "Return failure"),
+    ("sh", "Run a shell script: sh <file>"),
+    (
+        "exit",
+        "Exit the shell or the current script: exit [status]",
+    ),
+];
+
+// 命令行参数，切片指向输入行缓冲区
+pub struct Args<'a> {
+    argv: [&'a str; MAX_ARGS],
+    operators: [bool; MAX_ARGS], // 未加引号的 | < > >> &
+    argc: usize,
+    truncated: bool, // 超过MAX_ARGS个参数，后面的被丢弃
+}
+
+impl<'a> Args<'a> {
+    // 原地分词：以空白分隔，支持单引号和双引号，引号会被去掉
+    // 未加引号的 | < > >> & 即使没有空白分隔也单独成词
+    pub fn parse(line: &'a mut [u8]) -> Self {
+        let mut ranges = [(0usize, 0usize); MAX_ARGS];
+        let mut operators = [false; MAX_ARGS];
+        let mut argc = 0;
+        let mut read = 0;
+        let mut write = 0;
+
+        while read < line.len() && argc < MAX_ARGS {
+            // 跳过空白
+            while read < line.len() && line[read].is_ascii_whitespace() {
+                read += 1;
+            }
+            if read >= line.len() {
+                break;
+            }
+
+            let start = write;
+            let mut started = false;
+            let mut quote: Option<u8> = None;
+            while read < line.len() {
+                let c = line[read];
+                match quote {
+                    Some(q) if c == q => quote = None,
+                    Some(_) => {
+                        line[write] = c;
+                        write += 1;
+                    }
+                    None if c == b'"' || c == b'\'' => quote = Some(c),
+                    None if c.is_ascii_whitespace() => break,
+                    None if is_operator_char(c) => {
+                        if started {
+                            break;
+                        }
+                        line[write] = c;
+                        write += 1;
+                        read += 1;
+                        if c == b'>' && read < line.len() && line[read] == b'>' {
+                            line[write] = c;
+                            write += 1;
+                            read += 1;
+                        }
+                        operators[argc] = true;
+                        break;
+                    }
+                    None => {
+                        line[write] = c;
+                        write += 1;
+                    }
+                }
+                started = true;
+                read += 1;
+            }
+
+            ranges[argc] = (start, write);
+            argc += 1;
+        }
+        let truncated = line[read..].iter().any(|c| !c.is_ascii_whitespace());
+
+        let line: &'a [u8] = line;
+        let mut argv = [""; MAX_ARGS];
+        for i in 0..argc {
+            let (start, end) = ranges[i];
+            argv[i] = core::str::from_utf8(&line[start..end]).unwrap_or("");
+        }
+        Self {
+            argv,
+            operators,
+            argc,
+            truncated,
+        }
+    }
+
+    // 由已分好的词构造，不包含操作符
+    fn from_slice(words: &[&'a str]) -> Self {
+        let mut argv = [""; MAX_ARGS];
+        let argc = words.len().min(MAX_ARGS);
+        argv[..argc].copy_from_slice(&words[..argc]);
+        Self {
+            argv,
+            operators: [false; MAX_ARGS],
+            argc,
+            truncated: false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.argc
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.argc == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&'a str> {
+        if index < self.argc {
+            Some(self.argv[index])
+        } else {
+            None
+        }
+    }
+
+    pub fn as_slice(&self) -> &[&'a str] {
+        &self.argv[..self.argc]
+    }
+
+    // 参数是否超过上限 (这时不应执行命令)
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    // 第index个参数是否为未加引号的操作符
+    pub fn is_operator(&self, index: usize) -> bool {
+        index < self.argc && self.operators[index]
+    }
+
+    // 是否包含管道或重定向
+    pub fn has_redirection(&self) -> bool {
+        (0..self.argc).any(|i| self.is_operator(i) && self.argv[i] != "&")
+    }
+
+    // 去掉结尾的 '&'，返回命令是否要在后台运行
+    pub fn take_background(&mut self) -> bool {
+        if self.argc > 0 && self.is_operator(self.argc - 1) && self.argv[self.argc - 1] == "&" {
+            self.argc -= 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+fn is_operator_char(c: u8) -> bool {
+    matches!(c, b'|' | b'<' | b'>' | b'&')
+}
+
+// 管道中的一条命令
+#[derive(Clone, Copy)]
+struct Stage<'a> {
+    argv: [&'a str; MAX_ARGS],
+    argc: usize,
+}
+
+impl<'a> Stage<'a> {
+    fn as_slice(&self) -> &[&'a str] {
+        &self.argv[..self.argc]
+    }
+}
+
+// 管道命令行：cmd1 < in | cmd2 | cmd3 > out
+struct Pipeline<'a> {
+    stages: [Stage<'a>; MAX_STAGES],
+    count: usize,
+    input: Option<&'a str>,
+    output: Option<&'a str>,
+    append: bool,
+}
+
+impl<'a> Pipeline<'a> {
+    fn parse(args: &Args<'a>) -> Result<Self, &'static str> {
+        let empty = Stage {
+            argv: [""; MAX_ARGS],
+            argc: 0,
+        };
+        let mut pipeline = Self {
+            stages: [empty; MAX_STAGES],
+            count: 1,
+            input: None,
+            output: None,
+            append: false,
+        };
+
+        let mut i = 0;
+        while i < args.len() {
+            let arg = args.argv[i];
+            let stage = pipeline.count - 1;
+            if !args.is_operator(i) {
+                let current = &mut pipeline.stages[stage];
+                current.argv[current.argc] = arg;
+                current.argc += 1;
+                i += 1;
+                continue;
+            }
+
+            match arg {
+                "|" => {
+                    if pipeline.stages[stage].argc == 0 {
+                        return Err("syntax error near '|'");
+                    }
+                    if pipeline.output.is_some() {
+                        return Err("output redirection only allowed on the last command");
+                    }
+                    if pipeline.count == MAX_STAGES {
+                        return Err("too many commands in pipeline");
+                    }
+                    pipeline.count += 1;
+                }
+                "<" | ">" | ">>" => {
+                    let path = match args.get(i + 1) {
+                        Some(path) if !args.is_operator(i + 1) => path,
+                        _ => return Err("missing file name after redirection"),
+                    };
+                    if arg == "<" {
+                        if stage != 0 {
+                            return Err("input redirection only allowed on the first command");
+                        }
+                        pipeline.input = Some(path);
+                    } else {
+                        pipeline.output = Some(path);
+                        pipeline.append = arg == ">>";
+                    }
+                    i += 1;
+                }
+                _ => return Err("syntax error near '&'"),
+            }
+            i += 1;
+        }
+
+        if pipeline.stages[pipeline.count - 1].argc == 0 {
+            return Err("missing command");
+        }
+        Ok(pipeline)
+    }
+
+    fn stages(&self) -> &[Stage<'a>] {
+        &self.stages[..self.count]
+    }
+}
 
 // Shell命令
 #[derive(Debug, Clone)]
-pub enum Command {
+pub enum Command<'a> {
     Help,
-    List,
+    Ps,
     Create,
+    Run,
+    Kill,
+    Wait,
+    Jobs,
+    Fg,
+    Ls,
+    Cat,
+    Write,
+    Rm,
+    Mv,
+    Mkdir,
+    Rmdir,
+    Stat,
+    Devices,
+    Sync,
+    Cache,
+    Partitions,
+    Mount,
+    Umount,
+    Echo,
+    Export,
+    Unset,
+    Set,
+    Test,
+    True,
+    False,
+    Sh,
     Exit,
-    Unknown(&'static str),
+    Empty,
+    Unknown(&'a str),
+}
+
+impl<'a> Command<'a> {
+    pub fn parse(args: &Args<'a>) -> Command<'a> {
+        match args.get(0) {
+            Some("help") => Command::Help,
+            Some("ps") | Some("list") => Command::Ps,
+            Some("create") => Command::Create,
+            Some("run") => Command::Run,
+            Some("kill") => Command::Kill,
+            Some("wait") => Command::Wait,
+            Some("jobs") => Command::Jobs,
+            Some("fg") => Command::Fg,
+            Some("ls") => Command::Ls,
+            Some("cat") => Command::Cat,
+            Some("write") => Command::Write,
+            Some("rm") => Command::Rm,
+            Some("mv") => Command::Mv,
+            Some("mkdir") => Command::Mkdir,
+            Some("rmdir") => Command::Rmdir,
+            Some("stat") => Command::Stat,
+            Some("devices") => Command::Devices,
+            Some("sync") => Command::Sync,
+            Some("cache") => Command::Cache,
+            Some("partitions") => Command::Partitions,
+            Some("mount") => Command::Mount,
+            Some("umount") => Command::Umount,
+            Some("echo") => Command::Echo,
+            Some("export") => Command::Export,
+            Some("unset") => Command::Unset,
+            Some("set") => Command::Set,
+            Some("test") | Some("[") => Command::Test,
+            Some("true") => Command::True,
+            Some("false") => Command::False,
+            Some("sh") => Command::Sh,
+            Some("exit") => Command::Exit,
+            Some(name) => Command::Unknown(name),
+            None => Command::Empty,
+        }
+    }
 }
 
-impl Command {
-    pub fn parse(input: &str) -> Command {
-        // 简化实现，直接匹配命令
-        match input.trim() {
-            "help" => Command::Help,
-            "list" => Command::List,
-            "create" => Command::Create,
-            "exit" => Command::Exit,
-            "" => Command::Unknown(""),
-            _ => Command::Unknown("unknown"),
+// Tab补全：第一个词补全命令名，其余补全文件名
+struct ShellCompleter;
+
+impl Completer for ShellCompleter {
+    fn complete(&self, line: &str, word_start: usize, add: &mut dyn FnMut(&str)) {
+        if word_start == 0 {
+            for (name, _) in COMMANDS {
+                add(name);
+            }
+            return;
+        }
+
+        // 候选是词中最后一个 '/' 之前的目录里的文件，带上目录前缀
+        let word = &line[word_start..];
+        let prefix = &word[..word.rfind('/').map_or(0, |i| i + 1)];
+        let mut candidate = [0u8; 64];
+        if prefix.len() > candidate.len() {
+            return;
         }
+        candidate[..prefix.len()].copy_from_slice(prefix.as_bytes());
+        let dir = if prefix.is_empty() { "/" } else { prefix };
+        let _ = vfs::for_each_entry(dir, |entry, _| {
+            let name = entry.name().as_bytes();
+            let mut len = prefix.len() + name.len();
+            if len + 1 > candidate.len() {
+                return;
+            }
+            candidate[prefix.len()..len].copy_from_slice(name);
+            if entry.kind == InodeType::Directory {
+                candidate[len] = b'/';
+                len += 1;
+            }
+            if let Ok(name) = core::str::from_utf8(&candidate[..len]) {
+                add(name);
+            }
+        });
+    }
+}
+
+// 后台作业
+#[derive(Clone, Copy)]
+struct Job {
+    id: usize,
+    pid: usize,
+    command: [u8; JOB_COMMAND_LEN],
+    command_len: usize,
+}
+
+impl Job {
+    fn command_str(&self) -> &str {
+        core::str::from_utf8(&self.command[..self.command_len]).unwrap_or("?")
     }
 }
 
 // Shell结构
 pub struct Shell {
     running: bool,
+    editor: LineEditor,
+    jobs: [Option<Job>; MAX_JOBS],
+    next_job_id: usize,
+    env: Environment,
+    status: i32,          // 上一条命令的退出码 ($?)
+    exit_requested: bool, // exit 命令结束当前脚本或shell
+    interrupted: bool,    // Ctrl-C 中断正在执行的脚本或循环
+    script_depth: usize,
+    block: [u8; BLOCK_INPUT_LEN], // 交互输入中尚未闭合的 if/while
+    block_len: usize,
 }
 
 impl Shell {
-    pub fn new() -> Self {
-        Self { running: true }
+    pub const fn new() -> Self {
+        Self {
+            running: true,
+            editor: LineEditor::new(),
+            jobs: [None; MAX_JOBS],
+            next_job_id: 1,
+            env: Environment::new(),
+            status: 0,
+            exit_requested: false,
+            interrupted: false,
+            script_depth: 0,
+            block: [0; BLOCK_INPUT_LEN],
+            block_len: 0,
+        }
+    }
+
+    // 以init身份运行程序，程序退出后进入交互shell
+    pub fn run_init(&mut self, path: &str) {
+        stdio::print("Running init: ");
+        stdio::println(path);
+        self.status = self.run_program(&[path], false);
+        stdio::print("init exited with status ");
+        stdio::print_int(self.status as isize);
+        stdio::println(", starting shell");
+        self.run();
     }
 
     pub fn run(&mut self) {
-        uart::println("Rust OS Shell v1.0");
-        uart::println("Type 'help' for available commands");
+        stdio::println("Rust OS Shell v1.0");
+        stdio::println("Type 'help' for available commands");
+
+        // 启动脚本
+        if vfs::stat(BOOT_SCRIPT).is_ok() {
+            self.status = self.run_script(BOOT_SCRIPT);
+        }
 
         while self.running {
-            uart::print("> ");
+            // 报告已结束的后台作业
+            self.reap_jobs();
+
+            // 读取用户输入，未闭合的 if/while 继续读下一行
+            let prompt = if self.block_len > 0 { "... " } else { "> " };
+            let mut line = [0u8; MAX_LINE];
+            let len = match self.editor.read_line(prompt, &ShellCompleter, &mut line) {
+                Some(len) => len,
+                None if self.block_len > 0 => {
+                    // Ctrl-D 放弃未完成的语句
+                    stdio::println("");
+                    self.block_len = 0;
+                    continue;
+                }
+                None => {
+                    // 空行上的Ctrl-D (EOF) 退出shell
+                    stdio::println("exit");
+                    break;
+                }
+            };
 
-            // 读取用户输入
-            let input = self.read_input();
+            if core::str::from_utf8(&line[..len]).is_err() {
+                stdio::println("Invalid input: not UTF-8");
+                continue;
+            }
+            if self.block_len + len + 1 > BLOCK_INPUT_LEN {
+                stdio::println("shell: input too long");
+                self.block_len = 0;
+                continue;
+            }
+            self.block[self.block_len..self.block_len + len].copy_from_slice(&line[..len]);
+            self.block[self.block_len + len] = b'\n';
+            self.block_len += len + 1;
 
-            // 解析并执行命令
-            self.execute_command(&input);
+            let block = self.block;
+            let source = &block[..self.block_len];
+            let script = match Script::parse(source) {
+                Ok(script) => script,
+                Err(message) => {
+                    print_syntax_error(message);
+                    self.block_len = 0;
+                    continue;
+                }
+            };
+            if script.open_blocks() > 0 {
+                continue;
+            }
+            self.block_len = 0;
+
+            self.interrupted = false;
+            tty::take_interrupt();
+            self.status = self.run_items(source, script.items());
+            if self.exit_requested {
+                stdio::println("Goodbye!");
+                self.running = false;
+            }
         }
     }
 
-    fn read_input(&self) -> &'static str {
-        // 简化实现，返回固定输入
-        "help"
+    // 展开变量后执行一条命令，返回退出码
+    fn run_line(&mut self, raw: &[u8]) -> i32 {
+        let mut line = [0u8; MAX_LINE];
+        let len = match env::expand(raw, &self.env, self.status, &mut line) {
+            Some(len) => len,
+            None => {
+                stdio::println("shell: command too long");
+                return 2;
+            }
+        };
+        if core::str::from_utf8(&line[..len]).is_err() {
+            stdio::println("Invalid input: not UTF-8");
+            return 2;
+        }
+
+        let mut args = Args::parse(&mut line[..len]);
+        if args.is_empty() {
+            return self.status;
+        }
+        if args.is_truncated() {
+            stdio::println("shell: too many arguments");
+            return 2;
+        }
+
+        // NAME=value 赋值
+        if args.len() == 1 && !args.is_operator(0) {
+            if let Some((name, value)) = args.get(0).and_then(env::parse_assignment) {
+                return self.set_variable(name, value);
+            }
+        }
+
+        let background = args.take_background();
+        let status = if args.has_redirection() {
+            self.run_pipeline(&args, background)
+        } else {
+            self.execute_command(&args, background)
+        };
+        self.status = status;
+        status
     }
 
-    fn execute_command(&mut self, input: &str) {
-        let command = Command::parse(input);
+    fn execute_command(&mut self, args: &Args, background: bool) -> i32 {
+        let command = Command::parse(args);
 
         match command {
             Command::Help => {
-                uart::println("Available commands:");
-                uart::println("  help   - Show this help message");
-                uart::println("  list   - List all processes");
-                uart::println("  create - Create a new process");
-                uart::println("  exit   - Exit the shell");
+                stdio::println("Available commands:");
+                for (name, description) in COMMANDS {
+                    stdio::print("  ");
+                    stdio::print(name);
+                    for _ in name.len()..6 {
+                        stdio::put_char(b' ');
+                    }
+                    stdio::print(" - ");
+                    stdio::println(description);
+                }
+                stdio::println(
+                    "Scripting: NAME=value, $NAME, $?, if/then/else/fi, while/do/done, # comments",
+                );
+                0
             }
-            Command::List => {
-                uart::println("Process list:");
-                process::list_processes();
+            Command::Ps => {
+                self.cmd_ps();
+                0
             }
             Command::Create => {
-                uart::println("Creating new process...");
+                stdio::println("Creating new process...");
                 if let Some(pid) = process::create_process(4096) {
-                    uart::print("Process created with PID: ");
-                    uart::println(&pid.to_string());
+                    stdio::print("Process created with PID: ");
+                    stdio::println(&pid.to_string());
+                    0
+                } else {
+                    stdio::println("Failed to create process");
+                    1
+                }
+            }
+            Command::Run => match args.as_slice().get(1..) {
+                Some(argv) if !argv.is_empty() => self.run_program(argv, background),
+                _ => {
+                    stdio::println("Usage: run <program> [args...] [&]");
+                    2
+                }
+            },
+            Command::Kill => self.cmd_kill(args),
+            Command::Wait => self.cmd_wait(args),
+            Command::Jobs => {
+                self.cmd_jobs();
+                0
+            }
+            Command::Fg => self.cmd_fg(args),
+            Command::Ls => self.cmd_ls(args),
+            Command::Cat => self.cmd_cat(args),
+            Command::Write => self.cmd_write(args),
+            Command::Rm => self.cmd_rm(args),
+            Command::Mv => self.cmd_mv(args),
+            Command::Mkdir => self.cmd_mkdir(args),
+            Command::Rmdir => self.cmd_rmdir(args),
+            Command::Stat => self.cmd_stat(args),
+            Command::Devices => {
+                driver::list_devices();
+                0
+            }
+            Command::Sync => {
+                if syscall::sys_sync() == 0 {
+                    0
                 } else {
-                    uart::println("Failed to create process");
+                    stdio::println("sync: I/O error");
+                    1
+                }
+            }
+            Command::Cache => {
+                bcache::print_stats();
+                0
+            }
+            Command::Partitions => {
+                partition::list_partitions();
+                0
+            }
+            Command::Mount => cmd_mount(args),
+            Command::Umount => cmd_umount(args),
+            Command::Echo => cmd_echo(args),
+            Command::Export => self.cmd_export(args),
+            Command::Unset => {
+                for &name in &args.as_slice()[1..] {
+                    self.env.unset(name);
                 }
+                0
+            }
+            Command::Set => {
+                self.print_variables(false);
+                0
             }
+            Command::Test => cmd_test(args.as_slice()),
+            Command::True => 0,
+            Command::False => 1,
+            Command::Sh => match args.get(1) {
+                Some(path) => self.run_script(path),
+                None => {
+                    stdio::println("Usage: sh <file>");
+                    2
+                }
+            },
             Command::Exit => {
-                uart::println("Goodbye!");
-                self.running = false;
+                let status = match args.get(1).map(|code| code.parse::<i32>()) {
+                    Some(Ok(code)) => code,
+                    Some(Err(_)) => {
+                        stdio::println("exit: numeric argument required");
+                        2
+                    }
+                    None => self.status,
+                };
+                self.exit_requested = true;
+                status
+            }
+            Command::Empty => self.status,
+            Command::Unknown(cmd) if cmd.ends_with(".sh") && vfs::stat(cmd).is_ok() => {
+                self.run_script(cmd)
+            }
+            Command::Unknown(cmd) if vfs::stat(cmd).is_ok() => {
+                // 不是内置命令，尝试作为文件系统中的程序运行
+                self.run_program(args.as_slice(), background)
             }
             Command::Unknown(cmd) => {
-                if !cmd.is_empty() {
-                    uart::print("Unknown command: ");
-                    uart::println(&cmd);
-                    uart::println("Type 'help' for available commands");
+                stdio::print("Unknown command: ");
+                stdio::println(cmd);
+                stdio::println("Type 'help' for available commands");
+                127
+            }
+        }
+    }
+}
+
+// 脚本执行
+impl Shell {
+    // 读取并执行脚本文件；脚本中的exit只结束脚本
+    fn run_script(&mut self, path: &str) -> i32 {
+        if self.script_depth >= MAX_SCRIPT_DEPTH {
+            stdio::print("sh: ");
+            stdio::print(path);
+            stdio::println(": scripts nested too deeply");
+            return 2;
+        }
+
+        let metadata = match vfs::stat(path) {
+            Ok(metadata) if metadata.is_directory() => {
+                print_error("sh", path, FsError::IsADirectory);
+                return 1;
+            }
+            Ok(metadata) => metadata,
+            Err(err) => {
+                print_error("sh", path, err);
+                return 127;
+            }
+        };
+        let size = metadata.size;
+        if size > MAX_SCRIPT_SIZE {
+            stdio::print("sh: ");
+            stdio::print(path);
+            stdio::println(": script too large");
+            return 1;
+        }
+        if size == 0 {
+            return 0;
+        }
+
+        // 每层嵌套的脚本使用自己的静态缓冲区，执行期间源码一直有效
+        let source = &mut script_buffers()[self.script_depth][..size];
+        let mut total = 0;
+        if let Ok(file) = vfs::open(path, vfs::O_RDONLY) {
+            while total < size {
+                match file.read(&mut source[total..]) {
+                    Ok(0) | Err(_) => break,
+                    Ok(count) => total += count,
+                }
+            }
+            let _ = vfs::close(file);
+        }
+
+        let status = match Script::parse(&source[..total]) {
+            Ok(script) => {
+                self.script_depth += 1;
+                let status = self.run_items(&source[..total], script.items());
+                self.script_depth -= 1;
+                self.exit_requested = false;
+                status
+            }
+            Err(message) => {
+                print_syntax_error(message);
+                2
+            }
+        };
+        status
+    }
+
+    // 按顺序执行脚本元素，遇到 if/while 时递归执行各分支
+    fn run_items(&mut self, source: &[u8], items: &[Item]) -> i32 {
+        let mut status = self.status;
+        let mut pos = 0;
+        while pos < items.len() && !self.should_stop() {
+            match items[pos] {
+                Item::Command(start, end) => {
+                    status = self.run_line(&source[start..end]);
+                    pos += 1;
+                }
+                Item::If => {
+                    let parts = match script::find_if(items, pos) {
+                        Ok(parts) if parts.then > pos + 1 => parts,
+                        Ok(_) => return print_syntax_error("missing condition after 'if'"),
+                        Err(message) => return print_syntax_error(message),
+                    };
+                    let end = parts.otherwise.unwrap_or(parts.fi);
+                    status = if self.run_items(source, &items[pos + 1..parts.then]) == 0 {
+                        self.run_items(source, &items[parts.then + 1..end])
+                    } else if let Some(otherwise) = parts.otherwise {
+                        self.run_items(source, &items[otherwise + 1..parts.fi])
+                    } else {
+                        0
+                    };
+                    pos = parts.fi + 1;
+                }
+                Item::While => {
+                    let (body, done) = match script::find_loop(items, pos) {
+                        Ok((body, done)) if body > pos + 1 => (body, done),
+                        Ok(_) => return print_syntax_error("missing condition after 'while'"),
+                        Err(message) => return print_syntax_error(message),
+                    };
+                    status = 0;
+                    while !self.should_stop() && self.run_items(source, &items[pos + 1..body]) == 0
+                    {
+                        status = self.run_items(source, &items[body + 1..done]);
+                    }
+                    pos = done + 1;
+                }
+                _ => return print_syntax_error("unexpected keyword"),
+            }
+        }
+        self.status = status;
+        status
+    }
+
+    // exit 或 Ctrl-C 时停止执行剩余的命令
+    fn should_stop(&mut self) -> bool {
+        tty::poll_input();
+        if tty::take_interrupt() {
+            self.interrupted = true;
+            self.status = 130;
+        }
+        self.exit_requested || self.interrupted
+    }
+
+    fn set_variable(&mut self, name: &str, value: &str) -> i32 {
+        if self.env.set(name, value) {
+            0
+        } else {
+            stdio::print("shell: cannot set ");
+            stdio::println(name);
+            1
+        }
+    }
+
+    // export [NAME[=value]...]，不带参数时列出导出的变量
+    fn cmd_export(&mut self, args: &Args) -> i32 {
+        if args.len() < 2 {
+            self.print_variables(true);
+            return 0;
+        }
+
+        let mut status = 0;
+        for &word in &args.as_slice()[1..] {
+            let name = match env::parse_assignment(word) {
+                Some((name, value)) => {
+                    if self.set_variable(name, value) != 0 {
+                        status = 1;
+                        continue;
+                    }
+                    name
+                }
+                None => word,
+            };
+            if !self.env.export(name) {
+                stdio::print("export: invalid variable name: ");
+                stdio::println(name);
+                status = 1;
+            }
+        }
+        status
+    }
+
+    fn print_variables(&self, exported_only: bool) {
+        self.env.for_each(|name, value, exported| {
+            if exported_only && !exported {
+                return;
+            }
+            if exported {
+                stdio::print("export ");
+            }
+            stdio::print(name);
+            stdio::print("=");
+            stdio::println(value);
+        });
+    }
+}
+
+// echo [-n] [args...]
+fn cmd_echo(args: &Args) -> i32 {
+    let mut words = &args.as_slice()[1..];
+    let newline = words.first() != Some(&"-n");
+    if !newline {
+        words = &words[1..];
+    }
+    for (i, word) in words.iter().enumerate() {
+        if i > 0 {
+            let _ = stdio::write(STDOUT, b" ");
+        }
+        let _ = stdio::write(STDOUT, word.as_bytes());
+    }
+    if newline {
+        let _ = stdio::write(STDOUT, b"\n");
+    }
+    0
+}
+
+// mount：列出挂载表；mount <source> <target> <type>：挂载文件系统
+fn cmd_mount(args: &Args) -> i32 {
+    match args.as_slice() {
+        [_] => {
+            vfs::list_mounts();
+            0
+        }
+        [_, source, target, fstype] => {
+            if syscall::sys_mount(source, target, fstype) == 0 {
+                0
+            } else {
+                stdio::print("mount: ");
+                stdio::print(target);
+                stdio::println(": mount failed");
+                1
+            }
+        }
+        _ => {
+            stdio::println("Usage: mount [<source> <target> <type>]");
+            stdio::println("Types: fat, ramfs, devfs, procfs, initramfs");
+            2
+        }
+    }
+}
+
+// umount <target>
+fn cmd_umount(args: &Args) -> i32 {
+    match args.as_slice() {
+        [_, target] => {
+            if syscall::sys_umount(target) == 0 {
+                0
+            } else {
+                stdio::print("umount: ");
+                stdio::print(target);
+                stdio::println(": unmount failed");
+                1
+            }
+        }
+        _ => {
+            stdio::println("Usage: umount <target>");
+            2
+        }
+    }
+}
+
+// test EXPR 或 [ EXPR ]：字符串、整数和文件条件，结果为真时返回0
+fn cmd_test(argv: &[&str]) -> i32 {
+    let mut expr = &argv[1..];
+    if argv[0] == "[" {
+        match expr.split_last() {
+            Some((&"]", rest)) => expr = rest,
+            _ => {
+                stdio::println("[: missing ']'");
+                return 2;
+            }
+        }
+    }
+
+    let mut negate = false;
+    if expr.first() == Some(&"!") {
+        negate = true;
+        expr = &expr[1..];
+    }
+
+    let result = match *expr {
+        [] => Some(false),
+        [s] => Some(!s.is_empty()),
+        ["-z", s] => Some(s.is_empty()),
+        ["-n", s] => Some(!s.is_empty()),
+        ["-e", path] => Some(vfs::stat(path).is_ok()),
+        ["-f", path] => Some(vfs::stat(path).is_ok_and(|info| info.is_regular_file())),
+        ["-d", path] => Some(vfs::stat(path).is_ok_and(|info| info.is_directory())),
+        [a, "=", b] | [a, "==", b] => Some(a == b),
+        [a, "!=", b] => Some(a != b),
+        [a, op, b] => match (a.parse::<i64>(), b.parse::<i64>()) {
+            (Ok(a), Ok(b)) => match op {
+                "-eq" => Some(a == b),
+                "-ne" => Some(a != b),
+                "-lt" => Some(a < b),
+                "-le" => Some(a <= b),
+                "-gt" => Some(a > b),
+                "-ge" => Some(a >= b),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    };
+
+    match result {
+        Some(value) if value != negate => 0,
+        Some(_) => 1,
+        None => {
+            stdio::println("test: invalid expression");
+            2
+        }
+    }
+}
+
+fn print_syntax_error(message: &str) -> i32 {
+    stdio::eprint("shell: ");
+    stdio::eprintln(message);
+    2
+}
+
+// 进程控制命令
+impl Shell {
+    // ps：PID、父进程、状态、CPU时间 (时钟节拍) 和名称
+    fn cmd_ps(&self) {
+        stdio::println("  PID  PPID STATE         TIME NAME");
+        process::for_each_process(|p| {
+            stdio::print_dec_padded(p.pid, 5);
+            stdio::print_dec_padded(p.parent, 6);
+            stdio::print(" ");
+            print_padded(state_name(p.state), 8);
+            stdio::print_dec_padded(p.cpu_time, 10);
+            stdio::print(" ");
+            let name = p.name_str();
+            stdio::println(if name.is_empty() { "-" } else { name });
+        });
+    }
+
+    // kill <pid|%job> [signal]，默认SIGTERM
+    fn cmd_kill(&mut self, args: &Args) -> i32 {
+        let pid = match args.get(1).and_then(|target| self.resolve_pid(target)) {
+            Some(pid) => pid,
+            None => {
+                stdio::println("Usage: kill <pid|%job> [signal]");
+                return 2;
+            }
+        };
+        let signal = match args.get(2) {
+            Some(name) => match parse_signal(name) {
+                Some(signal) => signal,
+                None => {
+                    stdio::print("kill: unknown signal: ");
+                    stdio::println(name);
+                    return 2;
+                }
+            },
+            None => process::SIGTERM,
+        };
+
+        if !process::send_signal(pid, signal) {
+            stdio::print("kill: no such process: ");
+            stdio::print_dec(pid);
+            stdio::println("");
+            return 1;
+        }
+        0
+    }
+
+    // wait [pid|%job]，不带参数时等待所有后台作业
+    fn cmd_wait(&mut self, args: &Args) -> i32 {
+        if let Some(target) = args.get(1) {
+            return match self.resolve_pid(target) {
+                Some(pid) => {
+                    let job = self.remove_job(pid);
+                    self.wait_foreground(pid, job.as_ref().map_or("", |job| job.command_str()))
+                }
+                None => {
+                    stdio::print("wait: no such process: ");
+                    stdio::println(target);
+                    127
+                }
+            };
+        }
+
+        let mut status = 0;
+        while let Some(job) = self.jobs.iter().flatten().next().copied() {
+            self.remove_job(job.pid);
+            status = self.wait_foreground(job.pid, job.command_str());
+        }
+        status
+    }
+
+    // jobs：列出后台作业
+    fn cmd_jobs(&self) {
+        for job in self.jobs.iter().flatten() {
+            stdio::print("[");
+            stdio::print_dec(job.id);
+            stdio::print("] ");
+            stdio::print_dec_padded(job.pid, 5);
+            stdio::print(" ");
+            let state = process::process_state(job.pid).unwrap_or(ProcessState::Terminated);
+            print_padded(
+                match state {
+                    ProcessState::Blocked => "Stopped",
+                    ProcessState::Terminated => "Done",
+                    _ => "Running",
+                },
+                8,
+            );
+            stdio::println(job.command_str());
+        }
+    }
+
+    // fg [%job]，默认最近的作业
+    fn cmd_fg(&mut self, args: &Args) -> i32 {
+        let pid = match args.get(1) {
+            Some(target) => self.resolve_pid(target),
+            None => self
+                .jobs
+                .iter()
+                .flatten()
+                .max_by_key(|job| job.id)
+                .map(|job| job.pid),
+        };
+        let job = match pid.and_then(|pid| self.remove_job(pid)) {
+            Some(job) => job,
+            None => {
+                stdio::println("fg: no such job");
+                return 1;
+            }
+        };
+
+        stdio::println(job.command_str());
+        if process::process_state(job.pid) == Some(ProcessState::Blocked) {
+            process::send_signal(job.pid, process::SIGCONT);
+        }
+        self.wait_foreground(job.pid, job.command_str())
+    }
+
+    // 加载并运行磁盘上的程序，argv[0]为程序路径
+    fn run_program(&mut self, argv: &[&str], background: bool) -> i32 {
+        let pid = match self.exec(argv) {
+            Ok(pid) => pid,
+            Err(status) => return status,
+        };
+
+        let mut buffer = [0u8; JOB_COMMAND_LEN];
+        let command = join_command(argv, &mut buffer);
+        if background {
+            self.add_job(pid, command);
+            0
+        } else {
+            self.wait_foreground(pid, command)
+        }
+    }
+
+    // 创建进程，导出的变量作为环境变量传给程序；失败时返回退出码
+    fn exec(&self, argv: &[&str]) -> Result<usize, i32> {
+        let mut block = [0u8; ENV_BLOCK_SIZE];
+        let mut envp = [""; MAX_VARS];
+        let envc = self.env.exported(&mut block, &mut envp);
+        process::exec(argv[0], argv, &envp[..envc], 0).map_err(|err| {
+            stdio::print("run: ");
+            stdio::print(argv[0]);
+            stdio::print(": ");
+            stdio::println(err.as_str());
+            if err == ElfError::NotFound {
+                127
+            } else {
+                126
+            }
+        })
+    }
+
+    // 运行管道和重定向：每条命令一个进程，相邻命令之间用内核管道连接
+    fn run_pipeline(&mut self, args: &Args, background: bool) -> i32 {
+        let pipeline = match Pipeline::parse(args) {
+            Ok(pipeline) => pipeline,
+            Err(message) => {
+                stdio::print("shell: ");
+                stdio::println(message);
+                return 2;
+            }
+        };
+
+        // 内置命令在shell中执行，标准输入输出通过stdio重定向到管道或文件
+        let mut programs = [[""; MAX_ARGS]; MAX_STAGES];
+        let mut argcs = [0; MAX_STAGES];
+        let mut builtins = [false; MAX_STAGES];
+        for (i, stage) in pipeline.stages().iter().enumerate() {
+            let mut argv = stage.as_slice();
+            if argv[0] == "run" && argv.len() > 1 {
+                argv = &argv[1..];
+            } else {
+                builtins[i] = COMMANDS.iter().any(|(name, _)| *name == argv[0]);
+            }
+            programs[i][..argv.len()].copy_from_slice(argv);
+            argcs[i] = argv.len();
+        }
+
+        // 先打开重定向的文件和管道，失败时不执行任何命令
+        // stdins/stdouts持有打开文件表中的引用，交给命令后释放
+        let mut stdins = [None; MAX_STAGES];
+        let mut stdouts = [None; MAX_STAGES];
+        stdins[0] = match pipeline.input.map(file::open_read).transpose() {
+            Ok(input) => input,
+            Err(err) => {
+                print_file_error(pipeline.input.unwrap_or(""), err);
+                return 1;
+            }
+        };
+        stdouts[pipeline.count - 1] = match pipeline
+            .output
+            .map(|path| file::open_write(path, pipeline.append))
+            .transpose()
+        {
+            Ok(output) => output,
+            Err(err) => {
+                print_file_error(pipeline.output.unwrap_or(""), err);
+                release_files(&mut stdins);
+                return 1;
+            }
+        };
+        for i in 0..pipeline.count - 1 {
+            // 相邻的内置命令依次执行，写者不能等待读者，管道要装下写者的全部输出
+            let pipe = if builtins[i] && builtins[i + 1] {
+                file::open_spooled_pipe()
+            } else {
+                file::open_pipe()
+            };
+            match pipe {
+                Ok((read_end, write_end)) => {
+                    stdouts[i] = Some(write_end);
+                    stdins[i + 1] = Some(read_end);
+                }
+                Err(err) => {
+                    print_file_error("pipe", err);
+                    release_files(&mut stdins);
+                    release_files(&mut stdouts);
+                    return 1;
                 }
             }
         }
+
+        // 先启动外部命令，内置命令读写管道时可以调度它们
+        let mut pids = [None; MAX_STAGES];
+        let mut status = 0;
+        for i in 0..pipeline.count {
+            if builtins[i] {
+                continue;
+            }
+            let pid = match self.exec(&programs[i][..argcs[i]]) {
+                Ok(pid) => pid,
+                Err(code) => {
+                    // 启动失败时结束已经启动的命令
+                    for &pid in pids.iter().flatten() {
+                        process::send_signal(pid, process::SIGKILL);
+                    }
+                    release_files(&mut stdins);
+                    release_files(&mut stdouts);
+                    return code;
+                }
+            };
+            for (fd, index) in [(0, stdins[i].take()), (1, stdouts[i].take())] {
+                if let Some(index) = index {
+                    process::set_fd(pid, fd, index);
+                    file::release(index);
+                }
+            }
+            pids[i] = Some(pid);
+        }
+
+        // 按顺序执行内置命令，结束后关闭它的管道端点，读者看到EOF
+        for i in 0..pipeline.count {
+            if !builtins[i] {
+                continue;
+            }
+            let saved_stdin = stdio::redirect(STDIN, stdins[i].take());
+            let saved_stdout = stdio::redirect(STDOUT, stdouts[i].take());
+            let code = self.execute_command(&Args::from_slice(&programs[i][..argcs[i]]), false);
+            for index in [
+                stdio::redirect(STDIN, saved_stdin),
+                stdio::redirect(STDOUT, saved_stdout),
+            ]
+            .into_iter()
+            .flatten()
+            {
+                file::release(index);
+            }
+            if i + 1 == pipeline.count {
+                status = code;
+            }
+            // 输出超过管道缓冲区时报告，不悄悄截断
+            if i + 1 < pipeline.count && builtins[i + 1] {
+                if let Some(read_end) = stdins[i + 1] {
+                    if file::pipe_overflowed(read_end) {
+                        stdio::eprintln("shell: pipe: output too large, truncated");
+                    }
+                }
+            }
+        }
+
+        let last = pids[pipeline.count - 1];
+        let mut buffer = [0u8; JOB_COMMAND_LEN];
+        let command = join_command(args.as_slice(), &mut buffer);
+        if background {
+            // 作业以最后一条外部命令为准，其余的结束后由reap_jobs回收
+            if let Some(&pid) = pids.iter().flatten().last() {
+                self.add_job(pid, command);
+            }
+        } else {
+            // 管道的退出码取最后一条命令的退出码
+            for &pid in pids.iter().flatten() {
+                let code = self.wait_foreground(pid, command);
+                if Some(pid) == last {
+                    status = code;
+                }
+            }
+        }
+        status
+    }
+
+    // 把进程设为终端前台进程 (接收Ctrl-C) 并等待它结束，返回退出码；被停止时转入作业表
+    fn wait_foreground(&mut self, pid: usize, command: &str) -> i32 {
+        tty::set_foreground(Some(pid));
+        let status = loop {
+            if let Some((_, code)) = process::wait(Some(pid), 0) {
+                break Some(code);
+            }
+            match process::process_state(pid) {
+                None => break None,
+                Some(ProcessState::Blocked) => {
+                    stdio::println("");
+                    self.add_job(pid, command);
+                    tty::set_foreground(None);
+                    return 128 + process::SIGSTOP as i32;
+                }
+                _ => {}
+            }
+            tty::poll_input();
+            process::schedule();
+            core::hint::spin_loop();
+        };
+        tty::set_foreground(None);
+
+        if let Some(code) = status {
+            if code != 0 {
+                stdio::print("Process ");
+                stdio::print_dec(pid);
+                stdio::print(" exited with status ");
+                stdio::print_int(code as isize);
+                stdio::println("");
+            }
+        }
+        status.unwrap_or(0)
+    }
+
+    fn add_job(&mut self, pid: usize, command: &str) {
+        let slot = match self.jobs.iter_mut().find(|job| job.is_none()) {
+            Some(slot) => slot,
+            None => {
+                stdio::println("Job table full, process runs detached");
+                return;
+            }
+        };
+
+        let mut job = Job {
+            id: self.next_job_id,
+            pid,
+            command: [0; JOB_COMMAND_LEN],
+            command_len: command.len().min(JOB_COMMAND_LEN),
+        };
+        job.command[..job.command_len].copy_from_slice(&command.as_bytes()[..job.command_len]);
+        *slot = Some(job);
+        self.next_job_id += 1;
+
+        stdio::print("[");
+        stdio::print_dec(job.id);
+        stdio::print("] ");
+        stdio::print_dec(pid);
+        stdio::println("");
+    }
+
+    fn remove_job(&mut self, pid: usize) -> Option<Job> {
+        let slot = self
+            .jobs
+            .iter_mut()
+            .find(|job| job.is_some_and(|job| job.pid == pid))?;
+        let job = slot.take();
+        if self.jobs.iter().all(|job| job.is_none()) {
+            self.next_job_id = 1;
+        }
+        job
+    }
+
+    // 回收已结束的后台作业并报告
+    fn reap_jobs(&mut self) {
+        for i in 0..MAX_JOBS {
+            let job = match self.jobs[i] {
+                Some(job) => job,
+                None => continue,
+            };
+            let code = match process::process_state(job.pid) {
+                Some(ProcessState::Terminated) => {
+                    process::wait(Some(job.pid), 0).map(|(_, code)| code)
+                }
+                Some(_) => continue,
+                None => None,
+            };
+            self.jobs[i] = None;
+
+            stdio::print("[");
+            stdio::print_dec(job.id);
+            stdio::print("]  ");
+            match code {
+                Some(0) | None => stdio::print("Done      "),
+                Some(code) => {
+                    stdio::print("Exit ");
+                    stdio::print_int_padded(code as isize, 4);
+                    stdio::print(" ");
+                }
+            }
+            stdio::println(job.command_str());
+        }
+
+        // 回收不在作业表中的子进程 (例如后台管道中前面的命令)
+        while process::wait(None, 0).is_some() {}
+
+        if self.jobs.iter().all(|job| job.is_none()) {
+            self.next_job_id = 1;
+        }
+    }
+
+    // 解析 "123" 或 "%1" (作业号)
+    fn resolve_pid(&self, target: &str) -> Option<usize> {
+        match target.strip_prefix('%') {
+            Some(id) => {
+                let id = id.parse::<usize>().ok()?;
+                self.jobs
+                    .iter()
+                    .flatten()
+                    .find(|job| job.id == id)
+                    .map(|job| job.pid)
+            }
+            None => target.parse::<usize>().ok(),
+        }
     }
 }
 
+fn state_name(state: ProcessState) -> &'static str {
+    match state {
+        ProcessState::Ready => "Ready",
+        ProcessState::Running => "Running",
+        ProcessState::Blocked => "Blocked",
+        ProcessState::Terminated => "Zombie",
+    }
+}
+
+// 信号可以写成数字、INT 或 SIGINT
+fn parse_signal(name: &str) -> Option<usize> {
+    if let Ok(signal) = name.parse::<usize>() {
+        return Some(signal);
+    }
+    let name = name.strip_prefix("SIG").unwrap_or(name);
+    match name {
+        "INT" => Some(process::SIGINT),
+        "KILL" => Some(process::SIGKILL),
+        "TERM" => Some(process::SIGTERM),
+        "CHLD" => Some(process::SIGCHLD),
+        "CONT" => Some(process::SIGCONT),
+        "STOP" => Some(process::SIGSTOP),
+        _ => None,
+    }
+}
+
+// 把参数用空格连接成命令行文本，超出缓冲区的部分被截断
+fn join_command<'b>(argv: &[&str], buffer: &'b mut [u8; JOB_COMMAND_LEN]) -> &'b str {
+    let mut len = 0;
+    for (i, arg) in argv.iter().enumerate() {
+        for &c in (if i > 0 { " " } else { "" })
+            .as_bytes()
+            .iter()
+            .chain(arg.as_bytes())
+        {
+            if len < JOB_COMMAND_LEN {
+                buffer[len] = c;
+                len += 1;
+            }
+        }
+    }
+    core::str::from_utf8(&buffer[..len]).unwrap_or("?")
+}
+
+fn print_padded(s: &str, width: usize) {
+    stdio::print(s);
+    for _ in s.len()..width {
+        stdio::put_char(b' ');
+    }
+}
+
+// 文件系统命令
+impl Shell {
+    // ls [path]
+    fn cmd_ls(&self, args: &Args) -> i32 {
+        let path = args.get(1).unwrap_or("/");
+        match vfs::stat(path) {
+            Ok(metadata) if !metadata.is_directory() => {
+                print_entry(path, &metadata);
+                return 0;
+            }
+            Ok(_) => {}
+            Err(err) => {
+                print_error("ls", path, err);
+                return 1;
+            }
+        }
+
+        let mut count = 0;
+        let mut total = 0;
+        let result = vfs::for_each_entry(path, |entry, inode| {
+            if let Ok(metadata) = inode.metadata() {
+                print_entry(entry.name(), &metadata);
+                count += 1;
+                total += metadata.size;
+            }
+        });
+        if let Err(err) = result {
+            print_error("ls", path, err);
+            return 1;
+        }
+        stdio::print_dec(count);
+        stdio::print(" entries, ");
+        stdio::print_dec(total);
+        stdio::println(" bytes");
+        0
+    }
+
+    // cat <file>...，没有参数时把标准输入复制到标准输出
+    fn cmd_cat(&self, args: &Args) -> i32 {
+        if args.len() < 2 {
+            let mut buffer = [0u8; 512];
+            loop {
+                match stdio::read(STDIN, &mut buffer) {
+                    Ok(0) => return 0,
+                    Ok(count) => {
+                        let _ = stdio::write(STDOUT, &buffer[..count]);
+                    }
+                    Err(err) => {
+                        print_file_error("cat", err);
+                        return 1;
+                    }
+                }
+            }
+        }
+
+        let mut status = 0;
+
+        for &name in &args.as_slice()[1..] {
+            let file = match vfs::open(name, vfs::O_RDONLY) {
+                Ok(file) => file,
+                Err(err) => {
+                    print_error("cat", name, err);
+                    status = 1;
+                    continue;
+                }
+            };
+            let mut buffer = [0u8; 512];
+            loop {
+                match file.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(count) => {
+                        let _ = stdio::write(STDOUT, &buffer[..count]);
+                    }
+                    Err(err) => {
+                        print_error("cat", name, err);
+                        status = 1;
+                        break;
+                    }
+                }
+            }
+            let _ = vfs::close(file);
+        }
+        status
+    }
+
+    // write <file> [text...]，没有text时从终端读取直到Ctrl-D
+    fn cmd_write(&self, args: &Args) -> i32 {
+        let name = match args.get(1) {
+            Some(name) => name,
+            None => {
+                stdio::println("Usage: write <file> [text...]");
+                return 2;
+            }
+        };
+
+        let file = match vfs::open(name, vfs::O_WRONLY | vfs::O_CREAT | vfs::O_TRUNC) {
+            Ok(file) => file,
+            Err(err) => {
+                print_error("write", name, err);
+                return 1;
+            }
+        };
+
+        let mut written = 0;
+        let mut result = Ok(());
+        if args.len() > 2 {
+            // 词之间用空格分隔，最后加换行
+            let words = &args.as_slice()[2..];
+            for (i, word) in words.iter().enumerate() {
+                let separator: &[u8] = if i + 1 < words.len() { b" " } else { b"\n" };
+                for data in [word.as_bytes(), separator] {
+                    if result.is_ok() {
+                        result = write_counted(file, data, &mut written);
+                    }
+                }
+            }
+        } else {
+            if stdio::get_fd(STDIN).is_none() {
+                stdio::println("Enter text, Ctrl-D on an empty line to finish:");
+            }
+            let mut buffer = [0u8; MAX_LINE];
+            loop {
+                let count = match stdio::read(STDIN, &mut buffer) {
+                    Ok(0) | Err(_) => break,
+                    Ok(count) => count,
+                };
+                result = write_counted(file, &buffer[..count], &mut written);
+                if result.is_err() {
+                    break;
+                }
+            }
+        }
+        // 文件大小在关闭时写回目录项
+        let closed = vfs::close(file);
+        match result.and(closed) {
+            Ok(_) => {
+                stdio::print_dec(written);
+                stdio::println(" bytes written");
+                0
+            }
+            Err(err) => {
+                print_error("write", name, err);
+                1
+            }
+        }
+    }
+
+    // rm <file>...
+    fn cmd_rm(&self, args: &Args) -> i32 {
+        if args.len() < 2 {
+            stdio::println("Usage: rm <file>...");
+            return 2;
+        }
+        let mut status = 0;
+        for &name in &args.as_slice()[1..] {
+            if let Err(err) = vfs::remove_file(name) {
+                print_error("rm", name, err);
+                status = 1;
+            }
+        }
+        status
+    }
+
+    // mv <old> <new>
+    fn cmd_mv(&self, args: &Args) -> i32 {
+        let (old, new) = match args.as_slice() {
+            [_, old, new] => (*old, *new),
+            _ => {
+                stdio::println("Usage: mv <old> <new>");
+                return 2;
+            }
+        };
+        match vfs::rename(old, new) {
+            Ok(()) => 0,
+            Err(err) => {
+                print_error("mv", old, err);
+                1
+            }
+        }
+    }
+
+    // mkdir <dir>...
+    fn cmd_mkdir(&self, args: &Args) -> i32 {
+        if args.len() < 2 {
+            stdio::println("Usage: mkdir <dir>...");
+            return 2;
+        }
+        let mut status = 0;
+        for &name in &args.as_slice()[1..] {
+            if let Err(err) = vfs::create_dir(name) {
+                print_error("mkdir", name, err);
+                status = 1;
+            }
+        }
+        status
+    }
+
+    // rmdir <dir>...
+    fn cmd_rmdir(&self, args: &Args) -> i32 {
+        if args.len() < 2 {
+            stdio::println("Usage: rmdir <dir>...");
+            return 2;
+        }
+        let mut status = 0;
+        for &name in &args.as_slice()[1..] {
+            if let Err(err) = vfs::remove_dir(name) {
+                print_error("rmdir", name, err);
+                status = 1;
+            }
+        }
+        status
+    }
+
+    // stat <file>...
+    fn cmd_stat(&self, args: &Args) -> i32 {
+        if args.len() < 2 {
+            stdio::println("Usage: stat <file>...");
+            return 2;
+        }
+        let mut status = 0;
+        for &name in &args.as_slice()[1..] {
+            let metadata = match vfs::stat(name) {
+                Ok(metadata) => metadata,
+                Err(err) => {
+                    print_error("stat", name, err);
+                    status = 1;
+                    continue;
+                }
+            };
+
+            stdio::print("  Name: ");
+            stdio::println(name);
+            stdio::print("  Type: ");
+            stdio::println(match metadata.kind {
+                InodeType::Regular => "regular file",
+                InodeType::Directory => "directory",
+                InodeType::CharDevice => "character device",
+                InodeType::Symlink => "symbolic link",
+                InodeType::Fifo => "fifo",
+            });
+            stdio::print("  Size: ");
+            stdio::print_dec(metadata.size);
+            stdio::println(" bytes");
+            stdio::print("  Access: ");
+            stdio::println(if metadata.read_only {
+                "read-only"
+            } else {
+                "read-write"
+            });
+            stdio::print("  Inode: ");
+            stdio::print_dec(metadata.inode as usize);
+            stdio::println("");
+            stdio::print("  Created: ");
+            print_timestamp(&FatDateTime::from_unix_time(metadata.created));
+            stdio::print("  Modified: ");
+            print_timestamp(&FatDateTime::from_unix_time(metadata.modified));
+        }
+        status
+    }
+}
+
+// 打印 YYYY-MM-DD HH:MM:SS
+fn print_timestamp(timestamp: &FatDateTime) {
+    let two_digits = |value: usize| {
+        stdio::put_char(b'0' + (value / 10 % 10) as u8);
+        stdio::put_char(b'0' + (value % 10) as u8);
+    };
+    stdio::print_dec(timestamp.year());
+    stdio::print("-");
+    two_digits(timestamp.month());
+    stdio::print("-");
+    two_digits(timestamp.day());
+    stdio::print(" ");
+    two_digits(timestamp.hour());
+    stdio::print(":");
+    two_digits(timestamp.minute());
+    stdio::print(":");
+    two_digits(timestamp.second());
+    stdio::println("");
+}
+
+// ls的一行：类型和读写权限、大小、名称
+fn print_entry(name: &str, metadata: &Metadata) {
+    stdio::put_char(metadata.kind.as_char());
+    stdio::print(if metadata.read_only { "r-" } else { "rw" });
+    stdio::print(" ");
+    stdio::print_dec_padded(metadata.size, 10);
+    stdio::print("  ");
+    stdio::print(name);
+    if metadata.is_directory() {
+        stdio::put_char(b'/');
+    }
+    stdio::println("");
+}
+
+// 写入并累计实际写入的字节数
+fn write_counted(file: &mut dyn File, data: &[u8], written: &mut usize) -> Result<(), FsError> {
+    *written += file.write(data)?;
+    Ok(())
+}
+
+fn print_error(command: &str, name: &str, err: FsError) {
+    stdio::eprint(command);
+    stdio::eprint(": ");
+    stdio::eprint(name);
+    stdio::eprint(": ");
+    stdio::eprintln(err.as_str());
+}
+
+// 释放还没有交给命令的打开文件
+fn release_files(files: &mut [Option<usize>]) {
+    for index in files.iter_mut().filter_map(|index| index.take()) {
+        file::release(index);
+    }
+}
+
+fn print_file_error(name: &str, err: FileError) {
+    stdio::eprint("shell: ");
+    stdio::eprint(name);
+    stdio::eprint(": ");
+    stdio::eprintln(err.as_str());
+}
+
 // 全局Shell实例
-pub static mut SHELL: Shell = Shell { running: true };
+pub static mut SHELL: Shell = Shell::new();
+
+fn shell() -> &'static mut Shell {
+    unsafe { &mut *core::ptr::addr_of_mut!(SHELL) }
+}
 
 // 全局函数
 pub fn run_shell() {
-    unsafe {
-        SHELL.run();
-    }
+    shell().run();
+}
+
+pub fn run_init(path: &str) {
+    shell().run_init(path);
 }